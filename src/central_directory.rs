@@ -0,0 +1,497 @@
+use std::io;
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
+
+use crate::eocd::{
+    find_end_of_central_directory, read_u16, read_u32, read_u64, EndOfCentralDirectory,
+};
+
+const CENTRAL_DIRECTORY_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x01, 0x02];
+const CENTRAL_DIRECTORY_HEADER_LENGTH: usize = 46;
+const LOCAL_FILE_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
+const LOCAL_FILE_HEADER_LENGTH: usize = 30;
+const DATA_DESCRIPTOR_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x07, 0x08];
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const ZIP64_EOCD_LOCATOR_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x06, 0x07];
+const ZIP64_EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x06, 0x06];
+
+/// The header ID of the Zip64 extended information extra field.
+pub(crate) const ZIP64_EXTRA_FIELD: u16 = 0x0001;
+/// The value of a 32-bit field whose actual value is stored in the Zip64 extra field.
+const ZIP64_MARKER: u32 = 0xffff_ffff;
+/// The version needed to extract entries that use Zip64 extensions.
+const ZIP64_VERSION: u16 = 45;
+
+/// The compression method that marks an AES encrypted entry, whose actual method is stored in its
+/// AES extra field.
+const AES_COMPRESSION_METHOD: u16 = 99;
+/// The version needed to extract AES encrypted entries.
+const AES_VERSION: u16 = 51;
+
+/// The general purpose flag that marks an encrypted entry.
+const ENCRYPTED_FLAG: u16 = 0x0001;
+/// The general purpose flag that marks an entry whose sizes and CRC-32 follow its data.
+const DATA_DESCRIPTOR_FLAG: u16 = 0x0008;
+/// The general purpose flag that marks an entry whose name and comment are UTF-8.
+const UTF8_FLAG: u16 = 0x0800;
+
+/// An extra field of an entry header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ExtraField {
+    /// The header ID that identifies the kind of the field.
+    pub(crate) id: u16,
+    /// The data of the field.
+    pub(crate) data: Vec<u8>,
+}
+
+/// The fields of a central directory header, with the values of the Zip64 extra field applied.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CentralDirectoryHeader {
+    pub(crate) version_made_by: u16,
+    pub(crate) version_needed: u16,
+    pub(crate) flags: u16,
+    pub(crate) compression_method: u16,
+    pub(crate) modified_time: u16,
+    pub(crate) modified_date: u16,
+    pub(crate) crc32: u32,
+    pub(crate) compressed_size: u64,
+    pub(crate) uncompressed_size: u64,
+    pub(crate) internal_attributes: u16,
+    pub(crate) external_attributes: u32,
+    /// The offset of the local file header, relative to the start of the ZIP data.
+    pub(crate) local_header_offset: u64,
+    pub(crate) name: Vec<u8>,
+    /// The extra fields other than the Zip64 extra field, which is written as needed.
+    pub(crate) extra_fields: Vec<ExtraField>,
+    pub(crate) comment: Vec<u8>,
+}
+
+impl CentralDirectoryHeader {
+    /// Clears the data descriptor flag, so that the sizes and CRC-32 are written to the local
+    /// header instead. Encrypted entries keep it, since `ZipCrypto` then checks the password
+    /// against the modification time instead of the CRC-32.
+    pub(crate) fn drop_data_descriptor(&mut self) {
+        if self.flags & ENCRYPTED_FLAG == 0 {
+            self.flags &= !DATA_DESCRIPTOR_FLAG;
+        }
+    }
+
+    /// Sets the comment of the entry. A comment that is not ASCII is written as UTF-8 and sets
+    /// the UTF-8 flag, which also applies to the name; the zip writer writes names that are not
+    /// ASCII as UTF-8 as well.
+    pub(crate) fn set_comment(&mut self, comment: &str) {
+        if !comment.is_ascii() {
+            self.flags |= UTF8_FLAG;
+        }
+        self.comment = comment.as_bytes().to_vec();
+    }
+
+    /// Marks the entry as encrypted with AES in the AE-2 format, which stores no CRC-32, and sets
+    /// its uncompressed size.
+    fn mark_aes_encrypted(&mut self, uncompressed_size: u64) {
+        self.flags |= ENCRYPTED_FLAG;
+        self.compression_method = AES_COMPRESSION_METHOD;
+        self.version_needed = self.version_needed.max(AES_VERSION);
+        self.crc32 = 0;
+        self.uncompressed_size = uncompressed_size;
+    }
+
+    fn has_data_descriptor(&self) -> bool {
+        self.flags & DATA_DESCRIPTOR_FLAG != 0
+    }
+
+    fn has_zip64_sizes(&self) -> bool {
+        self.compressed_size >= u64::from(ZIP64_MARKER)
+            || self.uncompressed_size >= u64::from(ZIP64_MARKER)
+    }
+
+    fn version_needed(&self, zip64: bool) -> u16 {
+        if zip64 {
+            self.version_needed.max(ZIP64_VERSION)
+        } else {
+            self.version_needed
+        }
+    }
+}
+
+/// Reads all headers of the central directory that the given record describes.
+pub(crate) fn read_central_directory<R: Read + Seek>(
+    reader: &mut R,
+    eocd: &EndOfCentralDirectory,
+) -> io::Result<Vec<CentralDirectoryHeader>> {
+    if eocd.disk_number != 0 || eocd.central_directory_disk != 0 {
+        return Err(Error::new(
+            ErrorKind::Unsupported,
+            "Archives that span several disks are not supported.",
+        ));
+    }
+    let data_offset = eocd.data_offset().ok_or_else(invalid_central_directory)?;
+    reader.seek(SeekFrom::Start(data_offset + eocd.central_directory_offset))?;
+    let mut bytes: Vec<u8> = Vec::new();
+    reader
+        .take(eocd.central_directory_size)
+        .read_to_end(&mut bytes)?;
+
+    let mut headers: Vec<CentralDirectoryHeader> = Vec::new();
+    let mut position = 0;
+    while (headers.len() as u64) < eocd.total_entries {
+        let fixed = bytes
+            .get(position..position + CENTRAL_DIRECTORY_HEADER_LENGTH)
+            .filter(|fixed| fixed[..4] == CENTRAL_DIRECTORY_HEADER_SIGNATURE)
+            .ok_or_else(invalid_central_directory)?;
+        let name_length = usize::from(read_u16(fixed, 28));
+        let extra_length = usize::from(read_u16(fixed, 30));
+        let comment_length = usize::from(read_u16(fixed, 32));
+        let variable_start = position + CENTRAL_DIRECTORY_HEADER_LENGTH;
+        let variable = bytes
+            .get(variable_start..variable_start + name_length + extra_length + comment_length)
+            .ok_or_else(invalid_central_directory)?;
+        let (extra_fields, zip64) =
+            parse_extra_fields(&variable[name_length..name_length + extra_length])?;
+
+        let mut zip64 = zip64.as_slice();
+        let mut zip64_value = |value: u32| -> io::Result<u64> {
+            if value != ZIP64_MARKER {
+                return Ok(u64::from(value));
+            }
+            let bytes = zip64.get(..8).ok_or_else(invalid_central_directory)?;
+            let value = read_u64(bytes, 0);
+            zip64 = &zip64[8..];
+            Ok(value)
+        };
+        headers.push(CentralDirectoryHeader {
+            version_made_by: read_u16(fixed, 4),
+            version_needed: read_u16(fixed, 6),
+            flags: read_u16(fixed, 8),
+            compression_method: read_u16(fixed, 10),
+            modified_time: read_u16(fixed, 12),
+            modified_date: read_u16(fixed, 14),
+            crc32: read_u32(fixed, 16),
+            uncompressed_size: zip64_value(read_u32(fixed, 24))?,
+            compressed_size: zip64_value(read_u32(fixed, 20))?,
+            local_header_offset: zip64_value(read_u32(fixed, 42))?,
+            internal_attributes: read_u16(fixed, 36),
+            external_attributes: read_u32(fixed, 38),
+            name: variable[..name_length].to_vec(),
+            extra_fields,
+            comment: variable[name_length + extra_length..].to_vec(),
+        });
+        position = variable_start + variable.len();
+    }
+    Ok(headers)
+}
+
+/// Reads the central directory of an archive, lets `update` change its headers, and writes it
+/// again in place, followed by the records that end the archive and the archive comment. The
+/// archive is not truncated, so the headers must not get shorter.
+pub(crate) fn rewrite_central_directory<F, U>(file: &mut F, update: U) -> io::Result<()>
+where
+    F: Read + Write + Seek,
+    U: FnOnce(&mut [CentralDirectoryHeader]) -> io::Result<()>,
+{
+    let eocd = find_end_of_central_directory(file)?.ok_or_else(invalid_central_directory)?;
+    let mut archive_comment = vec![0; usize::from(eocd.comment_length)];
+    file.seek(SeekFrom::Start(eocd.position + 22))?;
+    file.read_exact(&mut archive_comment)?;
+    let data_offset = eocd.data_offset().ok_or_else(invalid_central_directory)?;
+    let mut headers = read_central_directory(file, &eocd)?;
+    update(&mut headers)?;
+
+    file.seek(SeekFrom::Start(data_offset + eocd.central_directory_offset))?;
+    let mut central_directory_size = 0;
+    for header in &headers {
+        central_directory_size += write_central_directory_header(file, header)?;
+    }
+    write_end_of_central_directory(
+        file,
+        headers.len() as u64,
+        (eocd.central_directory_offset, central_directory_size),
+        &archive_comment,
+    )?;
+    Ok(())
+}
+
+/// Marks the entries with the given indices as AES encrypted, with the given uncompressed sizes,
+/// in the central directory and in their local headers. The entries have been written as stored
+/// entries of the encrypted data with the AES extra field, so only fields of the same length
+/// change; entries whose sizes would need a Zip64 extra field are rejected before anything is
+/// written.
+pub(crate) fn mark_aes_entries<F: Read + Write + Seek>(
+    file: &mut F,
+    entries: &[(usize, u64)],
+) -> io::Result<()> {
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let eocd = find_end_of_central_directory(file)?.ok_or_else(invalid_central_directory)?;
+    let data_offset = eocd.data_offset().ok_or_else(invalid_central_directory)?;
+    let mut marked_headers: Vec<CentralDirectoryHeader> = Vec::with_capacity(entries.len());
+    rewrite_central_directory(file, |headers| {
+        for &(index, uncompressed_size) in entries {
+            let header = headers.get_mut(index).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "The archive has fewer entries than have been written.",
+                )
+            })?;
+            header.mark_aes_encrypted(uncompressed_size);
+            if header.has_zip64_sizes() {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "AES encrypted entries can not be written with Zip64 extra fields.",
+                ));
+            }
+            marked_headers.push(header.clone());
+        }
+        Ok(())
+    })?;
+    for header in &marked_headers {
+        let position = data_offset + header.local_header_offset;
+        let (data_position, extra_fields) = read_local_header(file, position)?;
+        file.seek(SeekFrom::Start(position))?;
+        if position + write_local_header(file, header, &extra_fields)? != data_position {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "The local header of an AES encrypted entry has changed its length.",
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Reads the local file header at the given absolute position, and returns the absolute position
+/// of the entry data along with the extra fields of the header other than the Zip64 extra field.
+pub(crate) fn read_local_header<R: Read + Seek>(
+    reader: &mut R,
+    position: u64,
+) -> io::Result<(u64, Vec<ExtraField>)> {
+    reader.seek(SeekFrom::Start(position))?;
+    let mut fixed = [0; LOCAL_FILE_HEADER_LENGTH];
+    reader.read_exact(&mut fixed)?;
+    if fixed[..4] != LOCAL_FILE_HEADER_SIGNATURE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("There is no local file header at position {position}."),
+        ));
+    }
+    let name_length = u64::from(read_u16(&fixed, 26));
+    let extra_length = read_u16(&fixed, 28);
+    reader.seek(SeekFrom::Current(i64::from(read_u16(&fixed, 26))))?;
+    let mut extra = vec![0; usize::from(extra_length)];
+    reader.read_exact(&mut extra)?;
+    let (extra_fields, _) = parse_extra_fields(&extra)?;
+    let data_position =
+        position + LOCAL_FILE_HEADER_LENGTH as u64 + name_length + u64::from(extra_length);
+    Ok((data_position, extra_fields))
+}
+
+/// Splits the extra field data of a header into its fields, and returns them along with the data
+/// of the Zip64 extra field, which is empty if there is none.
+fn parse_extra_fields(mut data: &[u8]) -> io::Result<(Vec<ExtraField>, Vec<u8>)> {
+    let mut extra_fields: Vec<ExtraField> = Vec::new();
+    let mut zip64: Vec<u8> = Vec::new();
+    while data.len() >= 4 {
+        let id = read_u16(data, 0);
+        let length = usize::from(read_u16(data, 2));
+        let field = data
+            .get(4..4 + length)
+            .ok_or_else(|| Error::new(ErrorKind::InvalidData, "An extra field is truncated."))?;
+        if id == ZIP64_EXTRA_FIELD {
+            zip64 = field.to_vec();
+        } else {
+            extra_fields.push(ExtraField {
+                id,
+                data: field.to_vec(),
+            });
+        }
+        data = &data[4 + length..];
+    }
+    Ok((extra_fields, zip64))
+}
+
+/// Writes a local file header for the entry with the given extra fields, and a Zip64 extra field
+/// if its sizes need one. Returns the number of bytes written.
+pub(crate) fn write_local_header<W: Write>(
+    writer: &mut W,
+    header: &CentralDirectoryHeader,
+    extra_fields: &[ExtraField],
+) -> io::Result<u64> {
+    let zip64 = header.has_zip64_sizes();
+    // With a data descriptor, the CRC-32 and sizes follow the data instead.
+    let (crc32, compressed_size, uncompressed_size) = if header.has_data_descriptor() {
+        (0, 0, 0)
+    } else {
+        (
+            header.crc32,
+            header.compressed_size,
+            header.uncompressed_size,
+        )
+    };
+    let mut extra: Vec<u8> = Vec::new();
+    if zip64 {
+        let mut zip64_field = uncompressed_size.to_le_bytes().to_vec();
+        zip64_field.extend_from_slice(&compressed_size.to_le_bytes());
+        write_extra_field(&mut extra, ZIP64_EXTRA_FIELD, &zip64_field)?;
+    }
+    for extra_field in extra_fields {
+        write_extra_field(&mut extra, extra_field.id, &extra_field.data)?;
+    }
+    let size_field = |size: u64| {
+        if zip64 {
+            ZIP64_MARKER
+        } else {
+            u32::try_from(size).unwrap_or(ZIP64_MARKER)
+        }
+    };
+
+    let mut bytes: Vec<u8> = LOCAL_FILE_HEADER_SIGNATURE.to_vec();
+    bytes.extend_from_slice(&header.version_needed(zip64).to_le_bytes());
+    bytes.extend_from_slice(&header.flags.to_le_bytes());
+    bytes.extend_from_slice(&header.compression_method.to_le_bytes());
+    bytes.extend_from_slice(&header.modified_time.to_le_bytes());
+    bytes.extend_from_slice(&header.modified_date.to_le_bytes());
+    bytes.extend_from_slice(&crc32.to_le_bytes());
+    bytes.extend_from_slice(&size_field(compressed_size).to_le_bytes());
+    bytes.extend_from_slice(&size_field(uncompressed_size).to_le_bytes());
+    bytes.extend_from_slice(&length_field(header.name.len())?.to_le_bytes());
+    bytes.extend_from_slice(&length_field(extra.len())?.to_le_bytes());
+    bytes.extend_from_slice(&header.name);
+    bytes.extend_from_slice(&extra);
+    writer.write_all(&bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+/// Writes the data descriptor that follows the data of the entry, if its flags call for one.
+/// Returns the number of bytes written.
+pub(crate) fn write_data_descriptor<W: Write>(
+    writer: &mut W,
+    header: &CentralDirectoryHeader,
+) -> io::Result<u64> {
+    if !header.has_data_descriptor() {
+        return Ok(0);
+    }
+    let mut bytes: Vec<u8> = DATA_DESCRIPTOR_SIGNATURE.to_vec();
+    bytes.extend_from_slice(&header.crc32.to_le_bytes());
+    if header.has_zip64_sizes() {
+        bytes.extend_from_slice(&header.compressed_size.to_le_bytes());
+        bytes.extend_from_slice(&header.uncompressed_size.to_le_bytes());
+    } else {
+        for size in [header.compressed_size, header.uncompressed_size] {
+            bytes.extend_from_slice(&u32::try_from(size).unwrap_or(ZIP64_MARKER).to_le_bytes());
+        }
+    }
+    writer.write_all(&bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+/// Writes the central directory header of the entry, with a Zip64 extra field for the values that
+/// need one. Returns the number of bytes written.
+pub(crate) fn write_central_directory_header<W: Write>(
+    writer: &mut W,
+    header: &CentralDirectoryHeader,
+) -> io::Result<u64> {
+    let mut zip64_field: Vec<u8> = Vec::new();
+    let mut value_field = |value: u64| match u32::try_from(value) {
+        Ok(value) if value != ZIP64_MARKER => value,
+        _ => {
+            zip64_field.extend_from_slice(&value.to_le_bytes());
+            ZIP64_MARKER
+        }
+    };
+    let uncompressed_size = value_field(header.uncompressed_size);
+    let compressed_size = value_field(header.compressed_size);
+    let local_header_offset = value_field(header.local_header_offset);
+    let mut extra: Vec<u8> = Vec::new();
+    if !zip64_field.is_empty() {
+        write_extra_field(&mut extra, ZIP64_EXTRA_FIELD, &zip64_field)?;
+    }
+    for extra_field in &header.extra_fields {
+        write_extra_field(&mut extra, extra_field.id, &extra_field.data)?;
+    }
+
+    let mut bytes: Vec<u8> = CENTRAL_DIRECTORY_HEADER_SIGNATURE.to_vec();
+    bytes.extend_from_slice(&header.version_made_by.to_le_bytes());
+    bytes.extend_from_slice(&header.version_needed(!zip64_field.is_empty()).to_le_bytes());
+    bytes.extend_from_slice(&header.flags.to_le_bytes());
+    bytes.extend_from_slice(&header.compression_method.to_le_bytes());
+    bytes.extend_from_slice(&header.modified_time.to_le_bytes());
+    bytes.extend_from_slice(&header.modified_date.to_le_bytes());
+    bytes.extend_from_slice(&header.crc32.to_le_bytes());
+    bytes.extend_from_slice(&compressed_size.to_le_bytes());
+    bytes.extend_from_slice(&uncompressed_size.to_le_bytes());
+    bytes.extend_from_slice(&length_field(header.name.len())?.to_le_bytes());
+    bytes.extend_from_slice(&length_field(extra.len())?.to_le_bytes());
+    bytes.extend_from_slice(&length_field(header.comment.len())?.to_le_bytes());
+    bytes.extend_from_slice(&0u16.to_le_bytes());
+    bytes.extend_from_slice(&header.internal_attributes.to_le_bytes());
+    bytes.extend_from_slice(&header.external_attributes.to_le_bytes());
+    bytes.extend_from_slice(&local_header_offset.to_le_bytes());
+    bytes.extend_from_slice(&header.name);
+    bytes.extend_from_slice(&extra);
+    bytes.extend_from_slice(&header.comment);
+    writer.write_all(&bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+/// Writes the records that end an archive with the given number of entries and central
+/// directory, including the Zip64 records if the values do not fit the end-of-central-directory
+/// record. Returns the number of bytes written.
+pub(crate) fn write_end_of_central_directory<W: Write>(
+    writer: &mut W,
+    total_entries: u64,
+    (central_directory_offset, central_directory_size): (u64, u64),
+    comment: &[u8],
+) -> io::Result<u64> {
+    let entries_field = u16::try_from(total_entries).unwrap_or(u16::MAX);
+    let size_field = u32::try_from(central_directory_size).unwrap_or(ZIP64_MARKER);
+    let offset_field = u32::try_from(central_directory_offset).unwrap_or(ZIP64_MARKER);
+    let mut bytes: Vec<u8> = Vec::new();
+    if entries_field == u16::MAX || size_field == ZIP64_MARKER || offset_field == ZIP64_MARKER {
+        let zip64_position = central_directory_offset + central_directory_size;
+        bytes.extend_from_slice(&ZIP64_EOCD_SIGNATURE);
+        bytes.extend_from_slice(&44u64.to_le_bytes());
+        bytes.extend_from_slice(&ZIP64_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&ZIP64_VERSION.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&total_entries.to_le_bytes());
+        bytes.extend_from_slice(&total_entries.to_le_bytes());
+        bytes.extend_from_slice(&central_directory_size.to_le_bytes());
+        bytes.extend_from_slice(&central_directory_offset.to_le_bytes());
+        bytes.extend_from_slice(&ZIP64_EOCD_LOCATOR_SIGNATURE);
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&zip64_position.to_le_bytes());
+        bytes.extend_from_slice(&1u32.to_le_bytes());
+    }
+    bytes.extend_from_slice(&EOCD_SIGNATURE);
+    bytes.extend_from_slice(&[0; 4]);
+    bytes.extend_from_slice(&entries_field.to_le_bytes());
+    bytes.extend_from_slice(&entries_field.to_le_bytes());
+    bytes.extend_from_slice(&size_field.to_le_bytes());
+    bytes.extend_from_slice(&offset_field.to_le_bytes());
+    bytes.extend_from_slice(&length_field(comment.len())?.to_le_bytes());
+    bytes.extend_from_slice(comment);
+    writer.write_all(&bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+fn write_extra_field(extra: &mut Vec<u8>, id: u16, data: &[u8]) -> io::Result<()> {
+    extra.extend_from_slice(&id.to_le_bytes());
+    extra.extend_from_slice(&length_field(data.len())?.to_le_bytes());
+    extra.extend_from_slice(data);
+    Ok(())
+}
+
+fn length_field(length: usize) -> io::Result<u16> {
+    u16::try_from(length).map_err(|_| {
+        Error::new(
+            ErrorKind::InvalidInput,
+            "A header field is longer than 65,535 bytes.",
+        )
+    })
+}
+
+fn invalid_central_directory() -> Error {
+    Error::new(
+        ErrorKind::InvalidData,
+        "The archive has no valid central directory.",
+    )
+}
@@ -0,0 +1,142 @@
+use std::io;
+use std::io::{Error, ErrorKind, Write};
+
+use aes::cipher::generic_array::GenericArray;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::Aes256;
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+/// The header ID of the extra field that describes an AES encrypted entry.
+const AES_EXTRA_FIELD: u16 = 0x9901;
+/// The vendor version of the AE-2 format, whose entries store no CRC-32.
+const AE_2: u16 = 2;
+/// The key strength that stands for 256-bit keys.
+const STRENGTH_256: u8 = 3;
+const SALT_LENGTH: usize = 16;
+const KEY_LENGTH: usize = 32;
+const VERIFIER_LENGTH: usize = 2;
+const MAC_LENGTH: usize = 10;
+const BLOCK_LENGTH: usize = 16;
+/// The number of PBKDF2 iterations that derive the keys from the password.
+const ITERATIONS: u32 = 1000;
+
+/// The number of bytes that encryption adds to the data of an entry: the salt and the password
+/// verifier in front of it, and the authentication code behind it.
+pub(crate) const AES_OVERHEAD: u64 = (SALT_LENGTH + VERIFIER_LENGTH + MAC_LENGTH) as u64;
+
+/// Returns the extra field, including its header, of an entry that is encrypted with AES-256 in
+/// the AE-2 format, given the number of the compression method its data has been compressed with.
+pub(crate) fn aes_extra_field(compression_method: u16) -> [u8; 11] {
+    let mut field = [0; 11];
+    field[..2].copy_from_slice(&AES_EXTRA_FIELD.to_le_bytes());
+    field[2..4].copy_from_slice(&7u16.to_le_bytes());
+    field[4..6].copy_from_slice(&AE_2.to_le_bytes());
+    field[6..8].copy_from_slice(b"AE");
+    field[8] = STRENGTH_256;
+    field[9..].copy_from_slice(&compression_method.to_le_bytes());
+    field
+}
+
+/// A writer that encrypts the data of an entry with AES-256, as specified for AE-2 entries: the
+/// keys are derived from the password and a random salt with PBKDF2-HMAC-SHA1, the data is
+/// encrypted in counter mode with a little-endian counter that starts at 1, and the encrypted data
+/// is authenticated with HMAC-SHA1.
+pub(crate) struct AesWriter<W: Write> {
+    inner: W,
+    cipher: Aes256,
+    mac: Hmac<Sha1>,
+    counter: u128,
+    keystream: [u8; BLOCK_LENGTH],
+    position: usize,
+}
+
+impl<W: Write> AesWriter<W> {
+    /// Derives the keys from the password and a random salt, and writes the salt and the password
+    /// verifier that precede the encrypted data.
+    pub(crate) fn new(inner: W, password: &[u8]) -> io::Result<AesWriter<W>> {
+        let mut salt = [0; SALT_LENGTH];
+        getrandom::getrandom(&mut salt).map_err(|error| Error::other(error.to_string()))?;
+        AesWriter::with_salt(inner, password, salt)
+    }
+
+    fn with_salt(
+        mut inner: W,
+        password: &[u8],
+        salt: [u8; SALT_LENGTH],
+    ) -> io::Result<AesWriter<W>> {
+        let mut keys = [0; 2 * KEY_LENGTH + VERIFIER_LENGTH];
+        pbkdf2::pbkdf2_hmac::<Sha1>(password, &salt, ITERATIONS, &mut keys);
+        let (encryption_key, keys) = keys.split_at(KEY_LENGTH);
+        let (authentication_key, verifier) = keys.split_at(KEY_LENGTH);
+        let mac = <Hmac<Sha1> as Mac>::new_from_slice(authentication_key)
+            .map_err(|_| Error::new(ErrorKind::InvalidInput, "The key length is invalid."))?;
+        inner.write_all(&salt)?;
+        inner.write_all(verifier)?;
+        Ok(AesWriter {
+            inner,
+            cipher: Aes256::new(GenericArray::from_slice(encryption_key)),
+            mac,
+            counter: 0,
+            keystream: [0; BLOCK_LENGTH],
+            position: BLOCK_LENGTH,
+        })
+    }
+
+    /// Writes the authentication code that follows the encrypted data, and returns the inner
+    /// writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        let code = self.mac.finalize().into_bytes();
+        self.inner.write_all(&code[..MAC_LENGTH])?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for AesWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut encrypted = buf.to_vec();
+        for byte in &mut encrypted {
+            if self.position == BLOCK_LENGTH {
+                self.counter += 1;
+                let mut block = GenericArray::from(self.counter.to_le_bytes());
+                self.cipher.encrypt_block(&mut block);
+                self.keystream.copy_from_slice(&block);
+                self.position = 0;
+            }
+            *byte ^= self.keystream[self.position];
+            self.position += 1;
+        }
+        self.inner.write_all(&encrypted)?;
+        self.mac.update(&encrypted);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::AesWriter;
+
+    #[test]
+    fn aes_writer_writes_salt_verifier_data_and_code() {
+        let salt = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+        let mut writer = AesWriter::with_salt(Vec::new(), b"hunter2", salt).unwrap();
+        // Written in two parts that do not end on a block boundary.
+        writer.write_all(b"The quick brown fox ").unwrap();
+        writer.write_all(b"jumps over the lazy dog").unwrap();
+        let output = writer.finish().unwrap();
+        assert_eq!(output[..16], salt);
+        let expected = [
+            0xcf, 0xcf, 0x5d, 0x7f, 0xe7, 0x6d, 0xf2, 0x40, 0xa2, 0x05, 0x69, 0xd3, 0xa8, 0x6b,
+            0x93, 0x11, 0x55, 0xc2, 0x4b, 0xce, 0x31, 0x58, 0x82, 0x67, 0x72, 0xbe, 0x1e, 0xf1,
+            0xd9, 0xf0, 0x7c, 0x05, 0x64, 0x73, 0xbb, 0x7a, 0x90, 0xc7, 0x37, 0x9d, 0xc1, 0x82,
+            0xb8, 0xd2, 0xa8, 0xd0, 0x5f, 0x9a, 0x09, 0xf1, 0x9e, 0x50, 0xd7, 0xac, 0x3d,
+        ];
+        assert_eq!(output[16..], expected);
+    }
+}
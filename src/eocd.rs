@@ -0,0 +1,140 @@
+use std::io;
+use std::io::{Read, Seek, SeekFrom};
+
+const EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+const EOCD_LENGTH: u64 = 22;
+pub(crate) const MAX_COMMENT_LENGTH: u64 = 0xffff;
+const ZIP64_EOCD_LOCATOR_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x06, 0x07];
+const ZIP64_EOCD_LOCATOR_LENGTH: usize = 20;
+const ZIP64_EOCD_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x06, 0x06];
+const ZIP64_EOCD_LENGTH: usize = 56;
+
+/// The fields of an end-of-central-directory record.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct EndOfCentralDirectory {
+    /// The absolute position of the record in the file.
+    pub(crate) position: u64,
+    /// The number of the disk that contains the record.
+    pub(crate) disk_number: u16,
+    /// The number of the disk on which the central directory starts.
+    pub(crate) central_directory_disk: u16,
+    /// The total number of entries in the central directory.
+    pub(crate) total_entries: u64,
+    /// The size of the central directory, in bytes.
+    pub(crate) central_directory_size: u64,
+    /// The offset of the central directory, relative to the start of the ZIP data.
+    pub(crate) central_directory_offset: u64,
+    /// The length of the archive comment that follows the record.
+    pub(crate) comment_length: u16,
+    /// The absolute position of the ZIP64 end-of-central-directory record, if there is one.
+    pub(crate) zip64_position: Option<u64>,
+}
+
+impl EndOfCentralDirectory {
+    /// Returns the absolute position at which the ZIP data starts, i.e. the length of any data
+    /// that has been prepended to the archive. Returns `None` if the record is inconsistent.
+    pub(crate) fn data_offset(&self) -> Option<u64> {
+        self.zip64_position
+            .unwrap_or(self.position)
+            .checked_sub(self.central_directory_size)?
+            .checked_sub(self.central_directory_offset)
+    }
+}
+
+/// Scans the end of the given reader backwards for the end-of-central-directory record.
+///
+/// Returns `None` if no consistent record is found.
+pub(crate) fn find_end_of_central_directory<R: Read + Seek>(
+    reader: &mut R,
+) -> io::Result<Option<EndOfCentralDirectory>> {
+    let file_length = reader.seek(SeekFrom::End(0))?;
+    if file_length < EOCD_LENGTH {
+        return Ok(None);
+    }
+    let tail_length = file_length.min(EOCD_LENGTH + MAX_COMMENT_LENGTH);
+    let tail_start = file_length - tail_length;
+    reader.seek(SeekFrom::Start(tail_start))?;
+    let mut tail: Vec<u8> = Vec::new();
+    reader.take(tail_length).read_to_end(&mut tail)?;
+
+    let last_candidate = tail.len() - 4;
+    for candidate in (0..=last_candidate).rev() {
+        if tail[candidate..candidate + 4] != EOCD_SIGNATURE {
+            continue;
+        }
+        let record = &tail[candidate..];
+        if (record.len() as u64) < EOCD_LENGTH {
+            continue;
+        }
+        let comment_length = read_u16(record, 20);
+        if candidate as u64 + EOCD_LENGTH + u64::from(comment_length) != tail_length {
+            continue;
+        }
+        let mut eocd = EndOfCentralDirectory {
+            position: tail_start + candidate as u64,
+            disk_number: read_u16(record, 4),
+            central_directory_disk: read_u16(record, 6),
+            total_entries: u64::from(read_u16(record, 10)),
+            central_directory_size: u64::from(read_u32(record, 12)),
+            central_directory_offset: u64::from(read_u32(record, 16)),
+            comment_length,
+            zip64_position: None,
+        };
+        read_zip64_end_of_central_directory(reader, &mut eocd)?;
+        return Ok(Some(eocd));
+    }
+    Ok(None)
+}
+
+/// Replaces the values of the given record with those of the ZIP64 record, if the archive has one.
+fn read_zip64_end_of_central_directory<R: Read + Seek>(
+    reader: &mut R,
+    eocd: &mut EndOfCentralDirectory,
+) -> io::Result<()> {
+    let locator_position = eocd.position.checked_sub(ZIP64_EOCD_LOCATOR_LENGTH as u64);
+    let Some(locator_position) = locator_position else {
+        return Ok(());
+    };
+    reader.seek(SeekFrom::Start(locator_position))?;
+    let mut locator = [0; ZIP64_EOCD_LOCATOR_LENGTH];
+    reader.read_exact(&mut locator)?;
+    if locator[..4] != ZIP64_EOCD_LOCATOR_SIGNATURE {
+        return Ok(());
+    }
+
+    // The record immediately precedes its locator. Its stored offset is not used, since it does
+    // not account for data that has been prepended to the archive.
+    let Some(record_position) = locator_position.checked_sub(ZIP64_EOCD_LENGTH as u64) else {
+        return Ok(());
+    };
+    reader.seek(SeekFrom::Start(record_position))?;
+    let mut record = [0; ZIP64_EOCD_LENGTH];
+    reader.read_exact(&mut record)?;
+    if record[..4] != ZIP64_EOCD_SIGNATURE {
+        return Ok(());
+    }
+
+    eocd.disk_number = u16::try_from(read_u32(&record, 16)).unwrap_or(u16::MAX);
+    eocd.central_directory_disk = u16::try_from(read_u32(&record, 20)).unwrap_or(u16::MAX);
+    eocd.total_entries = read_u64(&record, 32);
+    eocd.central_directory_size = read_u64(&record, 40);
+    eocd.central_directory_offset = read_u64(&record, 48);
+    eocd.zip64_position = Some(record_position);
+    Ok(())
+}
+
+pub(crate) fn read_u16(buffer: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([buffer[offset], buffer[offset + 1]])
+}
+
+pub(crate) fn read_u32(buffer: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&buffer[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+pub(crate) fn read_u64(buffer: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&buffer[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
@@ -1,7 +1,12 @@
-use std::fs::File;
+use std::fs::{File, Metadata};
 use std::io;
-use std::io::{Error, ErrorKind, Write};
+use std::io::{Error, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::read::LineEnding;
 
 /// Writes all bytes to a file.
 pub fn file_write_all_bytes(path: PathBuf, bytes: &[u8], overwrite: bool) -> io::Result<usize> {
@@ -16,6 +21,401 @@ pub fn file_write_all_bytes(path: PathBuf, bytes: &[u8], overwrite: bool) -> io:
     file.write(bytes)
 }
 
+/// The size of the chunks in which entry data is copied.
+pub(crate) const COPY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Copies all bytes from a reader to a writer, invoking the callback with the size of each chunk
+/// after it has been written. An error returned by the callback aborts the copy.
+pub(crate) fn copy_in_chunks<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    on_chunk: F,
+) -> io::Result<u64>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    F: FnMut(usize) -> io::Result<()>,
+{
+    copy_in_sized_chunks(reader, writer, COPY_CHUNK_SIZE, on_chunk)
+}
+
+/// Copies all bytes from a reader to a writer through a buffer of the given size, which bounds
+/// the memory used regardless of the amount of data, invoking the callback like
+/// `copy_in_chunks`.
+pub(crate) fn copy_in_sized_chunks<R, W, F>(
+    reader: &mut R,
+    writer: &mut W,
+    chunk_size: usize,
+    mut on_chunk: F,
+) -> io::Result<u64>
+where
+    R: Read + ?Sized,
+    W: Write + ?Sized,
+    F: FnMut(usize) -> io::Result<()>,
+{
+    let mut buffer = vec![0; chunk_size.max(1)];
+    let mut total: u64 = 0;
+    loop {
+        let bytes_read = match reader.read(&mut buffer) {
+            Ok(0) => return Ok(total),
+            Ok(bytes_read) => bytes_read,
+            Err(error) if error.kind() == ErrorKind::Interrupted => continue,
+            Err(error) => return Err(error),
+        };
+        writer.write_all(&buffer[..bytes_read])?;
+        total += bytes_read as u64;
+        on_chunk(bytes_read)?;
+    }
+}
+
+/// Computes the CRC-32 of the contents of a file, reading it in chunks.
+pub(crate) fn file_crc32(path: &Path) -> io::Result<u32> {
+    let mut file = File::open(path)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buffer = vec![0; COPY_CHUNK_SIZE];
+    loop {
+        match file.read(&mut buffer) {
+            Ok(0) => return Ok(hasher.finalize()),
+            Ok(bytes_read) => hasher.update(&buffer[..bytes_read]),
+            Err(error) if error.kind() == ErrorKind::Interrupted => {}
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+/// The delay before the first retry of a failed io operation, which grows with each retry.
+const RETRY_BACKOFF: Duration = Duration::from_millis(10);
+
+/// Determines whether an io error is transient, such that retrying the operation may succeed.
+fn is_transient(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::Interrupted | ErrorKind::WouldBlock | ErrorKind::TimedOut
+    )
+}
+
+/// Runs an io operation, retrying it up to the given number of times with a growing delay while
+/// it fails with a transient error. Other errors are returned immediately.
+pub(crate) fn retry_transient<T, F>(retries: u32, mut operation: F) -> io::Result<T>
+where
+    F: FnMut() -> io::Result<T>,
+{
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(error) if attempt < retries && is_transient(&error) => {
+                attempt += 1;
+                thread::sleep(RETRY_BACKOFF * attempt);
+            }
+            result => return result,
+        }
+    }
+}
+
+/// A writer that retries writes that fail with a transient error.
+pub(crate) struct RetryingWriter<W> {
+    inner: W,
+    retries: u32,
+}
+
+impl<W: Write> RetryingWriter<W> {
+    pub(crate) fn new(inner: W, retries: u32) -> RetryingWriter<W> {
+        RetryingWriter { inner, retries }
+    }
+}
+
+impl<W: Write> Write for RetryingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        retry_transient(self.retries, || self.inner.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        retry_transient(self.retries, || self.inner.flush())
+    }
+}
+
+/// A writer that converts the line endings of the written text, or passes the data through
+/// unchanged if no line ending is given. `finish` must be called after the last write.
+pub(crate) struct LineEndingWriter<W> {
+    inner: W,
+    line_ending: Option<LineEnding>,
+    // Whether the last written byte has been a carriage return.
+    after_carriage_return: bool,
+}
+
+impl<W: Write> LineEndingWriter<W> {
+    pub(crate) fn new(inner: W, line_ending: Option<LineEnding>) -> LineEndingWriter<W> {
+        LineEndingWriter {
+            inner,
+            line_ending,
+            after_carriage_return: false,
+        }
+    }
+
+    /// Writes a carriage return that is still held back, and returns the inner writer.
+    pub(crate) fn finish(mut self) -> io::Result<W> {
+        if self.line_ending == Some(LineEnding::Lf) && self.after_carriage_return {
+            self.inner.write_all(b"\r")?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for LineEndingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(line_ending) = self.line_ending else {
+            return self.inner.write(buf);
+        };
+        let mut converted = Vec::with_capacity(buf.len() + buf.len() / 8);
+        for &byte in buf {
+            match line_ending {
+                // A carriage return is held back until it is known whether a line feed follows.
+                LineEnding::Lf => {
+                    if self.after_carriage_return && byte != b'\n' {
+                        converted.push(b'\r');
+                    }
+                    if byte != b'\r' {
+                        converted.push(byte);
+                    }
+                }
+                LineEnding::CrLf => {
+                    if byte == b'\n' && !self.after_carriage_return {
+                        converted.push(b'\r');
+                    }
+                    converted.push(byte);
+                }
+            }
+            self.after_carriage_return = byte == b'\r';
+        }
+        self.inner.write_all(&converted)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// A reader that presents the data of the inner reader from the given offset onwards, as if it
+/// started there.
+pub(crate) struct OffsetReader<R> {
+    inner: R,
+    offset: u64,
+}
+
+impl<R: Read + Seek> OffsetReader<R> {
+    /// Positions the inner reader at the offset, failing with `ErrorKind::InvalidInput` if the
+    /// offset is beyond its end.
+    pub(crate) fn new(mut inner: R, offset: u64) -> io::Result<OffsetReader<R>> {
+        if offset > inner.seek(SeekFrom::End(0))? {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                format!("The offset {offset} is beyond the end of the file."),
+            ));
+        }
+        inner.seek(SeekFrom::Start(offset))?;
+        Ok(OffsetReader { inner, offset })
+    }
+}
+
+impl<R: Read> Read for OffsetReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<R: Seek> Seek for OffsetReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = match pos {
+            SeekFrom::Start(position) => {
+                self.inner.seek(SeekFrom::Start(self.offset + position))?
+            }
+            pos => self.inner.seek(pos)?,
+        };
+        if position < self.offset {
+            self.inner.seek(SeekFrom::Start(self.offset))?;
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Invalid seek to a position before the offset.",
+            ));
+        }
+        Ok(position - self.offset)
+    }
+}
+
+/// Returns the absolute, extended-length form of an existing path (`\\?\C:\...`), which is not
+/// subject to the 260 character limit of Windows paths.
+#[cfg(windows)]
+pub(crate) fn extended_length_path(path: &Path) -> io::Result<PathBuf> {
+    // On Windows, canonical paths are in the extended-length form.
+    path.canonicalize()
+}
+
+/// Returns the path unchanged, since paths are not limited to 260 characters on this platform.
+#[cfg(not(windows))]
+#[allow(clippy::unnecessary_wraps)]
+pub(crate) fn extended_length_path(path: &Path) -> io::Result<PathBuf> {
+    Ok(path.to_path_buf())
+}
+
+/// Numbers the temporary files of this process.
+static TEMPORARY_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Returns a path in the directory for a hidden temporary file that is named after the file it is
+/// written for, and that is unique within the process.
+pub(crate) fn temporary_file_path(directory: &Path, destination: &Path) -> PathBuf {
+    let file_name = destination
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    directory.join(format!(
+        ".{}.{}-{}.tmp",
+        file_name,
+        std::process::id(),
+        TEMPORARY_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Determines whether two existing paths are located on the same file system, which is required
+/// to rename a file from one to the other.
+#[cfg(unix)]
+pub(crate) fn same_file_system(first: &Path, second: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(std::fs::metadata(first)?.dev() == std::fs::metadata(second)?.dev())
+}
+
+/// Determines whether two existing paths are located on the same file system; this can not be
+/// determined on this platform, so the paths are assumed to be.
+#[cfg(not(unix))]
+pub(crate) fn same_file_system(first: &Path, second: &Path) -> io::Result<bool> {
+    std::fs::metadata(first)?;
+    std::fs::metadata(second)?;
+    Ok(true)
+}
+
+/// Applies the given Unix permission bits to a file or directory.
+#[cfg(unix)]
+pub(crate) fn set_unix_permissions(path: &Path, mode: u32) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+}
+
+/// Applies the given Unix permission bits to a file or directory; does nothing on this platform.
+#[cfg(not(unix))]
+pub(crate) fn set_unix_permissions(_path: &Path, _mode: u32) -> io::Result<()> {
+    Ok(())
+}
+
+/// Makes a file read-only, by clearing its write bits on Unix and setting its read-only attribute
+/// on Windows.
+pub(crate) fn set_read_only(path: &Path) -> io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(true);
+    std::fs::set_permissions(path, permissions)
+}
+
+/// Returns the Unix permission bits of a file or directory.
+#[cfg(unix)]
+pub(crate) fn unix_permissions(_path: &Path, metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+    metadata.permissions().mode() & 0o777
+}
+
+/// Returns default Unix permission bits for a file or directory: 0o755 for directories and files
+/// with an executable extension, 0o644 for other files, without write bits for read-only files.
+#[cfg(not(unix))]
+pub(crate) fn unix_permissions(path: &Path, metadata: &Metadata) -> u32 {
+    const EXECUTABLE_EXTENSIONS: [&str; 5] = ["bat", "cmd", "com", "exe", "sh"];
+    let executable = metadata.is_dir()
+        || path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .is_some_and(|extension| {
+                EXECUTABLE_EXTENSIONS
+                    .iter()
+                    .any(|executable| extension.eq_ignore_ascii_case(executable))
+            });
+    let mode = if executable { 0o755 } else { 0o644 };
+    if metadata.permissions().readonly() {
+        mode & !0o222
+    } else {
+        mode
+    }
+}
+
+/// Converts a path to a ZIP entry name that uses forward slashes as separators.
+pub(crate) fn path_to_entry_name<P: AsRef<Path>>(path: P) -> String {
+    path.as_ref()
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Resolves the `.` and `..` components of an entry name lexically, so that `a/./b/../c.txt`
+/// becomes `a/c.txt`. The name is cut off at the first NUL character, backslashes are treated as
+/// separators on every platform, and root and drive prefixes are dropped. Returns `None` if a `..`
+/// component leads above the start of the name, as joining such a path to a target directory would
+/// point outside of it.
+pub(crate) fn normalize_entry_path(name: &str) -> Option<PathBuf> {
+    let name = name
+        .split('\0')
+        .next()
+        .unwrap_or_default()
+        .replace('\\', "/");
+    let mut normalized = PathBuf::new();
+    for component in Path::new(&name).components() {
+        match component {
+            Component::Normal(part) => normalized.push(part),
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    return None;
+                }
+            }
+            Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+        }
+    }
+    Some(normalized)
+}
+
+/// Determines whether a path with forward slashes as separators matches a glob pattern. `*`
+/// matches any characters within a segment, `?` matches a single character other than `/`, and a
+/// `**` segment matches any number of segments, including none.
+pub(crate) fn glob_matches(pattern: &str, path: &str) -> bool {
+    let pattern_segments: Vec<&str> = pattern.split('/').collect();
+    let path_segments: Vec<&str> = path.split('/').collect();
+    segments_match(&pattern_segments, &path_segments)
+}
+
+fn segments_match(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => {
+            (0..=path.len()).any(|skipped| segments_match(rest, &path[skipped..]))
+        }
+        Some((first, rest)) => path.split_first().is_some_and(|(segment, path_rest)| {
+            segment_matches(first.as_bytes(), segment.as_bytes()) && segments_match(rest, path_rest)
+        }),
+    }
+}
+
+fn segment_matches(pattern: &[u8], segment: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => segment.is_empty(),
+        Some((b'*', rest)) => {
+            (0..=segment.len()).any(|skipped| segment_matches(rest, &segment[skipped..]))
+        }
+        Some((b'?', rest)) => !segment.is_empty() && segment_matches(rest, &segment[1..]),
+        Some((first, rest)) => {
+            segment.first() == Some(first) && segment_matches(rest, &segment[1..])
+        }
+    }
+}
+
 /// Returns a relative path from one path to another.
 pub(crate) fn make_relative_path<P1: AsRef<Path>, P2: AsRef<Path>>(
     root: P1,
@@ -37,3 +437,187 @@ pub(crate) fn make_relative_path<P1: AsRef<Path>, P2: AsRef<Path>>(
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Error, ErrorKind, Write};
+
+    use super::{retry_transient, RetryingWriter};
+
+    /// A writer whose first writes fail with the given error kinds.
+    struct FlakyWriter {
+        failures: Vec<ErrorKind>,
+        written: Vec<u8>,
+    }
+
+    impl Write for FlakyWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if self.failures.is_empty() {
+                self.written.extend_from_slice(buf);
+                Ok(buf.len())
+            } else {
+                Err(Error::from(self.failures.remove(0)))
+            }
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transient_errors_are_retried() {
+        let flaky = FlakyWriter {
+            failures: vec![ErrorKind::WouldBlock, ErrorKind::TimedOut],
+            written: Vec::new(),
+        };
+        let mut writer = RetryingWriter::new(flaky, 2);
+        writer.write_all(b"data").unwrap();
+        assert_eq!(writer.inner.written, b"data");
+
+        let mut attempts = 0;
+        let result = retry_transient(1, || -> io::Result<()> {
+            attempts += 1;
+            Err(Error::from(ErrorKind::TimedOut))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::TimedOut);
+        assert_eq!(attempts, 2);
+    }
+
+    #[test]
+    fn line_endings_are_converted_across_writes() {
+        use super::LineEndingWriter;
+        use crate::read::LineEnding;
+
+        let convert = |line_ending: Option<LineEnding>, chunks: &[&[u8]]| {
+            let mut writer = LineEndingWriter::new(Vec::new(), line_ending);
+            for chunk in chunks {
+                writer.write_all(chunk).unwrap();
+            }
+            writer.finish().unwrap()
+        };
+        let chunks: &[&[u8]] = &[b"a\r", b"\nb\n", b"c\rd\r"];
+        assert_eq!(convert(Some(LineEnding::Lf), chunks), b"a\nb\nc\rd\r");
+        assert_eq!(convert(Some(LineEnding::CrLf), chunks), b"a\r\nb\r\nc\rd\r");
+        assert_eq!(convert(None, chunks), b"a\r\nb\nc\rd\r");
+        assert_eq!(
+            convert(Some(LineEnding::CrLf), &[b"\n", b"\n"]),
+            b"\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn file_crc32_hashes_file_contents() {
+        use super::file_crc32;
+        use crate::test_utils::TestDir;
+
+        let test_dir = TestDir::new("file-crc32");
+        let path = test_dir.path().join("check.txt");
+        std::fs::write(&path, b"123456789").unwrap();
+        assert_eq!(file_crc32(&path).unwrap(), 0xcbf4_3926);
+    }
+
+    /// A reader that produces the given number of zero bytes without allocating them, and records
+    /// the largest buffer it has been asked to fill.
+    struct ZeroReader {
+        remaining: u64,
+        largest_buffer: usize,
+    }
+
+    impl io::Read for ZeroReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.largest_buffer = self.largest_buffer.max(buf.len());
+            let bytes_read = buf
+                .len()
+                .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+            buf[..bytes_read].fill(0);
+            self.remaining -= bytes_read as u64;
+            Ok(bytes_read)
+        }
+    }
+
+    #[test]
+    fn copy_in_sized_chunks_uses_a_bounded_buffer() {
+        use super::copy_in_sized_chunks;
+
+        let total: u64 = 4 * 1024 * 1024 * 1024;
+        let mut reader = ZeroReader {
+            remaining: total,
+            largest_buffer: 0,
+        };
+        let mut chunks: u64 = 0;
+        let copied = copy_in_sized_chunks(&mut reader, &mut io::sink(), 1024 * 1024, |chunk| {
+            assert!(chunk <= 1024 * 1024);
+            chunks += 1;
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(copied, total);
+        assert_eq!(chunks, 4 * 1024);
+        assert_eq!(reader.largest_buffer, 1024 * 1024);
+    }
+
+    #[test]
+    fn glob_patterns_match_segments() {
+        use super::glob_matches;
+
+        assert!(glob_matches("__MACOSX/**", "__MACOSX"));
+        assert!(glob_matches("__MACOSX/**", "__MACOSX/a/._b.txt"));
+        assert!(!glob_matches("__MACOSX/**", "src/__MACOSX"));
+        assert!(glob_matches("**/.DS_Store", ".DS_Store"));
+        assert!(glob_matches("**/.DS_Store", "a/b/.DS_Store"));
+        assert!(glob_matches("*.txt", "notes.txt"));
+        assert!(!glob_matches("*.txt", "docs/notes.txt"));
+        assert!(glob_matches("docs/?.md", "docs/a.md"));
+        assert!(!glob_matches("docs/?.md", "docs/ab.md"));
+    }
+
+    #[test]
+    fn other_errors_are_not_retried() {
+        let mut attempts = 0;
+        let result = retry_transient(5, || -> io::Result<()> {
+            attempts += 1;
+            Err(Error::from(ErrorKind::PermissionDenied))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::PermissionDenied);
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn entry_paths_are_normalized_lexically() {
+        use std::path::PathBuf;
+
+        use super::normalize_entry_path;
+
+        assert_eq!(
+            normalize_entry_path("a/../b.txt"),
+            Some(PathBuf::from("b.txt"))
+        );
+        assert_eq!(
+            normalize_entry_path("./a/./c/../d.txt"),
+            Some(PathBuf::from("a/d.txt"))
+        );
+        assert_eq!(
+            normalize_entry_path("/etc/passwd"),
+            Some(PathBuf::from("etc/passwd"))
+        );
+        assert_eq!(
+            normalize_entry_path("a.txt\0/../b"),
+            Some(PathBuf::from("a.txt"))
+        );
+        assert_eq!(
+            normalize_entry_path("dir\\sub\\..\\file.txt"),
+            Some(PathBuf::from("dir/file.txt"))
+        );
+        assert_eq!(normalize_entry_path("a/.."), Some(PathBuf::new()));
+        for escaping in [
+            "..",
+            "../a.txt",
+            "a/../../b.txt",
+            "a/b/../../../c",
+            "a\\..\\..\\x",
+        ] {
+            assert_eq!(normalize_entry_path(escaping), None, "{escaping}");
+        }
+    }
+}
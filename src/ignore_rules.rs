@@ -0,0 +1,107 @@
+use std::collections::HashMap;
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::overrides::{Override, OverrideBuilder};
+use ignore::Match;
+
+/// The names of the per-directory ignore files, in increasing order of precedence.
+const IGNORE_FILE_NAMES: [&str; 2] = [".gitignore", ".ignore"];
+
+/// The gitignore rules that apply while walking a directory tree.
+///
+/// Rules of deeper directories take precedence over those of their ancestors, which in turn take
+/// precedence over the repository's `.git/info/exclude` file and the global excludes file.
+pub(crate) struct IgnoreRules {
+    root: PathBuf,
+    directory_rules: HashMap<PathBuf, Gitignore>,
+    repository_rules: Gitignore,
+    global_rules: Gitignore,
+    force_include: Override,
+}
+
+impl IgnoreRules {
+    /// Loads the repository and global rules for a walk of the given root directory. Paths that
+    /// match one of the force-include patterns are never ignored.
+    pub(crate) fn new(root: &Path, force_include: &[String]) -> io::Result<IgnoreRules> {
+        let exclude_file = root.join(".git").join("info").join("exclude");
+        let repository_rules = if exclude_file.is_file() {
+            build_rules(root, &[exclude_file])?
+        } else {
+            Gitignore::empty()
+        };
+
+        let mut override_builder = OverrideBuilder::new(root);
+        for pattern in force_include {
+            override_builder.add(pattern).map_err(invalid_rules)?;
+        }
+
+        Ok(IgnoreRules {
+            root: root.to_path_buf(),
+            directory_rules: HashMap::new(),
+            repository_rules,
+            global_rules: Gitignore::global().0,
+            force_include: override_builder.build().map_err(invalid_rules)?,
+        })
+    }
+
+    /// Loads the ignore files of a directory, before its entries are checked.
+    pub(crate) fn enter_directory(&mut self, directory: &Path) -> io::Result<()> {
+        let ignore_files: Vec<PathBuf> = IGNORE_FILE_NAMES
+            .iter()
+            .map(|name| directory.join(name))
+            .filter(|path| path.is_file())
+            .collect();
+        if !ignore_files.is_empty() {
+            let rules = build_rules(directory, &ignore_files)?;
+            self.directory_rules.insert(directory.to_path_buf(), rules);
+        }
+        Ok(())
+    }
+
+    /// Determines whether a path beneath the root is ignored. The `.git` directory is always
+    /// ignored, since it is never part of the tracked tree.
+    pub(crate) fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.force_include.matched(path, is_dir).is_whitelist() {
+            return false;
+        }
+        if is_dir && path.file_name().is_some_and(|name| name == ".git") {
+            return true;
+        }
+
+        let ancestors = path
+            .ancestors()
+            .skip(1)
+            .take_while(|ancestor| ancestor.starts_with(&self.root));
+        for ancestor in ancestors {
+            if let Some(rules) = self.directory_rules.get(ancestor) {
+                match rules.matched(path, is_dir) {
+                    Match::Ignore(_) => return true,
+                    Match::Whitelist(_) => return false,
+                    Match::None => {}
+                }
+            }
+        }
+        match self.repository_rules.matched(path, is_dir) {
+            Match::Ignore(_) => true,
+            Match::Whitelist(_) => false,
+            Match::None => self.global_rules.matched(path, is_dir).is_ignore(),
+        }
+    }
+}
+
+fn build_rules(root: &Path, ignore_files: &[PathBuf]) -> io::Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new(root);
+    for ignore_file in ignore_files {
+        if let Some(error) = builder.add(ignore_file) {
+            return Err(invalid_rules(error));
+        }
+    }
+    builder.build().map_err(invalid_rules)
+}
+
+fn invalid_rules(error: ignore::Error) -> Error {
+    Error::new(ErrorKind::InvalidData, error)
+}
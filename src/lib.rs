@@ -3,17 +3,30 @@
 pub use crate::read::*;
 pub use crate::write::*;
 
+mod central_directory;
+#[cfg(feature = "aes")]
+mod encryption;
+mod eocd;
 mod file_utils;
+#[cfg(feature = "ignore")]
+mod ignore_rules;
+#[cfg(feature = "rayon")]
+mod parallel;
 pub mod read;
+mod spool;
+#[cfg(test)]
+mod test_utils;
+mod time_utils;
 pub mod write;
 
 #[cfg(test)]
 mod tests {
     use std::fs::{self, File};
+    use std::io::Cursor;
     use std::path::PathBuf;
     use std::str::FromStr;
 
-    use crate::is_zip;
+    use crate::{is_zip, is_zip_bytes, try_is_zip_bytes, try_is_zip_reader};
 
     #[test]
     fn is_zip_returns_false_if_file_does_not_exists() {
@@ -33,4 +46,60 @@ mod tests {
         fs::remove_file(archive_file.as_path()).unwrap();
         assert!(actual);
     }
+
+    #[test]
+    fn try_is_zip_reader_consumes_only_the_signature() {
+        let mut reader = Cursor::new(b"PK\x03\x04remaining data".to_vec());
+        assert!(try_is_zip_reader(&mut reader).unwrap());
+        assert_eq!(reader.position(), 4);
+    }
+
+    #[test]
+    fn try_is_zip_reader_returns_false_for_short_input() {
+        let mut reader = Cursor::new(b"PK\x03".to_vec());
+        assert!(!try_is_zip_reader(&mut reader).unwrap());
+    }
+
+    #[test]
+    fn try_is_zip_bytes_accepts_exactly_four_bytes() {
+        assert!(try_is_zip_bytes(b"PK\x03\x04").unwrap());
+        assert!(is_zip_bytes(b"PK\x03\x04"));
+    }
+
+    #[test]
+    fn try_is_zip_bytes_fails_for_short_buffers() {
+        assert!(try_is_zip_bytes(b"PK\x03").is_err());
+        assert!(try_is_zip_bytes(b"").is_err());
+        assert!(!is_zip_bytes(b"PK\x03"));
+        assert!(!is_zip_bytes(b""));
+    }
+
+    #[test]
+    fn try_is_zip_bytes_rejects_invalid_format_bytes() {
+        assert!(!try_is_zip_bytes(b"PK\x00\x00").unwrap());
+        assert!(!try_is_zip_bytes(b"PK\x99\x99 and more").unwrap());
+        assert!(!try_is_zip_bytes(b"NOPE").unwrap());
+    }
+
+    #[test]
+    fn try_is_zip_bytes_accepts_all_zip_signatures() {
+        for signature in [b"PK\x03\x04", b"PK\x05\x06", b"PK\x07\x08"] {
+            assert!(try_is_zip_bytes(signature).unwrap(), "{signature:?}");
+        }
+    }
+
+    #[test]
+    fn try_is_zip_bytes_rejects_near_miss_signatures() {
+        for signature in [
+            b"PK\x03\x99",
+            b"PK\x99\x04",
+            b"PK\x03\x06",
+            b"PK\x05\x04",
+            b"PK\x07\x06",
+            b"pk\x03\x04",
+            b"PL\x03\x04",
+        ] {
+            assert!(!try_is_zip_bytes(signature).unwrap(), "{signature:?}");
+        }
+    }
 }
@@ -0,0 +1,11 @@
+use rayon::prelude::*;
+use zip::result::ZipResult;
+
+/// Runs the operation for every item concurrently, and returns the first error if any fails.
+pub(crate) fn try_for_each_parallel<T, F>(items: Vec<T>, operation: F) -> ZipResult<()>
+where
+    T: Send,
+    F: Fn(T) -> ZipResult<()> + Sync + Send,
+{
+    items.into_par_iter().try_for_each(operation)
+}
@@ -1,13 +1,27 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt;
 use std::fs::File;
+use std::hash::BuildHasher;
 use std::io;
-use std::io::{Error, ErrorKind, Read};
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
 
 use zip::read::ZipFile;
 use zip::result::{ZipError, ZipResult};
-use zip::ZipArchive;
+use zip::{CompressionMethod, ZipArchive};
 
-use crate::file_utils::file_write_all_bytes;
+use crate::eocd::find_end_of_central_directory;
+#[cfg(any(feature = "regex", feature = "sha2"))]
+use crate::file_utils::path_to_entry_name;
+use crate::file_utils::{
+    copy_in_chunks, extended_length_path, file_crc32, file_write_all_bytes, normalize_entry_path,
+    retry_transient, same_file_system, set_read_only, set_unix_permissions, temporary_file_path,
+    LineEndingWriter, OffsetReader, RetryingWriter,
+};
+use crate::time_utils::zip_datetime_to_system_time;
 
 /// Extracts a ZIP file to the given directory.
 /// # Errors
@@ -15,12 +29,101 @@ use crate::file_utils::file_write_all_bytes;
 pub fn zip_extract<P1: AsRef<Path>, P2: AsRef<Path>>(
     archive_file: P1,
     target_dir: P2,
+) -> ZipResult<()> {
+    let mut archive = open_archive(archive_file)?;
+    archive.extract(target_dir)
+}
+
+/// Extracts a ZIP archive that is embedded in a larger file at the given offset, such as after
+/// the stub of a self-extracting executable, to the given directory.
+///
+/// `zip_extract` already handles data prepended to an archive by locating the central directory
+/// from the end of the file. This is for files where the start of the archive has to be given
+/// explicitly; the archive is read as if the file started at the offset.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directory, if there is no
+/// archive at the offset, or with `ErrorKind::InvalidInput` if the offset is beyond the end of
+/// the file.
+pub fn zip_extract_from_offset<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    offset: u64,
+    target_dir: P2,
 ) -> ZipResult<()> {
     let file = File::open(archive_file)?;
-    let mut archive = ZipArchive::new(file)?;
+    let reader = OffsetReader::new(BufReader::new(file), offset)?;
+    let mut archive = ZipArchive::new(reader).map_err(classify_open_error)?;
     archive.extract(target_dir)
 }
 
+/// Extracts a ZIP file to the given directory, using the specified options.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directory.
+pub fn zip_extract_with_options<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    target_dir: P2,
+    options: ExtractOptions,
+) -> ZipResult<ExtractReport> {
+    let mut archive = open_archive(archive_file)?;
+    archive.extract_with_options(target_dir, options)
+}
+
+/// Extracts a ZIP file to the given directory after the given closure has verified it, such as
+/// by checking a detached signature. If the closure returns an error, nothing is extracted.
+///
+/// The closure receives the path of the archive; the crate does not verify anything itself.
+/// The archive should not be writable by untrusted parties, since it is opened again after it
+/// has been verified.
+/// # Errors
+/// Will return `ZipError` with `ErrorKind::InvalidData` naming the reason if the verification
+/// fails, or `ZipError` for relevant file io error on archive or directory.
+pub fn zip_extract_verified<P1, P2, F>(archive_file: P1, target_dir: P2, verify: F) -> ZipResult<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    F: FnOnce(&Path) -> Result<(), String>,
+{
+    let archive_file = archive_file.as_ref();
+    if let Err(reason) = verify(archive_file) {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "The archive {} failed verification: {}",
+                archive_file.display(),
+                reason
+            ),
+        )));
+    }
+    zip_extract(archive_file, target_dir)
+}
+
+/// Extracts a ZIP file to the given directory, passing the decompressed contents of each file
+/// entry through the given transform before they are written.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directory, or if the transform
+/// fails.
+pub fn zip_extract_with_transform<P1, P2, F>(
+    archive_file: P1,
+    target_dir: P2,
+    transform: F,
+) -> ZipResult<()>
+where
+    P1: AsRef<Path>,
+    P2: AsRef<Path>,
+    F: Fn(&Path, Vec<u8>) -> io::Result<Vec<u8>>,
+{
+    let mut archive = open_archive(archive_file)?;
+    archive.extract_with_transform(target_dir, transform)
+}
+
+/// Extracts a ZIP file to the current working directory, using the default options.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directory.
+pub fn zip_extract_here<P: AsRef<Path>>(archive_file: P) -> ZipResult<()> {
+    let target_dir = std::env::current_dir()?;
+    zip_extract_with_options(archive_file, target_dir, ExtractOptions::default())?;
+    Ok(())
+}
+
 /// Extracts and entry in the ZIP archive to the given directory.
 /// # Errors
 /// Will return `ZipError` for relevant file io error on archive or directory.
@@ -30,8 +133,7 @@ pub fn zip_extract_file<P1: AsRef<Path>, P2: AsRef<Path>, P3: AsRef<Path>>(
     target_dir: P3,
     overwrite: bool,
 ) -> ZipResult<()> {
-    let file = File::open(archive_file)?;
-    let mut archive = ZipArchive::new(file)?;
+    let mut archive = open_archive(archive_file)?;
     let file_number: usize = match archive.file_number(entry_path.as_ref()) {
         Some(index) => index,
         None => return Err(ZipError::FileNotFound),
@@ -48,8 +150,7 @@ pub fn zip_extract_file_to_memory<P1: AsRef<Path>, P2: AsRef<Path>>(
     entry_path: P2,
     buffer: &mut Vec<u8>,
 ) -> ZipResult<()> {
-    let file = File::open(archive_file)?;
-    let mut archive = ZipArchive::new(file)?;
+    let mut archive = open_archive(archive_file)?;
     let file_number: usize = match archive.file_number(entry_path) {
         Some(index) => index,
         None => return Err(ZipError::FileNotFound),
@@ -57,29 +158,184 @@ pub fn zip_extract_file_to_memory<P1: AsRef<Path>, P2: AsRef<Path>>(
     archive.extract_file_to_memory(file_number, buffer)
 }
 
+/// Streams the decompressed contents of an entry in the ZIP archive to the given writer, such as
+/// `io::stdout()`, without buffering the whole entry. Returns the number of bytes written.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or writer.
+pub fn zip_extract_file_to_writer<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    entry_path: P2,
+    writer: &mut dyn io::Write,
+) -> ZipResult<u64> {
+    let mut archive = open_archive(archive_file)?;
+    let file_number: usize = match archive.file_number(entry_path) {
+        Some(index) => index,
+        None => return Err(ZipError::FileNotFound),
+    };
+    archive.extract_file_to_writer(file_number, writer)
+}
+
+/// Extracts the entry at the given index in the ZIP archive and returns its contents.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, or if the index is out of range
+/// or does not indicate a file entry.
+pub fn zip_extract_index_to_memory<P: AsRef<Path>>(
+    archive_file: P,
+    index: usize,
+) -> ZipResult<Vec<u8>> {
+    let mut archive = open_archive(archive_file)?;
+    if index >= archive.len() {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            "The specified index is out of range.",
+        )));
+    }
+    let mut buffer: Vec<u8> = Vec::new();
+    archive.extract_file_to_memory(index, &mut buffer)?;
+    Ok(buffer)
+}
+
+/// Finds the first file entry, in the order they are stored, whose extension matches the given
+/// one, and returns its sanitized path and contents. The extension is matched case-insensitively
+/// and may be given with or without a leading dot. Returns `None` if no file entry matches.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive.
+pub fn zip_read_first_with_extension<P: AsRef<Path>>(
+    archive_file: P,
+    extension: &str,
+) -> ZipResult<Option<(PathBuf, Vec<u8>)>> {
+    let mut archive = open_archive(archive_file)?;
+    let extension = extension.trim_start_matches('.').to_lowercase();
+    for file_number in 0..archive.len() {
+        let next: ZipFile<'_> = archive.by_index_raw(file_number)?;
+        if !next.is_file() {
+            continue;
+        }
+        let path = resolved_entry_path(&next)?;
+        drop(next);
+        let matches = path
+            .extension()
+            .is_some_and(|candidate| candidate.to_string_lossy().to_lowercase() == extension);
+        if matches {
+            let mut buffer: Vec<u8> = Vec::new();
+            archive.extract_file_to_memory(file_number, &mut buffer)?;
+            return Ok(Some((path, buffer)));
+        }
+    }
+    Ok(None)
+}
+
+/// Computes a SHA-256 hash over the names and decompressed contents of all file entries.
+///
+/// Entries are hashed in the order of their names, and directory entries, compression settings
+/// and other metadata are ignored. Archives that contain byte-identical file sets therefore hash
+/// identically, regardless of how they were built.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive.
+#[cfg(feature = "sha2")]
+pub fn zip_content_hash<P: AsRef<Path>>(archive_file: P) -> ZipResult<[u8; 32]> {
+    use sha2::{Digest, Sha256};
+
+    let mut archive = open_archive(archive_file)?;
+
+    let mut entries: Vec<(String, usize)> = Vec::new();
+    for file_number in 0..archive.len() {
+        let next: ZipFile<'_> = archive.by_index(file_number)?;
+        if next.is_file() {
+            entries.push((path_to_entry_name(resolved_entry_path(&next)?), file_number));
+        }
+    }
+    entries.sort();
+
+    let mut archive_hasher = Sha256::new();
+    for (entry_name, file_number) in entries {
+        let mut next: ZipFile<'_> = archive.by_index(file_number)?;
+        let mut entry_hasher = Sha256::new();
+        entry_hasher.update((entry_name.len() as u64).to_le_bytes());
+        entry_hasher.update(entry_name.as_bytes());
+        io::copy(&mut next, &mut entry_hasher)?;
+        archive_hasher.update(entry_hasher.finalize());
+    }
+    Ok(archive_hasher.finalize().into())
+}
+
+/// Lists the entries of an archive like `ZipArchiveExtensions::list_entries`, as a JSON array of
+/// objects with the fields of `EntryInfo`, so that archives can be inspected with tools such as
+/// `jq`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, or if an entry path is not
+/// valid UTF-8.
+#[cfg(feature = "serde")]
+pub fn zip_metadata_json<P: AsRef<Path>>(archive_file: P) -> ZipResult<String> {
+    let entries = open_archive(archive_file)?.list_entries()?;
+    serde_json::to_string(&entries).map_err(|error| ZipError::Io(error.into()))
+}
+
 /// Determines whether the specified file is a ZIP file, or not.
 /// # Errors
 /// Will return `ZipError` for relevant file io error on archive.
 pub fn try_is_zip<P: AsRef<Path>>(file: P) -> ZipResult<bool> {
-    const ZIP_SIGNATURE: [u8; 2] = [0x50, 0x4b];
-    const ZIP_ARCHIVE_FORMAT: [u8; 6] = [0x03, 0x04, 0x05, 0x06, 0x07, 0x08];
     let mut file = File::open(file)?;
-    let mut buffer: [u8; 4] = [0; 4];
-    let bytes_read = file.read(&mut buffer)?;
-    if bytes_read == buffer.len() {
-        for i in 0..ZIP_SIGNATURE.len() {
-            if buffer[i] != ZIP_SIGNATURE[i] {
-                return Ok(false);
-            }
-        }
+    try_is_zip_reader(&mut file)
+}
 
-        for i in (0..ZIP_ARCHIVE_FORMAT.len()).step_by(2) {
-            if buffer[2] == ZIP_ARCHIVE_FORMAT[i] || buffer[3] == ZIP_ARCHIVE_FORMAT[i + 1] {
-                return Ok(true);
-            }
-        }
+/// Determines whether the data in the specified reader starts with a ZIP signature, or not.
+///
+/// At most the first four bytes are consumed from the reader; shorter inputs are not ZIP data.
+/// # Errors
+/// Will return `ZipError` for relevant io error on the reader.
+pub fn try_is_zip_reader<R: Read>(reader: &mut R) -> ZipResult<bool> {
+    let mut buffer: Vec<u8> = Vec::with_capacity(ZIP_SIGNATURE_LENGTH);
+    reader
+        .by_ref()
+        .take(ZIP_SIGNATURE_LENGTH as u64)
+        .read_to_end(&mut buffer)?;
+    Ok(buffer.len() == ZIP_SIGNATURE_LENGTH && is_zip_signature(&buffer))
+}
+
+/// Determines whether the specified buffer starts with a ZIP signature, or not.
+/// # Errors
+/// Will return `ZipError` if the buffer is too short to tell.
+pub fn try_is_zip_bytes(bytes: &[u8]) -> ZipResult<bool> {
+    if bytes.len() < ZIP_SIGNATURE_LENGTH {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::UnexpectedEof,
+            "The buffer is too short to contain a ZIP signature.",
+        )));
     }
-    Ok(false)
+    Ok(is_zip_signature(&bytes[..ZIP_SIGNATURE_LENGTH]))
+}
+
+/// Determines whether the specified buffer starts with a ZIP signature, or not.
+#[must_use]
+pub fn is_zip_bytes(bytes: &[u8]) -> bool {
+    try_is_zip_bytes(bytes).unwrap_or_default()
+}
+
+const ZIP_SIGNATURE_LENGTH: usize = 4;
+const ZIP_LOCAL_FILE_HEADER_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x03, 0x04];
+/// An archive without entries consists of the end-of-central-directory record only.
+const ZIP_EMPTY_ARCHIVE_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x05, 0x06];
+/// Spanned archives start with the data descriptor signature.
+const ZIP_SPANNED_ARCHIVE_SIGNATURE: [u8; 4] = [0x50, 0x4b, 0x07, 0x08];
+const ZIP_SIGNATURES: [[u8; 4]; 3] = [
+    ZIP_LOCAL_FILE_HEADER_SIGNATURE,
+    ZIP_EMPTY_ARCHIVE_SIGNATURE,
+    ZIP_SPANNED_ARCHIVE_SIGNATURE,
+];
+
+/// Determines whether the given four bytes form a ZIP signature.
+fn is_zip_signature(buffer: &[u8]) -> bool {
+    ZIP_SIGNATURES.iter().any(|signature| buffer == signature)
+}
+
+/// Determines whether the specified ZIP file contains no entries.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, or if the file is not a ZIP
+/// archive.
+pub fn is_empty_zip<P: AsRef<Path>>(archive_file: P) -> ZipResult<bool> {
+    let archive = open_archive(archive_file)?;
+    Ok(archive.is_empty())
 }
 
 /// Determines whether the specified file is a ZIP file, or not.
@@ -87,110 +343,4191 @@ pub fn is_zip<P: AsRef<Path>>(file: P) -> bool {
     try_is_zip(file).unwrap_or_default()
 }
 
-pub trait ZipArchiveExtensions {
-    /// Extracts the current archive to the given directory path.
-    /// # Errors
-    /// Will return `ZipError` for relevant file io error on archive or directory.
-    fn extract<P: AsRef<Path>>(&mut self, path: P) -> ZipResult<()>;
+/// Determines the offset at which the ZIP data in the specified file starts.
+///
+/// The offset is non-zero for self-extracting archives and other files that have data prepended
+/// to the ZIP data; it is computed from the end-of-central-directory record, which is located by
+/// scanning backwards from the end of the file. The extraction helpers of this crate handle such
+/// files transparently. Returns `None` if the file does not contain an end-of-central-directory
+/// record.
+/// # Errors
+/// Will return `ZipError` for relevant file io error.
+pub fn zip_data_offset<P: AsRef<Path>>(file: P) -> ZipResult<Option<u64>> {
+    let mut file = File::open(file)?;
+    let eocd = find_end_of_central_directory(&mut file)?;
+    Ok(eocd.and_then(|eocd| eocd.data_offset()))
+}
 
-    /// Extracts an entry in the zip archive to a file.
-    /// # Errors
-    /// Will return `ZipError` for relevant file io error on archive or directory.
-    fn extract_file<P: AsRef<Path>>(
-        &mut self,
-        file_number: usize,
-        destination_file_path: P,
-        overwrite: bool,
-    ) -> ZipResult<()>;
+/// Determines whether the specified file is a volume of a split (multi-volume) archive.
+///
+/// Split archives are recognized by the spanning marker at the start of the first volume, or by
+/// disk numbers in the end-of-central-directory record of the last volume. Such archives can not
+/// be extracted by this crate.
+/// # Errors
+/// Will return `ZipError` for relevant file io error.
+pub fn is_split_archive<P: AsRef<Path>>(file: P) -> ZipResult<bool> {
+    let file = file.as_ref();
+    let open_error = inspect_archive_file(&mut File::open(file)?, file)?;
+    Ok(matches!(open_error, Some(OpenError::Spanned { .. })))
+}
 
-    /// Extracts an entry in the ZIP archive to the given memory buffer.
-    /// # Errors
-    /// Will return `ZipError` for relevant file io error on archive.
-    fn extract_file_to_memory(&mut self, file_number: usize, buffer: &mut Vec<u8>)
-        -> ZipResult<()>;
+/// Opens the specified ZIP file and checks that all of its entries can be read.
+///
+/// Failures are classified as an `OpenError`, wrapped in the returned io error, so that callers
+/// can tell a file that is not an archive at all from a truncated, corrupt, encrypted or spanned
+/// one; see `OpenError::from_zip_error`. The other helpers of this crate classify failures to
+/// open an archive the same way, but do not check the individual entries up front.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, or if the archive can not be
+/// read.
+pub fn zip_open<P: AsRef<Path>>(archive_file: P) -> ZipResult<ZipArchive<BufReader<File>>> {
+    let mut archive = open_archive(archive_file)?;
+    for file_number in 0..archive.len() {
+        let compression_method = archive
+            .by_index_raw(file_number)
+            .map_err(classify_open_error)?
+            .compression();
+        let open_error = match archive.by_index(file_number) {
+            Ok(_) => continue,
+            Err(ZipError::UnsupportedArchive(message)) if is_encryption_error(message) => {
+                OpenError::Encrypted
+            }
+            Err(ZipError::UnsupportedArchive(_)) => OpenError::Unsupported(compression_method),
+            Err(error) => return Err(classify_open_error(error)),
+        };
+        return Err(ZipError::Io(open_error.into()));
+    }
+    Ok(archive)
+}
 
-    /// Gets an entry´s path.
-    /// # Errors
-    /// Will return `ZipError` for relevant file io error on archive.
-    fn entry_path(&mut self, file_number: usize) -> ZipResult<PathBuf>;
+/// Determines whether the specified file is a usable ZIP file, by attempting to parse its central
+/// directory. Unlike `is_zip`, this does not accept files that merely start with a ZIP signature.
+/// # Errors
+/// Will return `ZipError` for relevant file io error.
+pub fn is_valid_zip<P: AsRef<Path>>(archive_file: P) -> ZipResult<bool> {
+    let file = File::open(archive_file)?;
+    match ZipArchive::new(BufReader::new(file)) {
+        Ok(_) => Ok(true),
+        Err(ZipError::InvalidArchive(_) | ZipError::UnsupportedArchive(_)) => Ok(false),
+        Err(ZipError::Io(error)) if error.kind() == ErrorKind::UnexpectedEof => Ok(false),
+        Err(error) => Err(error),
+    }
+}
 
-    /// Finds the index of the specified entry.
-    fn file_number<P: AsRef<Path>>(&mut self, entry_path: P) -> Option<usize>;
+/// Determines whether the specified file is a ZIP file, or contains ZIP data after a prefix such
+/// as the stub of a self-extracting archive.
+pub fn is_zip_lenient<P: AsRef<Path>>(file: P) -> bool {
+    is_zip(&file) || matches!(zip_data_offset(&file), Ok(Some(_)))
 }
 
-#[allow(deprecated)]
-impl<R: Read + io::Seek> ZipArchiveExtensions for ZipArchive<R> {
-    fn extract<P: AsRef<Path>>(&mut self, target_directory: P) -> ZipResult<()> {
-        if !target_directory.as_ref().is_dir() {
-            return Err(ZipError::Io(Error::new(
-                ErrorKind::InvalidInput,
-                "The specified path does not indicate a valid directory path.",
-            )));
+/// Options that control how an archive is extracted.
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ExtractOptions {
+    preserve_permissions: bool,
+    permission_mask: Option<u32>,
+    directory_mode: Option<u32>,
+    name_validator: Option<NameValidator>,
+    max_entries: Option<usize>,
+    reject_collisions: bool,
+    conflict_policy: ConflictPolicy,
+    directory_conflicts: DirectoryConflictPolicy,
+    io_retries: u32,
+    extension_line_endings: HashMap<String, LineEnding>,
+    make_read_only: bool,
+    atomic_writes: bool,
+    temp_dir: Option<PathBuf>,
+    long_paths: bool,
+    lowercase_paths: bool,
+    password: Option<Vec<u8>>,
+    skip_unsafe_entries: bool,
+    on_progress: Option<ProgressCallback>,
+    on_skip: Option<SkipCallback>,
+}
+
+/// Describes what happens when a file entry is extracted to a path that already exists.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    /// The existing file is replaced.
+    #[default]
+    Overwrite,
+    /// The entry is not extracted, and the existing file is kept.
+    Skip,
+    /// Extraction fails with `ErrorKind::AlreadyExists`.
+    Fail,
+    /// The entry is extracted to the first free path that is formed by appending ` (1)`, ` (2)`,
+    /// and so on to the file stem, preserving the extension (`report (1).pdf`).
+    Rename,
+    /// The existing file is kept if its size and CRC-32 match those stored for the entry, and
+    /// replaced otherwise. Existing files of the same size are read in full to compute their
+    /// CRC-32.
+    SkipUnchanged,
+}
+
+impl ConflictPolicy {
+    /// Returns the reason that is reported for the entries this policy skips.
+    fn skip_reason(self) -> SkipReason {
+        match self {
+            ConflictPolicy::SkipUnchanged => SkipReason::Unchanged,
+            _ => SkipReason::AlreadyExists,
         }
+    }
+}
 
-        for file_number in 0..self.len() {
-            let mut next: ZipFile<'_> = self.by_index(file_number)?;
-            let sanitized_name = next.sanitized_name();
-            if next.is_dir() {
-                let extracted_folder_path = target_directory.as_ref().join(sanitized_name);
-                std::fs::create_dir_all(extracted_folder_path)?;
-            } else if next.is_file() {
-                let mut buffer: Vec<u8> = Vec::new();
-                let _bytes_read = next.read_to_end(&mut buffer)?;
-                let extracted_file_path = target_directory.as_ref().join(sanitized_name);
-                file_write_all_bytes(extracted_file_path, buffer.as_ref(), true)?;
+/// Describes what happens when an archive contains a file entry at a path where other entries
+/// need a directory, such as both `foo` and `foo/`, or `foo` and `foo/bar.txt`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DirectoryConflictPolicy {
+    /// The directory wins: the file entry is not extracted, and is listed in the report.
+    #[default]
+    SkipFile,
+    /// Extraction fails with `ErrorKind::InvalidData` before anything is written.
+    Fail,
+}
+
+/// The line ending that text entries are converted to on extraction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// Line feeds (`\n`), as used on Unix. Carriage returns that are followed by a line feed are
+    /// removed.
+    Lf,
+    /// Carriage returns followed by line feeds (`\r\n`), as used on Windows. Line feeds that are
+    /// not preceded by a carriage return get one.
+    CrLf,
+}
+
+type NameValidator = Box<dyn Fn(&Path) -> Result<(), String>>;
+
+type ProgressCallback = Box<dyn FnMut(&ExtractProgress)>;
+
+type SkipCallback = Box<dyn FnMut(&Path, SkipReason)>;
+
+/// The reason why an entry has not been extracted, as passed to the callback set with
+/// `ExtractOptions::on_skip`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// A file exists at the destination, and the conflict policy is `ConflictPolicy::Skip`.
+    AlreadyExists,
+    /// The file at the destination has the size and CRC-32 of the entry, and the conflict policy
+    /// is `ConflictPolicy::SkipUnchanged`.
+    Unchanged,
+    /// Other entries need a directory at the path of the file entry, and the directory conflict
+    /// policy is `DirectoryConflictPolicy::SkipFile`.
+    DirectoryConflict,
+    /// The name of the file entry is empty once sanitized, such as `/` or `a/..`, so it has no
+    /// destination.
+    EmptyName,
+    /// The entry uses a compression method or encryption that is not supported, or is encrypted
+    /// and no password is set, and `ExtractOptions::skip_unsafe_entries` is set.
+    Unsupported,
+    /// The path of the entry leads outside of the target directory, and
+    /// `ExtractOptions::skip_unsafe_entries` is set.
+    PathEscape,
+    /// Other entries would be extracted to the same path, and `ExtractOptions::skip_unsafe_entries`
+    /// is set along with `ExtractOptions::reject_collisions` or `ExtractOptions::lowercase_paths`.
+    Collision,
+}
+
+impl ExtractOptions {
+    /// Sets whether the Unix permissions stored in the archive are applied to extracted entries.
+    ///
+    /// Permissions are only applied on Unix platforms, and only for entries that store them.
+    #[must_use]
+    pub fn preserve_permissions(mut self, preserve: bool) -> ExtractOptions {
+        self.preserve_permissions = preserve;
+        self
+    }
+
+    /// Sets a mask that is combined with the stored Unix permissions (bitwise AND) before they
+    /// are applied, e.g. `0o755` to strip group and world write access.
+    ///
+    /// The mask only takes effect if permissions are preserved.
+    #[must_use]
+    pub fn permission_mask(mut self, mask: Option<u32>) -> ExtractOptions {
+        self.permission_mask = mask;
+        self
+    }
+
+    /// Sets the Unix permissions, such as `0o755`, of the directories that are created during
+    /// extraction, regardless of the umask of the process. Directories that already exist are
+    /// left unchanged, and the preserved permissions of directory entries take precedence.
+    ///
+    /// The permissions are applied once all entries have been written, and only on Unix
+    /// platforms.
+    #[must_use]
+    pub fn directory_mode(mut self, mode: Option<u32>) -> ExtractOptions {
+        self.directory_mode = mode;
+        self
+    }
+
+    /// Sets a validator that is consulted for every entry before it is extracted.
+    ///
+    /// The validator receives the entry's path relative to the target directory. If it returns an
+    /// error, extraction stops with an error that names the rejected entry and the reason; see
+    /// `portable_name_validator` for a validator covering common cross-platform pitfalls.
+    #[must_use]
+    pub fn name_validator<F>(mut self, validator: F) -> ExtractOptions
+    where
+        F: Fn(&Path) -> Result<(), String> + 'static,
+    {
+        self.name_validator = Some(Box::new(validator));
+        self
+    }
+
+    /// Sets the maximum number of entries an archive may declare; extraction of archives that
+    /// declare more entries fails before anything is written.
+    #[must_use]
+    pub fn max_entries(mut self, max_entries: Option<usize>) -> ExtractOptions {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Sets what happens when a file entry is extracted to a path that already exists.
+    #[must_use]
+    pub fn conflict_policy(mut self, policy: ConflictPolicy) -> ExtractOptions {
+        self.conflict_policy = policy;
+        self
+    }
+
+    /// Sets what happens when a file entry is at a path where other entries need a directory.
+    /// Conflicts are detected before anything is written, regardless of the order of the
+    /// entries.
+    #[must_use]
+    pub fn directory_conflicts(mut self, policy: DirectoryConflictPolicy) -> ExtractOptions {
+        self.directory_conflicts = policy;
+        self
+    }
+
+    /// Sets how many times creating a directory or file, or writing file data, is retried with a
+    /// short, growing delay if it fails with a transient error (`Interrupted`, `WouldBlock` or
+    /// `TimedOut`), such as on network file systems. Other errors fail immediately.
+    #[must_use]
+    pub fn io_retries(mut self, retries: u32) -> ExtractOptions {
+        self.io_retries = retries;
+        self
+    }
+
+    /// Sets the line ending that files with the given extension, matched case-insensitively, are
+    /// converted to, such as `Lf` for `txt`. Files with other extensions are extracted unchanged.
+    ///
+    /// Conversion is opt-in per extension, since converting binary data corrupts it; only add
+    /// extensions that are known to be text. Lone carriage returns are kept.
+    #[must_use]
+    pub fn line_ending_for_extension(
+        mut self,
+        extension: &str,
+        line_ending: LineEnding,
+    ) -> ExtractOptions {
+        let extension = extension.trim_start_matches('.').to_lowercase();
+        self.extension_line_endings.insert(extension, line_ending);
+        self
+    }
+
+    /// Sets whether all extracted files are made read-only once extraction has finished, by
+    /// clearing the write bits on Unix and setting the read-only attribute on Windows.
+    ///
+    /// This runs as a post-pass after the stored permissions have been applied, so it also
+    /// removes write access granted by preserved permissions. Directories and files that have
+    /// not been written, such as skipped existing files, are left unchanged.
+    #[must_use]
+    pub fn make_read_only(mut self, read_only: bool) -> ExtractOptions {
+        self.make_read_only = read_only;
+        self
+    }
+
+    /// Sets whether each file is written to a temporary file first, and renamed to its destination
+    /// once it is complete, so that a file is never seen partially written. The temporary files
+    /// are placed next to their destination, unless `temp_dir` is set.
+    #[must_use]
+    pub fn atomic_writes(mut self, atomic: bool) -> ExtractOptions {
+        self.atomic_writes = atomic;
+        self
+    }
+
+    /// Sets the directory that the temporary files of `atomic_writes` are placed in. It must be
+    /// on the same file system as the target directory, so that the files can be renamed; on
+    /// Unix, extraction fails with `ErrorKind::InvalidInput` before anything is written if it is
+    /// not.
+    #[must_use]
+    pub fn temp_dir(mut self, temp_dir: Option<PathBuf>) -> ExtractOptions {
+        self.temp_dir = temp_dir;
+        self
+    }
+
+    /// Sets whether entries are extracted through the extended-length form of the target
+    /// directory (`\\?\C:\...`) on Windows, so that deep trees whose paths exceed 260
+    /// characters can be extracted. Paths are not limited like this on other platforms, where
+    /// this has no effect.
+    #[must_use]
+    pub fn long_paths(mut self, long_paths: bool) -> ExtractOptions {
+        self.long_paths = long_paths;
+        self
+    }
+
+    /// Sets whether entry paths are lowercased before they are extracted, so that archives that
+    /// have been built on a case-sensitive file system extract consistently onto a
+    /// case-insensitive one. Since entries whose paths only differ in case would then be
+    /// extracted to the same path, extraction fails before anything is written if there are any.
+    /// The error wraps `DestinationCollisions`, with the lowercased paths.
+    #[must_use]
+    pub fn lowercase_paths(mut self, lowercase: bool) -> ExtractOptions {
+        self.lowercase_paths = lowercase;
+        self
+    }
+
+    /// Sets the password that encrypted entries are decrypted with. Entries that are not
+    /// encrypted are extracted as usual, and extraction fails with `ErrorKind::InvalidInput` if
+    /// the password does not match an encrypted entry. AES encrypted entries can only be
+    /// decrypted with the `aes` feature.
+    ///
+    /// Without a password, extraction of an encrypted entry fails with
+    /// `ZipError::UnsupportedArchive`.
+    #[must_use]
+    pub fn password(mut self, password: &[u8]) -> ExtractOptions {
+        self.password = Some(password.to_vec());
+        self
+    }
+
+    /// Sets whether extraction fails before anything is written if several entries would be
+    /// extracted to the same path. The error wraps `DestinationCollisions`.
+    #[must_use]
+    pub fn reject_collisions(mut self, reject: bool) -> ExtractOptions {
+        self.reject_collisions = reject;
+        self
+    }
+
+    /// Sets whether entries that can not be extracted safely are skipped and reported to the
+    /// callback set with `ExtractOptions::on_skip`, instead of failing the extraction: entries with
+    /// an unsupported compression method or encryption, entries whose path leads outside of the
+    /// target directory, and entries that would be extracted to the same path as others if
+    /// collisions are rejected or paths are lowercased. All entries of such a group are skipped.
+    #[must_use]
+    pub fn skip_unsafe_entries(mut self, skip: bool) -> ExtractOptions {
+        self.skip_unsafe_entries = skip;
+        self
+    }
+
+    /// Sets a callback that is invoked with the current progress after each chunk of file data
+    /// has been written and after each entry has been processed.
+    #[must_use]
+    pub fn on_progress<F>(mut self, callback: F) -> ExtractOptions
+    where
+        F: FnMut(&ExtractProgress) + 'static,
+    {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is invoked whenever an entry is not extracted, with the path of the
+    /// entry relative to the target directory, or its stored name if that path is empty, and the
+    /// reason.
+    ///
+    /// Entries that can not be extracted safely are only skipped and reported if
+    /// `ExtractOptions::skip_unsafe_entries` is set, and fail the extraction otherwise. Entries
+    /// whose name is rejected by `ExtractOptions::name_validator` always fail it.
+    #[must_use]
+    pub fn on_skip<F>(mut self, callback: F) -> ExtractOptions
+    where
+        F: FnMut(&Path, SkipReason) + 'static,
+    {
+        self.on_skip = Some(Box::new(callback));
+        self
+    }
+
+    fn validate_name(&self, entry_path: &Path) -> ZipResult<()> {
+        if let Some(validator) = &self.name_validator {
+            if let Err(reason) = validator(entry_path) {
+                return Err(ZipError::Io(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "The entry {} was rejected: {}",
+                        entry_path.display(),
+                        reason
+                    ),
+                )));
             }
         }
-
         Ok(())
     }
 
-    fn extract_file<P: AsRef<Path>>(
-        &mut self,
-        file_number: usize,
-        destination_file_path: P,
-        overwrite: bool,
-    ) -> ZipResult<()> {
-        let mut buffer: Vec<u8> = Vec::new();
-        self.extract_file_to_memory(file_number, &mut buffer)?;
-        file_write_all_bytes(
-            destination_file_path.as_ref().to_path_buf(),
-            buffer.as_ref(),
-            overwrite,
-        )?;
-        Ok(())
+    fn report_progress(&mut self, progress: &ExtractProgress) {
+        if let Some(on_progress) = &mut self.on_progress {
+            on_progress(progress);
+        }
     }
 
-    fn extract_file_to_memory(
+    fn report_skip(&mut self, entry_path: &Path, reason: SkipReason) {
+        if let Some(on_skip) = &mut self.on_skip {
+            on_skip(entry_path, reason);
+        }
+    }
+
+    /// Reports an entry that is skipped because it can not be extracted safely, with the path, the
+    /// compressed size and the reason returned by `unsafe_entry`.
+    fn skip_unsafe_entry(
         &mut self,
-        file_number: usize,
-        buffer: &mut Vec<u8>,
-    ) -> ZipResult<()> {
-        let mut next: ZipFile<'_> = self.by_index(file_number)?;
-        if next.is_file() {
-            let _bytes_read = next.read_to_end(buffer)?;
-            return Ok(());
+        (entry_path, compressed_size, reason): (PathBuf, u64, SkipReason),
+        report: &mut ExtractReport,
+        progress: &mut ExtractProgress,
+    ) {
+        self.report_skip(&entry_path, reason);
+        report.files_skipped += 1;
+        progress.entries_processed += 1;
+        progress.compressed_bytes_processed += compressed_size;
+        self.report_progress(progress);
+    }
+
+    /// Returns the path, relative to the target directory, to which the entry with the given
+    /// sanitized path is extracted.
+    fn destination_name(&self, sanitized_name: &Path) -> PathBuf {
+        match sanitized_name.to_str() {
+            Some(name) if self.lowercase_paths => PathBuf::from(name.to_lowercase()),
+            _ => sanitized_name.to_path_buf(),
         }
-        Err(ZipError::Io(Error::new(
-            ErrorKind::InvalidInput,
-            "The specified index does not indicate a file entry.",
-        )))
     }
 
-    fn entry_path(&mut self, file_number: usize) -> ZipResult<PathBuf> {
-        let next: ZipFile<'_> = self.by_index(file_number)?;
-        Ok(next.sanitized_name())
+    /// Returns the directory that entries are extracted below, in its extended-length form if long
+    /// paths are enabled.
+    fn extraction_root(&self, target_directory: &Path) -> io::Result<PathBuf> {
+        if self.long_paths {
+            extended_length_path(target_directory)
+        } else {
+            Ok(target_directory.to_path_buf())
+        }
     }
 
-    fn file_number<P: AsRef<Path>>(&mut self, entry_path: P) -> Option<usize> {
-        for file_number in 0..self.len() {
-            if let Ok(next) = self.by_index(file_number) {
-                let sanitized_name = next.sanitized_name();
-                if sanitized_name == *entry_path.as_ref() {
-                    return Some(file_number);
-                }
-            }
+    /// Returns the path of the temporary file that a file is written to before it is renamed to
+    /// the destination, or `None` if files are written in place.
+    fn temporary_path(&self, destination: &Path) -> Option<PathBuf> {
+        if !self.atomic_writes {
+            return None;
         }
-        None
+        let directory = self
+            .temp_dir
+            .as_deref()
+            .or_else(|| destination.parent())
+            .unwrap_or(Path::new("."));
+        Some(temporary_file_path(directory, destination))
+    }
+
+    fn line_ending(&self, entry_path: &Path) -> Option<LineEnding> {
+        let extension = entry_path.extension()?.to_string_lossy().to_lowercase();
+        self.extension_line_endings.get(&extension).copied()
+    }
+
+    fn effective_mode(&self, unix_mode: Option<u32>) -> Option<u32> {
+        if !self.preserve_permissions {
+            return None;
+        }
+        let mask = self.permission_mask.unwrap_or(0o7777);
+        unix_mode.map(|mode| mode & 0o7777 & mask)
+    }
+}
+
+/// Validates that an entry path can be represented on all common platforms.
+///
+/// Rejects path components that contain control characters, that are reserved device names on
+/// Windows (such as `CON`, `NUL` or `COM1`, with or without extension), or that end with a dot or a
+/// space, which Windows silently strips.
+/// # Errors
+/// Returns a description of the problem if the path is not portable.
+pub fn portable_name_validator(entry_path: &Path) -> Result<(), String> {
+    const RESERVED_NAMES: [&str; 22] = [
+        "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+        "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+    ];
+    for component in entry_path.components() {
+        let name = component.as_os_str().to_string_lossy();
+        if name.chars().any(char::is_control) {
+            return Err(format!("`{name}` contains control characters"));
+        }
+        let stem = name.split('.').next().unwrap_or_default();
+        if RESERVED_NAMES
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(stem.trim_end()))
+        {
+            return Err(format!("`{name}` is a reserved name on Windows"));
+        }
+        if name.ends_with('.') || name.ends_with(' ') {
+            return Err(format!("`{name}` ends with a dot or a space"));
+        }
+    }
+    Ok(())
+}
+
+/// The error that is returned, wrapped in an io error, if an operation has been cancelled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("The operation has been cancelled.")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+impl From<Cancelled> for Error {
+    fn from(cancelled: Cancelled) -> Error {
+        Error::other(cancelled)
+    }
+}
+
+/// The reason why a file could not be opened as a ZIP archive.
+///
+/// The error is returned wrapped in an io error by `zip_open` and the other helpers of this crate
+/// that open an archive file; use `OpenError::from_zip_error` to retrieve it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum OpenError {
+    /// The file neither starts with a ZIP signature nor contains an end-of-central-directory
+    /// record.
+    NotAZip,
+    /// The file starts like a ZIP archive, but ends before its central directory.
+    Truncated,
+    /// An entry uses a compression method that is not supported by this build.
+    Unsupported(CompressionMethod),
+    /// An entry is encrypted.
+    Encrypted,
+    /// The file is a volume of a spanned/multi-volume archive.
+    Spanned {
+        /// The file that has been opened.
+        archive: PathBuf,
+        /// The file names of all volumes of the archive, as far as they are known.
+        volumes: Vec<String>,
+    },
+    /// The central directory or an entry header is malformed.
+    Corrupt(String),
+}
+
+impl OpenError {
+    /// Returns the classification of a `ZipError`, if it has been returned for a file that could
+    /// not be opened as an archive.
+    #[must_use]
+    pub fn from_zip_error(error: &ZipError) -> Option<&OpenError> {
+        match error {
+            ZipError::Io(error) => error.get_ref()?.downcast_ref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for OpenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OpenError::NotAZip => f.write_str("The file is not a ZIP archive."),
+            OpenError::Truncated => f.write_str("The archive is truncated."),
+            OpenError::Unsupported(compression_method) => write!(
+                f,
+                "The archive uses the compression method {compression_method:?}, which is not \
+                 supported."
+            ),
+            OpenError::Encrypted => f.write_str("The archive contains encrypted entries."),
+            OpenError::Spanned { archive, volumes } => write!(
+                f,
+                "{} is part of a spanned/multi-volume archive, which is not supported; the \
+                 archive consists of the volumes {}.",
+                archive.display(),
+                volumes.join(", ")
+            ),
+            OpenError::Corrupt(detail) => write!(f, "The archive is corrupt: {detail}"),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {}
+
+impl From<OpenError> for Error {
+    fn from(open_error: OpenError) -> Error {
+        let kind = match open_error {
+            OpenError::NotAZip | OpenError::Corrupt(_) => ErrorKind::InvalidData,
+            OpenError::Truncated => ErrorKind::UnexpectedEof,
+            OpenError::Unsupported(_) | OpenError::Encrypted | OpenError::Spanned { .. } => {
+                ErrorKind::Unsupported
+            }
+        };
+        Error::new(kind, open_error)
+    }
+}
+
+/// A group of entries that resolve to the same destination path.
+pub type DestinationCollision = (PathBuf, Vec<usize>);
+
+/// The error that is returned, wrapped in an io error, if several entries would be extracted to
+/// the same path and collisions are rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DestinationCollisions {
+    /// The colliding entries, grouped by their destination path and sorted by that path.
+    pub collisions: Vec<DestinationCollision>,
+}
+
+impl fmt::Display for DestinationCollisions {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Several entries would be extracted to the same path:")?;
+        for (destination, file_numbers) in &self.collisions {
+            write!(f, " {} (entries {:?})", destination.display(), file_numbers)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for DestinationCollisions {}
+
+impl From<DestinationCollisions> for Error {
+    fn from(collisions: DestinationCollisions) -> Error {
+        Error::new(ErrorKind::AlreadyExists, collisions)
+    }
+}
+
+/// The file system operations that `ZipArchiveExtensions::extract_to_fs` uses, so that archives
+/// can be extracted to virtual, in-memory or remote file systems.
+///
+/// Paths are the sanitized entry paths, relative to the root of the file system.
+pub trait FsWriter {
+    /// Creates a directory and all of its missing parent directories.
+    /// # Errors
+    /// Will return an io error if the directory cannot be created.
+    fn create_dir(&mut self, path: &Path) -> io::Result<()>;
+
+    /// Creates a file, or truncates an existing one, and returns a writer for its contents.
+    /// The parent directory has already been created.
+    /// # Errors
+    /// Will return an io error if the file cannot be created.
+    fn create_file(&mut self, path: &Path) -> io::Result<Box<dyn io::Write + '_>>;
+}
+
+/// An `FsWriter` that writes below a directory of the real file system using `std::fs`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StdFsWriter {
+    root: PathBuf,
+}
+
+impl StdFsWriter {
+    /// Creates a writer for the given root directory, which must exist.
+    pub fn new<P: AsRef<Path>>(root: P) -> StdFsWriter {
+        StdFsWriter {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+}
+
+impl FsWriter for StdFsWriter {
+    fn create_dir(&mut self, path: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(self.root.join(path))
+    }
+
+    fn create_file(&mut self, path: &Path) -> io::Result<Box<dyn io::Write + '_>> {
+        Ok(Box::new(File::create(self.root.join(path))?))
+    }
+}
+
+/// The progress of an extraction, as passed to the callback set with `ExtractOptions::on_progress`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ExtractProgress {
+    /// The number of entries that have been processed, including skipped entries.
+    pub entries_processed: usize,
+    /// The number of entries in the archive.
+    pub total_entries: usize,
+    /// The number of uncompressed bytes that have been written.
+    pub bytes_processed: u64,
+    /// The sum of the compressed sizes of the entries that have been processed, which
+    /// corresponds to the number of bytes consumed from the archive.
+    pub compressed_bytes_processed: u64,
+}
+
+/// Summarizes the outcome of an extraction.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ExtractReport {
+    /// The number of file entries that have been written.
+    pub files_extracted: usize,
+    /// The number of directory entries that have been created.
+    pub directories_created: usize,
+    /// The number of file entries that have not been extracted because the destination exists,
+    /// because their name is empty, or because they can not be extracted safely and
+    /// `ExtractOptions::skip_unsafe_entries` is set.
+    pub files_skipped: usize,
+    /// The file entries that have been extracted to a different path because the destination
+    /// exists, as pairs of the original and the actually used path.
+    pub renamed: Vec<(PathBuf, PathBuf)>,
+    /// The file entries that have not been extracted because other entries need a directory at
+    /// their path.
+    pub skipped_directory_conflicts: Vec<PathBuf>,
+}
+
+/// Describes the kind of document a ZIP-based file represents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ZipKind {
+    /// A ZIP archive that does not match any of the known document formats.
+    PlainZip,
+    /// A Java archive (contains `META-INF/MANIFEST.MF`).
+    Jar,
+    /// An Office Open XML document such as `.docx` or `.xlsx` (contains `[Content_Types].xml`).
+    OfficeOpenXml,
+    /// An EPUB publication (stored `mimetype` entry with `application/epub+zip`).
+    Epub,
+    /// An `OpenDocument` file such as `.odt` (stored `mimetype` entry with an OASIS mime type).
+    OpenDocument,
+    /// An Android package (contains `AndroidManifest.xml`).
+    Apk,
+    /// The file is not a readable ZIP archive.
+    Unknown,
+}
+
+/// Determines which kind of ZIP-based document the specified file is.
+///
+/// Detection only reads the central directory, plus the `mimetype` entry if the archive starts
+/// with one. If an archive matches several formats, the first match in the following order wins:
+/// a stored leading `mimetype` entry (`Epub`, `OpenDocument`), `AndroidManifest.xml` (`Apk`, since
+/// APKs also carry a JAR manifest), `[Content_Types].xml` (`OfficeOpenXml`), and
+/// `META-INF/MANIFEST.MF` (`Jar`). Archives that match none of them are `PlainZip`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive.
+pub fn zip_kind<P: AsRef<Path>>(file: P) -> ZipResult<ZipKind> {
+    const EPUB_MIME_TYPE: &str = "application/epub+zip";
+    const OPEN_DOCUMENT_MIME_TYPE_PREFIX: &str = "application/vnd.oasis.opendocument.";
+
+    let file = File::open(file)?;
+    let mut archive = match ZipArchive::new(BufReader::new(file)) {
+        Ok(archive) => archive,
+        Err(ZipError::Io(error)) => return Err(ZipError::Io(error)),
+        Err(_) => return Ok(ZipKind::Unknown),
+    };
+
+    if !archive.is_empty() {
+        let first: ZipFile<'_> = archive.by_index(0)?;
+        if first.name() == "mimetype" && first.compression() == CompressionMethod::Stored {
+            let mut mime_type = String::new();
+            first.take(256).read_to_string(&mut mime_type)?;
+            let mime_type = mime_type.trim();
+            if mime_type == EPUB_MIME_TYPE {
+                return Ok(ZipKind::Epub);
+            }
+            if mime_type.starts_with(OPEN_DOCUMENT_MIME_TYPE_PREFIX) {
+                return Ok(ZipKind::OpenDocument);
+            }
+        }
+    }
+
+    let has_entry = |name: &str| archive.file_names().any(|entry_name| entry_name == name);
+    let kind = if has_entry("AndroidManifest.xml") {
+        ZipKind::Apk
+    } else if has_entry("[Content_Types].xml") {
+        ZipKind::OfficeOpenXml
+    } else if has_entry("META-INF/MANIFEST.MF") {
+        ZipKind::Jar
+    } else {
+        ZipKind::PlainZip
+    };
+    Ok(kind)
+}
+
+/// Describes the container format of a file, as detected from its magic bytes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArchiveKind {
+    /// A ZIP archive.
+    Zip,
+    /// A gzip stream.
+    Gzip,
+    /// A POSIX tar archive (`ustar` magic at offset 257).
+    Tar,
+    /// A 7z archive.
+    SevenZip,
+    /// A RAR archive.
+    Rar,
+    /// An xz stream.
+    Xz,
+    /// A Zstandard frame.
+    Zstd,
+    /// The format is not recognized.
+    Unknown,
+}
+
+/// The number of bytes that are read to detect an archive kind, enough to cover the tar magic.
+const ARCHIVE_KIND_SNIFF_LENGTH: usize = 262;
+const TAR_MAGIC_OFFSET: usize = 257;
+
+/// Determines the container format of the specified file from its magic bytes.
+///
+/// Unlike `zip_kind`, this does not parse the file. Files that are shorter than a magic are
+/// `Unknown`.
+/// # Errors
+/// Will return `io::Error` for relevant file io error.
+pub fn detect_archive_kind<P: AsRef<Path>>(file: P) -> io::Result<ArchiveKind> {
+    let mut file = File::open(file)?;
+    detect_archive_kind_reader(&mut file)
+}
+
+/// Determines the container format of the data in the specified reader from its magic bytes.
+///
+/// At most the first 262 bytes are consumed from the reader.
+/// # Errors
+/// Will return `io::Error` for relevant io error on the reader.
+pub fn detect_archive_kind_reader<R: Read>(reader: &mut R) -> io::Result<ArchiveKind> {
+    const MAGICS: [(&[u8], ArchiveKind); 5] = [
+        (&[0x1f, 0x8b], ArchiveKind::Gzip),
+        (&[0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c], ArchiveKind::SevenZip),
+        (b"Rar!\x1a\x07", ArchiveKind::Rar),
+        (&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00], ArchiveKind::Xz),
+        (&[0x28, 0xb5, 0x2f, 0xfd], ArchiveKind::Zstd),
+    ];
+
+    let mut buffer: Vec<u8> = Vec::with_capacity(ARCHIVE_KIND_SNIFF_LENGTH);
+    reader
+        .by_ref()
+        .take(ARCHIVE_KIND_SNIFF_LENGTH as u64)
+        .read_to_end(&mut buffer)?;
+
+    if buffer.len() >= ZIP_SIGNATURE_LENGTH && is_zip_signature(&buffer[..ZIP_SIGNATURE_LENGTH]) {
+        return Ok(ArchiveKind::Zip);
+    }
+    if let Some((_, kind)) = MAGICS.iter().find(|(magic, _)| buffer.starts_with(magic)) {
+        return Ok(*kind);
+    }
+    if buffer.get(TAR_MAGIC_OFFSET..) == Some(b"ustar".as_slice()) {
+        return Ok(ArchiveKind::Tar);
+    }
+    Ok(ArchiveKind::Unknown)
+}
+
+/// Describes an entry in a ZIP archive.
+///
+/// With the `serde` feature, this implements `serde::Serialize`, with the compression method
+/// serialized by its name, such as `"Deflated"`, and the modification time as seconds since the
+/// Unix epoch, or `null` if it is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EntryInfo {
+    /// The index of the entry in the archive.
+    pub index: usize,
+    /// The sanitized path of the entry.
+    pub path: PathBuf,
+    /// Whether the entry is a directory.
+    pub is_dir: bool,
+    /// The uncompressed size of the entry, in bytes.
+    pub size: u64,
+    /// The compressed size of the entry, in bytes.
+    pub compressed_size: u64,
+    /// The CRC-32 checksum of the uncompressed entry data.
+    pub crc32: u32,
+    /// The compression method used for the entry.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "serialize_compression_method")
+    )]
+    pub compression: CompressionMethod,
+    /// The last modification time of the entry, interpreted as UTC; `None` if the stored
+    /// timestamp is invalid.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_unix_time"))]
+    pub last_modified: Option<SystemTime>,
+    /// The comment of the entry, which is empty if it has none.
+    pub comment: String,
+}
+
+impl EntryInfo {
+    fn from_zip_file(index: usize, file: &ZipFile<'_>) -> ZipResult<EntryInfo> {
+        Ok(EntryInfo {
+            index,
+            path: resolved_entry_path(file)?,
+            is_dir: file.is_dir(),
+            size: file.size(),
+            compressed_size: file.compressed_size(),
+            crc32: file.crc32(),
+            compression: file.compression(),
+            last_modified: zip_datetime_to_system_time(file.last_modified()),
+            comment: file.comment().to_string(),
+        })
+    }
+}
+
+#[cfg(feature = "serde")]
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn serialize_compression_method<S>(
+    method: &CompressionMethod,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    serializer.collect_str(method)
+}
+
+#[cfg(feature = "serde")]
+#[allow(clippy::ref_option)]
+fn serialize_unix_time<S>(time: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    match time.and_then(|time| time.duration_since(SystemTime::UNIX_EPOCH).ok()) {
+        Some(duration) => serializer.serialize_some(&duration.as_secs()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Aggregated statistics for the entries that use one compression method.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MethodStats {
+    /// The number of entries that use the compression method.
+    pub entries: usize,
+    /// The total compressed size of these entries, in bytes.
+    pub compressed_size: u64,
+    /// The total uncompressed size of these entries, in bytes.
+    pub uncompressed_size: u64,
+    /// Whether the compression method is supported by the enabled features; entries using an
+    /// unsupported method cannot be extracted.
+    pub supported: bool,
+}
+
+/// An entry whose CRC-32 differs from the one in the expected manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrcMismatch {
+    /// The sanitized path of the entry.
+    pub path: PathBuf,
+    /// The CRC-32 from the manifest.
+    pub expected: u32,
+    /// The CRC-32 stored in the archive.
+    pub actual: u32,
+}
+
+/// The outcome of verifying an archive against an expected manifest of CRC-32 values.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ManifestReport {
+    /// The entries whose stored CRC-32 differs from the manifest.
+    pub mismatched: Vec<CrcMismatch>,
+    /// The entries whose data does not match their stored CRC-32, or cannot be decompressed.
+    pub corrupted: Vec<PathBuf>,
+    /// The paths from the manifest that are not in the archive.
+    pub missing: Vec<PathBuf>,
+    /// The file entries in the archive that are not in the manifest.
+    pub extra: Vec<PathBuf>,
+}
+
+impl ManifestReport {
+    /// Returns whether the archive matches the manifest exactly.
+    #[must_use]
+    pub fn is_match(&self) -> bool {
+        self.mismatched.is_empty()
+            && self.corrupted.is_empty()
+            && self.missing.is_empty()
+            && self.extra.is_empty()
+    }
+}
+
+/// Verifies the file entries of the ZIP archive against an expected manifest of CRC-32 values,
+/// without extracting anything to disk.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive.
+pub fn zip_verify_against_manifest<P: AsRef<Path>, S: BuildHasher>(
+    archive_file: P,
+    manifest: &HashMap<PathBuf, u32, S>,
+) -> ZipResult<ManifestReport> {
+    let mut archive = open_archive(archive_file)?;
+    archive.verify_against_manifest(manifest)
+}
+
+/// An entry that is contained in both archives of a comparison, with differing contents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryDifference {
+    /// The sanitized path of the entry.
+    pub path: PathBuf,
+    /// The uncompressed size of the entry in the first archive, in bytes.
+    pub size_a: u64,
+    /// The uncompressed size of the entry in the second archive, in bytes.
+    pub size_b: u64,
+    /// The CRC-32 of the entry in the first archive.
+    pub crc32_a: u32,
+    /// The CRC-32 of the entry in the second archive.
+    pub crc32_b: u32,
+}
+
+/// The differences between the contents of two archives, each sorted by path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ArchiveDiff {
+    /// The entries that are only contained in the first archive.
+    pub only_in_a: Vec<PathBuf>,
+    /// The entries that are only contained in the second archive.
+    pub only_in_b: Vec<PathBuf>,
+    /// The entries that are contained in both archives, with a differing size or CRC-32.
+    pub changed: Vec<EntryDifference>,
+}
+
+impl ArchiveDiff {
+    /// Returns whether both archives have the same contents.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.only_in_a.is_empty() && self.only_in_b.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Compares the contents of two ZIP archives by the size and CRC-32 of their entries, as stored
+/// in the central directories, without decompressing anything.
+///
+/// Entries are matched by their sanitized paths, regardless of their order. Modification times,
+/// permissions, compression methods and comments are ignored, so that archives that have been
+/// packaged reproducibly compare as equal even if they were compressed differently.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on either archive.
+pub fn zip_diff<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_a: P1,
+    archive_b: P2,
+) -> ZipResult<ArchiveDiff> {
+    let entries_a = central_directory_entries(archive_a.as_ref())?;
+    let mut entries_b = central_directory_entries(archive_b.as_ref())?;
+    let mut diff = ArchiveDiff::default();
+    for (path, (size_a, crc32_a)) in entries_a {
+        match entries_b.remove(&path) {
+            None => diff.only_in_a.push(path),
+            Some((size_b, crc32_b)) if size_a != size_b || crc32_a != crc32_b => {
+                diff.changed.push(EntryDifference {
+                    path,
+                    size_a,
+                    size_b,
+                    crc32_a,
+                    crc32_b,
+                });
+            }
+            Some(_) => {}
+        }
+    }
+    diff.only_in_b = entries_b.into_keys().collect();
+    Ok(diff)
+}
+
+/// Returns the size and CRC-32 of every entry of an archive by its resolved path.
+fn central_directory_entries(archive_file: &Path) -> ZipResult<BTreeMap<PathBuf, (u64, u32)>> {
+    let mut archive = open_archive(archive_file)?;
+    let mut entries = BTreeMap::new();
+    for file_number in 0..archive.len() {
+        let next: ZipFile<'_> = archive.by_index_raw(file_number)?;
+        entries.insert(resolved_entry_path(&next)?, (next.size(), next.crc32()));
+    }
+    Ok(entries)
+}
+
+pub trait ZipArchiveExtensions {
+    /// Extracts the current archive to the given directory path. The `.` and `..` components of
+    /// entry paths are resolved before extracting, and archives with entries that lead outside of
+    /// the directory are refused before anything is written.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory.
+    fn extract<P: AsRef<Path>>(&mut self, path: P) -> ZipResult<()>;
+
+    /// Extracts the current archive to the given directory path, using the specified options.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory.
+    fn extract_with_options<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        options: ExtractOptions,
+    ) -> ZipResult<ExtractReport>;
+
+    /// Extracts the current archive to the given directory path, rewriting only the files whose
+    /// contents differ from the entries, as determined by their sizes and CRC-32 values, and
+    /// returns a report in which the unchanged files are counted as skipped.
+    ///
+    /// Unlike comparing modification times, this is reliable when timestamps are not, but every
+    /// existing file whose size matches its entry is read in full to compute its CRC-32, which
+    /// costs about as much io as extracting it. See `ConflictPolicy::SkipUnchanged`.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive, directory or existing files.
+    fn extract_if_crc_differs<P: AsRef<Path>>(&mut self, path: P) -> ZipResult<ExtractReport>;
+
+    /// Extracts the current archive to the given directory path, until the given flag is set.
+    ///
+    /// The flag is checked before each entry and after each chunk of entry data. Entries that
+    /// have been extracted before cancellation remain in place, and the entry that was being
+    /// written may be incomplete.
+    /// # Errors
+    /// Will return `ZipError::Io` wrapping `Cancelled` if the extraction has been cancelled, or
+    /// `ZipError` for relevant file io error on archive or directory.
+    fn extract_with_cancel<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        cancel: &AtomicBool,
+    ) -> ZipResult<()>;
+
+    /// Extracts the entries of the current archive to the given directory path, beginning at the
+    /// given index, until the total uncompressed size of the extracted entries would exceed the
+    /// byte budget. Returns the index of the first entry that has not been extracted, from which
+    /// a later call can resume, or the number of entries if all have been extracted.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory, or if the entry
+    /// at the start index alone exceeds the byte budget.
+    fn extract_until_bytes<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        byte_budget: u64,
+        start_index: usize,
+    ) -> ZipResult<usize>;
+
+    /// Extracts the current archive to the given directory path, passing the decompressed
+    /// contents of each file entry through the given transform before they are written, such as
+    /// to decode or decrypt the payloads. The transform receives the sanitized entry path and the
+    /// contents. Directory entries are created without invoking the transform.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory, or if the
+    /// transform fails.
+    fn extract_with_transform<P, F>(&mut self, path: P, transform: F) -> ZipResult<()>
+    where
+        P: AsRef<Path>,
+        F: Fn(&Path, Vec<u8>) -> io::Result<Vec<u8>>;
+
+    /// Finds entries that would be extracted to the same path, grouped by that path and sorted
+    /// by it. Groups that only consist of directory entries are not collisions.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn find_collisions(&mut self) -> ZipResult<Vec<DestinationCollision>>;
+
+    /// Creates the directory tree of the current archive below the given directory path, without
+    /// writing any file contents. This includes directory entries as well as the parent
+    /// directories of file entries.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory.
+    fn extract_directories_only<P: AsRef<Path>>(&mut self, path: P) -> ZipResult<()>;
+
+    /// Extracts the `count` largest file entries of the current archive, by the uncompressed size
+    /// recorded in the central directory, to the given directory path, and returns their paths
+    /// from the largest to the smallest. Entries of the same size are taken in the order they are
+    /// stored. Existing files are overwritten, and directory entries are not extracted, apart
+    /// from the parent directories of the selected files.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory.
+    fn extract_largest<P: AsRef<Path>>(&mut self, path: P, count: usize)
+        -> ZipResult<Vec<PathBuf>>;
+
+    /// Extracts the entries below the given prefix of the current archive to the given directory
+    /// path. The prefix is matched against whole path components, so `docs` selects `docs/a.txt`
+    /// but not `docs.txt`. If `strip_prefix` is set, the prefix is removed from the extracted
+    /// paths so that the subtree lands at the root of the directory.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory, if no entry is
+    /// below the prefix, or if a file already exists and `overwrite` is not set.
+    fn extract_subtree<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        prefix: P1,
+        path: P2,
+        strip_prefix: bool,
+        overwrite: bool,
+    ) -> ZipResult<()>;
+
+    /// Extracts all entries of the current archive to the given file system abstraction, which
+    /// decouples extraction from the real file system. Use `StdFsWriter` to write below a
+    /// directory with `std::fs`.
+    /// # Errors
+    /// Will return `ZipError` for relevant io error on archive or file system.
+    fn extract_to_fs(&mut self, fs: &mut dyn FsWriter) -> ZipResult<()>;
+
+    /// Extracts an entry in the zip archive to a file.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory.
+    fn extract_file<P: AsRef<Path>>(
+        &mut self,
+        file_number: usize,
+        destination_file_path: P,
+        overwrite: bool,
+    ) -> ZipResult<()>;
+
+    /// Extracts an entry in the ZIP archive to the given memory buffer.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn extract_file_to_memory(&mut self, file_number: usize, buffer: &mut Vec<u8>)
+        -> ZipResult<()>;
+
+    /// Streams the decompressed contents of an entry in the ZIP archive to the given writer.
+    /// Returns the number of bytes written.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or writer.
+    fn extract_file_to_writer(
+        &mut self,
+        file_number: usize,
+        writer: &mut dyn io::Write,
+    ) -> ZipResult<u64>;
+
+    /// Reads an entry in the ZIP archive fully and discards its contents, which decompresses the
+    /// data and validates its CRC-32 without allocating a buffer. Returns the number of bytes
+    /// read.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive, or if the entry data is
+    /// corrupt.
+    fn read_entry_to_sink(&mut self, file_number: usize) -> ZipResult<u64>;
+
+    /// Gets an entry´s path, with its `.` and `..` components resolved as on extraction.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive, or if the path leads outside
+    /// of the archive.
+    fn entry_path(&mut self, file_number: usize) -> ZipResult<PathBuf>;
+
+    /// Finds the index of the specified entry by its path, as returned by `entry_path`.
+    fn file_number<P: AsRef<Path>>(&mut self, entry_path: P) -> Option<usize>;
+
+    /// Gets the absolute byte offset within the underlying reader at which the data of an entry
+    /// begins, after its local header. For stored entries, the next `compressed_size` bytes are
+    /// the uncompressed contents, which can be read or memory-mapped directly.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn entry_data_offset(&mut self, file_number: usize) -> ZipResult<u64>;
+
+    /// Gets information about an entry, without decompressing its data.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn entry_info(&mut self, file_number: usize) -> ZipResult<EntryInfo>;
+
+    /// Lists information about all entries, in the order they are stored in the archive.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn list_entries(&mut self) -> ZipResult<Vec<EntryInfo>>;
+
+    /// Iterates over information about all entries, in the order they are stored in the
+    /// archive, without decompressing their data.
+    fn entries(&mut self) -> impl Iterator<Item = ZipResult<EntryInfo>> + '_;
+
+    /// Invokes the callback for every entry, in the order they are stored in the archive, with
+    /// information about the entry and a reader of its decompressed contents. An error returned
+    /// by the callback stops the iteration.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive, or if the callback fails.
+    fn for_each_entry<F>(&mut self, callback: F) -> ZipResult<()>
+    where
+        F: FnMut(&EntryInfo, &mut dyn Read) -> ZipResult<()>;
+
+    /// Lists information about all entries, sorted by path for deterministic display.
+    ///
+    /// Paths are compared component by component, so that the entries of a directory follow the
+    /// directory itself. Entries with the same path keep the order they are stored in.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn list_entries_sorted(&mut self) -> ZipResult<Vec<EntryInfo>>;
+
+    /// Summarizes how the entries in the archive are compressed, grouped by compression method
+    /// in the order the methods are first encountered. Entry data is not decompressed.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn compression_breakdown(&mut self) -> ZipResult<Vec<(CompressionMethod, MethodStats)>>;
+
+    /// Verifies the file entries against an expected manifest of CRC-32 values, keyed by
+    /// sanitized entry path. Entry data is decompressed, but not written anywhere, so that data
+    /// that does not match its stored CRC-32 is detected as well. Directory entries are ignored.
+    /// The paths in every list of the report are sorted.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn verify_against_manifest<S: BuildHasher>(
+        &mut self,
+        manifest: &HashMap<PathBuf, u32, S>,
+    ) -> ZipResult<ManifestReport>;
+
+    /// Finds all entries whose final path component is the specified name, such as every
+    /// `package.json` in the archive, in the order they are stored. Directory entries match by
+    /// their own name.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    fn find_by_basename(&mut self, name: &OsStr) -> ZipResult<Vec<(usize, PathBuf)>>;
+
+    /// Finds the stored file entries whose data does not begin at a multiple of the alignment,
+    /// counted from the start of the underlying reader, in the order they are stored. Archives
+    /// written with `CreateOptions::align_stored_entries` have none; compressed entries are not
+    /// checked, since they can not be memory-mapped anyway.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive, or if the alignment is 0.
+    fn check_alignment(&mut self, alignment: u16) -> ZipResult<Vec<PathBuf>>;
+
+    /// Finds all entries whose path matches the specified regular expression.
+    ///
+    /// Entry paths are matched using forward slashes as separators.
+    /// # Errors
+    /// Will return `ZipError` if the pattern is invalid, or for relevant file io error on archive.
+    #[cfg(feature = "regex")]
+    fn find_entries_regex(&mut self, pattern: &str) -> ZipResult<Vec<(usize, PathBuf)>>;
+
+    /// Computes a SHA-256 digest over the names, uncompressed sizes and CRC-32 values of all file
+    /// entries, in the order of their names, as they are recorded in the central directory.
+    ///
+    /// Unlike `zip_content_hash`, no entry is decompressed, so this is cheap even for large
+    /// archives, but it relies on the recorded CRC-32 values. Archives with the same files hash
+    /// identically regardless of entry order, directory entries and compression.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive.
+    #[cfg(feature = "sha2")]
+    fn archive_content_hash(&mut self) -> ZipResult<[u8; 32]>;
+}
+
+#[allow(deprecated)]
+impl<R: Read + io::Seek> ZipArchiveExtensions for ZipArchive<R> {
+    fn extract<P: AsRef<Path>>(&mut self, target_directory: P) -> ZipResult<()> {
+        self.extract_with_options(target_directory, ExtractOptions::default())?;
+        Ok(())
+    }
+
+    fn extract_with_options<P: AsRef<Path>>(
+        &mut self,
+        target_directory: P,
+        mut options: ExtractOptions,
+    ) -> ZipResult<ExtractReport> {
+        extract_archive(self, target_directory.as_ref(), &mut options, None)
+    }
+
+    fn extract_if_crc_differs<P: AsRef<Path>>(
+        &mut self,
+        target_directory: P,
+    ) -> ZipResult<ExtractReport> {
+        let options = ExtractOptions::default().conflict_policy(ConflictPolicy::SkipUnchanged);
+        self.extract_with_options(target_directory, options)
+    }
+
+    fn extract_with_cancel<P: AsRef<Path>>(
+        &mut self,
+        target_directory: P,
+        cancel: &AtomicBool,
+    ) -> ZipResult<()> {
+        extract_archive(
+            self,
+            target_directory.as_ref(),
+            &mut ExtractOptions::default(),
+            Some(cancel),
+        )?;
+        Ok(())
+    }
+
+    fn extract_until_bytes<P: AsRef<Path>>(
+        &mut self,
+        target_directory: P,
+        byte_budget: u64,
+        start_index: usize,
+    ) -> ZipResult<usize> {
+        let target_directory = target_directory.as_ref();
+        if !target_directory.is_dir() {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The specified path does not indicate a valid directory path.",
+            )));
+        }
+
+        let mut bytes_extracted: u64 = 0;
+        for file_number in start_index..self.len() {
+            let mut next: ZipFile<'_> = self.by_index(file_number)?;
+            let sanitized_name = resolved_entry_path(&next)?;
+            if bytes_extracted.saturating_add(next.size()) > byte_budget {
+                if file_number == start_index {
+                    return Err(ZipError::Io(Error::new(
+                        ErrorKind::InvalidInput,
+                        format!(
+                            "The entry {} alone exceeds the byte budget of {} bytes.",
+                            sanitized_name.display(),
+                            byte_budget
+                        ),
+                    )));
+                }
+                return Ok(file_number);
+            }
+            bytes_extracted += next.size();
+            let destination_path = target_directory.join(&sanitized_name);
+            if next.is_dir() {
+                std::fs::create_dir_all(&destination_path)?;
+            } else if next.is_file() {
+                if let Some(parent_directory) = destination_path.parent() {
+                    std::fs::create_dir_all(parent_directory)?;
+                }
+                io::copy(&mut next, &mut File::create(&destination_path)?)?;
+            }
+        }
+        Ok(self.len().max(start_index))
+    }
+
+    fn extract_with_transform<P, F>(&mut self, target_directory: P, transform: F) -> ZipResult<()>
+    where
+        P: AsRef<Path>,
+        F: Fn(&Path, Vec<u8>) -> io::Result<Vec<u8>>,
+    {
+        let target_directory = target_directory.as_ref();
+        if !target_directory.is_dir() {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The specified path does not indicate a valid directory path.",
+            )));
+        }
+
+        for file_number in 0..self.len() {
+            let mut next: ZipFile<'_> = self.by_index(file_number)?;
+            let sanitized_name = resolved_entry_path(&next)?;
+            let destination_path = target_directory.join(&sanitized_name);
+            if next.is_dir() {
+                std::fs::create_dir_all(&destination_path)?;
+            } else if next.is_file() {
+                let mut buffer: Vec<u8> = Vec::new();
+                next.read_to_end(&mut buffer)?;
+                let transformed = transform(&sanitized_name, buffer)?;
+                if let Some(parent_directory) = destination_path.parent() {
+                    std::fs::create_dir_all(parent_directory)?;
+                }
+                std::fs::write(&destination_path, transformed)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn find_collisions(&mut self) -> ZipResult<Vec<DestinationCollision>> {
+        destination_collisions(self, &ExtractOptions::default(), false)
+    }
+
+    fn extract_directories_only<P: AsRef<Path>>(&mut self, target_directory: P) -> ZipResult<()> {
+        let target_directory = target_directory.as_ref();
+        if !target_directory.is_dir() {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The specified path does not indicate a valid directory path.",
+            )));
+        }
+
+        for file_number in 0..self.len() {
+            let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+            let sanitized_name = resolved_entry_path(&next)?;
+            let directory = if next.is_dir() {
+                Some(sanitized_name.as_path())
+            } else {
+                sanitized_name.parent()
+            };
+            if let Some(directory) = directory {
+                std::fs::create_dir_all(target_directory.join(directory))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn extract_largest<P: AsRef<Path>>(
+        &mut self,
+        target_directory: P,
+        count: usize,
+    ) -> ZipResult<Vec<PathBuf>> {
+        let target_directory = target_directory.as_ref();
+        if !target_directory.is_dir() {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The specified path does not indicate a valid directory path.",
+            )));
+        }
+
+        let mut sizes: Vec<(u64, usize)> = Vec::new();
+        for file_number in 0..self.len() {
+            let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+            if next.is_file() {
+                sizes.push((next.size(), file_number));
+            }
+        }
+        // The sort is stable, so entries of the same size keep their stored order.
+        sizes.sort_by_key(|(size, _)| std::cmp::Reverse(*size));
+
+        let mut extracted: Vec<PathBuf> = Vec::new();
+        for (_, file_number) in sizes.into_iter().take(count) {
+            let mut next: ZipFile<'_> = self.by_index(file_number)?;
+            let sanitized_name = resolved_entry_path(&next)?;
+            let destination_path = target_directory.join(&sanitized_name);
+            if let Some(parent_directory) = destination_path.parent() {
+                std::fs::create_dir_all(parent_directory)?;
+            }
+            copy_in_chunks(&mut next, &mut File::create(&destination_path)?, |_| Ok(()))?;
+            extracted.push(sanitized_name);
+        }
+        Ok(extracted)
+    }
+
+    fn extract_subtree<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        prefix: P1,
+        target_directory: P2,
+        strip_prefix: bool,
+        overwrite: bool,
+    ) -> ZipResult<()> {
+        let prefix = prefix.as_ref();
+        let target_directory = target_directory.as_ref();
+        if !target_directory.is_dir() {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The specified path does not indicate a valid directory path.",
+            )));
+        }
+
+        let mut found = false;
+        for file_number in 0..self.len() {
+            let mut next: ZipFile<'_> = self.by_index(file_number)?;
+            let sanitized_name = resolved_entry_path(&next)?;
+            let destination_path = match sanitized_name.strip_prefix(prefix) {
+                Ok(relative) if strip_prefix => target_directory.join(relative),
+                Ok(_) => target_directory.join(&sanitized_name),
+                Err(_) => continue,
+            };
+            found = true;
+            if next.is_dir() {
+                std::fs::create_dir_all(&destination_path)?;
+            } else if next.is_file() {
+                if destination_path.exists() && !overwrite {
+                    return Err(ZipError::Io(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("The file {} already exists.", destination_path.display()),
+                    )));
+                }
+                if let Some(parent_directory) = destination_path.parent() {
+                    std::fs::create_dir_all(parent_directory)?;
+                }
+                copy_in_chunks(&mut next, &mut File::create(&destination_path)?, |_| Ok(()))?;
+            }
+        }
+        if !found {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::NotFound,
+                format!("The archive has no entries below {}.", prefix.display()),
+            )));
+        }
+        Ok(())
+    }
+
+    fn extract_to_fs(&mut self, fs: &mut dyn FsWriter) -> ZipResult<()> {
+        for file_number in 0..self.len() {
+            let mut next: ZipFile<'_> = self.by_index(file_number)?;
+            let sanitized_name = resolved_entry_path(&next)?;
+            if next.is_dir() {
+                fs.create_dir(&sanitized_name)?;
+            } else if next.is_file() {
+                if let Some(parent_directory) = sanitized_name.parent() {
+                    if !parent_directory.as_os_str().is_empty() {
+                        fs.create_dir(parent_directory)?;
+                    }
+                }
+                let mut writer = fs.create_file(&sanitized_name)?;
+                copy_in_chunks(&mut next, &mut writer, |_| Ok(()))?;
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn extract_file<P: AsRef<Path>>(
+        &mut self,
+        file_number: usize,
+        destination_file_path: P,
+        overwrite: bool,
+    ) -> ZipResult<()> {
+        let mut buffer: Vec<u8> = Vec::new();
+        self.extract_file_to_memory(file_number, &mut buffer)?;
+        file_write_all_bytes(
+            destination_file_path.as_ref().to_path_buf(),
+            buffer.as_ref(),
+            overwrite,
+        )?;
+        Ok(())
+    }
+
+    fn extract_file_to_memory(
+        &mut self,
+        file_number: usize,
+        buffer: &mut Vec<u8>,
+    ) -> ZipResult<()> {
+        let mut next: ZipFile<'_> = self.by_index(file_number)?;
+        if next.is_file() {
+            let _bytes_read = next.read_to_end(buffer)?;
+            return Ok(());
+        }
+        Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            "The specified index does not indicate a file entry.",
+        )))
+    }
+
+    fn extract_file_to_writer(
+        &mut self,
+        file_number: usize,
+        writer: &mut dyn io::Write,
+    ) -> ZipResult<u64> {
+        let mut next: ZipFile<'_> = self.by_index(file_number)?;
+        if next.is_file() {
+            return Ok(copy_in_chunks(&mut next, writer, |_| Ok(()))?);
+        }
+        Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            "The specified index does not indicate a file entry.",
+        )))
+    }
+
+    fn read_entry_to_sink(&mut self, file_number: usize) -> ZipResult<u64> {
+        let mut next: ZipFile<'_> = self.by_index(file_number)?;
+        Ok(io::copy(&mut next, &mut io::sink())?)
+    }
+
+    fn entry_path(&mut self, file_number: usize) -> ZipResult<PathBuf> {
+        let next: ZipFile<'_> = self.by_index(file_number)?;
+        resolved_entry_path(&next)
+    }
+
+    fn file_number<P: AsRef<Path>>(&mut self, entry_path: P) -> Option<usize> {
+        for file_number in 0..self.len() {
+            let Ok(next) = self.by_index(file_number) else {
+                continue;
+            };
+            if resolved_entry_path(&next).is_ok_and(|path| path == entry_path.as_ref()) {
+                return Some(file_number);
+            }
+        }
+        None
+    }
+
+    fn entry_data_offset(&mut self, file_number: usize) -> ZipResult<u64> {
+        Ok(self.by_index_raw(file_number)?.data_start())
+    }
+
+    fn entry_info(&mut self, file_number: usize) -> ZipResult<EntryInfo> {
+        let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+        EntryInfo::from_zip_file(file_number, &next)
+    }
+
+    fn list_entries(&mut self) -> ZipResult<Vec<EntryInfo>> {
+        self.entries().collect()
+    }
+
+    fn entries(&mut self) -> impl Iterator<Item = ZipResult<EntryInfo>> + '_ {
+        (0..self.len()).map(move |file_number| self.entry_info(file_number))
+    }
+
+    fn for_each_entry<F>(&mut self, mut callback: F) -> ZipResult<()>
+    where
+        F: FnMut(&EntryInfo, &mut dyn Read) -> ZipResult<()>,
+    {
+        for file_number in 0..self.len() {
+            let mut next: ZipFile<'_> = self.by_index(file_number)?;
+            let info = EntryInfo::from_zip_file(file_number, &next)?;
+            callback(&info, &mut next)?;
+        }
+        Ok(())
+    }
+
+    fn list_entries_sorted(&mut self) -> ZipResult<Vec<EntryInfo>> {
+        let mut entries = self.list_entries()?;
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn compression_breakdown(&mut self) -> ZipResult<Vec<(CompressionMethod, MethodStats)>> {
+        let mut breakdown: Vec<(CompressionMethod, MethodStats)> = Vec::new();
+        for file_number in 0..self.len() {
+            let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+            let method = next.compression();
+            let position =
+                if let Some(position) = breakdown.iter().position(|(other, _)| *other == method) {
+                    position
+                } else {
+                    let stats = MethodStats {
+                        supported: !matches!(method, CompressionMethod::Unsupported(_)),
+                        ..MethodStats::default()
+                    };
+                    breakdown.push((method, stats));
+                    breakdown.len() - 1
+                };
+            let stats = &mut breakdown[position].1;
+            stats.entries += 1;
+            stats.compressed_size += next.compressed_size();
+            stats.uncompressed_size += next.size();
+        }
+        Ok(breakdown)
+    }
+
+    fn verify_against_manifest<S: BuildHasher>(
+        &mut self,
+        manifest: &HashMap<PathBuf, u32, S>,
+    ) -> ZipResult<ManifestReport> {
+        let mut report = ManifestReport::default();
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        for file_number in 0..self.len() {
+            let mut next: ZipFile<'_> = self.by_index(file_number)?;
+            if next.is_dir() {
+                continue;
+            }
+            let path = resolved_entry_path(&next)?;
+            let actual = next.crc32();
+            match manifest.get(&path) {
+                Some(&expected) if expected != actual => report.mismatched.push(CrcMismatch {
+                    path: path.clone(),
+                    expected,
+                    actual,
+                }),
+                Some(_) => {}
+                None => report.extra.push(path.clone()),
+            }
+            match io::copy(&mut next, &mut io::sink()) {
+                Ok(_) => {}
+                Err(error) if is_corrupt_data_error(&error) => {
+                    report.corrupted.push(path.clone());
+                }
+                Err(error) => return Err(error.into()),
+            }
+            seen.insert(path);
+        }
+        report.missing = manifest
+            .keys()
+            .filter(|path| !seen.contains(*path))
+            .cloned()
+            .collect();
+
+        report.mismatched.sort_by(|a, b| a.path.cmp(&b.path));
+        report.corrupted.sort();
+        report.missing.sort();
+        report.extra.sort();
+        Ok(report)
+    }
+
+    fn find_by_basename(&mut self, name: &OsStr) -> ZipResult<Vec<(usize, PathBuf)>> {
+        let mut matches: Vec<(usize, PathBuf)> = Vec::new();
+        for file_number in 0..self.len() {
+            let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+            let path = resolved_entry_path(&next)?;
+            if path.file_name() == Some(name) {
+                matches.push((file_number, path));
+            }
+        }
+        Ok(matches)
+    }
+
+    fn check_alignment(&mut self, alignment: u16) -> ZipResult<Vec<PathBuf>> {
+        if alignment == 0 {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The alignment must not be 0.",
+            )));
+        }
+        let mut misaligned: Vec<PathBuf> = Vec::new();
+        for file_number in 0..self.len() {
+            let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+            if next.is_file()
+                && next.compression() == CompressionMethod::Stored
+                && next.data_start() % u64::from(alignment) != 0
+            {
+                misaligned.push(resolved_entry_path(&next)?);
+            }
+        }
+        Ok(misaligned)
+    }
+
+    #[cfg(feature = "sha2")]
+    fn archive_content_hash(&mut self) -> ZipResult<[u8; 32]> {
+        use sha2::{Digest, Sha256};
+
+        let mut entries: Vec<(String, u64, u32)> = Vec::new();
+        for file_number in 0..self.len() {
+            let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+            if next.is_file() {
+                let entry_name = path_to_entry_name(resolved_entry_path(&next)?);
+                entries.push((entry_name, next.size(), next.crc32()));
+            }
+        }
+        entries.sort();
+
+        let mut hasher = Sha256::new();
+        for (entry_name, size, crc32) in entries {
+            hasher.update((entry_name.len() as u64).to_le_bytes());
+            hasher.update(entry_name.as_bytes());
+            hasher.update(size.to_le_bytes());
+            hasher.update(crc32.to_le_bytes());
+        }
+        Ok(hasher.finalize().into())
+    }
+
+    #[cfg(feature = "regex")]
+    fn find_entries_regex(&mut self, pattern: &str) -> ZipResult<Vec<(usize, PathBuf)>> {
+        let regex = regex::Regex::new(pattern)
+            .map_err(|error| ZipError::Io(Error::new(ErrorKind::InvalidInput, error)))?;
+        let mut matches: Vec<(usize, PathBuf)> = Vec::new();
+        for file_number in 0..self.len() {
+            let next: ZipFile<'_> = self.by_index_raw(file_number)?;
+            let path = resolved_entry_path(&next)?;
+            if regex.is_match(&path_to_entry_name(&path)) {
+                matches.push((file_number, path));
+            }
+        }
+        Ok(matches)
+    }
+}
+
+/// Opens the specified ZIP file, classifying failures as an `OpenError`.
+///
+/// The file is wrapped in a `BufReader`, so that reading the central directory and entry headers
+/// does not cost a system call per field.
+pub(crate) fn open_archive<P: AsRef<Path>>(
+    archive_file: P,
+) -> ZipResult<ZipArchive<BufReader<File>>> {
+    let archive_file = archive_file.as_ref();
+    let mut file = File::open(archive_file)?;
+    if let Some(open_error) = inspect_archive_file(&mut file, archive_file)? {
+        return Err(ZipError::Io(open_error.into()));
+    }
+    ZipArchive::new(BufReader::new(file)).map_err(classify_open_error)
+}
+
+/// Detects files that can not be opened as an archive from their signature and their
+/// end-of-central-directory record.
+fn inspect_archive_file(file: &mut File, archive_file: &Path) -> io::Result<Option<OpenError>> {
+    file.seek(SeekFrom::Start(0))?;
+    let mut signature: Vec<u8> = Vec::with_capacity(ZIP_SIGNATURE_LENGTH);
+    file.by_ref()
+        .take(ZIP_SIGNATURE_LENGTH as u64)
+        .read_to_end(&mut signature)?;
+
+    let open_error = match find_end_of_central_directory(file)? {
+        Some(eocd) if eocd.disk_number > 0 || eocd.central_directory_disk > 0 => {
+            let last_disk = eocd.disk_number.max(eocd.central_directory_disk);
+            Some(spanned_error(archive_file, Some(u32::from(last_disk) + 1)))
+        }
+        Some(_) => None,
+        None if signature == ZIP_SPANNED_ARCHIVE_SIGNATURE => {
+            Some(spanned_error(archive_file, None))
+        }
+        None if signature.len() == ZIP_SIGNATURE_LENGTH && is_zip_signature(&signature) => {
+            Some(OpenError::Truncated)
+        }
+        None => Some(OpenError::NotAZip),
+    };
+    file.seek(SeekFrom::Start(0))?;
+    Ok(open_error)
+}
+
+/// Describes a volume of a split archive, given the number of volumes if it is known.
+fn spanned_error(archive_file: &Path, volumes: Option<u32>) -> OpenError {
+    let stem = archive_file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut names: Vec<String> = match volumes {
+        Some(volumes) => (1..volumes)
+            .map(|volume| format!("{stem}.z{volume:02}"))
+            .collect(),
+        None => vec![
+            format!("{stem}.z01"),
+            format!("{stem}.z02"),
+            "...".to_string(),
+        ],
+    };
+    names.push(format!("{stem}.zip"));
+    OpenError::Spanned {
+        archive: archive_file.to_path_buf(),
+        volumes: names,
+    }
+}
+
+/// Maps an error of the zip crate while opening an archive to an `OpenError`, where it can be
+/// classified.
+fn classify_open_error(error: ZipError) -> ZipError {
+    let open_error = match error {
+        ZipError::Io(error) if error.kind() == ErrorKind::UnexpectedEof => OpenError::Truncated,
+        ZipError::InvalidArchive(detail) => OpenError::Corrupt(detail.to_string()),
+        ZipError::UnsupportedArchive(message) if is_encryption_error(message) => {
+            OpenError::Encrypted
+        }
+        error => return error,
+    };
+    ZipError::Io(open_error.into())
+}
+
+/// Determines whether the zip crate rejected an entry because it is encrypted.
+fn is_encryption_error(message: &str) -> bool {
+    message == ZipError::PASSWORD_REQUIRED || message.contains("AES")
+}
+
+/// Determines whether an error that occurred while decompressing an entry means that its data is
+/// corrupt. The `zip` crate reports a CRC-32 mismatch as `ErrorKind::Other` with the message
+/// "Invalid checksum", and the decompressors report malformed data as `ErrorKind::InvalidData`
+/// or `ErrorKind::InvalidInput`.
+fn is_corrupt_data_error(error: &io::Error) -> bool {
+    match error.kind() {
+        ErrorKind::InvalidData | ErrorKind::InvalidInput => true,
+        ErrorKind::Other => error.to_string() == "Invalid checksum",
+        _ => false,
+    }
+}
+
+/// Opens the entry for reading, decrypting it with the password if one is given.
+fn open_entry<'a, R: Read + io::Seek>(
+    archive: &'a mut ZipArchive<R>,
+    file_number: usize,
+    password: Option<&[u8]>,
+) -> ZipResult<ZipFile<'a>> {
+    let Some(password) = password else {
+        return archive.by_index(file_number);
+    };
+    archive
+        .by_index_decrypt(file_number, password)?
+        .map_err(|_| {
+            ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!("The password for entry {file_number} is incorrect"),
+            ))
+        })
+}
+
+/// Returns the path of an entry relative to the target directory, with its `.` and `..`
+/// components resolved. Fails for entries whose path leads outside of the target directory.
+fn resolved_entry_path(next: &ZipFile<'_>) -> ZipResult<PathBuf> {
+    normalize_entry_path(next.name()).ok_or_else(|| {
+        ZipError::Io(Error::new(
+            ErrorKind::InvalidData,
+            format!(
+                "The entry {} leads outside of the target directory.",
+                next.name()
+            ),
+        ))
+    })
+}
+
+/// Returns the resolved path of an entry like `resolved_entry_path`, or `None` if it leads outside
+/// of the target directory and such entries are skipped.
+fn extracted_entry_path(
+    next: &ZipFile<'_>,
+    options: &ExtractOptions,
+) -> ZipResult<Option<PathBuf>> {
+    match resolved_entry_path(next) {
+        Ok(path) => Ok(Some(options.destination_name(&path))),
+        Err(_) if options.skip_unsafe_entries => Ok(None),
+        Err(error) => Err(error),
+    }
+}
+
+/// Determines whether an entry is skipped because it can not be extracted safely, and returns the
+/// path it is reported with, its compressed size and the reason if it is.
+fn unsafe_entry<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    file_number: usize,
+    options: &ExtractOptions,
+    collisions: &HashSet<usize>,
+) -> ZipResult<Option<(PathBuf, u64, SkipReason)>> {
+    if !options.skip_unsafe_entries {
+        return Ok(None);
+    }
+    let next: ZipFile<'_> = archive.by_index_raw(file_number)?;
+    let (name, compressed_size) = (PathBuf::from(next.name()), next.compressed_size());
+    let extracted_path = extracted_entry_path(&next, options)?;
+    drop(next);
+    let (path, reason) = match extracted_path {
+        None => (PathBuf::new(), SkipReason::PathEscape),
+        Some(path) if collisions.contains(&file_number) => (path, SkipReason::Collision),
+        Some(path) => match open_entry(archive, file_number, options.password.as_deref()) {
+            Err(ZipError::UnsupportedArchive(_)) => (path, SkipReason::Unsupported),
+            result => return result.map(|_| None),
+        },
+    };
+    let path = if path.as_os_str().is_empty() {
+        name
+    } else {
+        path
+    };
+    Ok(Some((path, compressed_size, reason)))
+}
+
+/// Extracts all entries of an archive to the given directory.
+fn extract_archive<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    target_directory: &Path,
+    options: &mut ExtractOptions,
+    cancel: Option<&AtomicBool>,
+) -> ZipResult<ExtractReport> {
+    let (directory_paths, collisions) = check_extraction(archive, target_directory, options)?;
+    let target_directory = options.extraction_root(target_directory)?;
+
+    let check_cancelled = || -> io::Result<()> {
+        match cancel {
+            Some(cancel) if cancel.load(Ordering::Relaxed) => Err(Cancelled.into()),
+            _ => Ok(()),
+        }
+    };
+
+    let mut report = ExtractReport::default();
+    let mut progress = ExtractProgress {
+        total_entries: archive.len(),
+        ..ExtractProgress::default()
+    };
+    let mut directory_modes: Vec<(PathBuf, u32)> = Vec::new();
+    let mut created_directories: Vec<PathBuf> = Vec::new();
+    let mut extracted_files: Vec<PathBuf> = Vec::new();
+
+    for file_number in 0..archive.len() {
+        check_cancelled()?;
+        if let Some(entry) = unsafe_entry(archive, file_number, options, &collisions)? {
+            options.skip_unsafe_entry(entry, &mut report, &mut progress);
+            continue;
+        }
+        let mut next: ZipFile<'_> = open_entry(archive, file_number, options.password.as_deref())?;
+        let compressed_size = next.compressed_size();
+        let sanitized_name = options.destination_name(&resolved_entry_path(&next)?);
+        options.validate_name(&sanitized_name)?;
+        let mode = options.effective_mode(next.unix_mode());
+        if next.is_dir() {
+            let extracted_folder_path = target_directory.join(sanitized_name);
+            create_directories(
+                &extracted_folder_path,
+                options.io_retries,
+                &mut created_directories,
+            )?;
+            if let Some(mode) = mode {
+                directory_modes.push((extracted_folder_path, mode));
+            }
+            report.directories_created += 1;
+        } else if next.is_file() && sanitized_name.as_os_str().is_empty() {
+            options.report_skip(Path::new(next.name()), SkipReason::EmptyName);
+            report.files_skipped += 1;
+        } else if next.is_file() && directory_paths.contains(&sanitized_name) {
+            options.report_skip(&sanitized_name, SkipReason::DirectoryConflict);
+            report.skipped_directory_conflicts.push(sanitized_name);
+        } else if next.is_file() {
+            let line_ending = options.line_ending(&sanitized_name);
+            let destination_path = target_directory.join(&sanitized_name);
+            if let Some(parent_directory) = destination_path.parent() {
+                create_directories(
+                    parent_directory,
+                    options.io_retries,
+                    &mut created_directories,
+                )?;
+            }
+            let extracted_file_path = resolve_destination(
+                destination_path,
+                options.conflict_policy,
+                (next.size(), next.crc32()),
+                &mut report,
+            )?;
+            if let Some(extracted_file_path) = extracted_file_path {
+                let temporary_path = options.temporary_path(&extracted_file_path);
+                write_through_temporary(&extracted_file_path, temporary_path.as_deref(), |path| {
+                    write_extracted_file(
+                        path,
+                        &mut next,
+                        (options.io_retries, line_ending, mode),
+                        |chunk_size| {
+                            progress.bytes_processed += chunk_size as u64;
+                            options.report_progress(&progress);
+                            check_cancelled()
+                        },
+                    )
+                })?;
+                if options.make_read_only {
+                    extracted_files.push(extracted_file_path);
+                }
+                report.files_extracted += 1;
+            } else {
+                options.report_skip(&sanitized_name, options.conflict_policy.skip_reason());
+            }
+        }
+        progress.entries_processed += 1;
+        progress.compressed_bytes_processed += compressed_size;
+        options.report_progress(&progress);
+    }
+
+    // Directory permissions are applied last, so that restrictive modes do not prevent
+    // writing the entries beneath them, and innermost first, so that they do not prevent
+    // changing the permissions of nested directories either.
+    if let Some(directory_mode) = options.directory_mode {
+        for created_directory in created_directories.iter().rev() {
+            set_unix_permissions(created_directory, directory_mode)?;
+        }
+    }
+    for (extracted_folder_path, mode) in directory_modes.iter().rev() {
+        set_unix_permissions(extracted_folder_path, *mode)?;
+    }
+    for extracted_file_path in &extracted_files {
+        set_read_only(extracted_file_path)?;
+    }
+
+    Ok(report)
+}
+
+/// Writes a file with the given function, to the temporary path if there is one, which is then
+/// renamed to the path. The temporary file is removed if writing fails.
+fn write_through_temporary<F>(
+    path: &Path,
+    temporary_path: Option<&Path>,
+    write: F,
+) -> io::Result<()>
+where
+    F: FnOnce(&Path) -> io::Result<()>,
+{
+    let result = write(temporary_path.unwrap_or(path)).and_then(|()| match temporary_path {
+        Some(temporary_path) => std::fs::rename(temporary_path, path),
+        None => Ok(()),
+    });
+    if result.is_err() {
+        if let Some(temporary_path) = temporary_path {
+            std::fs::remove_file(temporary_path).unwrap_or_default();
+        }
+    }
+    result
+}
+
+/// Writes the contents of a file entry to the given path, with the transient error retries, the
+/// line ending conversion and the Unix permissions to apply.
+fn write_extracted_file<R, F>(
+    path: &Path,
+    reader: &mut R,
+    (retries, line_ending, mode): (u32, Option<LineEnding>, Option<u32>),
+    on_chunk: F,
+) -> io::Result<()>
+where
+    R: Read,
+    F: FnMut(usize) -> io::Result<()>,
+{
+    let file = retry_transient(retries, || File::create(path))?;
+    let mut writer = LineEndingWriter::new(RetryingWriter::new(file, retries), line_ending);
+    copy_in_chunks(reader, &mut writer, on_chunk)?;
+    writer.finish()?;
+    if let Some(mode) = mode {
+        set_unix_permissions(path, mode)?;
+    }
+    Ok(())
+}
+
+/// Checks an archive and the target directory before anything is extracted, and returns the
+/// paths at which directories are extracted and the numbers of the colliding entries that are
+/// skipped.
+fn check_extraction<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    target_directory: &Path,
+    options: &ExtractOptions,
+) -> ZipResult<(HashSet<PathBuf>, HashSet<usize>)> {
+    if !target_directory.is_dir() {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            "The specified path does not indicate a valid directory path.",
+        )));
+    }
+
+    if let (true, Some(temp_dir)) = (options.atomic_writes, &options.temp_dir) {
+        if !same_file_system(temp_dir, target_directory)? {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "The temporary directory {} is not on the same file system as {}.",
+                    temp_dir.display(),
+                    target_directory.display()
+                ),
+            )));
+        }
+    }
+
+    if let Some(max_entries) = options.max_entries {
+        if archive.len() > max_entries {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidData,
+                format!(
+                    "The archive declares {} entries, which exceeds the limit of {}.",
+                    archive.len(),
+                    max_entries
+                ),
+            )));
+        }
+    }
+
+    let mut skipped_collisions = HashSet::new();
+    if options.reject_collisions || options.lowercase_paths {
+        let collisions = destination_collisions(archive, options, !options.reject_collisions)?;
+        if options.skip_unsafe_entries {
+            skipped_collisions.extend(collisions.into_iter().flat_map(|(_, numbers)| numbers));
+        } else if !collisions.is_empty() {
+            return Err(ZipError::Io(DestinationCollisions { collisions }.into()));
+        }
+    }
+
+    Ok((directory_paths(archive, options)?, skipped_collisions))
+}
+
+/// Finds entries that would be extracted to the same path with the given options, grouped by
+/// that path and sorted by it. Groups that only consist of directory entries are not
+/// collisions, and neither are groups whose entries have the same sanitized path if
+/// `renamed_only` is set, so that only the collisions caused by `ExtractOptions::lowercase_paths`
+/// are found.
+fn destination_collisions<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    options: &ExtractOptions,
+    renamed_only: bool,
+) -> ZipResult<Vec<DestinationCollision>> {
+    let mut destinations: BTreeMap<PathBuf, (Vec<usize>, bool, HashSet<PathBuf>)> = BTreeMap::new();
+    for file_number in 0..archive.len() {
+        let next: ZipFile<'_> = archive.by_index_raw(file_number)?;
+        let sanitized_name = match resolved_entry_path(&next) {
+            Ok(sanitized_name) => sanitized_name,
+            Err(_) if options.skip_unsafe_entries => continue,
+            Err(error) => return Err(error),
+        };
+        let (file_numbers, has_file, names) = destinations
+            .entry(options.destination_name(&sanitized_name))
+            .or_default();
+        file_numbers.push(file_number);
+        *has_file |= !next.is_dir();
+        names.insert(sanitized_name);
+    }
+    Ok(destinations
+        .into_iter()
+        .filter(|(_, (file_numbers, has_file, names))| {
+            *has_file && file_numbers.len() > 1 && (!renamed_only || names.len() > 1)
+        })
+        .map(|(destination, (file_numbers, ..))| (destination, file_numbers))
+        .collect())
+}
+
+/// Creates a directory and its missing parents, retrying transient errors, and appends the
+/// directories that did not exist before to `created`, outermost first.
+fn create_directories(path: &Path, retries: u32, created: &mut Vec<PathBuf>) -> io::Result<()> {
+    let missing: Vec<PathBuf> = path
+        .ancestors()
+        .take_while(|ancestor| !ancestor.as_os_str().is_empty() && !ancestor.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    retry_transient(retries, || std::fs::create_dir_all(path))?;
+    created.extend(missing.into_iter().rev());
+    Ok(())
+}
+
+/// Returns the path to which a file entry is extracted according to the conflict policy, or
+/// `None` if the entry is skipped.
+fn resolve_destination(
+    destination_path: PathBuf,
+    policy: ConflictPolicy,
+    (size, crc32): (u64, u32),
+    report: &mut ExtractReport,
+) -> ZipResult<Option<PathBuf>> {
+    match policy {
+        _ if !destination_path.exists() => Ok(Some(destination_path)),
+        ConflictPolicy::Overwrite => Ok(Some(destination_path)),
+        ConflictPolicy::Skip => {
+            report.files_skipped += 1;
+            Ok(None)
+        }
+        ConflictPolicy::Fail => Err(ZipError::Io(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("The file {} already exists.", destination_path.display()),
+        ))),
+        ConflictPolicy::Rename => {
+            let renamed_path = free_numbered_path(&destination_path);
+            report
+                .renamed
+                .push((destination_path, renamed_path.clone()));
+            Ok(Some(renamed_path))
+        }
+        ConflictPolicy::SkipUnchanged => {
+            let unchanged = std::fs::metadata(&destination_path)?.len() == size
+                && file_crc32(&destination_path)? == crc32;
+            if unchanged {
+                report.files_skipped += 1;
+                Ok(None)
+            } else {
+                Ok(Some(destination_path))
+            }
+        }
+    }
+}
+
+/// Returns the sanitized paths at which the entries of an archive need a directory: those of
+/// directory entries and the parent directories of all entries. Fails if a file entry is at one
+/// of these paths and such conflicts are not allowed.
+fn directory_paths<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    options: &ExtractOptions,
+) -> ZipResult<HashSet<PathBuf>> {
+    let mut directory_paths: HashSet<PathBuf> = HashSet::new();
+    for file_number in 0..archive.len() {
+        let next: ZipFile<'_> = archive.by_index_raw(file_number)?;
+        let Some(sanitized_name) = extracted_entry_path(&next, options)? else {
+            continue;
+        };
+        for ancestor in sanitized_name.ancestors().skip(1) {
+            if ancestor.as_os_str().is_empty() || !directory_paths.insert(ancestor.to_path_buf()) {
+                break;
+            }
+        }
+        if next.is_dir() {
+            directory_paths.insert(sanitized_name);
+        }
+    }
+
+    if options.directory_conflicts == DirectoryConflictPolicy::Fail {
+        for file_number in 0..archive.len() {
+            let next: ZipFile<'_> = archive.by_index_raw(file_number)?;
+            let Some(sanitized_name) = extracted_entry_path(&next, options)? else {
+                continue;
+            };
+            if next.is_file() && directory_paths.contains(&sanitized_name) {
+                return Err(ZipError::Io(Error::new(
+                    ErrorKind::InvalidData,
+                    format!(
+                        "The entry {} is a file, but other entries need a directory at its path.",
+                        sanitized_name.display()
+                    ),
+                )));
+            }
+        }
+    }
+    Ok(directory_paths)
+}
+
+/// Returns the first path that does not exist, formed by appending ` (1)`, ` (2)`, and so on to
+/// the file stem of the given path.
+fn free_numbered_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (1..=u32::MAX)
+        .map(|number| path.with_file_name(format!("{stem} ({number}){extension}")))
+        .find(|candidate| !candidate.exists())
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::File;
+    use std::io::Write;
+    use std::path::Path;
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use crate::test_utils::TestDir;
+    use crate::{zip_kind, ZipKind};
+
+    type Entries<'a> = &'a [(&'a str, &'a [u8])];
+
+    fn create_archive(archive_file: &Path, entries: Entries<'_>) {
+        let mut zip_writer = ZipWriter::new(File::create(archive_file).unwrap());
+        for (name, content) in entries {
+            zip_writer
+                .start_file(*name, FileOptions::default())
+                .unwrap();
+            zip_writer.write_all(content).unwrap();
+        }
+        zip_writer.finish().unwrap();
+    }
+
+    #[test]
+    fn portable_name_validator_rejects_windows_pitfalls() {
+        use crate::portable_name_validator;
+
+        assert!(portable_name_validator(Path::new("docs/readme.txt")).is_ok());
+        assert!(portable_name_validator(Path::new(".gitignore")).is_ok());
+        assert!(portable_name_validator(Path::new("docs/CON")).is_err());
+        assert!(portable_name_validator(Path::new("nul.txt")).is_err());
+        assert!(portable_name_validator(Path::new("Com1.log")).is_err());
+        assert!(portable_name_validator(Path::new("docs./readme.txt")).is_err());
+        assert!(portable_name_validator(Path::new("readme.txt ")).is_err());
+        assert!(portable_name_validator(Path::new("bell\u{7}.txt")).is_err());
+    }
+
+    #[test]
+    fn empty_archives_are_recognized_and_extract_as_no_op() {
+        use crate::{is_empty_zip, try_is_zip, zip_extract_with_options};
+        use crate::{ExtractOptions, ExtractReport};
+
+        let test_dir = TestDir::new("empty-archive");
+        let archive_file = test_dir.path().join("empty.zip");
+        create_archive(&archive_file, &[]);
+
+        assert!(try_is_zip(&archive_file).unwrap());
+        assert!(is_empty_zip(&archive_file).unwrap());
+
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir_all(&target_dir).unwrap();
+        let report =
+            zip_extract_with_options(&archive_file, &target_dir, ExtractOptions::default())
+                .unwrap();
+        assert_eq!(report, ExtractReport::default());
+        assert_eq!(std::fs::read_dir(&target_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn extract_with_cancel_stops_when_flag_is_set() {
+        use std::sync::atomic::AtomicBool;
+
+        use zip::ZipArchive;
+
+        use crate::{Cancelled, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("extract-cancel");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(&archive_file, &[("a.txt", b"alpha"), ("b.txt", b"beta")]);
+
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir_all(&target_dir).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let cancel = AtomicBool::new(true);
+        let error = archive
+            .extract_with_cancel(&target_dir, &cancel)
+            .unwrap_err();
+
+        let is_cancelled = match error {
+            zip::result::ZipError::Io(error) => error
+                .get_ref()
+                .and_then(|inner| inner.downcast_ref::<Cancelled>())
+                .is_some(),
+            _ => false,
+        };
+        assert!(is_cancelled);
+        assert!(!target_dir.join("a.txt").exists());
+    }
+
+    #[test]
+    fn zip_data_offset_detects_prepended_data() {
+        use std::io::Cursor;
+
+        use crate::{
+            is_zip, is_zip_lenient, zip_data_offset, zip_extract, zip_extract_from_offset,
+            zip_extract_index_to_memory,
+        };
+
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        zip_writer
+            .start_file("a.txt", FileOptions::default())
+            .unwrap();
+        zip_writer.write_all(b"alpha").unwrap();
+        let zip_data = zip_writer.finish().unwrap().into_inner();
+
+        let test_dir = TestDir::new("data-offset");
+        let plain_archive = test_dir.path().join("plain.zip");
+        std::fs::write(&plain_archive, &zip_data).unwrap();
+        let stub = b"MZ self-extractor stub";
+        let sfx_archive = test_dir.path().join("sfx.exe");
+        std::fs::write(&sfx_archive, [stub.as_slice(), &zip_data].concat()).unwrap();
+
+        assert_eq!(zip_data_offset(&plain_archive).unwrap(), Some(0));
+        assert_eq!(
+            zip_data_offset(&sfx_archive).unwrap(),
+            Some(stub.len() as u64)
+        );
+        assert!(!is_zip(&sfx_archive));
+        assert!(is_zip_lenient(&sfx_archive));
+        assert_eq!(
+            zip_extract_index_to_memory(&sfx_archive, 0).unwrap(),
+            b"alpha"
+        );
+
+        let target_dir = test_dir.path().join("extracted");
+        zip_extract(&sfx_archive, &target_dir).unwrap();
+        assert_eq!(std::fs::read(target_dir.join("a.txt")).unwrap(), b"alpha");
+        let target_dir = test_dir.path().join("extracted-from-offset");
+        zip_extract_from_offset(&sfx_archive, stub.len() as u64, &target_dir).unwrap();
+        assert_eq!(std::fs::read(target_dir.join("a.txt")).unwrap(), b"alpha");
+        assert!(zip_extract_from_offset(&sfx_archive, 4096, &target_dir).is_err());
+    }
+
+    #[test]
+    fn files_at_directory_paths_are_skipped_or_rejected() {
+        use std::io::ErrorKind;
+        use std::path::PathBuf;
+
+        use crate::{zip_extract_with_options, DirectoryConflictPolicy, ExtractOptions};
+
+        let test_dir = TestDir::new("directory-conflicts");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_file,
+            &[
+                ("foo", b"file"),
+                ("foo/bar.txt", b"bar"),
+                ("baz/", b""),
+                ("baz", b"file"),
+                ("qux.txt", b"qux"),
+            ],
+        );
+
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        let report =
+            zip_extract_with_options(&archive_file, &target_dir, ExtractOptions::default())
+                .unwrap();
+        assert_eq!(
+            report.skipped_directory_conflicts,
+            [PathBuf::from("foo"), PathBuf::from("baz")]
+        );
+        assert_eq!(report.files_extracted, 2);
+        assert_eq!(
+            std::fs::read(target_dir.join("foo/bar.txt")).unwrap(),
+            b"bar"
+        );
+        assert!(target_dir.join("baz").is_dir());
+
+        let failing_dir = test_dir.path().join("failing");
+        std::fs::create_dir(&failing_dir).unwrap();
+        let options = ExtractOptions::default().directory_conflicts(DirectoryConflictPolicy::Fail);
+        let error = zip_extract_with_options(&archive_file, &failing_dir, options).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert_eq!(std::fs::read_dir(&failing_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn split_archives_are_detected_and_rejected() {
+        use std::io::ErrorKind;
+
+        use crate::{is_split_archive, zip_extract_file_to_memory};
+
+        let test_dir = TestDir::new("split-archive");
+        let plain_archive = test_dir.path().join("plain.zip");
+        create_archive(&plain_archive, &[("a.txt", b"alpha")]);
+        assert!(!is_split_archive(&plain_archive).unwrap());
+
+        // The end-of-central-directory record of the third volume of a split archive.
+        let mut last_volume = vec![0x50, 0x4b, 0x05, 0x06, 2, 0, 2, 0];
+        last_volume.extend_from_slice(&[0; 14]);
+        let split_archive = test_dir.path().join("backup.zip");
+        std::fs::write(&split_archive, last_volume).unwrap();
+        assert!(is_split_archive(&split_archive).unwrap());
+
+        let mut buffer = Vec::new();
+        let error = zip_extract_file_to_memory(&split_archive, Path::new("a.txt"), &mut buffer)
+            .unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert!(error
+            .to_string()
+            .contains("backup.z01, backup.z02, backup.zip"));
+    }
+
+    #[test]
+    fn open_failures_are_classified() {
+        use crate::{zip_open, OpenError};
+
+        let test_dir = TestDir::new("open-errors");
+        let not_a_zip = test_dir.path().join("notes.zip");
+        std::fs::write(&not_a_zip, b"just some text").unwrap();
+        let error = zip_open(&not_a_zip).unwrap_err();
+        assert_eq!(OpenError::from_zip_error(&error), Some(&OpenError::NotAZip));
+
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(&archive_file, &[("a.txt", b"alpha")]);
+        assert_eq!(zip_open(&archive_file).unwrap().len(), 1);
+
+        let mut bytes = std::fs::read(&archive_file).unwrap();
+        let truncated = test_dir.path().join("truncated.zip");
+        std::fs::write(&truncated, &bytes[..bytes.len() / 2]).unwrap();
+        let error = zip_open(&truncated).unwrap_err();
+        assert_eq!(
+            OpenError::from_zip_error(&error),
+            Some(&OpenError::Truncated)
+        );
+
+        // Declare the unknown compression method 99 in the local and the central header.
+        bytes[8..10].copy_from_slice(&99u16.to_le_bytes());
+        let central_header = bytes
+            .windows(4)
+            .position(|window| window == [0x50, 0x4b, 0x01, 0x02])
+            .unwrap();
+        bytes[central_header + 10..central_header + 12].copy_from_slice(&99u16.to_le_bytes());
+        let unsupported = test_dir.path().join("unsupported.zip");
+        std::fs::write(&unsupported, bytes).unwrap();
+        let error = zip_open(&unsupported).unwrap_err();
+        assert!(matches!(
+            OpenError::from_zip_error(&error),
+            Some(OpenError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn is_valid_zip_requires_a_central_directory() {
+        use crate::{is_valid_zip, is_zip};
+
+        let test_dir = TestDir::new("valid-zip");
+        let valid_archive = test_dir.path().join("valid.zip");
+        create_archive(&valid_archive, &[("a.txt", b"alpha")]);
+        let fake_archive = test_dir.path().join("fake.zip");
+        std::fs::write(&fake_archive, b"PK\x03\x04 but nothing else").unwrap();
+
+        assert!(is_valid_zip(&valid_archive).unwrap());
+        assert!(is_zip(&fake_archive));
+        assert!(!is_valid_zip(&fake_archive).unwrap());
+        assert!(is_valid_zip(test_dir.path().join("missing.zip")).is_err());
+    }
+
+    #[test]
+    fn extract_directories_only_creates_tree_without_files() {
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("directories-only");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[("docs/", b""), ("src/bin/main.rs", b"fn main() {}")],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        archive.extract_directories_only(&target_dir).unwrap();
+
+        assert!(target_dir.join("docs").is_dir());
+        assert!(target_dir.join("src/bin").is_dir());
+        assert!(!target_dir.join("src/bin/main.rs").exists());
+    }
+
+    #[test]
+    fn detect_archive_kind_reader_recognizes_magic_bytes() {
+        use std::io::Cursor;
+
+        use crate::{detect_archive_kind_reader, ArchiveKind};
+
+        let detect = |bytes: &[u8]| detect_archive_kind_reader(&mut Cursor::new(bytes)).unwrap();
+        let mut tar_header = vec![0; 512];
+        tar_header[257..263].copy_from_slice(b"ustar\0");
+
+        assert_eq!(detect(b"PK\x03\x04rest"), ArchiveKind::Zip);
+        assert_eq!(detect(&[0x1f, 0x8b, 0x08]), ArchiveKind::Gzip);
+        assert_eq!(detect(&tar_header), ArchiveKind::Tar);
+        assert_eq!(
+            detect(&[0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c, 0x00]),
+            ArchiveKind::SevenZip
+        );
+        assert_eq!(detect(b"Rar!\x1a\x07\x01\x00"), ArchiveKind::Rar);
+        assert_eq!(
+            detect(&[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+            ArchiveKind::Xz
+        );
+        assert_eq!(detect(&[0x28, 0xb5, 0x2f, 0xfd]), ArchiveKind::Zstd);
+        assert_eq!(detect(b""), ArchiveKind::Unknown);
+        assert_eq!(detect(b"PK"), ArchiveKind::Unknown);
+        assert_eq!(detect(&tar_header[..260]), ArchiveKind::Unknown);
+    }
+
+    #[test]
+    fn reject_collisions_fails_before_writing() {
+        use crate::{DestinationCollisions, ExtractOptions, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("collisions");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("docs/", b""),
+                ("a.txt", b"first"),
+                ("docs/", b""),
+                ("./a.txt", b"second"),
+            ],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let expected = vec![(Path::new("a.txt").to_path_buf(), vec![1, 3])];
+        assert_eq!(archive.find_collisions().unwrap(), expected);
+
+        let options = ExtractOptions::default().reject_collisions(true);
+        let error = archive
+            .extract_with_options(&target_dir, options)
+            .unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        let collisions = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<DestinationCollisions>());
+        assert_eq!(collisions.map(|c| &c.collisions), Some(&expected));
+        assert!(!target_dir.join("a.txt").exists());
+    }
+
+    #[test]
+    fn lowercase_paths_extracts_lowercased_and_detects_case_collisions() {
+        use crate::{DestinationCollisions, ExtractOptions, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("lowercase-paths");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("Docs/", b""),
+                ("Docs/ReadMe.TXT", b"hello"),
+                ("docs/", b""),
+            ],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let options = ExtractOptions::default().lowercase_paths(true);
+        archive.extract_with_options(&target_dir, options).unwrap();
+        let names: Vec<_> = std::fs::read_dir(&target_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["docs"]);
+        assert_eq!(
+            std::fs::read(target_dir.join("docs/readme.txt")).unwrap(),
+            b"hello"
+        );
+
+        let archive_path = test_dir.path().join("colliding.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("Notes.txt", b"upper"),
+                ("notes.txt", b"lower"),
+                ("a.txt", b""),
+            ],
+        );
+        let target_dir = test_dir.path().join("colliding");
+        std::fs::create_dir(&target_dir).unwrap();
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let options = ExtractOptions::default().lowercase_paths(true);
+        let error = archive
+            .extract_with_options(&target_dir, options)
+            .unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        let collisions = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<DestinationCollisions>());
+        let expected = vec![(Path::new("notes.txt").to_path_buf(), vec![0, 1])];
+        assert_eq!(collisions.map(|c| &c.collisions), Some(&expected));
+        assert!(!target_dir.join("a.txt").exists());
+    }
+
+    #[test]
+    fn zip_extract_file_to_writer_streams_entry() {
+        use crate::zip_extract_file_to_writer;
+
+        let test_dir = TestDir::new("to-writer");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(&archive_path, &[("docs/readme.txt", b"hello")]);
+
+        let mut output: Vec<u8> = Vec::new();
+        let written =
+            zip_extract_file_to_writer(&archive_path, Path::new("docs/readme.txt"), &mut output)
+                .unwrap();
+        assert_eq!(written, 5);
+        assert_eq!(output, b"hello");
+        assert!(matches!(
+            zip_extract_file_to_writer(&archive_path, Path::new("missing.txt"), &mut output),
+            Err(zip::result::ZipError::FileNotFound)
+        ));
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn compression_breakdown_groups_entries_by_method() {
+        use std::io::Cursor;
+
+        use zip::{CompressionMethod, ZipArchive};
+
+        use crate::{MethodStats, ZipArchiveExtensions};
+
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let entries = [
+            ("a.txt", CompressionMethod::Deflated, vec![b'a'; 1000]),
+            ("b.txt", CompressionMethod::Stored, vec![b'b'; 100]),
+            ("c.txt", CompressionMethod::Deflated, vec![b'c'; 2000]),
+        ];
+        for (name, method, content) in &entries {
+            let options = FileOptions::default().compression_method(*method);
+            zip_writer.start_file(*name, options).unwrap();
+            zip_writer.write_all(content).unwrap();
+        }
+        let mut archive = ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+        let compressed_size = |archive: &mut ZipArchive<_>, file_number| {
+            archive.entry_info(file_number).unwrap().compressed_size
+        };
+        let deflated_size = compressed_size(&mut archive, 0) + compressed_size(&mut archive, 2);
+        assert!(deflated_size < 3000);
+
+        assert_eq!(
+            archive.compression_breakdown().unwrap(),
+            [
+                (
+                    CompressionMethod::Deflated,
+                    MethodStats {
+                        entries: 2,
+                        compressed_size: deflated_size,
+                        uncompressed_size: 3000,
+                        supported: true,
+                    }
+                ),
+                (
+                    CompressionMethod::Stored,
+                    MethodStats {
+                        entries: 1,
+                        compressed_size: 100,
+                        uncompressed_size: 100,
+                        supported: true,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn find_entries_regex_matches_entry_paths() {
+        use std::io::ErrorKind;
+        use std::path::PathBuf;
+
+        use zip::result::ZipError;
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("find-regex");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_file,
+            &[
+                ("src/lib.rs", b""),
+                ("src/read.rs", b""),
+                ("README.md", b""),
+                ("tests/src/main.rs", b""),
+            ],
+        );
+        let mut archive = zip::ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(
+            archive.find_entries_regex(r"^src/.*\.rs$").unwrap(),
+            [
+                (0, PathBuf::from("src/lib.rs")),
+                (1, PathBuf::from("src/read.rs"))
+            ]
+        );
+        assert!(archive.find_entries_regex(r"\.toml$").unwrap().is_empty());
+
+        let error = archive.find_entries_regex("src/(").unwrap_err();
+        assert!(matches!(error, ZipError::Io(error) if error.kind() == ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn list_entries_sorted_orders_by_path() {
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("sorted-entries");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("b.txt", b""),
+                ("a-b.txt", b""),
+                ("a/c.txt", b""),
+                ("a/", b""),
+            ],
+        );
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let stored: Vec<usize> = archive
+            .list_entries()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.index)
+            .collect();
+        assert_eq!(stored, [0, 1, 2, 3]);
+        let sorted: Vec<usize> = archive
+            .list_entries_sorted()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.index)
+            .collect();
+        assert_eq!(sorted, [3, 2, 1, 0]);
+    }
+
+    #[test]
+    fn extract_until_bytes_resumes_where_the_budget_ends() {
+        use std::io::ErrorKind;
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("byte-budget");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("a.txt", b"0123456789"),
+                ("b/", b""),
+                ("b/c.txt", b"0123456789"),
+                ("d.txt", b"0123456789"),
+            ],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        assert_eq!(archive.extract_until_bytes(&target_dir, 25, 0).unwrap(), 3);
+        assert!(target_dir.join("b/c.txt").is_file());
+        assert!(!target_dir.join("d.txt").exists());
+        assert_eq!(archive.extract_until_bytes(&target_dir, 25, 3).unwrap(), 4);
+        assert!(target_dir.join("d.txt").is_file());
+        assert_eq!(archive.extract_until_bytes(&target_dir, 25, 4).unwrap(), 4);
+
+        let zip::result::ZipError::Io(error) =
+            archive.extract_until_bytes(&target_dir, 5, 0).unwrap_err()
+        else {
+            panic!("expected an io error");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn entry_data_offset_points_at_stored_bytes() {
+        use std::io::{Read, Seek, SeekFrom};
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("entry-data-offset");
+        let archive_path = test_dir.path().join("archive.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&archive_path).unwrap());
+        let options = FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        for (name, content) in [("a.txt", b"alpha".as_slice()), ("b.txt", b"beta")] {
+            zip_writer.start_file(name, options).unwrap();
+            zip_writer.write_all(content).unwrap();
+        }
+        zip_writer.finish().unwrap();
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let offset = archive.entry_data_offset(1).unwrap();
+        let mut file = File::open(&archive_path).unwrap();
+        file.seek(SeekFrom::Start(offset)).unwrap();
+        let mut data = [0; 4];
+        file.read_exact(&mut data).unwrap();
+        assert_eq!(&data, b"beta");
+    }
+
+    #[test]
+    fn entries_iterate_in_stored_order() {
+        use std::path::PathBuf;
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("entry-iterator");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[("b.txt", b"beta"), ("a/", b""), ("a/c.txt", b"gamma")],
+        );
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let files: Vec<PathBuf> = archive
+            .entries()
+            .filter_map(Result::ok)
+            .filter(|entry| !entry.is_dir)
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(files, [PathBuf::from("b.txt"), PathBuf::from("a/c.txt")]);
+
+        let mut contents = Vec::new();
+        archive
+            .for_each_entry(|entry, reader| {
+                let mut content = String::new();
+                reader.read_to_string(&mut content)?;
+                contents.push((entry.index, content));
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            contents,
+            [
+                (0, "beta".to_string()),
+                (1, String::new()),
+                (2, "gamma".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn zip_verify_against_manifest_reports_differences() {
+        use std::collections::HashMap;
+        use std::path::PathBuf;
+
+        use crate::{zip_verify_against_manifest, CrcMismatch, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("manifest");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[("a.txt", b"alpha"), ("b.txt", b"beta"), ("c.txt", b"gamma")],
+        );
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let crc_a = archive.entry_info(0).unwrap().crc32;
+
+        let manifest: HashMap<PathBuf, u32> = [
+            (PathBuf::from("a.txt"), crc_a),
+            (PathBuf::from("b.txt"), 0),
+            (PathBuf::from("d.txt"), 0),
+        ]
+        .into_iter()
+        .collect();
+        let report = zip_verify_against_manifest(&archive_path, &manifest).unwrap();
+
+        assert!(!report.is_match());
+        let expected_mismatch = CrcMismatch {
+            path: PathBuf::from("b.txt"),
+            expected: 0,
+            actual: archive.entry_info(1).unwrap().crc32,
+        };
+        assert_eq!(report.mismatched, [expected_mismatch]);
+        assert!(report.corrupted.is_empty());
+        assert_eq!(report.missing, [PathBuf::from("d.txt")]);
+        assert_eq!(report.extra, [PathBuf::from("c.txt")]);
+    }
+
+    #[test]
+    fn zip_verify_against_manifest_reports_corrupted_entries() {
+        use std::collections::HashMap;
+        use std::path::PathBuf;
+
+        use crate::{zip_verify_against_manifest, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("manifest-corrupted");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(&archive_path, &[("a.txt", b"alpha"), ("b.txt", b"beta")]);
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let manifest: HashMap<PathBuf, u32> = (0..2)
+            .map(|file_number| {
+                let info = archive.entry_info(file_number).unwrap();
+                (info.path, info.crc32)
+            })
+            .collect();
+        drop(archive);
+
+        let mut bytes = std::fs::read(&archive_path).unwrap();
+        let position = bytes
+            .windows(5)
+            .position(|window| window == b"alpha")
+            .unwrap();
+        bytes[position] = b'A';
+        std::fs::write(&archive_path, bytes).unwrap();
+
+        let report = zip_verify_against_manifest(&archive_path, &manifest).unwrap();
+        assert!(!report.is_match());
+        assert!(report.mismatched.is_empty());
+        assert_eq!(report.corrupted, [PathBuf::from("a.txt")]);
+        assert!(report.missing.is_empty());
+        assert!(report.extra.is_empty());
+    }
+
+    #[test]
+    fn zip_diff_reports_added_removed_and_changed_entries() {
+        use std::path::PathBuf;
+
+        use crate::{zip_diff, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("diff");
+        let archive_a = test_dir.path().join("a.zip");
+        let archive_b = test_dir.path().join("b.zip");
+        create_archive(
+            &archive_a,
+            &[
+                ("same.txt", b"same"),
+                ("changed.txt", b"old"),
+                ("removed.txt", b"gone"),
+            ],
+        );
+        create_archive(
+            &archive_b,
+            &[
+                ("added.txt", b"new"),
+                ("changed.txt", b"newer"),
+                ("same.txt", b"same"),
+            ],
+        );
+
+        assert!(zip_diff(&archive_a, &archive_a).unwrap().is_empty());
+        let diff = zip_diff(&archive_a, &archive_b).unwrap();
+        assert!(!diff.is_empty());
+        assert_eq!(diff.only_in_a, [PathBuf::from("removed.txt")]);
+        assert_eq!(diff.only_in_b, [PathBuf::from("added.txt")]);
+        let crc32 = |archive_file: &PathBuf, file_number: usize| {
+            let mut archive = zip::ZipArchive::new(File::open(archive_file).unwrap()).unwrap();
+            archive.entry_info(file_number).unwrap().crc32
+        };
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].path, PathBuf::from("changed.txt"));
+        assert_eq!((diff.changed[0].size_a, diff.changed[0].size_b), (3, 5));
+        assert_eq!(diff.changed[0].crc32_a, crc32(&archive_a, 1));
+        assert_eq!(diff.changed[0].crc32_b, crc32(&archive_b, 1));
+    }
+
+    #[test]
+    fn zip_extract_verified_extracts_only_verified_archives() {
+        use std::io::ErrorKind;
+
+        use zip::result::ZipError;
+
+        use crate::zip_extract_verified;
+
+        let test_dir = TestDir::new("extract-verified");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(&archive_path, &[("a.txt", b"alpha")]);
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let error = zip_extract_verified(&archive_path, &target_dir, |path| {
+            assert_eq!(path, archive_path);
+            Err("bad signature".to_string())
+        })
+        .unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+        assert!(error.to_string().contains("bad signature"));
+        assert_eq!(std::fs::read_dir(&target_dir).unwrap().count(), 0);
+
+        zip_extract_verified(&archive_path, &target_dir, |_| Ok(())).unwrap();
+        assert_eq!(std::fs::read(target_dir.join("a.txt")).unwrap(), b"alpha");
+    }
+
+    #[test]
+    fn encrypted_entries_are_extracted_with_the_password() {
+        use std::io::{Cursor, ErrorKind};
+
+        use zip::result::ZipError;
+
+        use crate::{ExtractOptions, ZipArchiveExtensions};
+
+        // `a.txt` containing "secret contents", encrypted with ZipCrypto and the password
+        // "hunter2" by Info-ZIP.
+        const ENCRYPTED_ARCHIVE: [u8; 151] = [
+            0x50, 0x4b, 0x03, 0x04, 0x0a, 0x00, 0x09, 0x00, 0x00, 0x00, 0x66, 0x6c, 0x4e, 0x5d,
+            0xa8, 0xa8, 0xcc, 0x3e, 0x1b, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x05, 0x00,
+            0x00, 0x00, 0x61, 0x2e, 0x74, 0x78, 0x74, 0x99, 0xa4, 0xe6, 0x03, 0x33, 0xda, 0xde,
+            0x38, 0x43, 0x18, 0x5b, 0xd8, 0xd1, 0x41, 0xd6, 0xa0, 0x56, 0x7a, 0xfb, 0x70, 0x17,
+            0x2e, 0x26, 0x0f, 0x12, 0xde, 0xaa, 0x50, 0x4b, 0x07, 0x08, 0xa8, 0xa8, 0xcc, 0x3e,
+            0x1b, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x50, 0x4b, 0x01, 0x02, 0x1e, 0x03,
+            0x0a, 0x00, 0x09, 0x00, 0x00, 0x00, 0x66, 0x6c, 0x4e, 0x5d, 0xa8, 0xa8, 0xcc, 0x3e,
+            0x1b, 0x00, 0x00, 0x00, 0x0f, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x00, 0x00, 0x61, 0x2e,
+            0x74, 0x78, 0x74, 0x50, 0x4b, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01,
+            0x00, 0x33, 0x00, 0x00, 0x00, 0x4e, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+
+        let test_dir = TestDir::new("extract-password");
+        let extract = |options: ExtractOptions| {
+            let mut archive = zip::ZipArchive::new(Cursor::new(ENCRYPTED_ARCHIVE)).unwrap();
+            archive.extract_with_options(test_dir.path(), options)
+        };
+
+        let error = extract(ExtractOptions::default()).unwrap_err();
+        assert!(matches!(
+            error,
+            ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)
+        ));
+        let error = extract(ExtractOptions::default().password(b"wrong")).unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        extract(ExtractOptions::default().password(b"hunter2")).unwrap();
+        assert_eq!(
+            std::fs::read(test_dir.path().join("a.txt")).unwrap(),
+            b"secret contents"
+        );
+    }
+
+    #[test]
+    fn extract_if_crc_differs_rewrites_only_changed_files() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("extract-crc");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("same.txt", b"alpha"),
+                ("changed.txt", b"beta"),
+                ("new.txt", b"gamma"),
+            ],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        std::fs::write(target_dir.join("same.txt"), b"alpha").unwrap();
+        std::fs::write(target_dir.join("changed.txt"), b"BETA").unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+        for name in ["same.txt", "changed.txt"] {
+            let file = File::options()
+                .write(true)
+                .open(target_dir.join(name))
+                .unwrap();
+            file.set_modified(modified).unwrap();
+        }
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let report = archive.extract_if_crc_differs(&target_dir).unwrap();
+        assert_eq!(report.files_extracted, 2);
+        assert_eq!(report.files_skipped, 1);
+        assert_eq!(
+            std::fs::read(target_dir.join("changed.txt")).unwrap(),
+            b"beta"
+        );
+        assert_eq!(std::fs::read(target_dir.join("new.txt")).unwrap(), b"gamma");
+        let same_metadata = std::fs::metadata(target_dir.join("same.txt")).unwrap();
+        assert_eq!(same_metadata.modified().unwrap(), modified);
+    }
+
+    #[test]
+    fn conflict_policy_rename_appends_a_number() {
+        use crate::{zip_extract_with_options, ConflictPolicy, ExtractOptions};
+
+        let test_dir = TestDir::new("conflict-rename");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[("report.pdf", b"new"), ("notes.txt", b"new")],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        std::fs::write(target_dir.join("report.pdf"), b"old").unwrap();
+        std::fs::write(target_dir.join("report (1).pdf"), b"old").unwrap();
+        std::fs::write(target_dir.join("notes.txt"), b"old").unwrap();
+
+        let options = ExtractOptions::default().conflict_policy(ConflictPolicy::Rename);
+        let report = zip_extract_with_options(&archive_path, &target_dir, options).unwrap();
+
+        let renamed_report = target_dir.join("report (2).pdf");
+        let renamed_notes = target_dir.join("notes (1).txt");
+        assert_eq!(
+            report.renamed,
+            [
+                (target_dir.join("report.pdf"), renamed_report.clone()),
+                (target_dir.join("notes.txt"), renamed_notes.clone())
+            ]
+        );
+        assert_eq!(std::fs::read(renamed_report).unwrap(), b"new");
+        assert_eq!(std::fs::read(renamed_notes).unwrap(), b"new");
+        assert_eq!(
+            std::fs::read(target_dir.join("report.pdf")).unwrap(),
+            b"old"
+        );
+
+        let options = ExtractOptions::default().conflict_policy(ConflictPolicy::Skip);
+        let report = zip_extract_with_options(&archive_path, &target_dir, options).unwrap();
+        assert_eq!((report.files_extracted, report.files_skipped), (0, 2));
+    }
+
+    #[test]
+    fn zip_kind_detects_document_formats() {
+        let test_dir = TestDir::new("zip-kind");
+        let cases: [(&str, Entries<'_>, ZipKind); 6] = [
+            ("plain.zip", &[("readme.txt", b"hello")], ZipKind::PlainZip),
+            (
+                "library.jar",
+                &[("META-INF/MANIFEST.MF", b"")],
+                ZipKind::Jar,
+            ),
+            (
+                "app.apk",
+                &[("META-INF/MANIFEST.MF", b""), ("AndroidManifest.xml", b"")],
+                ZipKind::Apk,
+            ),
+            (
+                "document.docx",
+                &[("[Content_Types].xml", b""), ("word/document.xml", b"")],
+                ZipKind::OfficeOpenXml,
+            ),
+            (
+                "book.epub",
+                &[
+                    ("mimetype", b"application/epub+zip"),
+                    ("META-INF/MANIFEST.MF", b""),
+                ],
+                ZipKind::Epub,
+            ),
+            (
+                "text.odt",
+                &[("mimetype", b"application/vnd.oasis.opendocument.text")],
+                ZipKind::OpenDocument,
+            ),
+        ];
+        for (file_name, entries, expected) in cases {
+            let archive_file = test_dir.path().join(file_name);
+            create_archive(&archive_file, entries);
+            assert_eq!(zip_kind(&archive_file).unwrap(), expected, "{file_name}");
+        }
+
+        let not_a_zip = test_dir.path().join("not-a-zip.txt");
+        std::fs::write(&not_a_zip, b"plain text").unwrap();
+        assert_eq!(zip_kind(&not_a_zip).unwrap(), ZipKind::Unknown);
+    }
+
+    #[test]
+    fn extract_with_options_rejects_too_many_entries_up_front() {
+        use std::io::ErrorKind;
+
+        use zip::result::ZipError;
+
+        use crate::{zip_extract_with_options, ExtractOptions};
+
+        let test_dir = TestDir::new("max-entries");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_file,
+            &[("a.txt", b"a"), ("b.txt", b"b"), ("c.txt", b"c")],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let options = ExtractOptions::default().max_entries(Some(2));
+        let error = zip_extract_with_options(&archive_file, &target_dir, options).unwrap_err();
+        assert!(matches!(error, ZipError::Io(error) if error.kind() == ErrorKind::InvalidData));
+        assert_eq!(std::fs::read_dir(&target_dir).unwrap().count(), 0);
+
+        let options = ExtractOptions::default().max_entries(Some(3));
+        let report = zip_extract_with_options(&archive_file, &target_dir, options).unwrap();
+        assert_eq!(report.files_extracted, 3);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_with_options_applies_permission_mask() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        use zip::ZipArchive;
+
+        use crate::{ExtractOptions, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("permission-mask");
+        let archive_file = test_dir.path().join("archive.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&archive_file).unwrap());
+        let options = FileOptions::default().unix_permissions(0o777);
+        zip_writer.start_file("script.sh", options).unwrap();
+        zip_writer.write_all(b"#!/bin/sh").unwrap();
+        zip_writer.finish().unwrap();
+
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let options = ExtractOptions::default()
+            .preserve_permissions(true)
+            .permission_mask(Some(0o755));
+        let report = archive.extract_with_options(&target_dir, options).unwrap();
+
+        let metadata = fs::metadata(target_dir.join("script.sh")).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o755);
+        assert_eq!(report.files_extracted, 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn extract_with_options_applies_directory_mode() {
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+
+        use zip::ZipArchive;
+
+        use crate::{ExtractOptions, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("directory-mode");
+        let archive_file = test_dir.path().join("archive.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&archive_file).unwrap());
+        let options = FileOptions::default().unix_permissions(0o700);
+        zip_writer.add_directory("private/", options).unwrap();
+        zip_writer.start_file("a/b/file.txt", options).unwrap();
+        zip_writer.write_all(b"data").unwrap();
+        zip_writer.finish().unwrap();
+
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::set_permissions(&target_dir, fs::Permissions::from_mode(0o777)).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let options = ExtractOptions::default()
+            .preserve_permissions(true)
+            .directory_mode(Some(0o750));
+        archive.extract_with_options(&target_dir, options).unwrap();
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&target_dir.join("a")), 0o750);
+        assert_eq!(mode(&target_dir.join("a").join("b")), 0o750);
+        assert_eq!(mode(&target_dir.join("private")), 0o700);
+        assert_eq!(mode(&target_dir), 0o777);
+    }
+
+    #[test]
+    fn extract_with_options_converts_line_endings_of_text_files() {
+        use std::fs;
+
+        use zip::ZipArchive;
+
+        use crate::{ExtractOptions, LineEnding, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("line-endings");
+        let archive_file = test_dir.path().join("archive.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&archive_file).unwrap());
+        for name in ["notes.txt", "README.MD", "image.bin"] {
+            zip_writer.start_file(name, FileOptions::default()).unwrap();
+            zip_writer.write_all(b"one\r\ntwo\n").unwrap();
+        }
+        zip_writer.finish().unwrap();
+
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let options = ExtractOptions::default()
+            .line_ending_for_extension("txt", LineEnding::Lf)
+            .line_ending_for_extension(".md", LineEnding::CrLf);
+        archive.extract_with_options(&target_dir, options).unwrap();
+
+        let read = |name: &str| fs::read(target_dir.join(name)).unwrap();
+        assert_eq!(read("notes.txt"), b"one\ntwo\n");
+        assert_eq!(read("README.MD"), b"one\r\ntwo\r\n");
+        assert_eq!(read("image.bin"), b"one\r\ntwo\n");
+    }
+
+    #[test]
+    fn extract_largest_extracts_the_biggest_files() {
+        use std::fs;
+        use std::path::PathBuf;
+
+        use zip::ZipArchive;
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("extract-largest");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_file,
+            &[
+                ("small.txt", b"a"),
+                ("assets/", b""),
+                ("assets/first.bin", b"12345"),
+                ("medium.txt", b"abc"),
+                ("assets/second.bin", b"67890"),
+            ],
+        );
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir(&target_dir).unwrap();
+        let extracted = archive.extract_largest(&target_dir, 2).unwrap();
+        assert_eq!(
+            extracted,
+            [
+                PathBuf::from("assets/first.bin"),
+                PathBuf::from("assets/second.bin")
+            ]
+        );
+        assert_eq!(
+            fs::read(target_dir.join("assets/second.bin")).unwrap(),
+            b"67890"
+        );
+        assert!(!target_dir.join("medium.txt").exists());
+        assert!(!target_dir.join("small.txt").exists());
+
+        let all = archive.extract_largest(&target_dir, 10).unwrap();
+        assert_eq!(all.len(), 4);
+        assert_eq!(all[3], PathBuf::from("small.txt"));
+    }
+
+    #[test]
+    fn extract_subtree_extracts_entries_below_the_prefix() {
+        use std::fs;
+        use std::io::ErrorKind;
+
+        use zip::result::ZipError;
+        use zip::ZipArchive;
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("extract-subtree");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_file,
+            &[
+                ("docs/guide/intro.md", b"intro"),
+                ("docs/readme.txt", b"readme"),
+                ("docs.txt", b"not below docs"),
+                ("src/main.rs", b"fn main() {}"),
+            ],
+        );
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+
+        let kept_dir = test_dir.path().join("kept");
+        fs::create_dir(&kept_dir).unwrap();
+        archive
+            .extract_subtree("docs", &kept_dir, false, false)
+            .unwrap();
+        assert_eq!(
+            fs::read(kept_dir.join("docs/guide/intro.md")).unwrap(),
+            b"intro"
+        );
+        assert!(!kept_dir.join("docs.txt").exists());
+        assert!(!kept_dir.join("src").exists());
+
+        let stripped_dir = test_dir.path().join("stripped");
+        fs::create_dir(&stripped_dir).unwrap();
+        archive
+            .extract_subtree("docs/", &stripped_dir, true, false)
+            .unwrap();
+        assert_eq!(
+            fs::read(stripped_dir.join("readme.txt")).unwrap(),
+            b"readme"
+        );
+        assert_eq!(
+            fs::read(stripped_dir.join("guide/intro.md")).unwrap(),
+            b"intro"
+        );
+
+        let error = archive
+            .extract_subtree("docs", &stripped_dir, true, false)
+            .unwrap_err();
+        assert!(matches!(error, ZipError::Io(e) if e.kind() == ErrorKind::AlreadyExists));
+        archive
+            .extract_subtree("docs", &stripped_dir, true, true)
+            .unwrap();
+        let error = archive
+            .extract_subtree("missing", &stripped_dir, true, false)
+            .unwrap_err();
+        assert!(matches!(error, ZipError::Io(e) if e.kind() == ErrorKind::NotFound));
+    }
+
+    #[test]
+    fn long_paths_extract_deep_trees() {
+        use crate::{zip_extract_with_options, ExtractOptions};
+
+        let test_dir = TestDir::new("extract-long-paths");
+        let archive_file = test_dir.path().join("archive.zip");
+        let mut components = vec!["d".repeat(60); 5];
+        components.push("deep.txt".to_string());
+        let entry_name = components.join("/");
+        create_archive(&archive_file, &[(entry_name.as_str(), b"deep")]);
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let options = ExtractOptions::default().long_paths(true);
+        zip_extract_with_options(&archive_file, &target_dir, options).unwrap();
+        let extracted_file = components.iter().fold(
+            super::extended_length_path(&target_dir).unwrap(),
+            |path, component| path.join(component),
+        );
+        #[cfg(windows)]
+        assert!(extracted_file.as_os_str().len() > 260);
+        assert_eq!(std::fs::read(extracted_file).unwrap(), b"deep");
+    }
+
+    #[test]
+    fn find_by_basename_returns_all_matches() {
+        use std::ffi::OsStr;
+        use std::path::PathBuf;
+
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("find-basename");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_file,
+            &[
+                ("package.json", b"{}"),
+                ("packages/app/package.json", b"{}"),
+                ("packages/app/package.json.bak", b"{}"),
+                ("packages/lib/package.json", b"{}"),
+            ],
+        );
+        let mut archive = zip::ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let matches = archive
+            .find_by_basename(OsStr::new("package.json"))
+            .unwrap();
+        assert_eq!(
+            matches,
+            [
+                (0, PathBuf::from("package.json")),
+                (1, PathBuf::from("packages/app/package.json")),
+                (3, PathBuf::from("packages/lib/package.json")),
+            ]
+        );
+        assert!(archive
+            .find_by_basename(OsStr::new("missing.json"))
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn atomic_writes_rename_temporary_files_into_place() {
+        use crate::{zip_extract_with_options, ExtractOptions};
+
+        let test_dir = TestDir::new("extract-atomic");
+        let archive_file = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_file,
+            &[("a.txt", b"alpha"), ("nested/b.txt", b"beta")],
+        );
+        let temp_dir = test_dir.path().join("temp");
+        std::fs::create_dir(&temp_dir).unwrap();
+
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        std::fs::write(target_dir.join("a.txt"), b"old").unwrap();
+        let options = ExtractOptions::default()
+            .atomic_writes(true)
+            .temp_dir(Some(temp_dir.clone()));
+        zip_extract_with_options(&archive_file, &target_dir, options).unwrap();
+        assert_eq!(std::fs::read(target_dir.join("a.txt")).unwrap(), b"alpha");
+        assert_eq!(
+            std::fs::read(target_dir.join("nested").join("b.txt")).unwrap(),
+            b"beta"
+        );
+        assert_eq!(std::fs::read_dir(&temp_dir).unwrap().count(), 0);
+
+        let target_dir = test_dir.path().join("next-to-destination");
+        std::fs::create_dir(&target_dir).unwrap();
+        let options = ExtractOptions::default().atomic_writes(true);
+        zip_extract_with_options(&archive_file, &target_dir, options).unwrap();
+        let mut names: Vec<String> = std::fs::read_dir(&target_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, ["a.txt", "nested"]);
+
+        let target_dir = test_dir.path().join("missing-temp-dir");
+        std::fs::create_dir(&target_dir).unwrap();
+        let options = ExtractOptions::default()
+            .atomic_writes(true)
+            .temp_dir(Some(test_dir.path().join("missing")));
+        assert!(zip_extract_with_options(&archive_file, &target_dir, options).is_err());
+        assert_eq!(std::fs::read_dir(&target_dir).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn extract_with_options_makes_files_read_only() {
+        use std::fs;
+
+        use zip::ZipArchive;
+
+        use crate::{ExtractOptions, ZipArchiveExtensions};
+
+        let test_dir = TestDir::new("read-only");
+        let archive_file = test_dir.path().join("archive.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&archive_file).unwrap());
+        let options = FileOptions::default().unix_permissions(0o666);
+        zip_writer.add_directory("data/", options).unwrap();
+        zip_writer.start_file("data/table.csv", options).unwrap();
+        zip_writer.write_all(b"a,b").unwrap();
+        zip_writer.finish().unwrap();
+
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let options = ExtractOptions::default()
+            .preserve_permissions(true)
+            .make_read_only(true);
+        archive.extract_with_options(&target_dir, options).unwrap();
+
+        let file_metadata = fs::metadata(target_dir.join("data").join("table.csv")).unwrap();
+        assert!(file_metadata.permissions().readonly());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(file_metadata.permissions().mode() & 0o777, 0o444);
+        }
+        let directory_metadata = fs::metadata(target_dir.join("data")).unwrap();
+        assert!(!directory_metadata.permissions().readonly());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn zip_content_hash_ignores_entry_order_and_directories() {
+        use crate::zip_content_hash;
+
+        let test_dir = TestDir::new("content-hash");
+        let options = FileOptions::default();
+
+        let first_archive = test_dir.path().join("first.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&first_archive).unwrap());
+        zip_writer.start_file("a.txt", options).unwrap();
+        zip_writer.write_all(b"alpha").unwrap();
+        zip_writer.start_file("dir/b.txt", options).unwrap();
+        zip_writer.write_all(b"beta").unwrap();
+        zip_writer.finish().unwrap();
+
+        let second_archive = test_dir.path().join("second.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&second_archive).unwrap());
+        zip_writer.add_directory("dir/", options).unwrap();
+        zip_writer.start_file("dir/b.txt", options).unwrap();
+        zip_writer.write_all(b"beta").unwrap();
+        zip_writer.start_file("a.txt", options).unwrap();
+        zip_writer.write_all(b"alpha").unwrap();
+        zip_writer.finish().unwrap();
+
+        let third_archive = test_dir.path().join("third.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&third_archive).unwrap());
+        zip_writer.start_file("a.txt", options).unwrap();
+        zip_writer.write_all(b"alpha").unwrap();
+        zip_writer.start_file("dir/b.txt", options).unwrap();
+        zip_writer.write_all(b"gamma").unwrap();
+        zip_writer.finish().unwrap();
+
+        let first_hash = zip_content_hash(&first_archive).unwrap();
+        assert_eq!(first_hash, zip_content_hash(&second_archive).unwrap());
+        assert_ne!(first_hash, zip_content_hash(&third_archive).unwrap());
+    }
+
+    #[cfg(feature = "sha2")]
+    #[test]
+    fn archive_content_hash_ignores_order_and_compression() {
+        use std::io::Cursor;
+
+        use zip::{CompressionMethod, ZipArchive};
+
+        use crate::ZipArchiveExtensions;
+
+        #[cfg(feature = "deflate")]
+        let method = CompressionMethod::Deflated;
+        #[cfg(not(feature = "deflate"))]
+        let method = CompressionMethod::Stored;
+
+        let write = |entries: &[(&str, &[u8])], options: FileOptions| {
+            let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+            for (name, content) in entries {
+                if let Some(directory) = name.strip_suffix('/') {
+                    zip_writer.add_directory(directory, options).unwrap();
+                } else {
+                    zip_writer.start_file(*name, options).unwrap();
+                    zip_writer.write_all(content).unwrap();
+                }
+            }
+            let mut archive = ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+            archive.archive_content_hash().unwrap()
+        };
+        let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+        let compressed = FileOptions::default().compression_method(method);
+
+        let first_hash = write(&[("a.txt", b"alpha"), ("dir/b.txt", b"beta")], stored);
+        let second_hash = write(
+            &[("dir/", b""), ("dir/b.txt", b"beta"), ("a.txt", b"alpha")],
+            compressed,
+        );
+        let third_hash = write(&[("a.txt", b"alpha"), ("dir/b.txt", b"gamma")], stored);
+        assert_eq!(first_hash, second_hash);
+        assert_ne!(first_hash, third_hash);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn zip_metadata_json_serializes_entry_info() {
+        use zip::{CompressionMethod, DateTime};
+
+        use crate::zip_metadata_json;
+
+        let test_dir = TestDir::new("metadata-json");
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(DateTime::from_date_and_time(2020, 5, 17, 8, 30, 0).unwrap());
+        let mut zip_writer = ZipWriter::new(File::create(&archive_file).unwrap());
+        zip_writer.add_directory("docs/", options).unwrap();
+        zip_writer.start_file("docs/a.txt", options).unwrap();
+        zip_writer.write_all(b"hello").unwrap();
+        zip_writer.finish().unwrap();
+
+        let json = zip_metadata_json(&archive_file).unwrap();
+        assert!(json.starts_with('['), "{json}");
+        assert!(
+            json.contains(concat!(
+                r#"{"index":1,"path":"docs/a.txt","is_dir":false,"size":5,"#,
+                r#""compressed_size":5,"crc32":907060870,"compression":"Stored","#,
+                r#""last_modified":1589704200,"comment":""}"#
+            )),
+            "{json}"
+        );
+        assert!(json.contains(r#""path":"docs","is_dir":true"#), "{json}");
+    }
+
+    #[test]
+    fn extract_with_options_reports_compressed_progress() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        use crate::{
+            zip_extract_with_options, ExtractOptions, ExtractProgress, ZipArchiveExtensions,
+        };
+
+        let test_dir = TestDir::new("extract-progress");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(&archive_path, &[("docs/", b""), ("docs/a.txt", b"hello")]);
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let updates: Rc<RefCell<Vec<ExtractProgress>>> = Rc::default();
+        let recorder = Rc::clone(&updates);
+        let options = ExtractOptions::default()
+            .on_progress(move |progress| recorder.borrow_mut().push(*progress));
+        zip_extract_with_options(&archive_path, &target_dir, options).unwrap();
+
+        let updates = updates.borrow();
+        let last = updates.last().unwrap();
+        assert_eq!(last.entries_processed, 2);
+        assert_eq!(last.total_entries, 2);
+        assert_eq!(last.bytes_processed, 5);
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        let compressed_size: u64 = archive
+            .list_entries()
+            .unwrap()
+            .iter()
+            .map(|entry| entry.compressed_size)
+            .sum();
+        assert_eq!(last.compressed_bytes_processed, compressed_size);
+        assert!(updates
+            .windows(2)
+            .all(|pair| pair[0].compressed_bytes_processed <= pair[1].compressed_bytes_processed));
+    }
+
+    #[test]
+    fn extract_resolves_dot_components_within_entry_paths() {
+        use std::io::ErrorKind;
+
+        use zip::result::ZipError;
+
+        use crate::zip_extract;
+
+        let test_dir = TestDir::new("extract-dot-components");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[("a/../b.txt", b"b"), ("a/./c/../d.txt", b"d")],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        zip_extract(&archive_path, &target_dir).unwrap();
+        assert_eq!(std::fs::read(target_dir.join("b.txt")).unwrap(), b"b");
+        assert_eq!(std::fs::read(target_dir.join("a/d.txt")).unwrap(), b"d");
+        assert!(!target_dir.join("a/c").exists());
+
+        let escaping_path = test_dir.path().join("escaping.zip");
+        create_archive(
+            &escaping_path,
+            &[("inside.txt", b"inside"), ("a/../../evil.txt", b"evil")],
+        );
+        let escaping_target = test_dir.path().join("escaping");
+        std::fs::create_dir(&escaping_target).unwrap();
+        match zip_extract(&escaping_path, &escaping_target) {
+            Err(ZipError::Io(error)) => assert_eq!(error.kind(), ErrorKind::InvalidData),
+            other => panic!("unexpected result {other:?}"),
+        }
+        assert!(!test_dir.path().join("evil.txt").exists());
+        assert!(!escaping_target.join("inside.txt").exists());
+    }
+
+    #[test]
+    fn entry_paths_match_the_extracted_paths() {
+        use std::path::{Path, PathBuf};
+
+        use zip::ZipArchive;
+
+        use crate::read::ZipArchiveExtensions;
+        use crate::zip_extract;
+
+        let test_dir = TestDir::new("resolved-entry-paths");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[("a/../b.txt", b"b"), ("dir\\file.txt", b"file")],
+        );
+        let mut archive = ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        assert_eq!(archive.entry_path(0).unwrap(), Path::new("b.txt"));
+        assert_eq!(archive.file_number("b.txt"), Some(0));
+        assert_eq!(archive.file_number("a/b.txt"), None);
+        assert_eq!(
+            archive.entry_path(1).unwrap(),
+            ["dir", "file.txt"].iter().collect::<PathBuf>()
+        );
+        let paths: Vec<PathBuf> = archive
+            .list_entries()
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                archive.entry_path(0).unwrap(),
+                archive.entry_path(1).unwrap()
+            ]
+        );
+
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        zip_extract(&archive_path, &target_dir).unwrap();
+        assert_eq!(std::fs::read(target_dir.join("b.txt")).unwrap(), b"b");
+        assert_eq!(
+            std::fs::read(target_dir.join("dir").join("file.txt")).unwrap(),
+            b"file"
+        );
+    }
+
+    #[test]
+    fn extract_with_options_reports_skipped_entries() {
+        use std::cell::RefCell;
+        use std::path::PathBuf;
+        use std::rc::Rc;
+
+        use crate::{zip_extract_with_options, ConflictPolicy, ExtractOptions, SkipReason};
+
+        let test_dir = TestDir::new("extract-skips");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("existing.txt", b"new"),
+                ("foo", b"file"),
+                ("foo/bar.txt", b"bar"),
+                (".", b"nameless"),
+                ("extracted.txt", b"extracted"),
+            ],
+        );
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        std::fs::write(target_dir.join("existing.txt"), "old").unwrap();
+
+        let skips: Rc<RefCell<Vec<(PathBuf, SkipReason)>>> = Rc::default();
+        let recorder = Rc::clone(&skips);
+        let options = ExtractOptions::default()
+            .conflict_policy(ConflictPolicy::Skip)
+            .on_skip(move |path, reason| recorder.borrow_mut().push((path.to_path_buf(), reason)));
+        let report = zip_extract_with_options(&archive_path, &target_dir, options).unwrap();
+        assert_eq!(
+            *skips.borrow(),
+            [
+                (PathBuf::from("existing.txt"), SkipReason::AlreadyExists),
+                (PathBuf::from("foo"), SkipReason::DirectoryConflict),
+                (PathBuf::from("."), SkipReason::EmptyName),
+            ]
+        );
+        assert_eq!(report.files_skipped, 2);
+        assert_eq!(report.files_extracted, 2);
+        assert_eq!(
+            std::fs::read(target_dir.join("existing.txt")).unwrap(),
+            b"old"
+        );
+
+        skips.borrow_mut().clear();
+        let recorder = Rc::clone(&skips);
+        let options = ExtractOptions::default()
+            .conflict_policy(ConflictPolicy::SkipUnchanged)
+            .on_skip(move |path, reason| recorder.borrow_mut().push((path.to_path_buf(), reason)));
+        zip_extract_with_options(&archive_path, &target_dir, options).unwrap();
+        assert!(skips
+            .borrow()
+            .contains(&(PathBuf::from("extracted.txt"), SkipReason::Unchanged)));
+        assert_eq!(
+            std::fs::read(target_dir.join("existing.txt")).unwrap(),
+            b"new"
+        );
+    }
+
+    #[test]
+    fn skip_unsafe_entries_reports_instead_of_failing() {
+        use std::cell::RefCell;
+        use std::path::PathBuf;
+        use std::rc::Rc;
+
+        use crate::{zip_extract_with_options, ExtractOptions, SkipReason};
+
+        let test_dir = TestDir::new("extract-skip-unsafe");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[
+                ("method.txt", b"method"),
+                ("../escape.txt", b"escape"),
+                ("a.txt", b"lower"),
+                ("A.txt", b"upper"),
+                ("kept.txt", b"kept"),
+            ],
+        );
+        // Declare the unknown compression method 99 for the first entry.
+        let mut bytes = std::fs::read(&archive_path).unwrap();
+        bytes[8..10].copy_from_slice(&99u16.to_le_bytes());
+        let central_header = bytes
+            .windows(4)
+            .position(|window| window == [0x50, 0x4b, 0x01, 0x02])
+            .unwrap();
+        bytes[central_header + 10..central_header + 12].copy_from_slice(&99u16.to_le_bytes());
+        std::fs::write(&archive_path, bytes).unwrap();
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let options = ExtractOptions::default().lowercase_paths(true);
+        assert!(zip_extract_with_options(&archive_path, &target_dir, options).is_err());
+
+        let skips: Rc<RefCell<Vec<(PathBuf, SkipReason)>>> = Rc::default();
+        let recorder = Rc::clone(&skips);
+        let options = ExtractOptions::default()
+            .lowercase_paths(true)
+            .skip_unsafe_entries(true)
+            .on_skip(move |path, reason| recorder.borrow_mut().push((path.to_path_buf(), reason)));
+        let report = zip_extract_with_options(&archive_path, &target_dir, options).unwrap();
+        assert_eq!(
+            *skips.borrow(),
+            [
+                (PathBuf::from("method.txt"), SkipReason::Unsupported),
+                (PathBuf::from("../escape.txt"), SkipReason::PathEscape),
+                (PathBuf::from("a.txt"), SkipReason::Collision),
+                (PathBuf::from("a.txt"), SkipReason::Collision),
+            ]
+        );
+        assert_eq!(report.files_skipped, 4);
+        assert_eq!(report.files_extracted, 1);
+        assert_eq!(std::fs::read(target_dir.join("kept.txt")).unwrap(), b"kept");
+        assert!(!target_dir.join("a.txt").exists());
+        assert!(!test_dir.path().join("escape.txt").exists());
+    }
+
+    #[test]
+    fn check_alignment_lists_misaligned_stored_entries() {
+        use std::io::{Cursor, ErrorKind};
+        use std::path::PathBuf;
+
+        use zip::result::ZipError;
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_from_memory, CreateOptions, ZipArchiveExtensions};
+
+        let entries = vec![
+            ("a.txt".to_string(), b"a".to_vec()),
+            ("bb.txt".to_string(), b"b".to_vec()),
+            ("docs/".to_string(), Vec::new()),
+            ("docs/c.txt".to_string(), b"c".to_vec()),
+        ];
+        let mut buffer = Cursor::new(Vec::new());
+        let options = CreateOptions::default().compression_method(CompressionMethod::Stored);
+        zip_create_from_memory(&mut buffer, entries.clone(), options).unwrap();
+        let mut archive = zip::ZipArchive::new(buffer).unwrap();
+        // The data of `a.txt` begins after the 30-byte local header and its 5-byte name.
+        assert_eq!(
+            archive.check_alignment(4).unwrap(),
+            [PathBuf::from("a.txt")]
+        );
+        assert!(archive.check_alignment(1).unwrap().is_empty());
+        let error = archive.check_alignment(0).unwrap_err();
+        assert!(matches!(error, ZipError::Io(error) if error.kind() == ErrorKind::InvalidInput));
+
+        let mut buffer = Cursor::new(Vec::new());
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .align_stored_entries(Some(4096));
+        zip_create_from_memory(&mut buffer, entries, options).unwrap();
+        let mut archive = zip::ZipArchive::new(buffer).unwrap();
+        assert!(archive.check_alignment(4096).unwrap().is_empty());
+    }
+
+    #[test]
+    fn read_entry_to_sink_returns_uncompressed_size() {
+        use crate::ZipArchiveExtensions;
+
+        let test_dir = TestDir::new("entry-to-sink");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(&archive_path, &[("docs/", b""), ("docs/a.txt", b"hello")]);
+
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+        assert_eq!(archive.read_entry_to_sink(0).unwrap(), 0);
+        assert_eq!(archive.read_entry_to_sink(1).unwrap(), 5);
+        assert!(archive.read_entry_to_sink(2).is_err());
+    }
+
+    #[test]
+    fn extract_to_fs_writes_into_custom_file_system() {
+        use std::collections::{BTreeMap, BTreeSet};
+        use std::io;
+        use std::path::PathBuf;
+
+        use crate::{FsWriter, StdFsWriter, ZipArchiveExtensions};
+
+        #[derive(Default)]
+        struct MemoryFs {
+            directories: BTreeSet<PathBuf>,
+            files: BTreeMap<PathBuf, Vec<u8>>,
+        }
+
+        impl FsWriter for MemoryFs {
+            fn create_dir(&mut self, path: &Path) -> io::Result<()> {
+                self.directories.insert(path.to_path_buf());
+                Ok(())
+            }
+
+            fn create_file(&mut self, path: &Path) -> io::Result<Box<dyn io::Write + '_>> {
+                let contents = self.files.entry(path.to_path_buf()).or_default();
+                contents.clear();
+                Ok(Box::new(contents))
+            }
+        }
+
+        let test_dir = TestDir::new("extract-to-fs");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(
+            &archive_path,
+            &[("empty/", b""), ("docs/a.txt", b"hello"), ("b.txt", b"b")],
+        );
+        let mut archive = zip::ZipArchive::new(File::open(&archive_path).unwrap()).unwrap();
+
+        let mut fs = MemoryFs::default();
+        archive.extract_to_fs(&mut fs).unwrap();
+        let directories: Vec<&Path> = fs.directories.iter().map(PathBuf::as_path).collect();
+        assert_eq!(directories, [Path::new("docs"), Path::new("empty")]);
+        assert_eq!(fs.files[Path::new("docs/a.txt")], b"hello");
+        assert_eq!(fs.files[Path::new("b.txt")], b"b");
+
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+        archive
+            .extract_to_fs(&mut StdFsWriter::new(&target_dir))
+            .unwrap();
+        assert_eq!(
+            std::fs::read(target_dir.join("docs/a.txt")).unwrap(),
+            b"hello"
+        );
+        assert!(target_dir.join("empty").is_dir());
+    }
+
+    #[test]
+    fn zip_read_first_with_extension_matches_case_insensitively() {
+        use crate::zip_read_first_with_extension;
+
+        let test_dir = TestDir::new("first-with-extension");
+        let archive_path = test_dir.path().join("book.epub");
+        create_archive(
+            &archive_path,
+            &[
+                ("mimetype", b"application/epub+zip"),
+                ("OEBPS.opf/", b""),
+                ("OEBPS/content.OPF", b"<package/>"),
+                ("OEBPS/other.opf", b"<other/>"),
+            ],
+        );
+
+        let found = zip_read_first_with_extension(&archive_path, ".opf").unwrap();
+        assert_eq!(
+            found,
+            Some((
+                Path::new("OEBPS/content.OPF").to_path_buf(),
+                b"<package/>".to_vec()
+            ))
+        );
+        assert_eq!(
+            zip_read_first_with_extension(&archive_path, "xml").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn extract_with_transform_skips_directories() {
+        use std::cell::RefCell;
+
+        use crate::zip_extract_with_transform;
+
+        let test_dir = TestDir::new("extract-transform");
+        let archive_path = test_dir.path().join("archive.zip");
+        create_archive(&archive_path, &[("docs/", b""), ("docs/a.txt", b"hello")]);
+        let target_dir = test_dir.path().join("target");
+        std::fs::create_dir(&target_dir).unwrap();
+
+        let seen = RefCell::new(Vec::new());
+        zip_extract_with_transform(&archive_path, &target_dir, |path, bytes| {
+            seen.borrow_mut().push(path.to_path_buf());
+            Ok(bytes.to_ascii_uppercase())
+        })
+        .unwrap();
+        assert_eq!(seen.into_inner(), [Path::new("docs/a.txt")]);
+        assert_eq!(
+            std::fs::read(target_dir.join("docs/a.txt")).unwrap(),
+            b"HELLO"
+        );
+
+        let failing = zip_extract_with_transform(&archive_path, &target_dir, |_, _| {
+            Err(std::io::Error::other("cannot decode"))
+        });
+        assert!(failing.is_err());
     }
 }
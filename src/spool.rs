@@ -0,0 +1,165 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[cfg(feature = "aes")]
+use zip::read::ZipFile;
+use zip::result::ZipResult;
+use zip::write::FileOptions;
+#[cfg(feature = "aes")]
+use zip::CompressionMethod;
+use zip::{ZipArchive, ZipWriter};
+
+use crate::file_utils::copy_in_sized_chunks;
+
+/// The size above which an entry is compressed into a temporary file instead of memory.
+const SPILL_THRESHOLD: u64 = 8 * 1024 * 1024;
+
+/// Numbers the temporary files of this process.
+static SPILL_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// An entry that has been compressed ahead of time into a single-entry archive of its own, so
+/// that its data can be copied into the output archive without compressing it again.
+pub(crate) struct CompressedEntry {
+    archive: ZipArchive<Spool>,
+    size: u64,
+}
+
+impl CompressedEntry {
+    /// Compresses the contents of the reader, copied in chunks of the given size, into an entry
+    /// with the given name and options. The entry size is used to decide whether the data is held
+    /// in memory or spilled to a temporary file.
+    pub(crate) fn compress<R: Read + ?Sized>(
+        name: &str,
+        reader: &mut R,
+        options: FileOptions,
+        size: u64,
+        chunk_size: usize,
+    ) -> ZipResult<CompressedEntry> {
+        let spool = if size > SPILL_THRESHOLD {
+            Spool::temporary()?
+        } else {
+            Spool::Memory(Cursor::new(Vec::new()))
+        };
+        let mut zip_writer = ZipWriter::new(spool);
+        zip_writer.start_file(name, options)?;
+        copy_in_sized_chunks(reader, &mut zip_writer, chunk_size, |_| Ok(()))?;
+        let mut archive = ZipArchive::new(zip_writer.finish()?)?;
+        let size = archive.by_index_raw(0)?.size();
+        Ok(CompressedEntry { archive, size })
+    }
+
+    /// Returns the uncompressed size of the entry.
+    pub(crate) fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Returns the compression method of the entry, and a reader of its compressed data whose
+    /// compressed size is that of the data.
+    #[cfg(feature = "aes")]
+    pub(crate) fn raw_data(&mut self) -> ZipResult<(CompressionMethod, ZipFile<'_>)> {
+        let entry = self.archive.by_index_raw(0)?;
+        Ok((entry.compression(), entry))
+    }
+
+    /// Copies the compressed entry into the archive, with the local header and CRC-32 it has
+    /// been compressed with.
+    pub(crate) fn write_to<W: Write + Seek>(
+        mut self,
+        zip_writer: &mut ZipWriter<W>,
+    ) -> ZipResult<()> {
+        zip_writer.raw_copy_file(self.archive.by_index_raw(0)?)
+    }
+
+    /// Copies the compressed entry into the archive under another name, keeping it so that it
+    /// can be copied again. The local header and CRC-32 are those it has been compressed with.
+    pub(crate) fn copy_to<W: Write + Seek>(
+        &mut self,
+        zip_writer: &mut ZipWriter<W>,
+        name: &str,
+    ) -> ZipResult<()> {
+        zip_writer.raw_copy_file_rename(self.archive.by_index_raw(0)?, name)
+    }
+}
+
+/// The storage that compressed entry data is written to.
+enum Spool {
+    Memory(Cursor<Vec<u8>>),
+    File(TemporaryFile),
+}
+
+impl Spool {
+    fn temporary() -> io::Result<Spool> {
+        let path = std::env::temp_dir().join(format!(
+            "zip-extensions-{}-{}.tmp",
+            std::process::id(),
+            SPILL_COUNTER.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Spool::File(TemporaryFile {
+            file: Some(file),
+            path,
+        }))
+    }
+}
+
+/// A file that is closed and removed when it is dropped.
+struct TemporaryFile {
+    file: Option<File>,
+    path: PathBuf,
+}
+
+impl TemporaryFile {
+    fn file(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("the file is open until it is dropped")
+    }
+}
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        drop(self.file.take());
+        std::fs::remove_file(&self.path).unwrap_or_default();
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Spool::Memory(cursor) => cursor.read(buf),
+            Spool::File(file) => file.file().read(buf),
+        }
+    }
+}
+
+impl Write for Spool {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Spool::Memory(cursor) => cursor.write(buf),
+            Spool::File(file) => file.file().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Spool::Memory(cursor) => cursor.flush(),
+            Spool::File(file) => file.file().flush(),
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        match self {
+            Spool::Memory(cursor) => cursor.seek(pos),
+            Spool::File(file) => file.file().seek(pos),
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use std::io;
+use std::io::{Error, ErrorKind};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use zip::DateTime;
+
+/// Converts a timestamp stored in a ZIP archive to a `SystemTime`.
+///
+/// ZIP timestamps do not carry a time zone, so they are interpreted as UTC. Returns `None` if the
+/// stored date or time is invalid.
+pub(crate) fn zip_datetime_to_system_time(datetime: DateTime) -> Option<SystemTime> {
+    let year = i64::from(datetime.year());
+    let month = i64::from(datetime.month());
+    let day = i64::from(datetime.day());
+    let hour = u64::from(datetime.hour());
+    let minute = u64::from(datetime.minute());
+    let second = u64::from(datetime.second());
+    if !(1..=12).contains(&month)
+        || day < 1
+        || day > days_in_month(year, month)
+        || hour > 23
+        || minute > 59
+        || second > 59
+    {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    let seconds = days * 86_400 + hour * 3_600 + minute * 60 + second;
+    Some(UNIX_EPOCH + Duration::from_secs(seconds))
+}
+
+/// Converts a `SystemTime` to a timestamp that can be stored in a ZIP archive, as UTC.
+///
+/// ZIP timestamps can represent 1980-01-01 00:00:00 to 2107-12-31 23:59:58 with a resolution of
+/// two seconds. Times outside of this range are clamped to the nearest bound, and odd seconds are
+/// rounded down.
+pub(crate) fn system_time_to_zip_datetime(time: SystemTime) -> DateTime {
+    const SECONDS_PER_DAY: i64 = 86_400;
+    let earliest = days_from_civil(1980, 1, 1) * SECONDS_PER_DAY;
+    let latest = days_from_civil(2107, 12, 31) * SECONDS_PER_DAY + SECONDS_PER_DAY - 2;
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| {
+            i64::try_from(duration.as_secs()).unwrap_or(i64::MAX)
+        })
+        .clamp(earliest, latest);
+
+    let (year, month, day) = civil_from_days(seconds.div_euclid(SECONDS_PER_DAY));
+    let second_of_day = seconds.rem_euclid(SECONDS_PER_DAY);
+    let to_u8 = |value: i64| u8::try_from(value).unwrap_or_default();
+    DateTime::from_date_and_time(
+        u16::try_from(year).unwrap_or_default(),
+        to_u8(month),
+        to_u8(day),
+        to_u8(second_of_day / 3_600),
+        to_u8(second_of_day / 60 % 60),
+        to_u8(second_of_day % 60 / 2 * 2),
+    )
+    .unwrap_or_default()
+}
+
+/// Reads the `SOURCE_DATE_EPOCH` environment variable, which sets the timestamp of reproducible
+/// builds in seconds since 1970-01-01 00:00:00 UTC. Returns `None` if the variable is not set.
+pub(crate) fn source_date_epoch() -> io::Result<Option<DateTime>> {
+    let Some(value) = std::env::var_os("SOURCE_DATE_EPOCH") else {
+        return Ok(None);
+    };
+    let time = value
+        .to_str()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .and_then(|seconds| UNIX_EPOCH.checked_add(Duration::from_secs(seconds)))
+        .ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "SOURCE_DATE_EPOCH is not a valid number of seconds: {}",
+                    value.to_string_lossy()
+                ),
+            )
+        })?;
+    Ok(Some(system_time_to_zip_datetime(time)))
+}
+
+fn is_leap_year(year: i64) -> bool {
+    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Returns the number of days between 1970-01-01 and the given date of the proleptic Gregorian
+/// calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let month_index = (month + 9) % 12;
+    let day_of_year = (153 * month_index + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// Returns the date of the proleptic Gregorian calendar that is the given number of days after
+/// 1970-01-01, as year, month and day.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use zip::DateTime;
+
+    use crate::time_utils::{
+        civil_from_days, days_from_civil, system_time_to_zip_datetime, zip_datetime_to_system_time,
+    };
+
+    #[test]
+    fn days_from_civil_matches_known_dates() {
+        assert_eq!(days_from_civil(1970, 1, 1), 0);
+        assert_eq!(days_from_civil(1980, 1, 1), 3_652);
+        assert_eq!(days_from_civil(2000, 3, 1), 11_017);
+        assert_eq!(days_from_civil(2024, 2, 29), 19_782);
+    }
+
+    #[test]
+    fn civil_from_days_inverts_days_from_civil() {
+        for (year, month, day) in [(1970, 1, 1), (1980, 1, 1), (2000, 2, 29), (2107, 12, 31)] {
+            assert_eq!(
+                civil_from_days(days_from_civil(year, month, day)),
+                (year, month, day)
+            );
+        }
+    }
+
+    #[test]
+    fn system_time_to_zip_datetime_clamps_to_representable_range() {
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let converted = zip_datetime_to_system_time(system_time_to_zip_datetime(time));
+        assert_eq!(converted, Some(time));
+
+        let odd_second = UNIX_EPOCH + Duration::from_secs(1_700_000_001);
+        let converted = zip_datetime_to_system_time(system_time_to_zip_datetime(odd_second));
+        assert_eq!(converted, Some(time));
+
+        let earliest = zip_datetime_to_system_time(DateTime::default());
+        let converted = zip_datetime_to_system_time(system_time_to_zip_datetime(UNIX_EPOCH));
+        assert_eq!(converted, earliest);
+
+        let far_future = UNIX_EPOCH + Duration::from_secs(10_000_000_000);
+        let latest = system_time_to_zip_datetime(far_future);
+        assert_eq!(
+            (latest.year(), latest.month(), latest.day()),
+            (2107, 12, 31)
+        );
+        assert_eq!(
+            (latest.hour(), latest.minute(), latest.second()),
+            (23, 59, 58)
+        );
+    }
+
+    fn msdos_date(year: u16, month: u16, day: u16) -> u16 {
+        (year - 1980) * 512 + month * 32 + day
+    }
+
+    #[test]
+    fn zip_datetime_to_system_time_converts_valid_timestamps() {
+        let timepart = 12 * 2048 + 30 * 32 + 15;
+        let datetime = DateTime::from_msdos(msdos_date(2024, 2, 29), timepart);
+        let actual = zip_datetime_to_system_time(datetime);
+        let expected =
+            UNIX_EPOCH + Duration::from_secs(19_782 * 86_400 + 12 * 3_600 + 30 * 60 + 30);
+        assert_eq!(actual, Some(expected));
+    }
+
+    #[test]
+    fn zip_datetime_to_system_time_returns_none_for_invalid_timestamps() {
+        assert_eq!(
+            zip_datetime_to_system_time(DateTime::from_msdos(0, 0)),
+            None
+        );
+        let february_thirtieth = DateTime::from_msdos(msdos_date(2023, 2, 30), 0);
+        assert_eq!(zip_datetime_to_system_time(february_thirtieth), None);
+    }
+}
@@ -1,93 +1,7160 @@
-use std::fs::File;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{File, Metadata, OpenOptions};
 use std::io;
-use std::io::{Read, Write};
+use std::io::{BufReader, Error, ErrorKind, Read, Seek, SeekFrom, Write};
+use std::ops::ControlFlow;
 use std::path::{Path, PathBuf};
 
-use zip::result::ZipResult;
+use zip::result::{ZipError, ZipResult};
 use zip::write::FileOptions;
-use zip::{CompressionMethod, ZipWriter};
+use zip::{CompressionMethod, DateTime, ZipArchive, ZipWriter};
 
-use crate::file_utils::make_relative_path;
+use crate::central_directory::{
+    mark_aes_entries, read_central_directory, read_local_header, rewrite_central_directory,
+    write_central_directory_header, write_data_descriptor, write_end_of_central_directory,
+    write_local_header, ExtraField,
+};
+#[cfg(feature = "aes")]
+use crate::encryption::{aes_extra_field, AesWriter, AES_OVERHEAD};
+use crate::eocd::{find_end_of_central_directory, MAX_COMMENT_LENGTH};
+use crate::file_utils::{
+    copy_in_sized_chunks, file_crc32, glob_matches, make_relative_path, path_to_entry_name,
+    temporary_file_path, unix_permissions, COPY_CHUNK_SIZE,
+};
+#[cfg(feature = "ignore")]
+use crate::ignore_rules::IgnoreRules;
+#[cfg(feature = "rayon")]
+use crate::parallel::try_for_each_parallel;
+use crate::read::{open_archive, Cancelled};
+use crate::spool::CompressedEntry;
+use crate::time_utils::{source_date_epoch, system_time_to_zip_datetime};
 
 /// Creates a zip archive that contains the files and directories from the specified directory.
+///
+/// Entry names are relative to the directory and use forward slashes as separators. Entries are
+/// compressed with Deflate if the `deflate` feature is enabled, and stored without compression
+/// otherwise.
+/// Symbolic links are followed, and a link to a directory that contains it fails; see
+/// `CreateOptions::symlinks`. An empty directory produces a valid, empty archive.
+///
+/// Files are streamed into the archive one at a time, in chunks of 64 KiB, so memory use does not
+/// grow with the size of the files.
 /// # Errors
-/// Will return `ZipError` for relevant file io error on archive or directory.
+/// Will return `ZipError` for relevant file io error on archive or directory, or if the archive
+/// file would be created inside the directory.
 pub fn zip_create_from_directory<P1: AsRef<Path>, P2: AsRef<Path>>(
     archive_file: P1,
     directory: P2,
 ) -> ZipResult<()> {
-    let options = FileOptions::default().compression_method(CompressionMethod::Stored);
-    zip_create_from_directory_with_options(archive_file, directory, options)
+    zip_create_from_directory_with_options(archive_file, directory, CreateOptions::default())?;
+    Ok(())
 }
 
-/// Creates a zip archive that contains the files and directories from the specified directory, uses the specified compression level.
+/// Creates a zip archive that contains the files and directories from the specified directory,
+/// uses the specified options.
+///
+/// If creation fails, the partially written archive file is removed.
 /// # Errors
-/// Will return `ZipError` for relevant file io error on archive or directory.
+/// Will return `ZipError` for relevant file io error on archive or directory, if the archive
+/// file would be created inside the directory, or if the compression method is not available.
 pub fn zip_create_from_directory_with_options<P1: AsRef<Path>, P2: AsRef<Path>>(
     archive_file: P1,
     directory: P2,
-    options: FileOptions,
+    options: CreateOptions,
+) -> ZipResult<CreateReport> {
+    options.file_options()?;
+    ensure_archive_outside_directory(archive_file.as_ref(), directory.as_ref())?;
+    let archive_file = archive_file.as_ref();
+    let file = create_archive_file(archive_file)?;
+    let mut zip_writer = ZipWriter::new(SizeLimitedWriter::new(file, options.max_archive_size)?);
+    let result = create_from_path(&mut zip_writer, directory.as_ref(), "", options)
+        .and_then(|report| finish_archive(&mut zip_writer, &report).map(|_| report));
+    if result.is_err() {
+        drop(zip_writer);
+        std::fs::remove_file(archive_file).unwrap_or_default();
+    }
+    result
+}
+
+/// The largest size of an entry, or offset in an archive, that can be stored without ZIP64.
+const ZIP64_SIZE_THRESHOLD: u64 = 0xffff_ffff;
+
+/// The largest number of entries that an archive can have without ZIP64.
+const ZIP64_ENTRY_THRESHOLD: usize = 0xffff;
+
+/// Determines whether an archive of the files and directories from the specified directory
+/// requires ZIP64, since it would contain a file larger than 4 GiB, more than 65,535 entries,
+/// or more than 4 GiB of file data in total. Every file and directory is counted, regardless
+/// of any `CreateOptions`, and symbolic links are not followed.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on directory.
+pub fn requires_zip64<P: AsRef<Path>>(directory: P) -> ZipResult<bool> {
+    let mut entries: usize = 0;
+    let mut total_size: u64 = 0;
+    let mut directories = vec![directory.as_ref().to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in std::fs::read_dir(directory)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                directories.push(entry.path());
+            } else if metadata.len() > ZIP64_SIZE_THRESHOLD {
+                return Ok(true);
+            } else {
+                total_size += metadata.len();
+            }
+            entries += 1;
+            if entries > ZIP64_ENTRY_THRESHOLD || total_size > ZIP64_SIZE_THRESHOLD {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+/// Creates a zip archive in memory that contains the files and directories from the specified
+/// directory, uses the specified options, and returns the bytes of the archive.
+///
+/// Set `CreateOptions::max_archive_size` to fail instead of growing the buffer indefinitely.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on directory, if the compression method is
+/// not available, or if the archive would exceed the maximum size.
+pub fn zip_create_in_memory<P: AsRef<Path>>(
+    directory: P,
+    options: CreateOptions,
+) -> ZipResult<Vec<u8>> {
+    options.file_options()?;
+    let buffer = io::Cursor::new(Vec::new());
+    let mut zip_writer = ZipWriter::new(SizeLimitedWriter::new(buffer, options.max_archive_size)?);
+    let report = create_from_path(&mut zip_writer, directory.as_ref(), "", options)?;
+    Ok(finish_archive(&mut zip_writer, &report)?.inner.into_inner())
+}
+
+/// Creates a zip archive in memory that contains the given in-memory entries, and returns the
+/// bytes of the archive. See `zip_create_from_memory` for how the entries are written.
+/// # Errors
+/// Will return `ZipError` if an entry name is invalid, if two entries have the same name, if the
+/// compression method is not available, or if the archive would exceed the maximum size.
+pub fn zip_create_in_memory_from_entries<I>(
+    entries: I,
+    options: CreateOptions,
+) -> ZipResult<Vec<u8>>
+where
+    I: IntoIterator<Item = (String, Vec<u8>)>,
+{
+    let buffer = io::Cursor::new(Vec::new());
+    let mut zip_writer = ZipWriter::new(SizeLimitedWriter::new(buffer, options.max_archive_size)?);
+    let report = create_from_memory_entries(&mut zip_writer, entries, options)?;
+    Ok(finish_archive(&mut zip_writer, &report)?.inner.into_inner())
+}
+
+/// Describes a volume that has been written by `zip_create_split`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArchiveVolume {
+    /// The path of the volume.
+    pub path: PathBuf,
+    /// The size of the volume, in bytes.
+    pub size: u64,
+}
+
+/// Creates a set of zip archives that together contain the files and directories from the
+/// specified directory, each no larger than the given volume size.
+///
+/// The underlying zip writer can not produce a spanned archive (`base.z01`, `base.z02`, ...,
+/// `base.zip`), so the volumes are independent archives named `base-001.zip`, `base-002.zip` and so
+/// on, next to the base path, that can be extracted one after another. No entry is split across
+/// volumes. The archive is first written to a new temporary file with a unique name next to the
+/// base path, whose entries are then copied to the volumes without being compressed again. Returns
+/// the volumes in the order they have been written; if creation fails, the volumes are removed.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on directory or volumes, if the compression
+/// method is not available, if a single entry does not fit into a volume, or if entries are
+/// encrypted with AES-256.
+pub fn zip_create_split<P1: AsRef<Path>, P2: AsRef<Path>>(
+    directory: P1,
+    base_path: P2,
+    volume_size: u64,
+    options: CreateOptions,
+) -> ZipResult<Vec<ArchiveVolume>> {
+    let base_path = base_path.as_ref();
+    let directory = directory.as_ref();
+    options.file_options()?;
+    options.forbid_aes_encryption(
+        "AES-256 encrypted entries can not be split into volumes, since the entries are copied \
+         without their AES extra field. Use `EncryptionMethod::ZipCrypto` instead.",
+    )?;
+    let scratch_file = temporary_file_path(base_path.parent().unwrap_or(base_path), base_path);
+    ensure_archive_outside_directory(&scratch_file, directory)?;
+    let mut volumes: Vec<ArchiveVolume> = Vec::new();
+    let result = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&scratch_file)
+        .map_err(ZipError::Io)
+        .and_then(|file| {
+            let max_archive_size = options.max_archive_size;
+            let mut zip_writer = ZipWriter::new(SizeLimitedWriter::new(file, max_archive_size)?);
+            let report = create_from_path(&mut zip_writer, directory, "", options)?;
+            finish_archive(&mut zip_writer, &report)?;
+            Ok(())
+        })
+        .and_then(|()| split_archive(&scratch_file, base_path, volume_size, &mut volumes));
+    std::fs::remove_file(&scratch_file).unwrap_or_default();
+    if result.is_err() {
+        for volume in &volumes {
+            std::fs::remove_file(&volume.path).unwrap_or_default();
+        }
+    }
+    result.map(|()| volumes)
+}
+
+/// Creates a zip archive that contains the files and directories from several directories, each
+/// optionally placed under an entry name prefix.
+///
+/// Directories that occur in more than one source are merged. If two sources contain a file with
+/// the same entry name, creation fails with `ErrorKind::AlreadyExists` and the partially written
+/// archive file is removed, unless `CreateOptions::duplicate_entries` allows duplicates. The
+/// filter of the options is applied to every source, with paths relative to that source.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directories, if the archive
+/// file would be created inside one of the directories, if the compression method is not
+/// available, or if two sources contain the same file entry.
+pub fn zip_create_from_directories<P: AsRef<Path>>(
+    archive_file: P,
+    sources: &[(PathBuf, Option<PathBuf>)],
+    options: CreateOptions,
+) -> ZipResult<CreateReport> {
+    let archive_file = archive_file.as_ref();
+    options.file_options()?;
+    for (directory, _) in sources {
+        ensure_archive_outside_directory(archive_file, directory)?;
+    }
+    let sources: Vec<(&Path, String)> = sources
+        .iter()
+        .map(|(directory, prefix)| {
+            let prefix = prefix.as_deref().map(validate_entry_name).transpose()?;
+            Ok((directory.as_path(), prefix.unwrap_or_default()))
+        })
+        .collect::<ZipResult<_>>()?;
+    create_archive_from_sources(archive_file, &sources, options)
+}
+
+/// Creates a zip archive that contains the given files, each stored under the given entry name,
+/// in the given order.
+///
+/// The entries are pairs of an entry name and the path of the source file. Entry names must be
+/// relative and must not contain `..`; they are stored with forward slashes as separators. A
+/// source path that is a directory adds the directory and its contents under the entry name.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or files, or if an entry name is
+/// invalid. The partially written archive file is removed.
+pub fn zip_create_from_files<P1: AsRef<Path>, P2: AsRef<Path>, P3: AsRef<Path>>(
+    archive_file: P1,
+    entries: &[(P2, P3)],
 ) -> ZipResult<()> {
-    let file = File::create(archive_file)?;
-    let mut zip_writer = ZipWriter::new(file);
-    zip_writer.create_from_directory_with_options(directory, options)
+    zip_create_from_files_with_options(archive_file, entries, CreateOptions::default())?;
+    Ok(())
 }
 
-pub trait ZipWriterExtensions {
-    /// Creates a zip archive that contains the files and directories from the specified directory.
+/// Creates a zip archive that contains the given files, each stored under the given entry name,
+/// uses the specified options.
+///
+/// Source paths that are directories are added including their contents, or rejected with
+/// `ErrorKind::InvalidInput` if `CreateOptions::recurse_directories` is disabled. Duplicate entry
+/// names are handled according to `CreateOptions::duplicate_entries`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or files, if an entry name is
+/// invalid, if the compression method is not available, or if a source path is a directory that
+/// may not be recursed into. The partially written archive file is removed.
+pub fn zip_create_from_files_with_options<P1: AsRef<Path>, P2: AsRef<Path>, P3: AsRef<Path>>(
+    archive_file: P1,
+    entries: &[(P2, P3)],
+    options: CreateOptions,
+) -> ZipResult<CreateReport> {
+    let archive_file = archive_file.as_ref();
+    options.file_options()?;
+    let sources = validate_file_sources(entries, &options)?;
+    for (path, _) in &sources {
+        if path.is_dir() {
+            ensure_archive_outside_directory(archive_file, path)?;
+        }
+    }
+    create_archive_from_sources(archive_file, &sources, options)
+}
+
+/// Creates a zip archive that contains exactly the given file, uses the specified options.
+///
+/// The entry name defaults to the file name of the source file. The file is streamed into the
+/// archive without reading it into memory. An existing archive file is only replaced if
+/// `CreateOptions::overwrite` is set.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or file, with
+/// `ErrorKind::AlreadyExists` if the archive file exists, with `ErrorKind::InvalidInput` if the
+/// source is not a file, or if the entry name is invalid. The partially written archive file is
+/// removed.
+pub fn zip_create_from_file<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    source_file: P2,
+    entry_name: Option<&str>,
+    options: CreateOptions,
+) -> ZipResult<()> {
+    let archive_file = archive_file.as_ref();
+    let source_file = source_file.as_ref();
+    if !source_file.is_file() {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            format!("The path {} is not a file.", source_file.display()),
+        )));
+    }
+    let entry_name = match entry_name {
+        Some(entry_name) => Path::new(entry_name),
+        None => source_file.file_name().map(Path::new).ok_or_else(|| {
+            Error::new(
+                ErrorKind::InvalidInput,
+                format!("The path {} has no file name.", source_file.display()),
+            )
+        })?,
+    };
+    if !options.overwrite && archive_file.exists() {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("The archive {} already exists.", archive_file.display()),
+        )));
+    }
+    zip_create_from_files_with_options(archive_file, &[(entry_name, source_file)], options)?;
+    Ok(())
+}
+
+/// Appends the given files, each stored under the given entry name, to an existing zip archive,
+/// without rewriting the entries it already contains.
+///
+/// The new entries are written after the last existing entry, followed by a new central
+/// directory. An entry name that already exists is handled according to
+/// `CreateOptions::duplicate_entries`: `Fail` fails with `ErrorKind::AlreadyExists`, `KeepFirst`
+/// skips the new file, and `KeepLast` and `AllowDuplicates` write it anyway, so that it shadows
+/// the existing entry for readers that look entries up by name. Directories that already exist
+/// are not written again.
+/// `CreateOptions::max_archive_size` is not applied. If appending fails, the original central
+/// directory is restored, so that the archive keeps its previous contents.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or files, if the archive can not
+/// be read, if an entry name is invalid, if the compression method is not available, or if an
+/// entry name already exists and duplicates are not allowed.
+pub fn zip_append<P1: AsRef<Path>, P2: AsRef<Path>, P3: AsRef<Path>>(
+    archive_file: P1,
+    entries: &[(P2, P3)],
+    options: CreateOptions,
+) -> ZipResult<()> {
+    let sources = validate_file_sources(entries, &options)?;
+    append_to_archive(archive_file.as_ref(), &sources, options)?;
+    Ok(())
+}
+
+/// Appends the files and directories from the specified directory that the archive does not
+/// contain yet to an existing zip archive, without rewriting the entries it already contains,
+/// and returns a report of what has been written.
+///
+/// Entry names are relative to the directory, like with `zip_create_from_directory`, and files
+/// whose entry name already exists in the archive are skipped and counted as duplicates, without
+/// comparing their contents. This lets an archive accumulate the files of a directory across
+/// several runs, such as for incremental backups; an interrupted run leaves the archive with its
+/// previous contents, so the next run picks up the remaining files. See `zip_append` for how the
+/// entries are written.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directory, if the archive can
+/// not be read, or if the archive is located inside the directory.
+pub fn zip_append_directory<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    source_dir: P2,
+) -> ZipResult<CreateReport> {
+    let archive_file = archive_file.as_ref();
+    let source_dir = source_dir.as_ref();
+    ensure_archive_outside_directory(archive_file, source_dir)?;
+    let options = CreateOptions::default().duplicate_entries(DuplicateEntryPolicy::KeepFirst);
+    append_to_archive(archive_file, &[(source_dir, String::new())], options)
+}
+
+/// Appends the given sources, each stored under the given entry name, to an existing zip
+/// archive. See `zip_append`.
+fn append_to_archive(
+    archive_file: &Path,
+    sources: &[(&Path, String)],
+    mut options: CreateOptions,
+) -> ZipResult<CreateReport> {
+    let file_options = options.file_options()?;
+    let shadows_existing = matches!(
+        options.duplicate_entries,
+        DuplicateEntryPolicy::KeepLast | DuplicateEntryPolicy::AllowDuplicates
+    );
+    let archive = open_archive(archive_file)?;
+    let existing_entries = archive.len();
+    let mut entry_names: HashSet<String> = archive
+        .file_names()
+        .filter(|name| name.ends_with('/') || !shadows_existing)
+        .map(|name| options.entry_key(name))
+        .collect();
+    drop(archive);
+
+    let mut report = CreateReport::default();
+    let mut pending_entries: Vec<PendingEntry> = Vec::new();
+    for (path, entry_name) in sources {
+        collect_entries(
+            path,
+            entry_name,
+            &mut options,
+            file_options,
+            &mut pending_entries,
+            &mut report,
+        )?;
+    }
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(archive_file)?;
+    let original_tail = ArchiveTail::read(&mut file)?;
+    let mut zip_writer = ZipWriter::new_append(file)?;
+    let result = write_entries(
+        &mut zip_writer,
+        &mut options,
+        pending_entries,
+        &mut entry_names,
+        &mut report,
+    )
+    .and_then(|()| {
+        report.offset_entries(existing_entries);
+        finish_archive(&mut zip_writer, &report).map(|_| ())
+    });
+    if let Err(error) = result {
+        drop(zip_writer);
+        original_tail.restore(&mut OpenOptions::new().write(true).open(archive_file)?)?;
+        return Err(error);
+    }
+    Ok(report)
+}
+
+/// Sets the comment of an existing zip archive, by rewriting just the end-of-central-directory
+/// record in place, without touching the entry data. Comments longer than 65,535 bytes are
+/// rejected, and the comment is written as UTF-8; see `CreateOptions::archive_comment`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, if the archive has no valid
+/// end-of-central-directory record, or if the comment is too long.
+pub fn zip_set_comment<P: AsRef<Path>>(archive_file: P, comment: &str) -> ZipResult<()> {
+    let comment = comment.as_bytes();
+    let comment_length = check_comment_length(comment)?;
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(archive_file)?;
+    let eocd = find_end_of_central_directory(&mut file)?.ok_or_else(|| {
+        Error::new(
+            ErrorKind::InvalidData,
+            "The archive has no valid end-of-central-directory record.",
+        )
+    })?;
+    // The comment length is the last field of the record, directly followed by the comment.
+    let comment_position = eocd.position + 22;
+    file.seek(SeekFrom::Start(comment_position - 2))?;
+    file.write_all(&comment_length.to_le_bytes())?;
+    file.write_all(comment)?;
+    file.set_len(comment_position + comment.len() as u64)?;
+    Ok(())
+}
+
+/// The new contents of an entry that is replaced by `zip_replace_entries`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplacementSource {
+    /// The contents and modification time of a file.
+    File(PathBuf),
+    /// The given bytes.
+    Bytes(Vec<u8>),
+}
+
+/// Replaces the contents of entries in an existing zip archive, and adds the entries that do not
+/// exist yet at its end.
+///
+/// The archive is rewritten to a temporary file next to it, which is then renamed over the
+/// original, so that the original remains intact if anything fails. Unaffected entries are copied
+/// without being decompressed, and keep their position, modification time, permissions and
+/// comment, as does the archive comment; the zip writer does not carry over the extra fields of
+/// individual entries, though. Replaced entries keep their position and compression method, if
+/// that method is available, and are stored otherwise.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or files, if the archive can not
+/// be read, if an entry name is invalid, or if an entry is replaced more than once.
+pub fn zip_replace_entries<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    replacements: &[(P2, ReplacementSource)],
+) -> ZipResult<()> {
+    let archive_file = archive_file.as_ref();
+    let mut pending_replacements: Vec<(String, &ReplacementSource)> = Vec::new();
+    for (entry_name, source) in replacements {
+        let entry_name = validate_entry_name(entry_name.as_ref())?;
+        if pending_replacements
+            .iter()
+            .any(|(name, _)| *name == entry_name)
+        {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!("The entry {entry_name} is replaced more than once."),
+            )));
+        }
+        pending_replacements.push((entry_name, source));
+    }
+
+    rewrite_archive(archive_file, |archive, zip_writer, entry_comments| {
+        write_replaced_entries(archive, zip_writer, pending_replacements, entry_comments)
+    })
+}
+
+/// Options that control how entries are removed from an archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct RemoveOptions {
+    cascade: bool,
+}
+
+impl RemoveOptions {
+    /// Sets whether removing a directory also removes all entries beneath it, including those
+    /// of a directory that has no entry of its own.
+    #[must_use]
+    pub fn cascade(mut self, cascade: bool) -> RemoveOptions {
+        self.cascade = cascade;
+        self
+    }
+}
+
+/// Removes the named entries from an existing zip archive, and returns the number of entries
+/// that have been removed. See `zip_remove_entries_with_options`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, if the archive can not be read,
+/// or if one of the names does not exist in the archive.
+pub fn zip_remove_entries<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    names: &[P2],
+) -> ZipResult<usize> {
+    zip_remove_entries_with_options(archive_file, names, RemoveOptions::default())
+}
+
+/// Removes the named entries from an existing zip archive, uses the specified options, and
+/// returns the number of entries that have been removed.
+///
+/// Names are compared with forward slashes as separators, and name directories with or without
+/// a trailing slash. If a name does not exist in the archive, removal fails with
+/// `ErrorKind::NotFound`, listing all names that do not exist, and the archive is not changed.
+/// The archive is rewritten like by `zip_replace_entries`; surviving entries are copied without
+/// being decompressed.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, if the archive can not be read,
+/// or if one of the names does not exist in the archive.
+pub fn zip_remove_entries_with_options<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    names: &[P2],
+    options: RemoveOptions,
+) -> ZipResult<usize> {
+    let names: Vec<String> = names.iter().map(path_to_entry_name).collect();
+    rewrite_archive(
+        archive_file.as_ref(),
+        |archive, zip_writer, entry_comments| {
+            let mut found = vec![false; names.len()];
+            let removed: Vec<bool> = stored_entry_names(archive)?
+                .iter()
+                .map(|entry_name| {
+                    let entry_path = entry_name.trim_end_matches('/');
+                    let mut is_removed = false;
+                    for (name, found) in names.iter().zip(found.iter_mut()) {
+                        let is_beneath = options.cascade
+                            && entry_path
+                                .strip_prefix(name.as_str())
+                                .is_some_and(|rest| rest.starts_with('/'));
+                        if entry_path == name || is_beneath {
+                            *found = true;
+                            is_removed = true;
+                        }
+                    }
+                    is_removed
+                })
+                .collect();
+            let missing: Vec<&str> = names
+                .iter()
+                .zip(&found)
+                .filter(|(_, found)| !**found)
+                .map(|(name, _)| name.as_str())
+                .collect();
+            if !missing.is_empty() {
+                return Err(ZipError::Io(Error::new(
+                    ErrorKind::NotFound,
+                    format!(
+                        "The entries {} do not exist in the archive.",
+                        missing.join(", ")
+                    ),
+                )));
+            }
+            copy_surviving_entries(archive, zip_writer, &removed, entry_comments)
+        },
+    )
+}
+
+/// Options that control how archives are merged.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MergeOptions {
+    duplicate_entries: DuplicateEntryPolicy,
+    concatenate_comments: bool,
+}
+
+impl MergeOptions {
+    /// Sets what happens when several inputs contain a file entry with the same name.
+    /// Directories that occur in more than one input are always merged.
+    #[must_use]
+    pub fn duplicate_entries(mut self, policy: DuplicateEntryPolicy) -> MergeOptions {
+        self.duplicate_entries = policy;
+        self
+    }
+
+    /// Sets whether the comments of the inputs are joined, separated by line breaks, to become
+    /// the comment of the merged archive. Merging fails before anything is written if the joined
+    /// comment is longer than 65,535 bytes. By default, the comments are dropped.
+    #[must_use]
+    pub fn concatenate_comments(mut self, concatenate: bool) -> MergeOptions {
+        self.concatenate_comments = concatenate;
+        self
+    }
+}
+
+/// A file entry name that occurs in several inputs of a merge.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MergeConflict {
+    /// The entry name.
+    pub name: String,
+    /// The input whose entry has been written.
+    pub kept: PathBuf,
+    /// The inputs whose entries have been skipped, in the order of the inputs.
+    pub skipped: Vec<PathBuf>,
+}
+
+/// Summarizes the outcome of merging archives.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MergeReport {
+    /// The number of entries that have been written.
+    pub entries_written: usize,
+    /// The file entry names that occur in several inputs, in the order they are first found.
+    pub conflicts: Vec<MergeConflict>,
+}
+
+/// Merges several zip archives into a new one, handling file entries that occur in several
+/// inputs according to the policy. See `zip_merge_with_options`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on output or inputs, if an input can not be
+/// read, or if several inputs contain the same file entry and duplicates are not allowed.
+pub fn zip_merge<P1: AsRef<Path>, P2: AsRef<Path>>(
+    output_file: P1,
+    inputs: &[P2],
+    conflict: DuplicateEntryPolicy,
+) -> ZipResult<MergeReport> {
+    let options = MergeOptions::default().duplicate_entries(conflict);
+    zip_merge_with_options(output_file, inputs, options)
+}
+
+/// Merges several zip archives into a new one, uses the specified options.
+///
+/// Entries are written in the order of the inputs, and are copied without being decompressed,
+/// so that memory use does not depend on the size of the inputs and any compression method is
+/// accepted. With `DuplicateEntryPolicy::KeepFirst`, the entry of the first input that contains a
+/// name is written; with `KeepLast`, that of the last; with `AllowDuplicates`, all of them, and
+/// the conflicts list no skipped inputs. If merging fails, the partially written output file is
+/// removed.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on output or inputs, if an input can not be
+/// read, if the output is one of the inputs, if several inputs contain the same file entry and
+/// duplicates are not allowed, or if the concatenated comments are too long.
+pub fn zip_merge_with_options<P1: AsRef<Path>, P2: AsRef<Path>>(
+    output_file: P1,
+    inputs: &[P2],
+    options: MergeOptions,
+) -> ZipResult<MergeReport> {
+    let output_file = output_file.as_ref();
+    if let Ok(canonical_output) = output_file.canonicalize() {
+        for input in inputs {
+            if input.as_ref().canonicalize().ok().as_ref() == Some(&canonical_output) {
+                return Err(ZipError::Io(Error::new(
+                    ErrorKind::InvalidInput,
+                    "The output file must not be one of the inputs.",
+                )));
+            }
+        }
+    }
+
+    let mut archives = Vec::with_capacity(inputs.len());
+    let mut comments: Vec<Vec<u8>> = Vec::new();
+    // The input and entry numbers of the entries with each name.
+    let mut sources: HashMap<String, Vec<(usize, usize)>> = HashMap::new();
+    let mut report = MergeReport::default();
+    for (input_number, input) in inputs.iter().enumerate() {
+        let mut archive = open_archive(input)?;
+        if !archive.comment().is_empty() {
+            comments.push(archive.comment().to_vec());
+        }
+        for (file_number, name) in stored_entry_names(&mut archive)?.into_iter().enumerate() {
+            let entries = sources.entry(name.clone()).or_default();
+            if entries.len() == 1 && !name.ends_with('/') {
+                report.conflicts.push(MergeConflict {
+                    name,
+                    kept: PathBuf::new(),
+                    skipped: Vec::new(),
+                });
+            }
+            entries.push((input_number, file_number));
+        }
+        archives.push(archive);
+    }
+
+    if options.duplicate_entries == DuplicateEntryPolicy::Fail {
+        if let Some(conflict) = report.conflicts.first() {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "The entry {} is contained in several inputs.",
+                    conflict.name
+                ),
+            )));
+        }
+    }
+    let comment = options.concatenate_comments.then(|| comments.join(&b'\n'));
+    comment.as_deref().map(check_comment_length).transpose()?;
+    for conflict in &mut report.conflicts {
+        let mut entries = sources[&conflict.name].clone();
+        let kept = match options.duplicate_entries {
+            DuplicateEntryPolicy::KeepLast => entries.pop(),
+            DuplicateEntryPolicy::AllowDuplicates => {
+                let first = entries.first().copied();
+                entries.clear();
+                first
+            }
+            _ => Some(entries.remove(0)),
+        };
+        let input_path =
+            |(input_number, _): (usize, usize)| inputs[input_number].as_ref().to_path_buf();
+        conflict.kept = kept.map(input_path).unwrap_or_default();
+        conflict.skipped = entries.into_iter().map(input_path).collect();
+    }
+
+    let written = File::create(output_file)
+        .map_err(ZipError::from)
+        .and_then(|file| {
+            let mut zip_writer = ZipWriter::new(file);
+            if let Some(comment) = comment {
+                zip_writer.set_raw_comment(comment);
+            }
+            let mut written = 0;
+            for (input_number, archive) in archives.iter_mut().enumerate() {
+                for (file_number, name) in stored_entry_names(archive)?.iter().enumerate() {
+                    let entries = &sources[name];
+                    let is_file = !name.ends_with('/');
+                    let current = (input_number, file_number);
+                    let winner = match options.duplicate_entries {
+                        DuplicateEntryPolicy::KeepLast if is_file => entries.last(),
+                        DuplicateEntryPolicy::AllowDuplicates if is_file => Some(&current),
+                        _ => entries.first(),
+                    };
+                    if winner == Some(&current) {
+                        copy_raw_entry(&mut zip_writer, archive, file_number)?;
+                        written += 1;
+                    }
+                }
+            }
+            zip_writer.finish()?;
+            Ok(written)
+        });
+    match written {
+        Ok(written) => {
+            report.entries_written = written;
+            Ok(report)
+        }
+        Err(error) => {
+            std::fs::remove_file(output_file).unwrap_or_default();
+            Err(error)
+        }
+    }
+}
+
+/// The archive that `copy_entries_raw` copies entries into. Unlike a `ZipWriter`, it knows the
+/// names of the entries it contains, so that duplicate names can be detected, and it writes the
+/// comments of the copied entries into the central directory when it is finished, which the
+/// `zip` crate can not do. Dropping it without calling `finish` finishes the archive without the
+/// entry comments.
+pub struct RawCopyWriter<W: Read + Write + io::Seek> {
+    zip_writer: ZipWriter<W>,
+    /// The names of the entries, without the trailing slash of directories.
+    entry_names: HashSet<String>,
+    entry_count: usize,
+    /// The comments of the copied entries that have one, with the index of their entry.
+    entry_comments: Vec<(usize, String)>,
+}
+
+impl<W: Read + Write + io::Seek> RawCopyWriter<W> {
+    /// Starts a new archive in the given writer.
+    #[must_use]
+    pub fn new(inner: W) -> RawCopyWriter<W> {
+        RawCopyWriter {
+            zip_writer: ZipWriter::new(inner),
+            entry_names: HashSet::new(),
+            entry_count: 0,
+            entry_comments: Vec::new(),
+        }
+    }
+
+    /// Opens an existing archive, so that entries are copied after the entries it contains and
+    /// compared with their names.
     /// # Errors
-    /// Will return `ZipError` for relevant file io error on archive or directory.
-    fn create_from_directory<P: AsRef<Path>>(&mut self, directory: P) -> ZipResult<()>;
+    /// Will return `ZipError` for relevant io error, or if the archive can not be read.
+    pub fn new_append(inner: W) -> ZipResult<RawCopyWriter<W>> {
+        let mut archive = ZipArchive::new(inner)?;
+        let entry_names = stored_entry_names(&mut archive)?
+            .into_iter()
+            .map(|name| name.trim_end_matches('/').to_string())
+            .collect();
+        let entry_count = archive.len();
+        Ok(RawCopyWriter {
+            zip_writer: ZipWriter::new_append(archive.into_inner())?,
+            entry_names,
+            entry_count,
+            entry_comments: Vec::new(),
+        })
+    }
 
-    /// Creates a zip archive that contains the files and directories from the specified directory, uses the specified compression level.
+    /// Finishes the archive, writes the comments of the copied entries and returns the writer.
     /// # Errors
-    /// Will return `ZipError` for relevant file io error on archive or directory.
-    fn create_from_directory_with_options<P: AsRef<Path>>(
-        &mut self,
-        directory: P,
-        options: FileOptions,
-    ) -> ZipResult<()>;
+    /// Will return `ZipError` for relevant io error on the writer.
+    pub fn finish(mut self) -> ZipResult<W> {
+        let mut inner = self.zip_writer.finish()?;
+        write_entry_comments(&mut inner, &self.entry_comments)?;
+        Ok(inner)
+    }
 }
 
-#[allow(deprecated)]
-impl<W: Write + io::Seek> ZipWriterExtensions for ZipWriter<W> {
-    fn create_from_directory<P: AsRef<Path>>(&mut self, directory: P) -> ZipResult<()> {
-        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
-        self.create_from_directory_with_options(directory, options)
+/// Copies the named entries of an archive to a zip writer without decompressing them, and
+/// returns the number of entries that have been copied. See `copy_entries_raw_with_policy`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or writer, if one of the names
+/// does not exist in the archive, or if the archive contains several file entries with one of the
+/// names, or the writer already contains one of them.
+pub fn copy_entries_raw<W: Read + Write + io::Seek, R: Read + io::Seek, P: AsRef<Path>>(
+    dest_writer: &mut RawCopyWriter<W>,
+    src: &mut ZipArchive<R>,
+    names: &[P],
+) -> ZipResult<usize> {
+    copy_entries_raw_with_policy(dest_writer, src, names, DuplicateEntryPolicy::Fail)
+}
+
+/// Copies the named entries of an archive to a zip writer without decompressing them, handling
+/// names that occur several times according to the policy, and returns the number of entries
+/// that have been copied.
+///
+/// The compressed data, compression method, CRC-32, modification time and comment of every
+/// entry are copied as they are, in the order of the names; the comments are written when the
+/// writer is finished. Names are compared with forward slashes as separators, and name
+/// directories with or without a trailing slash. If the archive contains several file entries
+/// with a requested name, `DuplicateEntryPolicy::Fail` fails with `ErrorKind::AlreadyExists`,
+/// `KeepFirst` or `KeepLast` copies the first or last of them, and `AllowDuplicates` all of
+/// them. A name that the writer already contains is handled the way `zip_append` handles it:
+/// `Fail` fails with `ErrorKind::AlreadyExists`, `KeepFirst` skips the entry, and `KeepLast` and
+/// `AllowDuplicates` copy it anyway, so that it shadows the existing entry. Directories that the
+/// writer already contains are not copied again. A name requested twice is copied once. If a
+/// name does not exist in the archive, copying fails with `ErrorKind::NotFound`, listing all
+/// names that do not exist. The names are checked before anything is written.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or writer, if one of the names
+/// does not exist in the archive, or if the archive contains several file entries with one of the
+/// names, or the writer already contains one of them, and duplicates are not allowed.
+pub fn copy_entries_raw_with_policy<
+    W: Read + Write + io::Seek,
+    R: Read + io::Seek,
+    P: AsRef<Path>,
+>(
+    dest_writer: &mut RawCopyWriter<W>,
+    src: &mut ZipArchive<R>,
+    names: &[P],
+    policy: DuplicateEntryPolicy,
+) -> ZipResult<usize> {
+    let stored_names = stored_entry_names(src)?;
+    let mut requested: HashSet<String> = HashSet::new();
+    let mut missing: Vec<String> = Vec::new();
+    let mut file_numbers: Vec<usize> = Vec::new();
+    for name in names.iter().map(path_to_entry_name) {
+        if !requested.insert(name.clone()) {
+            continue;
+        }
+        let mut matches: Vec<usize> = (0..stored_names.len())
+            .filter(|file_number| stored_names[*file_number].trim_end_matches('/') == name)
+            .collect();
+        let is_file = matches
+            .first()
+            .is_some_and(|file_number| !stored_names[*file_number].ends_with('/'));
+        if !matches.is_empty() && dest_writer.entry_names.contains(&name) {
+            match policy {
+                _ if !is_file => continue,
+                DuplicateEntryPolicy::Fail => {
+                    return Err(ZipError::Io(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("The destination already contains the entry {name}."),
+                    )));
+                }
+                DuplicateEntryPolicy::KeepFirst => continue,
+                DuplicateEntryPolicy::KeepLast | DuplicateEntryPolicy::AllowDuplicates => {}
+            }
+        }
+        match matches.len() {
+            0 => missing.push(name),
+            1 => {}
+            _ if !is_file => matches.truncate(1),
+            _ => match policy {
+                DuplicateEntryPolicy::Fail => {
+                    return Err(ZipError::Io(Error::new(
+                        ErrorKind::AlreadyExists,
+                        format!("The archive contains the entry {name} more than once."),
+                    )));
+                }
+                DuplicateEntryPolicy::KeepFirst => matches.truncate(1),
+                DuplicateEntryPolicy::KeepLast => {
+                    matches.drain(..matches.len() - 1);
+                }
+                DuplicateEntryPolicy::AllowDuplicates => {}
+            },
+        }
+        file_numbers.extend(matches);
+    }
+    if !missing.is_empty() {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::NotFound,
+            format!(
+                "The entries {} do not exist in the archive.",
+                missing.join(", ")
+            ),
+        )));
+    }
+    for file_number in &file_numbers {
+        let comment = copy_raw_entry(&mut dest_writer.zip_writer, src, *file_number)?;
+        if !comment.is_empty() {
+            dest_writer
+                .entry_comments
+                .push((dest_writer.entry_count, comment));
+        }
+        dest_writer.entry_count += 1;
+        let name = stored_names[*file_number].trim_end_matches('/');
+        dest_writer.entry_names.insert(name.to_string());
     }
+    Ok(file_numbers.len())
+}
 
-    fn create_from_directory_with_options<P: AsRef<Path>>(
-        &mut self,
-        directory: P,
-        options: FileOptions,
-    ) -> ZipResult<()> {
-        let mut paths_queue: Vec<PathBuf> = vec![];
-        paths_queue.push(directory.as_ref().to_path_buf());
+/// The compressed sizes of a file entry that has been recompressed by `zip_recompress`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecompressedEntry {
+    /// The entry name.
+    pub name: String,
+    /// The compressed size of the entry in the input archive, in bytes.
+    pub size_before: u64,
+    /// The compressed size of the entry in the output archive, in bytes.
+    pub size_after: u64,
+}
+
+/// Summarizes the outcome of recompressing an archive.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RecompressReport {
+    /// The size of the input archive, in bytes.
+    pub size_before: u64,
+    /// The size of the output archive, in bytes.
+    pub size_after: u64,
+    /// The compressed sizes of the file entries, in the order of the archive.
+    pub entries: Vec<RecompressedEntry>,
+}
 
-        let mut buffer = Vec::new();
+impl RecompressReport {
+    /// Returns by how many bytes the output archive is larger than the input archive, which is
+    /// negative if it is smaller.
+    #[must_use]
+    pub fn size_increase(&self) -> i128 {
+        i128::from(self.size_after) - i128::from(self.size_before)
+    }
+}
 
-        while let Some(next) = paths_queue.pop() {
-            let directory_entry_iterator = std::fs::read_dir(next)?;
+/// Writes a copy of an archive whose file entries are compressed again with the compression
+/// method and level of the options, such as to deflate an archive of stored entries.
+///
+/// Entries are decompressed and compressed again one at a time, in chunks of
+/// `CreateOptions::chunk_size`, so memory use does not depend on the size of the entries. The
+/// CRC-32 of every entry is verified while it is decompressed. Entry names, order, modification
+/// times and permissions, and the archive comment are preserved, and the compression method can
+/// be chosen per entry with `CreateOptions::compression_method_for_extension` and
+/// `CreateOptions::compression_method_selector`. Symbolic links are copied as they are. Entry
+/// comments are preserved as well, written into the central directory once the archive is
+/// finished. File entries are encrypted if `CreateOptions::password` is set; AES-256 encrypted
+/// entries are compressed into memory or a temporary file before they are encrypted. If
+/// recompressing fails, the partially written output file is removed.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on input or output, if the input can not be
+/// read, if the CRC-32 of an entry does not match its contents, if the output is the input, or if
+/// the compression method is not available.
+pub fn zip_recompress<P1: AsRef<Path>, P2: AsRef<Path>>(
+    input_file: P1,
+    output_file: P2,
+    mut options: CreateOptions,
+) -> ZipResult<RecompressReport> {
+    let input_file = input_file.as_ref();
+    let output_file = output_file.as_ref();
+    if let Ok(canonical_output) = output_file.canonicalize() {
+        if input_file.canonicalize().ok() == Some(canonical_output) {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The output file must not be the input file.",
+            )));
+        }
+    }
+    let file_options = options.file_options()?;
+    let mut archive = open_archive(input_file)?;
 
-            for entry in directory_entry_iterator {
-                let entry_path = entry?.path();
-                let entry_metadata = std::fs::metadata(entry_path.clone())?;
-                if entry_metadata.is_file() {
-                    let mut f = File::open(&entry_path)?;
-                    f.read_to_end(&mut buffer)?;
-                    let relative_path = make_relative_path(&directory, &entry_path);
-                    self.start_file_from_path(&relative_path, options)?;
-                    self.write_all(buffer.as_ref())?;
-                    buffer.clear();
-                } else if entry_metadata.is_dir() {
-                    let relative_path = make_relative_path(&directory, &entry_path);
-                    self.add_directory_from_path(&relative_path, options)?;
-                    paths_queue.push(entry_path.clone());
+    let result = create_archive_file(output_file)
+        .map_err(ZipError::from)
+        .and_then(|file| {
+            let mut zip_writer = ZipWriter::new(file);
+            zip_writer.set_raw_comment(archive.comment().to_vec());
+            let mut entry_comments = Vec::with_capacity(archive.len());
+            let mut encrypted_entries = Vec::new();
+            for file_number in 0..archive.len() {
+                let (comment, encrypted_size) = recompress_entry(
+                    &mut zip_writer,
+                    &mut archive,
+                    file_number,
+                    &mut options,
+                    file_options,
+                )?;
+                entry_comments.push(comment);
+                if let Some(size) = encrypted_size {
+                    encrypted_entries.push((file_number, size));
                 }
             }
+            let mut file = zip_writer.finish()?;
+            write_entry_comments(&mut file, &indexed_comments(&entry_comments))?;
+            mark_aes_entries(&mut file, &encrypted_entries)?;
+            recompress_report(input_file, output_file, &mut archive)
+        });
+    if result.is_err() {
+        std::fs::remove_file(output_file).unwrap_or_default();
+    }
+    result
+}
+
+/// Writes a copy of an archive whose file entries are all stored without compression, so that
+/// their data can be read in place, such as from a memory-mapped file. See `zip_recompress` for
+/// what is preserved, including the entry and archive comments, and what is verified; the report
+/// tells how much larger the archive has become.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on input or output, if the input can not be
+/// read, if the CRC-32 of an entry does not match its contents, or if the output is the input.
+pub fn zip_convert_to_stored<P1: AsRef<Path>, P2: AsRef<Path>>(
+    input_file: P1,
+    output_file: P2,
+) -> ZipResult<RecompressReport> {
+    let options = CreateOptions::default().compression_method(CompressionMethod::Stored);
+    zip_recompress(input_file, output_file, options)
+}
+
+/// Selects which metadata `zip_minimize` keeps. By default, all optional extra fields and all
+/// comments are removed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct MinimizeKeep {
+    /// Keeps the extended timestamp and NTFS extra fields, which store precise modification,
+    /// access and creation times.
+    pub timestamps: bool,
+    /// Keeps the Info-ZIP Unix and PKWARE Unix extra fields, which store the user and group IDs
+    /// of the entries.
+    pub unix_owners: bool,
+    /// Keeps the entry comments, along with the Info-ZIP Unicode comment extra field.
+    pub entry_comments: bool,
+    /// Keeps the archive comment.
+    pub archive_comment: bool,
+}
+
+impl MinimizeKeep {
+    /// Sets whether the extended timestamp and NTFS extra fields are kept.
+    #[must_use]
+    pub fn timestamps(mut self, timestamps: bool) -> Self {
+        self.timestamps = timestamps;
+        self
+    }
+
+    /// Sets whether the extra fields that store user and group IDs are kept.
+    #[must_use]
+    pub fn unix_owners(mut self, unix_owners: bool) -> Self {
+        self.unix_owners = unix_owners;
+        self
+    }
+
+    /// Sets whether the entry comments are kept.
+    #[must_use]
+    pub fn entry_comments(mut self, entry_comments: bool) -> Self {
+        self.entry_comments = entry_comments;
+        self
+    }
+
+    /// Sets whether the archive comment is kept.
+    #[must_use]
+    pub fn archive_comment(mut self, archive_comment: bool) -> Self {
+        self.archive_comment = archive_comment;
+        self
+    }
+
+    /// Returns whether an extra field with the given header ID is kept. The AES encryption and
+    /// Unicode path extra fields are always kept, since entries can not be read correctly without
+    /// them, and Zip64 extra fields are written again as needed.
+    fn keeps_extra_field(self, id: u16) -> bool {
+        match id {
+            0x9901 | 0x7075 => true,
+            0x5455 | 0x000a => self.timestamps,
+            0x7875 | 0x7855 | 0x000d => self.unix_owners,
+            0x6375 => self.entry_comments,
+            _ => false,
         }
+    }
+}
 
-        self.finish()?;
-        Ok(())
+/// The result of `zip_minimize`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MinimizeReport {
+    /// The size of the input archive, in bytes.
+    pub size_before: u64,
+    /// The size of the output archive, in bytes.
+    pub size_after: u64,
+    /// The number of extra fields removed from local and central directory headers.
+    pub extra_fields_removed: usize,
+    /// The number of non-empty entry and archive comments removed.
+    pub comments_removed: usize,
+}
+
+impl MinimizeReport {
+    /// Returns by how many bytes the output archive is smaller than the input archive.
+    #[must_use]
+    pub fn bytes_saved(&self) -> u64 {
+        self.size_before.saturating_sub(self.size_after)
+    }
+}
+
+/// Writes a copy of an archive without the extra fields and comments that `keep` does not
+/// select, such as to shrink archives of many small files for distribution.
+///
+/// The compressed data of the entries is copied as it is, so their CRC-32 values, compression
+/// methods, names and order are unchanged. Data descriptors are dropped in favour of sizes in the
+/// local headers, except for encrypted entries, and data prepended to the archive is not copied.
+/// If minimizing fails, the partially written output file is removed.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on input or output, if the input has no
+/// valid central directory, if it spans several disks, or if the output is the input.
+pub fn zip_minimize<P1: AsRef<Path>, P2: AsRef<Path>>(
+    input_file: P1,
+    output_file: P2,
+    keep: MinimizeKeep,
+) -> ZipResult<MinimizeReport> {
+    let input_file = input_file.as_ref();
+    let output_file = output_file.as_ref();
+    if let Ok(canonical_output) = output_file.canonicalize() {
+        if input_file.canonicalize().ok() == Some(canonical_output) {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                "The output file must not be the input file.",
+            )));
+        }
+    }
+    let mut reader = BufReader::new(File::open(input_file)?);
+    let result = File::create(output_file)
+        .map_err(ZipError::from)
+        .and_then(|file| {
+            let mut writer = io::BufWriter::new(file);
+            let mut report = minimize_archive(&mut reader, &mut writer, keep)?;
+            writer.flush()?;
+            report.size_before = reader.get_ref().metadata()?.len();
+            report.size_after = std::fs::metadata(output_file)?.len();
+            Ok(report)
+        });
+    if result.is_err() {
+        std::fs::remove_file(output_file).unwrap_or_default();
+    }
+    result
+}
+
+/// Copies the entries of the archive to the writer, without the extra fields and comments that
+/// `keep` does not select, followed by a new central directory.
+fn minimize_archive<R: Read + Seek, W: Write>(
+    reader: &mut R,
+    writer: &mut W,
+    keep: MinimizeKeep,
+) -> ZipResult<MinimizeReport> {
+    let eocd = find_end_of_central_directory(reader)?.ok_or_else(|| {
+        ZipError::Io(Error::new(
+            ErrorKind::InvalidData,
+            "The archive has no valid central directory.",
+        ))
+    })?;
+    let mut archive_comment = vec![0; usize::from(eocd.comment_length)];
+    reader.seek(SeekFrom::Start(eocd.position + 22))?;
+    reader.read_exact(&mut archive_comment)?;
+    let data_offset = eocd.data_offset().unwrap_or_default();
+    let mut headers = read_central_directory(reader, &eocd)?;
+
+    let mut report = MinimizeReport::default();
+    let mut kept_extra_fields = |extra_fields: Vec<ExtraField>| -> Vec<ExtraField> {
+        let total = extra_fields.len();
+        let kept: Vec<ExtraField> = extra_fields
+            .into_iter()
+            .filter(|extra_field| keep.keeps_extra_field(extra_field.id))
+            .collect();
+        report.extra_fields_removed += total - kept.len();
+        kept
+    };
+    let mut position = 0;
+    for header in &mut headers {
+        let (data_position, local_extra_fields) =
+            read_local_header(reader, data_offset + header.local_header_offset)?;
+        let local_extra_fields = kept_extra_fields(local_extra_fields);
+        header.extra_fields = kept_extra_fields(std::mem::take(&mut header.extra_fields));
+        if !keep.entry_comments && !header.comment.is_empty() {
+            header.comment.clear();
+            report.comments_removed += 1;
+        }
+        header.drop_data_descriptor();
+        header.local_header_offset = position;
+
+        position += write_local_header(writer, header, &local_extra_fields)?;
+        reader.seek(SeekFrom::Start(data_position))?;
+        let copied = io::copy(&mut reader.take(header.compressed_size), writer)?;
+        if copied != header.compressed_size {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::UnexpectedEof,
+                "The data of an entry is truncated.",
+            )));
+        }
+        position += copied;
+        position += write_data_descriptor(writer, header)?;
+    }
+    let central_directory_offset = position;
+    for header in &headers {
+        position += write_central_directory_header(writer, header)?;
+    }
+    if !keep.archive_comment && !archive_comment.is_empty() {
+        archive_comment.clear();
+        report.comments_removed += 1;
+    }
+    write_end_of_central_directory(
+        writer,
+        headers.len() as u64,
+        (
+            central_directory_offset,
+            position - central_directory_offset,
+        ),
+        &archive_comment,
+    )?;
+    Ok(report)
+}
+
+/// Decompresses an entry of the archive and writes it again with the given options, keeping its
+/// modification time and permissions. Returns the comment of the entry, which has to be written
+/// by `write_entry_comments`, and the uncompressed size of an AES-256 encrypted entry, which has
+/// to be marked by `mark_aes_entries`.
+fn recompress_entry<R: Read + io::Seek>(
+    zip_writer: &mut ZipWriter<File>,
+    archive: &mut ZipArchive<R>,
+    file_number: usize,
+    options: &mut CreateOptions,
+    file_options: FileOptions,
+) -> ZipResult<(String, Option<u64>)> {
+    let mut entry = archive.by_index(file_number)?;
+    if entry
+        .unix_mode()
+        .is_some_and(|mode| mode & 0o170_000 == S_IFLNK)
+    {
+        drop(entry);
+        return Ok((copy_raw_entry(zip_writer, archive, file_number)?, None));
+    }
+    let comment = entry.comment().to_string();
+    let mut entry_options = file_options.last_modified_time(entry.last_modified());
+    if let Some(mode) = entry.unix_mode() {
+        entry_options = entry_options.unix_permissions(mode);
+    }
+    let name = entry.name().to_string();
+    if entry.is_dir() {
+        zip_writer.add_directory(name, entry_options)?;
+        return Ok((comment, None));
+    }
+    let (mut entry_options, _) = options.file_entry_options(entry_options, Path::new(&name))?;
+    if options.force_zip64 || entry.size() > ZIP64_SIZE_THRESHOLD {
+        entry_options = entry_options.large_file(true);
+    }
+    #[cfg(feature = "aes")]
+    if let Some(password) = options.aes_password() {
+        let size = entry.size();
+        let mut compressed =
+            CompressedEntry::compress(&name, &mut entry, entry_options, size, options.chunk_size)?;
+        write_aes_entry(zip_writer, &mut compressed, &name, entry_options, password)?;
+        return Ok((comment, Some(size)));
+    }
+    zip_writer.start_file(name, entry_options)?;
+    copy_in_sized_chunks(&mut entry, zip_writer, options.chunk_size, |_| Ok(()))?;
+    Ok((comment, None))
+}
+
+/// Compares the sizes of a recompressed archive and its file entries with those of the input.
+fn recompress_report<R: Read + io::Seek>(
+    input_file: &Path,
+    output_file: &Path,
+    archive: &mut ZipArchive<R>,
+) -> ZipResult<RecompressReport> {
+    let mut output = open_archive(output_file)?;
+    let mut report = RecompressReport {
+        size_before: std::fs::metadata(input_file)?.len(),
+        size_after: std::fs::metadata(output_file)?.len(),
+        entries: Vec::new(),
+    };
+    for file_number in 0..archive.len() {
+        let before = archive.by_index_raw(file_number)?;
+        if !before.is_dir() {
+            report.entries.push(RecompressedEntry {
+                name: before.name().to_string(),
+                size_before: before.compressed_size(),
+                size_after: output.by_index_raw(file_number)?.compressed_size(),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Describes how `zip_sync_directory` decides whether a file has changed since it was archived.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SyncComparison {
+    /// A file is unchanged if its size and CRC-32 match those of its entry. Files of the same size
+    /// are read in full to compute their CRC-32.
+    #[default]
+    Crc32,
+    /// A file is unchanged if its size and modification time match those of its entry, at the
+    /// two-second resolution of the ZIP format. This does not read the files, but does not work
+    /// with a fixed or deterministic modification time.
+    ModifiedTime,
+}
+
+/// Options that control how a directory is synchronized into an archive.
+#[derive(Default)]
+pub struct SyncOptions {
+    create_options: CreateOptions,
+    comparison: SyncComparison,
+    remove_missing: bool,
+}
+
+impl SyncOptions {
+    /// Sets the options that files and directories are found and written with, as by
+    /// `zip_create_from_directory_with_options`.
+    #[must_use]
+    pub fn create_options(mut self, options: CreateOptions) -> SyncOptions {
+        self.create_options = options;
+        self
+    }
+
+    /// Sets how changed files are detected.
+    #[must_use]
+    pub fn comparison(mut self, comparison: SyncComparison) -> SyncOptions {
+        self.comparison = comparison;
+        self
+    }
+
+    /// Sets whether entries that no longer exist in the directory are removed from the archive.
+    /// They are kept by default.
+    #[must_use]
+    pub fn remove_missing(mut self, remove: bool) -> SyncOptions {
+        self.remove_missing = remove;
+        self
+    }
+}
+
+/// Summarizes the outcome of synchronizing a directory into an archive.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SyncReport {
+    /// The number of files and directories that have been added.
+    pub added: usize,
+    /// The number of entries that have been written again because their file changed.
+    pub updated: usize,
+    /// The number of entries that have been removed because their file no longer exists.
+    pub removed: usize,
+    /// The number of entries that have been kept as they are, including those whose file no
+    /// longer exists if they are not removed.
+    pub unchanged: usize,
+}
+
+/// Synchronizes an existing zip archive with the contents of a directory, without recompressing
+/// the files that have not changed.
+///
+/// The entries of the archive are compared with the files and directories that are found in the
+/// directory, according to `SyncOptions::comparison`. Unchanged entries are copied without being
+/// decompressed and keep their position; updated and added entries are written after them, in
+/// the order they are found. The archive is rewritten to a temporary file next to it, which is
+/// then renamed over the original, so that the original remains intact if anything fails.
+/// Symbolic links that are stored as links are always written again.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directory, if the archive can
+/// not be read, if the archive file is inside the directory, if the compression method is not
+/// available, or if entries are encrypted with AES-256.
+pub fn zip_sync_directory<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    source_dir: P2,
+    options: SyncOptions,
+) -> ZipResult<SyncReport> {
+    let archive_file = archive_file.as_ref();
+    let source_dir = source_dir.as_ref();
+    let SyncOptions {
+        mut create_options,
+        comparison,
+        remove_missing,
+    } = options;
+    let file_options = create_options.file_options()?;
+    create_options.forbid_aes_encryption(
+        "AES-256 encrypted entries can not be synchronized, since unchanged entries are copied \
+         without their AES extra field. Use `EncryptionMethod::ZipCrypto` instead.",
+    )?;
+    ensure_archive_outside_directory(archive_file, source_dir)?;
+    let mut found_entries: Vec<PendingEntry> = Vec::new();
+    collect_entries(
+        source_dir,
+        "",
+        &mut create_options,
+        file_options,
+        &mut found_entries,
+        &mut CreateReport::default(),
+    )?;
+    apply_root_prefix(&create_options, &mut found_entries)?;
+    create_options.root_prefix = None;
+
+    rewrite_archive(archive_file, |archive, zip_writer, entry_comments| {
+        let found_names: HashMap<String, usize> = found_entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (entry.name.clone(), index))
+            .collect();
+        let mut found_entries: Vec<Option<PendingEntry>> =
+            found_entries.into_iter().map(Some).collect();
+        let mut report = SyncReport::default();
+        let mut changed_entries: HashSet<usize> = HashSet::new();
+        for file_number in 0..archive.len() {
+            let (name, stored) = {
+                let entry = archive.by_index_raw(file_number)?;
+                let stored = (
+                    entry.is_dir(),
+                    entry.size(),
+                    entry.crc32(),
+                    entry.last_modified(),
+                );
+                (entry.name().trim_end_matches('/').to_string(), stored)
+            };
+            let found = found_names
+                .get(&name)
+                .and_then(|&index| found_entries[index].as_ref().map(|entry| (index, entry)));
+            match found {
+                None if remove_missing => report.removed += 1,
+                None => {
+                    let comment = copy_raw_entry(zip_writer, archive, file_number)?;
+                    entry_comments.push((report.unchanged, comment));
+                    report.unchanged += 1;
+                }
+                Some((index, entry)) if is_unchanged(entry, stored, comparison)? => {
+                    let comment = copy_raw_entry(zip_writer, archive, file_number)?;
+                    entry_comments.push((report.unchanged, comment));
+                    found_entries[index] = None;
+                    report.unchanged += 1;
+                }
+                Some((index, _)) => {
+                    changed_entries.insert(index);
+                }
+            }
+        }
+        let mut written_entries: Vec<PendingEntry> = Vec::new();
+        for (index, entry) in found_entries.into_iter().enumerate() {
+            let Some(entry) = entry else {
+                continue;
+            };
+            if changed_entries.contains(&index) {
+                report.updated += 1;
+            } else {
+                report.added += 1;
+            }
+            written_entries.push(entry);
+        }
+        write_entries(
+            zip_writer,
+            &mut create_options,
+            written_entries,
+            &mut HashSet::new(),
+            &mut CreateReport::default(),
+        )?;
+        Ok(report)
+    })
+}
+
+/// Removes the entries whose names match the given glob pattern from an existing zip archive,
+/// and returns the number of entries that have been removed, which may be zero.
+///
+/// Entry names are matched with forward slashes as separators and without a trailing slash. In
+/// the pattern, `*` matches any characters within a path segment, `?` matches a single
+/// character, and a `**` segment matches any number of segments, so that `__MACOSX/**` matches
+/// that directory and everything beneath it. The archive is rewritten like by
+/// `zip_replace_entries`.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, or if the archive can not be
+/// read.
+pub fn zip_remove_entries_matching<P: AsRef<Path>>(
+    archive_file: P,
+    pattern: &str,
+) -> ZipResult<usize> {
+    rewrite_archive(
+        archive_file.as_ref(),
+        |archive, zip_writer, entry_comments| {
+            let removed: Vec<bool> = stored_entry_names(archive)?
+                .iter()
+                .map(|entry_name| glob_matches(pattern, entry_name.trim_end_matches('/')))
+                .collect();
+            copy_surviving_entries(archive, zip_writer, &removed, entry_comments)
+        },
+    )
+}
+
+/// Writes a zip archive that contains the given in-memory entries to the writer, and finishes it.
+///
+/// Entries are pairs of an entry name and the contents of the entry, and are written in the
+/// order of the iterator, or sorted by entry name in deterministic mode. Entry names that end
+/// with `/` become directory entries, whose contents are ignored. Entry names must be relative
+/// and must not contain `..`. Since there are no files to take them from, modification times and
+/// permissions are only stored if they are fixed or normalized by the options. An empty iterator
+/// produces a valid, empty archive. Pass `&mut writer` to keep the writer after the archive has
+/// been written.
+/// # Errors
+/// Will return `ZipError` for relevant io error on writer, if an entry name is invalid, if two
+/// entries have the same name, if the compression method is not available, or if the archive
+/// would exceed the maximum size, or if `CreateOptions::entry_comment` is set or entries are
+/// encrypted with AES-256.
+pub fn zip_create_from_memory<W, I>(writer: W, entries: I, options: CreateOptions) -> ZipResult<()>
+where
+    W: Write + io::Seek,
+    I: IntoIterator<Item = (String, Vec<u8>)>,
+{
+    options.forbid_entry_comment()?;
+    options.forbid_aes_encryption(
+        "AES-256 encrypted entries can not be written to a writer that is not read back, use \
+         `zip_create_from_memory_to_file` or `EncryptionMethod::ZipCrypto` instead.",
+    )?;
+    let mut zip_writer = ZipWriter::new(SizeLimitedWriter::new(writer, options.max_archive_size)?);
+    create_from_memory_entries(&mut zip_writer, entries, options)?;
+    zip_writer.finish()?;
+    Ok(())
+}
+
+/// Writes the given in-memory entries to the archive, and returns the report, whose entry
+/// comments are written by `finish_archive`.
+fn create_from_memory_entries<W, I>(
+    zip_writer: &mut ZipWriter<W>,
+    entries: I,
+    mut options: CreateOptions,
+) -> ZipResult<CreateReport>
+where
+    W: Write + io::Seek,
+    I: IntoIterator<Item = (String, Vec<u8>)>,
+{
+    let file_options = options.file_options()?;
+    let mut pending_entries: Vec<PendingEntry> = Vec::new();
+    for (entry_name, bytes) in entries {
+        let name = validate_entry_name(Path::new(&entry_name))?;
+        let entry_options = options.memory_entry_options(file_options, entry_name.ends_with('/'));
+        pending_entries.push(if entry_name.ends_with('/') {
+            PendingEntry::directory(name, entry_options)
+        } else {
+            let (entry_options, compression_method) =
+                options.file_entry_options(entry_options, Path::new(&name))?;
+            PendingEntry {
+                name,
+                source: EntrySource::Bytes(bytes),
+                options: entry_options,
+                compression_method,
+            }
+        });
+    }
+
+    let mut entry_names: HashSet<String> = HashSet::new();
+    let mut report = CreateReport::default();
+    write_entries(
+        zip_writer,
+        &mut options,
+        pending_entries,
+        &mut entry_names,
+        &mut report,
+    )?;
+    Ok(report)
+}
+
+/// Creates a zip archive file that contains the given in-memory entries. See
+/// `zip_create_from_memory` for how the entries are written.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive, if an entry name is invalid, if
+/// two entries have the same name, or if the compression method is not available. The partially
+/// written archive file is removed.
+pub fn zip_create_from_memory_to_file<P, I>(
+    archive_file: P,
+    entries: I,
+    options: CreateOptions,
+) -> ZipResult<()>
+where
+    P: AsRef<Path>,
+    I: IntoIterator<Item = (String, Vec<u8>)>,
+{
+    let archive_file = archive_file.as_ref();
+    let file = create_archive_file(archive_file)?;
+    let result = SizeLimitedWriter::new(file, options.max_archive_size)
+        .map_err(ZipError::from)
+        .and_then(|writer| {
+            let mut zip_writer = ZipWriter::new(writer);
+            let report = create_from_memory_entries(&mut zip_writer, entries, options)?;
+            finish_archive(&mut zip_writer, &report)?;
+            Ok(())
+        });
+    if result.is_err() {
+        std::fs::remove_file(archive_file).unwrap_or_default();
+    }
+    result
+}
+
+/// Writes a zip archive that contains the given entries to a writer that can not seek, such as a
+/// pipe or a socket. See `ZipWriterExtensions::create_from_iter` for how the entries are written.
+///
+/// The underlying zip writer requires `Seek` for every entry, because it goes back to the local
+/// header to fill in the sizes and CRC-32, and it has no mode that writes them to a data
+/// descriptor after the entry data instead. The archive is therefore written to a temporary file
+/// in the system temporary directory, and then copied to the writer. Nothing is written to the
+/// writer until all entries have been compressed, and the temporary directory needs space for the
+/// whole archive. The temporary file is removed afterwards, also if creation fails.
+/// # Errors
+/// Will return `ZipError` for relevant io error on writer, files or temporary file, if an entry
+/// name is invalid, or if the compression method is not available.
+pub fn zip_create_streaming<W, I>(
+    entries: I,
+    mut writer: W,
+    options: CreateOptions,
+) -> ZipResult<CreateReport>
+where
+    W: Write,
+    I: IntoIterator<Item = SourceEntry>,
+{
+    let temporary_path = temporary_file_path(&std::env::temp_dir(), Path::new("streaming.zip"));
+    let result = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&temporary_path)
+        .map_err(ZipError::Io)
+        .and_then(|file| {
+            let max_archive_size = options.max_archive_size;
+            let mut zip_writer = ZipWriter::new(SizeLimitedWriter::new(file, max_archive_size)?);
+            let report = create_from_source_entries(&mut zip_writer, entries, options)?;
+            let mut file = finish_archive(&mut zip_writer, &report)?.inner;
+            file.rewind()?;
+            io::copy(&mut file, &mut writer)?;
+            writer.flush()?;
+            Ok(report)
+        });
+    std::fs::remove_file(&temporary_path).unwrap_or_default();
+    result
+}
+
+/// Creates an archive file from sources that are pairs of a path and an entry name, removing the
+/// archive file if creation fails.
+fn create_archive_from_sources(
+    archive_file: &Path,
+    sources: &[(&Path, String)],
+    mut options: CreateOptions,
+) -> ZipResult<CreateReport> {
+    let file_options = options.file_options()?;
+    let file = create_archive_file(archive_file)?;
+    let mut zip_writer = ZipWriter::new(SizeLimitedWriter::new(file, options.max_archive_size)?);
+    let mut entry_names: HashSet<String> = HashSet::new();
+    let mut report = CreateReport::default();
+    let mut pending_entries: Vec<PendingEntry> = Vec::new();
+    let result = sources
+        .iter()
+        .try_for_each(|(path, entry_name)| {
+            collect_entries(
+                path,
+                entry_name,
+                &mut options,
+                file_options,
+                &mut pending_entries,
+                &mut report,
+            )
+        })
+        .and_then(|()| {
+            write_entries(
+                &mut zip_writer,
+                &mut options,
+                pending_entries,
+                &mut entry_names,
+                &mut report,
+            )
+        });
+    if let Err(error) = result.and_then(|()| finish_archive(&mut zip_writer, &report).map(|_| ())) {
+        drop(zip_writer);
+        std::fs::remove_file(archive_file).unwrap_or_default();
+        return Err(error);
+    }
+    Ok(report)
+}
+
+/// Validates the entry names of explicitly listed files and pairs each source path with its
+/// normalized entry name.
+fn validate_file_sources<'a, P1: AsRef<Path>, P2: AsRef<Path>>(
+    entries: &'a [(P1, P2)],
+    options: &CreateOptions,
+) -> ZipResult<Vec<(&'a Path, String)>> {
+    entries
+        .iter()
+        .map(|(entry_name, path)| {
+            let path = path.as_ref();
+            let entry_name = validate_entry_name(entry_name.as_ref())?;
+            if !options.recurse_directories && path.is_dir() {
+                return Err(ZipError::Io(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "The source path {} of the entry {} is a directory.",
+                        path.display(),
+                        entry_name
+                    ),
+                )));
+            }
+            Ok((path, entry_name))
+        })
+        .collect()
+}
+
+/// The reason why an entry name has been rejected by `normalize_entry_name`. Each variant holds
+/// the offending name as it has been given.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NameError {
+    /// The name has no components, such as `""` or `./`.
+    Empty(String),
+    /// The name starts with a separator, such as `/etc/passwd`.
+    Absolute(String),
+    /// The name starts with a drive letter, such as `C:\Windows`.
+    DriveLetter(String),
+    /// The name starts with a UNC prefix, such as `\\server\share`.
+    UncPrefix(String),
+    /// The name contains a `..` component, such as `docs/../../secret.txt`.
+    ParentDirectory(String),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameError::Empty(name) => write!(f, "The entry name `{name}` is empty."),
+            NameError::Absolute(name) => write!(f, "The entry name `{name}` is absolute."),
+            NameError::DriveLetter(name) => {
+                write!(f, "The entry name `{name}` starts with a drive letter.")
+            }
+            NameError::UncPrefix(name) => {
+                write!(f, "The entry name `{name}` starts with a UNC prefix.")
+            }
+            NameError::ParentDirectory(name) => {
+                write!(f, "The entry name `{name}` must not contain `..`.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for NameError {}
+
+impl From<NameError> for Error {
+    fn from(name_error: NameError) -> Error {
+        Error::new(ErrorKind::InvalidInput, name_error)
+    }
+}
+
+/// Converts an entry name to the form it is stored in, with forward slashes as separators and
+/// without leading `./` or redundant separators, such as `docs\\guide//./intro.md` to
+/// `docs/guide/intro.md`. Backslashes are treated as separators on every platform, so names
+/// taken from Windows paths are handled alike everywhere. All creation functions pass entry names
+/// through this, so it can be used to validate names up front.
+/// # Errors
+/// Will return `NameError` if the name is empty, absolute, starts with a drive letter or a UNC
+/// prefix, or contains `..`.
+pub fn normalize_entry_name(entry_name: &Path) -> Result<String, NameError> {
+    let given = entry_name.to_string_lossy();
+    let name = given.replace('\\', "/");
+    let bytes = name.as_bytes();
+    if name.starts_with("//") {
+        return Err(NameError::UncPrefix(given.into_owned()));
+    }
+    if name.starts_with('/') {
+        return Err(NameError::Absolute(given.into_owned()));
+    }
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        return Err(NameError::DriveLetter(given.into_owned()));
+    }
+    let mut components: Vec<&str> = Vec::new();
+    for component in name.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => return Err(NameError::ParentDirectory(given.into_owned())),
+            component => components.push(component),
+        }
+    }
+    if components.is_empty() {
+        return Err(NameError::Empty(given.into_owned()));
+    }
+    Ok(components.join("/"))
+}
+
+/// Normalizes an entry name with `normalize_entry_name`, failing with an io error if it is
+/// rejected.
+fn validate_entry_name(entry_name: &Path) -> ZipResult<String> {
+    normalize_entry_name(entry_name).map_err(|name_error| ZipError::Io(name_error.into()))
+}
+
+/// Creates a zip archive from the specified directory once per available compression method, and
+/// keeps the smallest result. Returns the compression method of the kept archive.
+///
+/// The candidates are `Stored`, plus `Deflated` at level 9, `Bzip2` at level 9 and `Zstd` at
+/// level 19 if the `deflate`, `bzip2` and `zstd` features are enabled. Every file is therefore
+/// compressed once per candidate, which makes this several times slower than
+/// `zip_create_from_directory_with_options` and mainly suited for archival storage. Candidates are
+/// written to temporary files next to the archive file.
+/// # Errors
+/// Will return `ZipError` for relevant file io error on archive or directory, or if the archive
+/// file would be created inside the directory.
+pub fn zip_create_best_compression<P1: AsRef<Path>, P2: AsRef<Path>>(
+    archive_file: P1,
+    directory: P2,
+) -> ZipResult<CompressionMethod> {
+    let candidates = [
+        #[cfg(feature = "deflate")]
+        (CompressionMethod::Deflated, Some(9)),
+        #[cfg(feature = "bzip2")]
+        (CompressionMethod::Bzip2, Some(9)),
+        #[cfg(feature = "zstd")]
+        (CompressionMethod::Zstd, Some(19)),
+    ];
+
+    let archive_file = archive_file.as_ref();
+    let directory = directory.as_ref();
+    let (mut best_file, mut best_size) =
+        create_candidate_archive(archive_file, directory, CompressionMethod::Stored, None)?;
+    let mut best_method = CompressionMethod::Stored;
+    for (method, level) in candidates {
+        let candidate = create_candidate_archive(archive_file, directory, method, level);
+        let (candidate_file, size) = match candidate {
+            Ok(candidate) => candidate,
+            Err(error) => {
+                std::fs::remove_file(&best_file).unwrap_or_default();
+                return Err(error);
+            }
+        };
+        if size < best_size {
+            std::fs::remove_file(&best_file)?;
+            (best_file, best_size, best_method) = (candidate_file, size, method);
+        } else {
+            std::fs::remove_file(&candidate_file)?;
+        }
+    }
+
+    std::fs::rename(best_file, archive_file)?;
+    Ok(best_method)
+}
+
+/// Creates a candidate archive for `zip_create_best_compression` in a new temporary file with a
+/// unique name next to the archive file, and returns the path and size of that file.
+fn create_candidate_archive(
+    archive_file: &Path,
+    directory: &Path,
+    method: CompressionMethod,
+    level: Option<i32>,
+) -> ZipResult<(PathBuf, u64)> {
+    let candidate_file =
+        temporary_file_path(archive_file.parent().unwrap_or(archive_file), archive_file);
+    ensure_archive_outside_directory(&candidate_file, directory)?;
+    let options = CreateOptions::default()
+        .compression_method(method)
+        .compression_level(level);
+    let mut zip_writer = ZipWriter::new(create_temporary_archive_file(&candidate_file)?);
+    let size = create_from_path(&mut zip_writer, directory, "", options)
+        .and_then(|report| finish_archive(&mut zip_writer, &report))
+        .and_then(|file| Ok(file.metadata()?.len()));
+    drop(zip_writer);
+    match size {
+        Ok(size) => Ok((candidate_file, size)),
+        Err(error) => {
+            std::fs::remove_file(&candidate_file).unwrap_or_default();
+            Err(error)
+        }
+    }
+}
+
+/// Options that control how an archive is created.
+///
+/// By default, entries are compressed with Deflate if the `deflate` feature is enabled and stored
+/// without compression otherwise, every directory gets an entry, and the Unix permissions and
+/// modification times of files and directories are stored.
+#[allow(clippy::struct_excessive_bools)]
+pub struct CreateOptions {
+    compression_method: CompressionMethod,
+    compression_level: Option<i32>,
+    extension_methods: HashMap<String, CompressionMethod>,
+    method_selector: Option<MethodSelector>,
+    include_empty_dirs: bool,
+    preserve_permissions: bool,
+    fixed_permissions: Option<u32>,
+    fixed_modified_time: Option<DateTime>,
+    deterministic: bool,
+    duplicate_entries: DuplicateEntryPolicy,
+    case_insensitive_names: bool,
+    symlinks: SymlinkPolicy,
+    recurse_directories: bool,
+    stored_alignment: Option<u16>,
+    max_archive_size: Option<u64>,
+    chunk_size: usize,
+    force_zip64: bool,
+    deduplicate_contents: bool,
+    overwrite: bool,
+    archive_comment: Option<String>,
+    root_prefix: Option<String>,
+    password: Option<Vec<u8>>,
+    encryption: EncryptionMethod,
+    on_progress: Option<ProgressCallback>,
+    entry_comment: Option<EntryCommentCallback>,
+    filter: Option<PathFilter>,
+    #[cfg(feature = "ignore")]
+    respect_gitignore: bool,
+    #[cfg(feature = "ignore")]
+    force_include: Vec<String>,
+    #[cfg(feature = "rayon")]
+    parallel_entries: Option<usize>,
+}
+
+/// Describes what happens when several files would be written under the same entry name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateEntryPolicy {
+    /// Creation fails with `ErrorKind::AlreadyExists`.
+    #[default]
+    Fail,
+    /// The first file is written, and later ones are skipped.
+    KeepFirst,
+    /// The last file is written, and earlier ones are skipped.
+    KeepLast,
+    /// Every file is written, so that the archive contains several entries with the same name.
+    /// Tools that look entries up by name disagree on which of them they find, so this is only
+    /// meant for the rare archives that need it.
+    AllowDuplicates,
+}
+
+/// Describes how symbolic links are handled while walking a directory tree. On Windows, this
+/// applies to directory junctions as well.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// The contents of the target are written under the name of the link. Links to a directory
+    /// that contains the link fail with `ErrorKind::InvalidInput`, and links whose target does
+    /// not exist are skipped.
+    #[default]
+    Follow,
+    /// The link itself is written as a symlink entry, whose content is the target path, like
+    /// Info-ZIP does.
+    Store,
+    /// Links are skipped.
+    Skip,
+}
+
+/// Describes how file entries are encrypted if a password is set with `CreateOptions::password`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EncryptionMethod {
+    /// AES-256 in the AE-2 format that most archivers read, which requires the `aes` feature and
+    /// is the default with it. Creation with a password fails with `ErrorKind::Unsupported`
+    /// before anything is written without the feature, or if `CreateOptions::force_zip64` is set.
+    ///
+    /// Entries are compressed ahead of time, into memory or a temporary file, and then encrypted
+    /// while they are written. They are not aligned or deduplicated, can not be larger than
+    /// 4 GiB, and their random salt makes the archive differ even in deterministic mode.
+    #[cfg_attr(feature = "aes", default)]
+    Aes256,
+    /// The legacy `ZipCrypto` scheme of PKZIP, which is the default without the `aes` feature.
+    /// Nearly every tool can read it, but it is easily broken, so it is only meant for recipients
+    /// that can not read AES encrypted entries.
+    #[cfg_attr(not(feature = "aes"), default)]
+    ZipCrypto,
+}
+
+type PathFilter = Box<dyn FnMut(&Path, &Metadata) -> bool>;
+
+type MethodSelector = Box<dyn FnMut(&Path) -> CompressionMethod>;
+
+/// The extensions of files whose contents are already compressed, which
+/// `CreateOptions::store_precompressed` stores without compression.
+const PRECOMPRESSED_EXTENSIONS: [&str; 34] = [
+    "7z", "aac", "apk", "avif", "br", "bz2", "docx", "epub", "flac", "gif", "gz", "heic", "jar",
+    "jpeg", "jpg", "m4a", "m4v", "mkv", "mov", "mp3", "mp4", "odt", "ogg", "opus", "png", "pptx",
+    "rar", "tgz", "webm", "webp", "woff2", "xlsx", "xz", "zip",
+];
+
+type ProgressCallback = Box<dyn FnMut(&CreateProgress) -> ControlFlow<()>>;
+
+type EntryCommentCallback = Box<dyn FnMut(&Path) -> Option<String>>;
+
+impl Default for CreateOptions {
+    fn default() -> Self {
+        CreateOptions {
+            #[cfg(feature = "deflate")]
+            compression_method: CompressionMethod::Deflated,
+            #[cfg(not(feature = "deflate"))]
+            compression_method: CompressionMethod::Stored,
+            compression_level: None,
+            extension_methods: HashMap::new(),
+            method_selector: None,
+            include_empty_dirs: true,
+            preserve_permissions: true,
+            fixed_permissions: None,
+            fixed_modified_time: None,
+            deterministic: false,
+            duplicate_entries: DuplicateEntryPolicy::Fail,
+            case_insensitive_names: false,
+            symlinks: SymlinkPolicy::Follow,
+            recurse_directories: true,
+            stored_alignment: None,
+            max_archive_size: None,
+            chunk_size: COPY_CHUNK_SIZE,
+            force_zip64: false,
+            deduplicate_contents: false,
+            overwrite: false,
+            archive_comment: None,
+            root_prefix: None,
+            password: None,
+            encryption: EncryptionMethod::default(),
+            on_progress: None,
+            entry_comment: None,
+            filter: None,
+            #[cfg(feature = "ignore")]
+            respect_gitignore: false,
+            #[cfg(feature = "ignore")]
+            force_include: Vec::new(),
+            #[cfg(feature = "rayon")]
+            parallel_entries: None,
+        }
+    }
+}
+
+impl CreateOptions {
+    /// Sets the compression method of the entries.
+    ///
+    /// Methods other than `Stored` require the Cargo feature of the same name (`deflate`,
+    /// `bzip2` or `zstd`) to be enabled.
+    #[must_use]
+    pub fn compression_method(mut self, method: CompressionMethod) -> CreateOptions {
+        self.compression_method = method;
+        self
+    }
+
+    /// Sets the compression level, or `None` for the default level of the compression method.
+    ///
+    /// The level is passed to the compression backend, which rejects levels outside of its range
+    /// (for example, 1 to 9 for `Deflated`).
+    #[must_use]
+    pub fn compression_level(mut self, level: Option<i32>) -> CreateOptions {
+        self.compression_level = level;
+        self
+    }
+
+    /// Sets the compression method of files with the given extension, matched
+    /// case-insensitively, such as `Stored` for `png`. The compression level only applies to
+    /// files that use the default compression method.
+    #[must_use]
+    pub fn compression_method_for_extension(
+        mut self,
+        extension: &str,
+        method: CompressionMethod,
+    ) -> CreateOptions {
+        let extension = extension.trim_start_matches('.').to_lowercase();
+        self.extension_methods.insert(extension, method);
+        self
+    }
+
+    /// Stores files whose contents are already compressed, such as images, audio, video and
+    /// archives, without compression, since compressing them again costs time for little gain.
+    /// Mappings for the same extensions that have been set before are replaced.
+    #[must_use]
+    pub fn store_precompressed(mut self) -> CreateOptions {
+        for extension in PRECOMPRESSED_EXTENSIONS {
+            self.extension_methods
+                .insert(extension.to_string(), CompressionMethod::Stored);
+        }
+        self
+    }
+
+    /// Sets a closure that selects the compression method of each file from its source path, or
+    /// from its entry name for in-memory entries. The closure takes precedence over the
+    /// extension mappings and the default compression method.
+    #[must_use]
+    pub fn compression_method_selector<F>(mut self, selector: F) -> CreateOptions
+    where
+        F: FnMut(&Path) -> CompressionMethod + 'static,
+    {
+        self.method_selector = Some(Box::new(selector));
+        self
+    }
+
+    /// Sets whether an explicit entry is written for every directory, which is required for
+    /// empty directories to be recreated on extraction. Directory entries are always stored
+    /// without compression. If disabled, directories are only implied by the paths of the files
+    /// beneath them.
+    #[must_use]
+    pub fn include_empty_dirs(mut self, include: bool) -> CreateOptions {
+        self.include_empty_dirs = include;
+        self
+    }
+
+    /// Sets whether the Unix permission bits of files and directories are stored, so that they can
+    /// be restored on extraction. On other platforms, directories and files with an executable
+    /// extension (such as `.exe` or `.sh`) are stored as 0o755, and other files as 0o644. Special
+    /// bits such as setuid are never stored. If disabled, the defaults of the `zip` crate apply.
+    #[must_use]
+    pub fn preserve_permissions(mut self, preserve: bool) -> CreateOptions {
+        self.preserve_permissions = preserve;
+        self
+    }
+
+    /// Sets permission bits that are stored for every file instead of the actual ones, for
+    /// reproducible archives. Directories additionally get the execute bit wherever the read bit
+    /// is set, so that they remain traversable.
+    #[must_use]
+    pub fn fixed_permissions(mut self, mode: Option<u32>) -> CreateOptions {
+        self.fixed_permissions = mode;
+        self
+    }
+
+    /// Sets a modification time that is stored for every entry instead of the actual ones, for
+    /// reproducible archives.
+    ///
+    /// Actual modification times are stored as UTC. Since ZIP timestamps can only represent
+    /// 1980-01-01 to 2107-12-31 with a resolution of two seconds, earlier and later times are
+    /// clamped to these bounds, and odd seconds are rounded down.
+    #[must_use]
+    pub fn fixed_modified_time(mut self, time: Option<DateTime>) -> CreateOptions {
+        self.fixed_modified_time = time;
+        self
+    }
+
+    /// Sets whether the archive is created reproducibly, so that the same tree always produces
+    /// a byte-identical archive regardless of the order in which the file system lists it.
+    ///
+    /// In deterministic mode, entries are sorted by entry name, which places directories before
+    /// their contents. Every entry gets the same modification time: the one set with
+    /// `fixed_modified_time` if any, otherwise the time given in seconds by the
+    /// `SOURCE_DATE_EPOCH` environment variable if it is set, and otherwise 1980-01-01 00:00:00.
+    /// Permissions are normalized to 0o755 for directories and files with an execute bit, and to
+    /// 0o644 for other files, unless `fixed_permissions` is set. No extra fields are written, and
+    /// the compression method and level are used as configured.
+    #[must_use]
+    pub fn deterministic(mut self, deterministic: bool) -> CreateOptions {
+        self.deterministic = deterministic;
+        self
+    }
+
+    /// Sets what happens when several files would be written under the same entry name.
+    /// Directories that occur more than once are always merged.
+    #[must_use]
+    pub fn duplicate_entries(mut self, policy: DuplicateEntryPolicy) -> CreateOptions {
+        self.duplicate_entries = policy;
+        self
+    }
+
+    /// Sets whether entry names that only differ in case, such as `Readme.md` and `README.md`,
+    /// are duplicates, as they are when the archive is extracted on a case-insensitive file
+    /// system. Directories whose names only differ in case are merged into the first one.
+    #[must_use]
+    pub fn case_insensitive_names(mut self, case_insensitive: bool) -> CreateOptions {
+        self.case_insensitive_names = case_insensitive;
+        self
+    }
+
+    /// Sets how symbolic links that are found while walking a directory tree are handled. A
+    /// source path that is itself a link is always followed.
+    #[must_use]
+    pub fn symlinks(mut self, policy: SymlinkPolicy) -> CreateOptions {
+        self.symlinks = policy;
+        self
+    }
+
+    /// Sets whether a source path of an explicitly listed entry that is a directory is added
+    /// including its contents, or rejected.
+    #[must_use]
+    pub fn recurse_directories(mut self, recurse: bool) -> CreateOptions {
+        self.recurse_directories = recurse;
+        self
+    }
+
+    /// Sets a byte boundary at which the data of stored file entries begins, such as 4 for
+    /// Android's `zipalign`, so that the entries can be memory-mapped. The extra field of the
+    /// local header is padded as needed. Compressed and AES-256 encrypted entries are not aligned.
+    #[must_use]
+    pub fn align_stored_entries(mut self, alignment: Option<u16>) -> CreateOptions {
+        self.stored_alignment = alignment;
+        self
+    }
+
+    /// Sets the maximum size of the archive in bytes. Creation fails as soon as the archive would
+    /// grow beyond it, which bounds the memory used by `zip_create_in_memory`. The limit applies
+    /// to the functions that create an archive, not to the methods of `ZipWriterExtensions`.
+    #[must_use]
+    pub fn max_archive_size(mut self, max_size: Option<u64>) -> CreateOptions {
+        self.max_archive_size = max_size;
+        self
+    }
+
+    /// Sets the size of the buffer through which the contents of each file are copied into the
+    /// archive, 64 KiB by default. Files are never read into memory as a whole, so this bounds
+    /// the memory used for file data regardless of the file sizes, and the progress callback is
+    /// invoked after each chunk.
+    #[must_use]
+    pub fn chunk_size(mut self, chunk_size: usize) -> CreateOptions {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Sets whether every file entry is written with ZIP64 extra fields. By default, they are only
+    /// written for files larger than 4 GiB, which require them. The ZIP64 end-of-central-directory
+    /// records are written whenever the archive has more than 65,535 entries or grows beyond
+    /// 4 GiB, regardless of this option. Since copies of compressed data lose the forced extra
+    /// fields, this turns off `parallel_compression` and `deduplicate_contents`.
+    #[must_use]
+    pub fn force_zip64(mut self, force: bool) -> CreateOptions {
+        self.force_zip64 = force;
+        self
+    }
+
+    /// Sets whether files whose contents are identical to those of a file that has already been
+    /// written are copied from its compressed data instead of being compressed again. They are
+    /// still separate entries, so extraction is unchanged. Only files with the same modification
+    /// time and permissions as the first file with those contents are copied, since a copy keeps
+    /// the attributes of the entry it is copied from; the others are compressed as usual. The
+    /// compressed data is held until the archive has been written, in memory or, for large files,
+    /// in temporary files. Stored and AES-256 encrypted entries are written as usual. Identical
+    /// files are listed in `CreateReport::duplicate_contents` either way.
+    #[must_use]
+    pub fn deduplicate_contents(mut self, deduplicate: bool) -> CreateOptions {
+        self.deduplicate_contents = deduplicate;
+        self
+    }
+
+    /// Sets whether `zip_create_from_file` replaces an archive file that already exists, instead
+    /// of failing with `ErrorKind::AlreadyExists`.
+    #[must_use]
+    pub fn overwrite(mut self, overwrite: bool) -> CreateOptions {
+        self.overwrite = overwrite;
+        self
+    }
+
+    /// Sets the comment of the archive, such as a build stamp. Comments longer than 65,535 bytes
+    /// are rejected before anything is written.
+    ///
+    /// The comment is written as UTF-8. The ZIP format has no flag that marks the archive comment
+    /// as UTF-8, so tools that assume the legacy IBM code page 437 may display non-ASCII
+    /// characters incorrectly.
+    #[must_use]
+    pub fn archive_comment(mut self, comment: String) -> CreateOptions {
+        self.archive_comment = Some(comment);
+        self
+    }
+
+    /// Sets a directory, such as `project-1.0`, that all entries are placed in, like the archives
+    /// of a source code host. Every entry name is prefixed with `prefix/`, and a directory entry
+    /// for the prefix itself is written first. The prefix must be relative and must not contain
+    /// `..`; it is rejected before anything is written otherwise.
+    #[must_use]
+    pub fn root_prefix(mut self, prefix: Option<String>) -> CreateOptions {
+        self.root_prefix = prefix;
+        self
+    }
+
+    /// Sets the password that file entries are encrypted with, using the method set with
+    /// `CreateOptions::encryption`. Directory entries and stored symbolic links are not
+    /// encrypted, so their names and the directory tree remain visible without the password.
+    #[must_use]
+    pub fn password(mut self, password: &[u8]) -> CreateOptions {
+        self.password = Some(password.to_vec());
+        self
+    }
+
+    /// Sets the method that file entries are encrypted with if a password is set. Defaults to
+    /// `EncryptionMethod::Aes256` with the `aes` feature, and to `EncryptionMethod::ZipCrypto`
+    /// without it.
+    #[must_use]
+    pub fn encryption(mut self, method: EncryptionMethod) -> CreateOptions {
+        self.encryption = method;
+        self
+    }
+
+    /// Sets a callback that is invoked with the current progress after each chunk of file data
+    /// has been written and after each file has been completed. Returning `ControlFlow::Break`
+    /// aborts creation with an error that wraps `Cancelled`; the functions that create an archive
+    /// file then remove the partially written file.
+    ///
+    /// Since all files and directories are discovered before the first entry is written, the
+    /// totals are known from the start without an additional pass over the directory tree.
+    #[must_use]
+    pub fn on_progress<F>(mut self, callback: F) -> CreateOptions
+    where
+        F: FnMut(&CreateProgress) -> ControlFlow<()> + 'static,
+    {
+        self.on_progress = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a callback that is invoked with the source path of every file that is added, or the
+    /// entry name of in-memory contents, and returns the comment of its entry, such as a source
+    /// URL or a license. Comments longer than 65,535 bytes fail with `ErrorKind::InvalidInput`,
+    /// naming the entry.
+    ///
+    /// `ZipWriter` can not write entry comments, so they are written into the central directory
+    /// once the archive is finished. They are therefore only written by the functions that create
+    /// or append to an archive themselves; the methods of `ZipWriterExtensions` and
+    /// `zip_create_from_memory`, which write to a given writer, fail with
+    /// `ErrorKind::Unsupported` if the callback is set.
+    #[must_use]
+    pub fn entry_comment<F>(mut self, callback: F) -> CreateOptions
+    where
+        F: FnMut(&Path) -> Option<String> + 'static,
+    {
+        self.entry_comment = Some(Box::new(callback));
+        self
+    }
+
+    /// Sets a filter that decides which files and directories are added. The filter receives the
+    /// path relative to the directory that is archived, and returns `false` to exclude the path.
+    /// Excluded directories are not descended into.
+    #[must_use]
+    pub fn filter<F>(mut self, filter: F) -> CreateOptions
+    where
+        F: FnMut(&Path, &Metadata) -> bool + 'static,
+    {
+        self.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Sets whether paths that are ignored by `.gitignore` and `.ignore` files are excluded, the
+    /// way ripgrep does. Ignore files in nested directories, the repository's
+    /// `.git/info/exclude` file and the global excludes file are honored, also outside of a git
+    /// repository, and the `.git` directory is always excluded. The filter is applied on top.
+    #[cfg(feature = "ignore")]
+    #[must_use]
+    pub fn respect_gitignore(mut self, respect: bool) -> CreateOptions {
+        self.respect_gitignore = respect;
+        self
+    }
+
+    /// Sets glob patterns, relative to the directory that is archived, of paths that are added
+    /// even if they are ignored by gitignore rules.
+    #[cfg(feature = "ignore")]
+    #[must_use]
+    pub fn force_include<I, S>(mut self, patterns: I) -> CreateOptions
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.force_include = patterns.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Sets the maximum number of entries that are compressed concurrently, or `None` to
+    /// compress them one after another, which is the default.
+    ///
+    /// Entries are compressed in batches of this size on the rayon thread pool, each into a
+    /// buffer of its own, and are then written in their usual order, so that the archive is
+    /// byte-identical to one that is created sequentially. Entries larger than 8 MiB are
+    /// compressed into temporary files instead of memory. Stored entries are not affected, and
+    /// entries are compressed one after another with `CreateOptions::force_zip64`.
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn parallel_compression(mut self, max_in_flight: Option<usize>) -> CreateOptions {
+        self.parallel_entries = max_in_flight.map(|max_in_flight| max_in_flight.max(1));
+        self
+    }
+
+    fn report_progress(&mut self, progress: &CreateProgress) -> io::Result<()> {
+        let Some(on_progress) = &mut self.on_progress else {
+            return Ok(());
+        };
+        match on_progress(progress) {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(Cancelled.into()),
+        }
+    }
+
+    /// Adds the comment that the callback set with `CreateOptions::entry_comment` returns for a
+    /// file entry that is about to be written to the report, failing if it is too long.
+    fn record_entry_comment(
+        &mut self,
+        entry: &PendingEntry,
+        report: &mut CreateReport,
+    ) -> ZipResult<()> {
+        let Some(entry_comment) = self.entry_comment.as_mut().filter(|_| entry.has_contents())
+        else {
+            return Ok(());
+        };
+        let comment = match &entry.source {
+            EntrySource::File(path, _) => entry_comment(path),
+            #[cfg(any(feature = "rayon", feature = "aes"))]
+            EntrySource::Compressed(path, _) => entry_comment(path),
+            _ => entry_comment(Path::new(&entry.name)),
+        };
+        let Some(comment) = comment else {
+            return Ok(());
+        };
+        if comment.len() as u64 > MAX_COMMENT_LENGTH {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "The comment of the entry {} is {} bytes long, which exceeds the limit \
+                     of {} bytes.",
+                    entry.name,
+                    comment.len(),
+                    MAX_COMMENT_LENGTH
+                ),
+            )));
+        }
+        report
+            .entry_comments
+            .push((report.entries_written(), comment));
+        Ok(())
+    }
+
+    /// Determines whether file entries are encrypted with AES-256.
+    fn encrypts_with_aes(&self) -> bool {
+        self.password.is_some() && self.encryption == EncryptionMethod::Aes256
+    }
+
+    /// Returns the password that file entries are encrypted with AES-256 with, if any.
+    #[cfg(feature = "aes")]
+    fn aes_password(&self) -> Option<&[u8]> {
+        self.password
+            .as_deref()
+            .filter(|_| self.encryption == EncryptionMethod::Aes256)
+    }
+
+    /// Fails with the given message if file entries are encrypted with AES-256 by a function
+    /// that copies compressed data, or writes to a given `ZipWriter`, which can not mark the
+    /// entries as encrypted.
+    fn forbid_aes_encryption(&self, message: &str) -> ZipResult<()> {
+        if self.encrypts_with_aes() {
+            return Err(ZipError::Io(Error::new(ErrorKind::Unsupported, message)));
+        }
+        Ok(())
+    }
+
+    /// Fails if entry comments are requested from a function that writes to a given `ZipWriter`,
+    /// which can not write them.
+    fn forbid_entry_comment(&self) -> ZipResult<()> {
+        if self.entry_comment.is_some() {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::Unsupported,
+                "Entry comments can not be written to a given `ZipWriter`, only by the functions \
+                 that create or append to an archive themselves.",
+            )));
+        }
+        Ok(())
+    }
+
+    /// Returns the entry options for a file or directory, including the permissions and the
+    /// modification time to store.
+    fn entry_options(
+        &self,
+        mut file_options: FileOptions,
+        path: &Path,
+        metadata: &Metadata,
+    ) -> FileOptions {
+        let (modified_time, permissions) = self.entry_attributes(path, metadata);
+        if let Some(modified_time) = modified_time {
+            file_options = file_options.last_modified_time(modified_time);
+        }
+        match permissions {
+            Some(mode) => file_options.unix_permissions(mode),
+            None => file_options,
+        }
+    }
+
+    /// Returns the modification time and the permissions that `entry_options` stores for a file
+    /// or directory, if they are not left to `file_options`.
+    fn entry_attributes(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+    ) -> (Option<DateTime>, Option<u32>) {
+        // In deterministic mode, the common modification time is set by `file_options`.
+        let modified_time = self.fixed_modified_time.or_else(|| {
+            let modified = metadata.modified().ok().filter(|_| !self.deterministic)?;
+            Some(system_time_to_zip_datetime(modified))
+        });
+        let actual_permissions = unix_permissions(path, metadata);
+        let permissions = self
+            .override_permissions(metadata.is_dir(), actual_permissions & 0o111 != 0)
+            .or_else(|| self.preserve_permissions.then_some(actual_permissions));
+        (modified_time, permissions)
+    }
+
+    /// Applies the compression method that is selected for a file and the encryption to its
+    /// entry options, and returns the options along with the method.
+    fn file_entry_options(
+        &mut self,
+        mut file_options: FileOptions,
+        path: &Path,
+    ) -> ZipResult<(FileOptions, CompressionMethod)> {
+        if let Some(password) = self
+            .password
+            .as_ref()
+            .filter(|_| self.encryption == EncryptionMethod::ZipCrypto)
+        {
+            file_options = file_options.with_deprecated_encryption(password);
+        }
+        let method = match &mut self.method_selector {
+            Some(selector) => selector(path),
+            None => path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_lowercase())
+                .and_then(|extension| self.extension_methods.get(&extension).copied())
+                .unwrap_or(self.compression_method),
+        };
+        if method == self.compression_method {
+            return Ok((file_options, method));
+        }
+        check_compression_method(method)?;
+        let file_options = file_options
+            .compression_method(method)
+            .compression_level(None);
+        Ok((file_options, method))
+    }
+
+    /// Returns the key under which an entry name is compared with the names of other entries to
+    /// detect duplicates.
+    fn entry_key(&self, name: &str) -> String {
+        if self.case_insensitive_names {
+            name.to_lowercase()
+        } else {
+            name.to_string()
+        }
+    }
+
+    /// Returns the root prefix with forward slashes as separators, failing if it is invalid.
+    fn validated_root_prefix(&self) -> ZipResult<Option<String>> {
+        self.root_prefix
+            .as_ref()
+            .map(|prefix| validate_entry_name(Path::new(prefix)))
+            .transpose()
+    }
+
+    /// Returns the entry options with the given compression method, and the compression level of
+    /// the archive if it is the method of the archive.
+    fn method_options(
+        &self,
+        file_options: FileOptions,
+        method: CompressionMethod,
+    ) -> ZipResult<FileOptions> {
+        check_compression_method(method)?;
+        let level = if method == self.compression_method {
+            self.compression_level
+        } else {
+            None
+        };
+        Ok(file_options
+            .compression_method(method)
+            .compression_level(level))
+    }
+
+    /// Returns the entry options for an entry whose contents are held in memory, which has no
+    /// modification time or permissions of its own.
+    fn memory_entry_options(&self, mut file_options: FileOptions, is_dir: bool) -> FileOptions {
+        let (modified_time, permissions) = self.memory_entry_attributes(is_dir);
+        if let Some(modified_time) = modified_time {
+            file_options = file_options.last_modified_time(modified_time);
+        }
+        match permissions {
+            Some(mode) => file_options.unix_permissions(mode),
+            None => file_options,
+        }
+    }
+
+    /// Returns the modification time and the permissions that `memory_entry_options` stores, if
+    /// they are not left to `file_options`.
+    fn memory_entry_attributes(&self, is_dir: bool) -> (Option<DateTime>, Option<u32>) {
+        (
+            self.fixed_modified_time,
+            self.override_permissions(is_dir, false),
+        )
+    }
+
+    /// Returns the permissions that replace the actual ones, either fixed or normalized in
+    /// deterministic mode.
+    fn override_permissions(&self, is_dir: bool, executable: bool) -> Option<u32> {
+        match self.fixed_permissions {
+            Some(mode) if is_dir => Some(mode | ((mode & 0o444) >> 2)),
+            Some(mode) => Some(mode),
+            None if self.deterministic && (is_dir || executable) => Some(0o755),
+            None if self.deterministic => Some(0o644),
+            None => None,
+        }
+    }
+
+    /// Converts the options to the entry options of the `zip` crate, failing if the compression
+    /// method is not available in this build, or if `SOURCE_DATE_EPOCH` is invalid in
+    /// deterministic mode.
+    fn file_options(&self) -> ZipResult<FileOptions> {
+        if let Some(comment) = &self.archive_comment {
+            check_comment_length(comment.as_bytes())?;
+        }
+        self.validated_root_prefix()?;
+        if self.encrypts_with_aes() {
+            if !cfg!(feature = "aes") {
+                return Err(ZipError::Io(Error::new(
+                    ErrorKind::Unsupported,
+                    "Encrypting entries with AES-256 requires the `aes` feature of \
+                     zip-extensions. Use `EncryptionMethod::ZipCrypto` to encrypt entries with \
+                     the legacy ZipCrypto scheme instead.",
+                )));
+            }
+            if self.force_zip64 {
+                return Err(ZipError::Io(Error::new(
+                    ErrorKind::Unsupported,
+                    "AES-256 encrypted entries can not be written with ZIP64 extra fields, so \
+                     `CreateOptions::force_zip64` can not be combined with them.",
+                )));
+            }
+        }
+        check_compression_method(self.compression_method)?;
+        for method in self.extension_methods.values() {
+            check_compression_method(*method)?;
+        }
+        let file_options = FileOptions::default()
+            .compression_method(self.compression_method)
+            .compression_level(self.compression_level);
+        if !self.deterministic {
+            return Ok(file_options);
+        }
+        let modified_time = match self.fixed_modified_time {
+            Some(modified_time) => modified_time,
+            None => source_date_epoch()?.unwrap_or_default(),
+        };
+        Ok(file_options.last_modified_time(modified_time))
+    }
+}
+
+/// Returns the length of a comment, failing if it is too long to be stored in the
+/// end-of-central-directory record.
+fn check_comment_length(comment: &[u8]) -> ZipResult<u16> {
+    u16::try_from(comment.len()).map_err(|_| {
+        ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "The archive comment is {} bytes long, which exceeds the limit of {} bytes.",
+                comment.len(),
+                MAX_COMMENT_LENGTH
+            ),
+        ))
+    })
+}
+
+/// Fails if the compression method is not available in this build.
+#[allow(deprecated)]
+fn check_compression_method(method: CompressionMethod) -> ZipResult<()> {
+    let required_feature = match method.to_u16() {
+        0 => None,
+        8 => Some(("deflate", cfg!(feature = "deflate"))),
+        12 => Some(("bzip2", cfg!(feature = "bzip2"))),
+        93 => Some(("zstd", cfg!(feature = "zstd"))),
+        _ => {
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::Unsupported,
+                format!(
+                    "The compression method {method:?} is not supported for creating archives."
+                ),
+            )))
+        }
+    };
+    if let Some((feature, false)) = required_feature {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "The compression method {method:?} requires the `{feature}` feature of \
+                 zip-extensions."
+            ),
+        )));
+    }
+    Ok(())
+}
+
+/// The progress of archive creation, as passed to the callback set with
+/// `CreateOptions::on_progress`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CreateProgress {
+    /// The source path of the file that is being written, or the entry name of an in-memory
+    /// entry.
+    pub current_path: PathBuf,
+    /// The number of files that have been written.
+    pub files_completed: usize,
+    /// The number of files that are to be written.
+    pub total_files: usize,
+    /// The number of bytes that have been read from the files.
+    pub bytes_read: u64,
+    /// The total size of the files that are to be written, as found while discovering them.
+    pub total_bytes: u64,
+}
+
+/// Summarizes the outcome of adding files and directories to an archive.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CreateReport {
+    /// The number of file entries that have been written.
+    pub files_added: usize,
+    /// The number of directory entries that have been written.
+    pub directories_added: usize,
+    /// The number of files and directories that have been excluded by the filter. The contents
+    /// of excluded directories are not counted.
+    pub skipped_by_filter: usize,
+    /// The number of files and directories that have been excluded by gitignore rules, which
+    /// requires the `ignore` feature. The contents of excluded directories are not counted.
+    pub skipped_by_gitignore: usize,
+    /// The number of files that have not been written because another file has the same entry
+    /// name.
+    pub skipped_duplicates: usize,
+    /// The number of symlink entries that have been written.
+    pub symlinks_added: usize,
+    /// The number of symbolic links that have been skipped, either by the symlink policy or
+    /// because their target does not exist.
+    pub skipped_symlinks: usize,
+    /// The entry names of the files with identical contents, grouped by contents, in the order
+    /// they have been written. Files of the same size are compared by CRC-32 and then byte for
+    /// byte; empty files and the contents of readers are not compared.
+    pub duplicate_contents: Vec<Vec<String>>,
+    /// The number of file entries that have been copied from the compressed data of a file with
+    /// identical contents, with `CreateOptions::deduplicate_contents`.
+    pub files_deduplicated: usize,
+    /// The comments that `CreateOptions::entry_comment` has returned, with the index of their
+    /// entry among the entries that have been written.
+    entry_comments: Vec<(usize, String)>,
+    /// The index of the AES-256 encrypted entries among the entries that have been written, and
+    /// their uncompressed size.
+    encrypted_entries: Vec<(usize, u64)>,
+}
+
+impl CreateReport {
+    /// Returns the number of entries that have been written.
+    fn entries_written(&self) -> usize {
+        self.files_added + self.directories_added + self.symlinks_added
+    }
+
+    /// Shifts the index of the entries with comments or AES-256 encryption by the number of
+    /// entries that precede them in the archive.
+    fn offset_entries(&mut self, offset: usize) {
+        for (index, _) in &mut self.entry_comments {
+            *index += offset;
+        }
+        for (index, _) in &mut self.encrypted_entries {
+            *index += offset;
+        }
+    }
+}
+
+/// A writer that fails instead of writing beyond a maximum size. Reads are passed through, so
+/// that the central directory can be written again once the archive is finished.
+struct SizeLimitedWriter<W> {
+    inner: W,
+    position: u64,
+    max_size: Option<u64>,
+}
+
+impl<W: Write + io::Seek> SizeLimitedWriter<W> {
+    fn new(mut inner: W, max_size: Option<u64>) -> io::Result<SizeLimitedWriter<W>> {
+        let position = inner.stream_position()?;
+        Ok(SizeLimitedWriter {
+            inner,
+            position,
+            max_size,
+        })
+    }
+}
+
+impl<W: Write> Write for SizeLimitedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(max_size) = self.max_size {
+            if self.position + buf.len() as u64 > max_size {
+                return Err(Error::other(format!(
+                    "The archive would exceed the maximum size of {max_size} bytes."
+                )));
+            }
+        }
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R: Read> Read for SizeLimitedWriter<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<W: io::Seek> io::Seek for SizeLimitedWriter<W> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.position = self.inner.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+/// Fails if the archive file would be created inside the directory that is being archived, which
+/// would make the archive include itself.
+fn ensure_archive_outside_directory(archive_file: &Path, directory: &Path) -> ZipResult<()> {
+    let directory = directory.canonicalize()?;
+    let archive_directory = match archive_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.canonicalize()?,
+        _ => std::env::current_dir()?,
+    };
+    if archive_directory.starts_with(&directory) {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            "The archive file must not be located inside the directory that is archived.",
+        )));
+    }
+    Ok(())
+}
+
+/// An entry that is added by `ZipWriterExtensions::create_from_iter`, with its name, its
+/// contents, and optionally its own compression method.
+pub struct SourceEntry {
+    name: String,
+    data: SourceData,
+    compression_method: Option<CompressionMethod>,
+}
+
+/// The contents of a `SourceEntry`.
+pub enum SourceData {
+    /// A directory entry. The entry name may end with a slash.
+    Directory,
+    /// A file that is streamed into the archive. A directory adds the directory and its contents
+    /// under the entry name, like `zip_create_from_files` does.
+    File(PathBuf),
+    /// Contents that are held in memory.
+    Bytes(Vec<u8>),
+    /// A reader that is streamed into the archive. Its size is not known ahead of time, so it is
+    /// not included in the progress totals, and contents larger than 4 GiB require
+    /// `CreateOptions::force_zip64`.
+    Reader(Box<dyn Read + Send>),
+}
+
+impl SourceEntry {
+    /// Creates an entry with the given name and contents, which is written with the options of
+    /// the archive.
+    pub fn new<S: Into<String>>(name: S, data: SourceData) -> SourceEntry {
+        SourceEntry {
+            name: name.into(),
+            data,
+            compression_method: None,
+        }
+    }
+
+    /// Sets the compression method of the entry, which takes precedence over the methods chosen
+    /// by `CreateOptions`. For a directory, it applies to the files it contains.
+    #[must_use]
+    pub fn compression_method(mut self, method: CompressionMethod) -> SourceEntry {
+        self.compression_method = Some(method);
+        self
+    }
+}
+
+/// Extends `ZipWriter` with methods that add files and directories from the file system.
+///
+/// The methods do not finish the archive, so that further entries can be added; call
+/// `ZipWriter::finish` once all entries have been written. Since AES-256 encrypted entries are
+/// only marked as encrypted once the archive is finished, the methods fail with
+/// `ErrorKind::Unsupported` for them; use `EncryptionMethod::ZipCrypto` instead.
+pub trait ZipWriterExtensions {
+    /// Adds the files and directories from the specified directory to the archive.
+    ///
+    /// Symbolic links are followed; directories that have already been visited through another
+    /// link are skipped.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory.
+    fn create_from_directory<P: AsRef<Path>>(&mut self, directory: P) -> ZipResult<()>;
+
+    /// Adds the files and directories from the specified directory to the archive, uses the
+    /// specified options.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or directory, or if the
+    /// compression method is not available.
+    fn create_from_directory_with_options<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        options: CreateOptions,
+    ) -> ZipResult<CreateReport>;
+
+    /// Adds a file, or a directory including its contents, to the archive under the given entry
+    /// name. The entries beneath a directory are named relative to `entry_name`; an empty entry
+    /// name adds them at the root of the archive.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or path.
+    fn add_path<P: AsRef<Path>>(&mut self, path: P, entry_name: &str) -> ZipResult<()>;
+
+    /// Adds a file, or a directory including its contents, to the archive under the given entry
+    /// name, uses the specified options.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or path, if the entry name is
+    /// invalid, or if the compression method is not available.
+    fn add_path_with_options<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        entry_name: &str,
+        options: CreateOptions,
+    ) -> ZipResult<CreateReport>;
+
+    /// Adds a file entry with the given name whose contents are streamed from the reader in
+    /// bounded chunks, without buffering them, and returns the number of bytes that have been
+    /// read. The size and CRC-32 are computed while the contents are written and filled into the
+    /// local header afterwards, which is why the writer needs `Seek`; contents larger than 4 GiB
+    /// require `CreateOptions::force_zip64`. The entry options of `options`, such as the
+    /// compression method, modification time and permissions, are applied; options that concern
+    /// a set of entries, such as the root prefix or the archive comment, are not.
+    /// # Errors
+    /// Will return `ZipError` for relevant io error on archive or reader, if the entry name is
+    /// invalid, or if the compression method is not available.
+    fn add_entry_from_reader<R: Read>(
+        &mut self,
+        entry_name: &str,
+        reader: R,
+        options: CreateOptions,
+    ) -> ZipResult<u64>;
+
+    /// Adds the given files to the archive, each under the given entry name, in the given order.
+    /// See `zip_create_from_files` for how entry names and directories are handled.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or files, or if an entry name
+    /// is invalid.
+    fn create_from_files<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        entries: &[(P1, P2)],
+    ) -> ZipResult<()>;
+
+    /// Adds the given files to the archive, each under the given entry name, in the given order,
+    /// uses the specified options.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive or files, if an entry name is
+    /// invalid, if the compression method is not available, or if a source path is a directory
+    /// that may not be recursed into.
+    fn create_from_files_with_options<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        entries: &[(P1, P2)],
+        options: CreateOptions,
+    ) -> ZipResult<CreateReport>;
+
+    /// Adds the given entries to the archive in the order they are produced, which lets files on
+    /// disk be combined with generated contents, and a custom traversal replace the directory
+    /// walk. Entry names must be relative and must not contain `..`.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive, files or readers, or if an
+    /// entry name is invalid.
+    fn create_from_iter<I: IntoIterator<Item = SourceEntry>>(
+        &mut self,
+        entries: I,
+    ) -> ZipResult<()>;
+
+    /// Adds the given entries to the archive in the order they are produced, uses the specified
+    /// options.
+    ///
+    /// Options that describe files on disk, such as permissions and symbolic link handling, apply
+    /// to `SourceData::File` entries. The entries are collected before they are written, so that
+    /// `CreateOptions::deterministic` and `CreateOptions::duplicate_entries` can be applied.
+    /// # Errors
+    /// Will return `ZipError` for relevant file io error on archive, files or readers, if an entry
+    /// name is invalid, or if the compression method is not available.
+    fn create_from_iter_with_options<I: IntoIterator<Item = SourceEntry>>(
+        &mut self,
+        entries: I,
+        options: CreateOptions,
+    ) -> ZipResult<CreateReport>;
+}
+
+/// The error message of the `ZipWriterExtensions` methods if entries are encrypted with AES-256.
+const AES_TO_ZIP_WRITER: &str = "AES-256 encrypted entries can not be written to a given \
+     `ZipWriter`, only by the functions that create or append to an archive themselves. Use \
+     `EncryptionMethod::ZipCrypto` to encrypt entries written to a `ZipWriter`.";
+
+impl<W: Write + io::Seek> ZipWriterExtensions for ZipWriter<W> {
+    fn create_from_directory<P: AsRef<Path>>(&mut self, directory: P) -> ZipResult<()> {
+        self.create_from_directory_with_options(directory, CreateOptions::default())?;
+        Ok(())
+    }
+
+    fn create_from_directory_with_options<P: AsRef<Path>>(
+        &mut self,
+        directory: P,
+        options: CreateOptions,
+    ) -> ZipResult<CreateReport> {
+        self.add_path_with_options(directory, "", options)
+    }
+
+    fn add_path<P: AsRef<Path>>(&mut self, path: P, entry_name: &str) -> ZipResult<()> {
+        self.add_path_with_options(path, entry_name, CreateOptions::default())?;
+        Ok(())
+    }
+
+    fn add_path_with_options<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        entry_name: &str,
+        options: CreateOptions,
+    ) -> ZipResult<CreateReport> {
+        options.forbid_entry_comment()?;
+        options.forbid_aes_encryption(AES_TO_ZIP_WRITER)?;
+        create_from_path(self, path.as_ref(), entry_name, options)
+    }
+
+    fn add_entry_from_reader<R: Read>(
+        &mut self,
+        entry_name: &str,
+        mut reader: R,
+        mut options: CreateOptions,
+    ) -> ZipResult<u64> {
+        options.forbid_entry_comment()?;
+        options.forbid_aes_encryption(AES_TO_ZIP_WRITER)?;
+        let file_options = options.file_options()?;
+        let name = validate_entry_name(Path::new(entry_name))?;
+        let entry_options = options.memory_entry_options(file_options, false);
+        let (mut entry_options, compression_method) =
+            options.file_entry_options(entry_options, Path::new(&name))?;
+        if options.force_zip64 {
+            entry_options = entry_options.large_file(true);
+        }
+        let alignment = match compression_method {
+            CompressionMethod::Stored => options.stored_alignment,
+            _ => None,
+        };
+        let mut bytes_read = 0;
+        write_file_entry(
+            self,
+            &mut reader,
+            &name,
+            entry_options,
+            alignment,
+            options.chunk_size,
+            |chunk_size| {
+                bytes_read += chunk_size as u64;
+                Ok(())
+            },
+        )?;
+        Ok(bytes_read)
+    }
+
+    fn create_from_files<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        entries: &[(P1, P2)],
+    ) -> ZipResult<()> {
+        self.create_from_files_with_options(entries, CreateOptions::default())?;
+        Ok(())
+    }
+
+    fn create_from_files_with_options<P1: AsRef<Path>, P2: AsRef<Path>>(
+        &mut self,
+        entries: &[(P1, P2)],
+        mut options: CreateOptions,
+    ) -> ZipResult<CreateReport> {
+        options.forbid_entry_comment()?;
+        options.forbid_aes_encryption(AES_TO_ZIP_WRITER)?;
+        let file_options = options.file_options()?;
+        let sources = validate_file_sources(entries, &options)?;
+        let mut report = CreateReport::default();
+        let mut pending_entries: Vec<PendingEntry> = Vec::new();
+        for (path, entry_name) in sources {
+            collect_entries(
+                path,
+                &entry_name,
+                &mut options,
+                file_options,
+                &mut pending_entries,
+                &mut report,
+            )?;
+        }
+        let mut entry_names: HashSet<String> = HashSet::new();
+        write_entries(
+            self,
+            &mut options,
+            pending_entries,
+            &mut entry_names,
+            &mut report,
+        )?;
+        Ok(report)
+    }
+
+    fn create_from_iter<I: IntoIterator<Item = SourceEntry>>(
+        &mut self,
+        entries: I,
+    ) -> ZipResult<()> {
+        self.create_from_iter_with_options(entries, CreateOptions::default())?;
+        Ok(())
+    }
+
+    fn create_from_iter_with_options<I: IntoIterator<Item = SourceEntry>>(
+        &mut self,
+        entries: I,
+        options: CreateOptions,
+    ) -> ZipResult<CreateReport> {
+        options.forbid_entry_comment()?;
+        options.forbid_aes_encryption(AES_TO_ZIP_WRITER)?;
+        create_from_source_entries(self, entries, options)
+    }
+}
+
+/// Adds a file, or a directory including its contents, to the archive under the given entry
+/// name, and returns the report, whose entry comments are written by `finish_archive`.
+fn create_from_path<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    path: &Path,
+    entry_name: &str,
+    mut options: CreateOptions,
+) -> ZipResult<CreateReport> {
+    let file_options = options.file_options()?;
+    let entry_name = if entry_name.is_empty() {
+        String::new()
+    } else {
+        validate_entry_name(Path::new(entry_name))?
+    };
+    let mut entry_names: HashSet<String> = HashSet::new();
+    let mut report = CreateReport::default();
+    add_path_to_archive(
+        zip_writer,
+        path,
+        &entry_name,
+        &mut options,
+        file_options,
+        &mut entry_names,
+        &mut report,
+    )?;
+    Ok(report)
+}
+
+/// Adds the given entries to the archive in the order they are produced, and returns the report,
+/// whose entry comments are written by `finish_archive`.
+fn create_from_source_entries<W, I>(
+    zip_writer: &mut ZipWriter<W>,
+    entries: I,
+    mut options: CreateOptions,
+) -> ZipResult<CreateReport>
+where
+    W: Write + io::Seek,
+    I: IntoIterator<Item = SourceEntry>,
+{
+    let file_options = options.file_options()?;
+    let mut report = CreateReport::default();
+    let mut pending_entries: Vec<PendingEntry> = Vec::new();
+    for entry in entries {
+        let first_pending = pending_entries.len();
+        collect_source_entry(
+            &entry.name,
+            entry.data,
+            &mut options,
+            file_options,
+            &mut pending_entries,
+            &mut report,
+        )?;
+        if let Some(method) = entry.compression_method {
+            for pending_entry in &mut pending_entries[first_pending..] {
+                if pending_entry.has_contents() {
+                    pending_entry.options =
+                        options.method_options(pending_entry.options, method)?;
+                    pending_entry.compression_method = method;
+                }
+            }
+        }
+    }
+    let mut entry_names: HashSet<String> = HashSet::new();
+    write_entries(
+        zip_writer,
+        &mut options,
+        pending_entries,
+        &mut entry_names,
+        &mut report,
+    )?;
+    Ok(report)
+}
+
+/// The central directory and the records that end an archive, which are overwritten when entries
+/// are appended.
+struct ArchiveTail {
+    position: u64,
+    bytes: Vec<u8>,
+}
+
+impl ArchiveTail {
+    fn read(file: &mut File) -> io::Result<ArchiveTail> {
+        let position = find_end_of_central_directory(file)?
+            .and_then(|eocd| {
+                eocd.zip64_position
+                    .unwrap_or(eocd.position)
+                    .checked_sub(eocd.central_directory_size)
+            })
+            .ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "The archive has no valid central directory.",
+                )
+            })?;
+        file.seek(SeekFrom::Start(position))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        file.seek(SeekFrom::Start(0))?;
+        Ok(ArchiveTail { position, bytes })
+    }
+
+    /// Writes the records back and truncates anything that has been written after them.
+    fn restore(&self, file: &mut File) -> io::Result<()> {
+        file.seek(SeekFrom::Start(self.position))?;
+        file.write_all(&self.bytes)?;
+        file.set_len(self.position + self.bytes.len() as u64)
+    }
+}
+
+/// An upper bound of the size of the records that end an archive: the end-of-central-directory
+/// record, and the Zip64 end-of-central-directory record and locator.
+const END_OF_ARCHIVE_SIZE: u64 = 22 + 56 + 20;
+
+/// The Unix file type bits of a symbolic link.
+const S_IFLNK: u32 = 0o120_000;
+
+/// Copies the entries of an archive to volumes of at most the given size, which are appended to
+/// `volumes` as soon as they are started.
+fn split_archive(
+    archive_file: &Path,
+    base_path: &Path,
+    volume_size: u64,
+    volumes: &mut Vec<ArchiveVolume>,
+) -> ZipResult<()> {
+    let mut archive = ZipArchive::new(BufReader::new(File::open(archive_file)?))?;
+    let comment = String::from_utf8_lossy(archive.comment()).into_owned();
+    let end_of_archive_size = END_OF_ARCHIVE_SIZE + comment.len() as u64;
+    // The writer of the current volume, an upper bound of the size it has reached, and the
+    // comments of the entries it contains.
+    let mut volume: Option<(ZipWriter<File>, u64, Vec<String>)> = None;
+    for file_number in 0..archive.len() {
+        let entry_size = raw_entry_size(&archive.by_index_raw(file_number)?);
+        if entry_size + end_of_archive_size > volume_size {
+            let name = archive.by_index_raw(file_number)?.name().to_string();
+            return Err(ZipError::Io(Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "The entry {name} alone does not fit into a volume of {volume_size} bytes."
+                ),
+            )));
+        }
+        let fits = volume.as_ref().is_some_and(|(_, written, _)| {
+            written + entry_size + end_of_archive_size <= volume_size
+        });
+        if !fits {
+            if let Some((zip_writer, _, entry_comments)) = volume.take() {
+                finish_volume(zip_writer, &entry_comments, volumes)?;
+            }
+            volume = Some((start_volume(base_path, &comment, volumes)?, 0, Vec::new()));
+        }
+        let Some((zip_writer, written, entry_comments)) = volume.as_mut() else {
+            unreachable!("a volume has just been started");
+        };
+        entry_comments.push(copy_raw_entry(zip_writer, &mut archive, file_number)?);
+        *written += entry_size;
+    }
+    let (zip_writer, entry_comments) = match volume {
+        Some((zip_writer, _, entry_comments)) => (zip_writer, entry_comments),
+        None => (start_volume(base_path, &comment, volumes)?, Vec::new()),
+    };
+    finish_volume(zip_writer, &entry_comments, volumes)
+}
+
+/// Returns an upper bound of the size that an entry occupies in an archive: its local header,
+/// its data and its central directory header, with room for Zip64 extra fields.
+fn raw_entry_size(entry: &zip::read::ZipFile<'_>) -> u64 {
+    let name_length = entry.name().len() as u64;
+    (30 + 20 + name_length) + entry.compressed_size() + (46 + 28 + name_length)
+}
+
+/// Creates the next volume with the given archive comment and appends it to `volumes`.
+fn start_volume(
+    base_path: &Path,
+    comment: &str,
+    volumes: &mut Vec<ArchiveVolume>,
+) -> io::Result<ZipWriter<File>> {
+    let stem = base_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let path = base_path.with_file_name(format!("{stem}-{:03}.zip", volumes.len() + 1));
+    let file = create_archive_file(&path)?;
+    volumes.push(ArchiveVolume { path, size: 0 });
+    let mut zip_writer = ZipWriter::new(file);
+    zip_writer.set_comment(comment);
+    Ok(zip_writer)
+}
+
+/// Finishes the last volume in `volumes`, writes the comments of its entries, and records its
+/// size.
+fn finish_volume(
+    mut zip_writer: ZipWriter<File>,
+    entry_comments: &[String],
+    volumes: &mut [ArchiveVolume],
+) -> ZipResult<()> {
+    let mut file = zip_writer.finish()?;
+    write_entry_comments(&mut file, &indexed_comments(entry_comments))?;
+    let size = file.metadata()?.len();
+    if let Some(volume) = volumes.last_mut() {
+        volume.size = size;
+    }
+    Ok(())
+}
+
+/// Creates or truncates an archive file. It is opened for reading as well, so that the entry
+/// comments can be written by `finish_archive`.
+fn create_archive_file(archive_file: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(archive_file)
+}
+
+/// Creates a new temporary archive file, failing if the file already exists so that no file is
+/// truncated. It is opened for reading as well, like by `create_archive_file`.
+fn create_temporary_archive_file(temporary_file: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(temporary_file)
+}
+
+/// Finishes an archive, and writes the entry comments of the report into its central directory,
+/// since `ZipWriter` can not write them.
+fn finish_archive<W: Read + Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    report: &CreateReport,
+) -> ZipResult<W> {
+    let mut writer = zip_writer.finish()?;
+    write_entry_comments(&mut writer, &report.entry_comments)?;
+    mark_aes_entries(&mut writer, &report.encrypted_entries)?;
+    Ok(writer)
+}
+
+/// Pairs the comments of the entries that have been written with the index of their entry,
+/// leaving out empty comments.
+fn indexed_comments(entry_comments: &[String]) -> Vec<(usize, String)> {
+    entry_comments
+        .iter()
+        .enumerate()
+        .filter(|(_, comment)| !comment.is_empty())
+        .map(|(index, comment)| (index, comment.clone()))
+        .collect()
+}
+
+/// Writes the comments of entries, given by the index of the entry, into the central directory
+/// of a finished archive. The central directory is left as it is if all comments are empty.
+fn write_entry_comments<F: Read + Write + io::Seek>(
+    file: &mut F,
+    comments: &[(usize, String)],
+) -> ZipResult<()> {
+    if comments.iter().all(|(_, comment)| comment.is_empty()) {
+        return Ok(());
+    }
+    rewrite_central_directory(file, |headers| {
+        for (index, comment) in comments.iter().filter(|(_, comment)| !comment.is_empty()) {
+            let header = headers.get_mut(*index).ok_or_else(|| {
+                Error::new(
+                    ErrorKind::InvalidData,
+                    "The archive has fewer entries than have been written.",
+                )
+            })?;
+            header.set_comment(comment);
+        }
+        Ok(())
+    })?;
+    Ok(())
+}
+
+/// Rewrites an archive to a new temporary file with a unique name next to it, which receives the
+/// archive comment and the entries that are written by the given function, and renames the
+/// temporary file over the original. The function records the comments of the entries it copies,
+/// with the index of their entry, which are written once the archive is finished. If anything
+/// fails, the temporary file is removed and the original remains intact.
+fn rewrite_archive<T, F>(archive_file: &Path, write: F) -> ZipResult<T>
+where
+    F: FnOnce(
+        &mut ZipArchive<BufReader<File>>,
+        &mut ZipWriter<File>,
+        &mut Vec<(usize, String)>,
+    ) -> ZipResult<T>,
+{
+    let mut archive = open_archive(archive_file)?;
+    let temporary_file =
+        temporary_file_path(archive_file.parent().unwrap_or(archive_file), archive_file);
+    let file = create_temporary_archive_file(&temporary_file)?;
+    let mut zip_writer = ZipWriter::new(file);
+    zip_writer.set_raw_comment(archive.comment().to_vec());
+    let mut entry_comments = Vec::new();
+    let result = write(&mut archive, &mut zip_writer, &mut entry_comments).and_then(|output| {
+        write_entry_comments(&mut zip_writer.finish()?, &entry_comments)?;
+        Ok(output)
+    });
+    drop(zip_writer);
+    drop(archive);
+    match result
+        .and_then(|output| Ok(std::fs::rename(&temporary_file, archive_file).map(|()| output)?))
+    {
+        Ok(output) => Ok(output),
+        Err(error) => {
+            std::fs::remove_file(&temporary_file).unwrap_or_default();
+            Err(error)
+        }
+    }
+}
+
+/// Returns the names of the entries of an archive, in the order they are stored.
+fn stored_entry_names<R: Read + io::Seek>(archive: &mut ZipArchive<R>) -> ZipResult<Vec<String>> {
+    (0..archive.len())
+        .map(|file_number| Ok(archive.by_index_raw(file_number)?.name().to_string()))
+        .collect()
+}
+
+/// Determines whether a file or directory that has been found matches the stored entry, given by
+/// whether it is a directory, its size, its CRC-32 and its modification time.
+fn is_unchanged(
+    entry: &PendingEntry,
+    (is_dir, size, crc32, modified): (bool, u64, u32, DateTime),
+    comparison: SyncComparison,
+) -> io::Result<bool> {
+    match (&entry.source, comparison) {
+        (EntrySource::Directory, _) => Ok(is_dir),
+        (EntrySource::File(_, file_size), _) if is_dir || *file_size != size => Ok(false),
+        (EntrySource::File(path, _), SyncComparison::Crc32) => Ok(file_crc32(path)? == crc32),
+        (EntrySource::File(path, _), SyncComparison::ModifiedTime) => {
+            let file_modified = system_time_to_zip_datetime(std::fs::metadata(path)?.modified()?);
+            Ok((file_modified.datepart(), file_modified.timepart())
+                == (modified.datepart(), modified.timepart()))
+        }
+        _ => Ok(false),
+    }
+}
+
+/// Copies the entries of an archive that are not flagged as removed without decompressing them,
+/// and returns the number of removed entries.
+fn copy_surviving_entries<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    zip_writer: &mut ZipWriter<File>,
+    removed: &[bool],
+    entry_comments: &mut Vec<(usize, String)>,
+) -> ZipResult<usize> {
+    let surviving = removed.iter().enumerate().filter(|(_, removed)| !**removed);
+    for (index, (file_number, _)) in surviving.enumerate() {
+        let comment = copy_raw_entry(zip_writer, archive, file_number)?;
+        entry_comments.push((index, comment));
+    }
+    Ok(removed.iter().filter(|removed| **removed).count())
+}
+
+/// Copies an archive, replacing or adding the given entries, and records the comments of the
+/// entries that are copied.
+fn write_replaced_entries<R: Read + io::Seek>(
+    archive: &mut ZipArchive<R>,
+    zip_writer: &mut ZipWriter<File>,
+    mut replacements: Vec<(String, &ReplacementSource)>,
+    entry_comments: &mut Vec<(usize, String)>,
+) -> ZipResult<()> {
+    for file_number in 0..archive.len() {
+        let (name, compression_method) = {
+            let entry = archive.by_index_raw(file_number)?;
+            (entry.name().to_string(), entry.compression())
+        };
+        let replacement = replacements
+            .iter()
+            .position(|(entry_name, _)| *entry_name == name);
+        if let Some(index) = replacement {
+            let (name, source) = replacements.remove(index);
+            write_replacement(zip_writer, name, source, compression_method)?;
+        } else {
+            let comment = copy_raw_entry(zip_writer, archive, file_number)?;
+            entry_comments.push((file_number, comment));
+        }
+    }
+    for (name, source) in replacements {
+        write_replacement(zip_writer, name, source, CompressionMethod::Stored)?;
+    }
+    Ok(())
+}
+
+/// Writes the new contents of a replaced entry, compressed with the given method if it is
+/// available.
+fn write_replacement(
+    zip_writer: &mut ZipWriter<File>,
+    name: String,
+    source: &ReplacementSource,
+    compression_method: CompressionMethod,
+) -> ZipResult<()> {
+    let compression_method = match check_compression_method(compression_method) {
+        Ok(()) => compression_method,
+        Err(_) => CompressionMethod::Stored,
+    };
+    let mut options = FileOptions::default()
+        .compression_method(compression_method)
+        .last_modified_time(DateTime::default());
+    match source {
+        ReplacementSource::File(path) => {
+            let mut file = File::open(path)?;
+            if let Ok(modified) = file.metadata().and_then(|metadata| metadata.modified()) {
+                options = options.last_modified_time(system_time_to_zip_datetime(modified));
+            }
+            zip_writer.start_file(name, options)?;
+            io::copy(&mut file, zip_writer)?;
+        }
+        ReplacementSource::Bytes(bytes) => {
+            zip_writer.start_file(name, options)?;
+            zip_writer.write_all(bytes)?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies an entry from one archive to another without decompressing it. Symbolic links are
+/// written again, since a raw copy does not preserve the file type. Returns the comment of the
+/// entry, which the zip writer does not copy, so that it can be written by
+/// `write_entry_comments`.
+fn copy_raw_entry<W: Write + io::Seek, R: Read + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    archive: &mut ZipArchive<R>,
+    file_number: usize,
+) -> ZipResult<String> {
+    let entry = archive.by_index_raw(file_number)?;
+    let comment = entry.comment().to_string();
+    let mode = entry.unix_mode().filter(|mode| mode & 0o170_000 == S_IFLNK);
+    let Some(mode) = mode else {
+        zip_writer.raw_copy_file(entry)?;
+        return Ok(comment);
+    };
+    let options = FileOptions::default()
+        .last_modified_time(entry.last_modified())
+        .unix_permissions(mode);
+    let name = entry.name().to_string();
+    drop(entry);
+    let mut target = String::new();
+    archive.by_index(file_number)?.read_to_string(&mut target)?;
+    zip_writer.add_symlink(name, target, options)?;
+    Ok(comment)
+}
+
+/// Adds a file, or a directory including its contents, to the archive under the given entry
+/// name. The names of the entries that have been written are tracked in `entry_names`, so that
+/// several paths can be added to one archive: directory entries that already exist are skipped,
+/// and file entries that already exist fail with `ErrorKind::AlreadyExists`.
+fn add_path_to_archive<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    path: &Path,
+    entry_name: &str,
+    options: &mut CreateOptions,
+    file_options: FileOptions,
+    entry_names: &mut HashSet<String>,
+    report: &mut CreateReport,
+) -> ZipResult<()> {
+    let mut pending_entries: Vec<PendingEntry> = Vec::new();
+    collect_entries(
+        path,
+        entry_name,
+        options,
+        file_options,
+        &mut pending_entries,
+        report,
+    )?;
+    write_entries(zip_writer, options, pending_entries, entry_names, report)
+}
+
+/// An entry that is to be written to the archive.
+struct PendingEntry {
+    name: String,
+    source: EntrySource,
+    options: FileOptions,
+    compression_method: CompressionMethod,
+}
+
+/// The contents of an entry that is to be written to the archive.
+enum EntrySource {
+    Directory,
+    /// A file and its size at the time it was found.
+    File(PathBuf, u64),
+    Bytes(Vec<u8>),
+    /// A reader, whose size is not known.
+    Reader(Box<dyn io::Read + Send>),
+    /// A symbolic link and its target.
+    Symlink(String),
+    /// A file or bytes that have already been compressed, and the path that is reported as
+    /// progress.
+    #[cfg(any(feature = "rayon", feature = "aes"))]
+    Compressed(PathBuf, CompressedEntry),
+}
+
+impl PendingEntry {
+    /// Creates a directory entry, which is always stored.
+    fn directory(name: String, options: FileOptions) -> PendingEntry {
+        PendingEntry {
+            name,
+            source: EntrySource::Directory,
+            options,
+            compression_method: CompressionMethod::Stored,
+        }
+    }
+
+    /// Describes where the contents of the entry come from, for error messages.
+    fn source_description(&self) -> String {
+        match &self.source {
+            EntrySource::File(path, _) => path.display().to_string(),
+            #[cfg(any(feature = "rayon", feature = "aes"))]
+            EntrySource::Compressed(path, _) => path.display().to_string(),
+            EntrySource::Bytes(_) | EntrySource::Reader(_) => "in-memory contents".to_string(),
+            EntrySource::Symlink(target) => format!("a symbolic link to {target}"),
+            EntrySource::Directory => "a directory".to_string(),
+        }
+    }
+
+    fn is_dir(&self) -> bool {
+        matches!(self.source, EntrySource::Directory)
+    }
+
+    /// Opens the contents of a file or bytes entry for reading; other entries read as empty.
+    fn contents(&self) -> io::Result<Box<dyn Read + '_>> {
+        match &self.source {
+            EntrySource::File(path, _) => Ok(Box::new(BufReader::new(File::open(path)?))),
+            EntrySource::Bytes(bytes) => Ok(Box::new(bytes.as_slice())),
+            _ => Ok(Box::new(io::empty())),
+        }
+    }
+
+    /// Determines whether the entry has contents that are read while writing it.
+    fn has_contents(&self) -> bool {
+        match self.source {
+            EntrySource::File(..) | EntrySource::Bytes(_) | EntrySource::Reader(_) => true,
+            #[cfg(any(feature = "rayon", feature = "aes"))]
+            EntrySource::Compressed(..) => true,
+            EntrySource::Directory | EntrySource::Symlink(_) => false,
+        }
+    }
+
+    fn size(&self) -> u64 {
+        match &self.source {
+            EntrySource::Directory | EntrySource::Reader(_) | EntrySource::Symlink(_) => 0,
+            EntrySource::File(_, size) => *size,
+            EntrySource::Bytes(bytes) => bytes.len() as u64,
+            #[cfg(any(feature = "rayon", feature = "aes"))]
+            EntrySource::Compressed(_, compressed) => compressed.size(),
+        }
+    }
+}
+
+/// Collects the entries for an entry of `ZipWriterExtensions::create_from_iter`.
+fn collect_source_entry(
+    entry_name: &str,
+    data: SourceData,
+    options: &mut CreateOptions,
+    file_options: FileOptions,
+    pending_entries: &mut Vec<PendingEntry>,
+    report: &mut CreateReport,
+) -> ZipResult<()> {
+    let name = validate_entry_name(Path::new(entry_name))?;
+    let source = match data {
+        SourceData::File(path) => {
+            return collect_entries(&path, &name, options, file_options, pending_entries, report);
+        }
+        SourceData::Directory => {
+            let entry_options = options.memory_entry_options(file_options, true);
+            pending_entries.push(PendingEntry::directory(name, entry_options));
+            return Ok(());
+        }
+        SourceData::Bytes(bytes) => EntrySource::Bytes(bytes),
+        SourceData::Reader(reader) => EntrySource::Reader(reader),
+    };
+    let entry_options = options.memory_entry_options(file_options, false);
+    let (entry_options, compression_method) =
+        options.file_entry_options(entry_options, Path::new(&name))?;
+    pending_entries.push(PendingEntry {
+        name,
+        source,
+        options: entry_options,
+        compression_method,
+    });
+    Ok(())
+}
+
+/// Collects the entries for a file, or a directory including its contents, in the order they
+/// are found while walking the directory tree.
+fn collect_entries(
+    path: &Path,
+    entry_name: &str,
+    options: &mut CreateOptions,
+    file_options: FileOptions,
+    pending_entries: &mut Vec<PendingEntry>,
+    report: &mut CreateReport,
+) -> ZipResult<()> {
+    let entry_name = entry_name.trim_end_matches('/');
+    let metadata = std::fs::metadata(path)?;
+    let entry_options = options.entry_options(file_options, path, &metadata);
+    if metadata.is_file() {
+        let (entry_options, compression_method) =
+            options.file_entry_options(entry_options, path)?;
+        pending_entries.push(PendingEntry {
+            name: entry_name.to_string(),
+            source: EntrySource::File(path.to_path_buf(), metadata.len()),
+            options: entry_options,
+            compression_method,
+        });
+        return Ok(());
+    }
+    if options.include_empty_dirs && !entry_name.is_empty() {
+        pending_entries.push(PendingEntry::directory(
+            entry_name.to_string(),
+            entry_options,
+        ));
+    }
+
+    // Each queued directory is paired with the canonical paths of itself and the directories
+    // it has been reached through, which detects symbolic links that point back into the tree.
+    let mut paths_queue: Vec<(PathBuf, Vec<PathBuf>)> = vec![];
+    paths_queue.push((path.to_path_buf(), vec![path.canonicalize()?]));
+
+    #[cfg(feature = "ignore")]
+    let mut ignore_rules = if options.respect_gitignore {
+        Some(IgnoreRules::new(path, &options.force_include)?)
+    } else {
+        None
+    };
+
+    while let Some((next, ancestors)) = paths_queue.pop() {
+        #[cfg(feature = "ignore")]
+        if let Some(ignore_rules) = ignore_rules.as_mut() {
+            ignore_rules.enter_directory(&next)?;
+        }
+        let directory_entry_iterator = std::fs::read_dir(next)?;
+
+        for entry in directory_entry_iterator {
+            let entry_path = entry?.path();
+            let Some((entry_metadata, is_symlink)) =
+                walk_metadata(&entry_path, options.symlinks, report)?
+            else {
+                continue;
+            };
+            #[cfg(feature = "ignore")]
+            if let Some(ignore_rules) = &ignore_rules {
+                if ignore_rules.is_ignored(&entry_path, entry_metadata.is_dir()) {
+                    report.skipped_by_gitignore += 1;
+                    continue;
+                }
+            }
+            let relative_path = make_relative_path(path, &entry_path);
+            if let Some(filter) = options.filter.as_mut() {
+                if !filter(&relative_path, &entry_metadata) {
+                    report.skipped_by_filter += 1;
+                    continue;
+                }
+            }
+            let entry_options = options.entry_options(file_options, &entry_path, &entry_metadata);
+            if entry_metadata.file_type().is_symlink() {
+                let target = std::fs::read_link(&entry_path)?;
+                pending_entries.push(PendingEntry {
+                    name: join_entry_name(entry_name, relative_path),
+                    source: EntrySource::Symlink(symlink_target(&target)),
+                    options: entry_options
+                        .compression_method(CompressionMethod::Stored)
+                        .compression_level(None),
+                    compression_method: CompressionMethod::Stored,
+                });
+            } else if entry_metadata.is_file() {
+                let (entry_options, compression_method) =
+                    options.file_entry_options(entry_options, &entry_path)?;
+                pending_entries.push(PendingEntry {
+                    name: join_entry_name(entry_name, relative_path),
+                    source: EntrySource::File(entry_path, entry_metadata.len()),
+                    options: entry_options,
+                    compression_method,
+                });
+            } else if entry_metadata.is_dir() {
+                let canonical_path = canonical_directory_path(&entry_path, is_symlink, &ancestors)?;
+                if options.include_empty_dirs {
+                    pending_entries.push(PendingEntry::directory(
+                        join_entry_name(entry_name, relative_path),
+                        entry_options,
+                    ));
+                }
+                let mut entry_ancestors = ancestors.clone();
+                entry_ancestors.push(canonical_path);
+                paths_queue.push((entry_path, entry_ancestors));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Returns the metadata of an entry that has been found while walking a directory tree, as
+/// determined by the symlink policy, and whether it is a symbolic link, or `None` if the entry is
+/// skipped.
+fn walk_metadata(
+    entry_path: &Path,
+    policy: SymlinkPolicy,
+    report: &mut CreateReport,
+) -> io::Result<Option<(Metadata, bool)>> {
+    let link_metadata = std::fs::symlink_metadata(entry_path)?;
+    let is_symlink = link_metadata.is_symlink();
+    if !is_symlink || policy == SymlinkPolicy::Store {
+        return Ok(Some((link_metadata, is_symlink)));
+    }
+    if policy == SymlinkPolicy::Follow {
+        match std::fs::metadata(entry_path) {
+            Ok(metadata) => return Ok(Some((metadata, true))),
+            Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+            Err(_) => {}
+        }
+    }
+    report.skipped_symlinks += 1;
+    Ok(None)
+}
+
+/// Returns the canonical path of a directory that has been found while walking a directory
+/// tree, given the canonical paths of the directories it has been reached through. Fails if the
+/// directory is a symbolic link to one of those directories.
+fn canonical_directory_path(
+    entry_path: &Path,
+    is_symlink: bool,
+    ancestors: &[PathBuf],
+) -> ZipResult<PathBuf> {
+    if !is_symlink {
+        let parent = ancestors.last().map_or(Path::new(""), PathBuf::as_path);
+        return Ok(parent.join(entry_path.file_name().unwrap_or_default()));
+    }
+    let canonical_path = entry_path.canonicalize()?;
+    if ancestors.contains(&canonical_path) {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::InvalidInput,
+            format!(
+                "The symbolic link {} points to {}, which contains the link.",
+                entry_path.display(),
+                canonical_path.display()
+            ),
+        )));
+    }
+    Ok(canonical_path)
+}
+
+/// Writes the collected entries to the archive, sorted by entry name in deterministic mode, and
+/// skips duplicate files according to the duplicate entry policy.
+fn write_entries<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    options: &mut CreateOptions,
+    mut pending_entries: Vec<PendingEntry>,
+    entry_names: &mut HashSet<String>,
+    report: &mut CreateReport,
+) -> ZipResult<()> {
+    let superseded = prepare_archive(zip_writer, options, &mut pending_entries)?;
+    let mut duplicates = DuplicateContents::find(&pending_entries, &superseded, options)?;
+    let mut progress = prepare_file_entries(&mut pending_entries, &superseded, options.force_zip64);
+
+    // The number of entries that are prepared at once before they are written.
+    #[cfg(feature = "rayon")]
+    let batch_size = options.parallel_entries.unwrap_or(1);
+    #[cfg(not(feature = "rayon"))]
+    let batch_size = 1;
+    let mut pending = pending_entries
+        .into_iter()
+        .zip(superseded)
+        .enumerate()
+        .peekable();
+    while pending.peek().is_some() {
+        #[allow(unused_mut)]
+        let (indices, mut batch): (Vec<usize>, Vec<(PendingEntry, bool)>) =
+            pending.by_ref().take(batch_size).unzip();
+        // A raw copy of compressed data only writes ZIP64 extra fields for entries that need
+        // them, so entries with forced ZIP64 extra fields are compressed while they are written.
+        #[cfg(feature = "rayon")]
+        if options.parallel_entries.is_some() && !options.force_zip64 {
+            compress_batch(&mut batch, options.chunk_size)?;
+        }
+        for (index, (mut entry, superseded)) in indices.into_iter().zip(batch) {
+            let key = options.entry_key(&entry.name);
+            if entry.is_dir() {
+                report.directories_added += write_directory_entry(
+                    zip_writer,
+                    entry_names,
+                    &key,
+                    entry.name,
+                    entry.options,
+                )?;
+                continue;
+            }
+            if superseded
+                || !is_new_entry(options.duplicate_entries, entry_names, &key, &entry.name)?
+            {
+                report.skipped_duplicates += 1;
+                continue;
+            }
+            entry_names.insert(key);
+            options.record_entry_comment(&entry, report)?;
+            if duplicates.write_entry(zip_writer, &mut entry, index, options, &mut progress)? {
+                report.files_added += 1;
+                options.report_progress(&progress)?;
+                continue;
+            }
+            write_pending_entry(zip_writer, entry, options, report, &mut progress)?;
+        }
+    }
+    duplicates.finish(report);
+    Ok(())
+}
+
+/// Writes a file or symlink entry that has not been written by `DuplicateContents`, and updates
+/// the report and the progress.
+fn write_pending_entry<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    #[cfg_attr(not(feature = "aes"), allow(unused_mut))] mut entry: PendingEntry,
+    options: &mut CreateOptions,
+    report: &mut CreateReport,
+    progress: &mut CreateProgress,
+) -> ZipResult<()> {
+    // AES-256 encrypted entries are encrypted after they have been compressed.
+    #[cfg(feature = "aes")]
+    if options.aes_password().is_some() {
+        compress_entry(&mut entry, options.chunk_size)?;
+    }
+    let mut reader: Box<dyn io::Read> = match entry.source {
+        EntrySource::Directory => return Ok(()),
+        EntrySource::File(path, _) => {
+            let reader = File::open(&path)?;
+            progress.current_path = path;
+            Box::new(reader)
+        }
+        EntrySource::Bytes(bytes) => {
+            progress.current_path = PathBuf::from(&entry.name);
+            Box::new(io::Cursor::new(bytes))
+        }
+        EntrySource::Reader(reader) => {
+            progress.current_path = PathBuf::from(&entry.name);
+            reader
+        }
+        EntrySource::Symlink(target) => {
+            zip_writer.add_symlink(entry.name, target, entry.options)?;
+            report.symlinks_added += 1;
+            return Ok(());
+        }
+        #[cfg(any(feature = "rayon", feature = "aes"))]
+        EntrySource::Compressed(path, compressed) => {
+            progress.current_path = path;
+            progress.bytes_read += compressed.size();
+            write_compressed_entry(
+                zip_writer,
+                compressed,
+                &entry.name,
+                entry.options,
+                options,
+                report,
+            )?;
+            report.files_added += 1;
+            progress.files_completed += 1;
+            options.report_progress(progress)?;
+            return Ok(());
+        }
+    };
+    let alignment = match entry.compression_method {
+        CompressionMethod::Stored => options.stored_alignment,
+        _ => None,
+    };
+    write_file_entry(
+        zip_writer,
+        &mut reader,
+        &entry.name,
+        entry.options,
+        alignment,
+        options.chunk_size,
+        |chunk_size| {
+            progress.bytes_read += chunk_size as u64;
+            options.report_progress(progress)
+        },
+    )?;
+    report.files_added += 1;
+    progress.files_completed += 1;
+    options.report_progress(progress)?;
+    Ok(())
+}
+
+/// Sets the archive comment, applies the root prefix and the order of the entries, and checks
+/// their names. Returns for each entry whether it is superseded by a later one.
+fn prepare_archive<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    options: &CreateOptions,
+    pending_entries: &mut Vec<PendingEntry>,
+) -> ZipResult<Vec<bool>> {
+    if let Some(comment) = &options.archive_comment {
+        zip_writer.set_comment(comment.clone());
+    }
+    apply_root_prefix(options, pending_entries)?;
+    if options.deterministic {
+        pending_entries.sort_by(|first, second| first.name.cmp(&second.name));
+    }
+    check_distinct_names(pending_entries, options)?;
+    Ok(superseded_entries(pending_entries, options))
+}
+
+/// The modification time, as MS-DOS date and time, and the permissions of an entry.
+type EntryAttributes = (Option<(u16, u16)>, Option<u32>);
+
+/// The file entries with identical contents among those that are written, and, with
+/// `CreateOptions::deduplicate_contents`, the compressed data of the first entry of each group.
+struct DuplicateContents {
+    /// The group of each pending entry, if another entry has the same contents.
+    groups: Vec<Option<usize>>,
+    /// The attributes of each pending entry that has a group, which have to match for its
+    /// compressed data to be copied.
+    attributes: Vec<Option<EntryAttributes>>,
+    /// The names of the entries of each group that have been written.
+    names: Vec<Vec<String>>,
+    /// The groups in the order their first entry has been written.
+    order: Vec<usize>,
+    deduplicate: bool,
+    /// The index of the pending entry whose compressed data is held for each group, and the data.
+    compressed: HashMap<usize, (usize, CompressedEntry)>,
+    copies: usize,
+}
+
+impl DuplicateContents {
+    /// Groups the file entries that are not superseded by their contents. Only entries of the
+    /// same size are read, first to compare their CRC-32, and then byte for byte.
+    fn find(
+        pending_entries: &[PendingEntry],
+        superseded: &[bool],
+        options: &CreateOptions,
+    ) -> io::Result<DuplicateContents> {
+        let mut by_size: HashMap<u64, Vec<usize>> = HashMap::new();
+        for (index, entry) in pending_entries.iter().enumerate() {
+            let comparable = matches!(entry.source, EntrySource::File(..) | EntrySource::Bytes(_));
+            if comparable && !superseded[index] && entry.size() > 0 {
+                by_size.entry(entry.size()).or_default().push(index);
+            }
+        }
+        let mut same_size: Vec<Vec<usize>> = by_size
+            .into_values()
+            .filter(|indices| indices.len() > 1)
+            .collect();
+        same_size.sort_unstable();
+
+        let mut groups: Vec<Option<usize>> = vec![None; pending_entries.len()];
+        let mut group_count = 0;
+        for indices in same_size {
+            let mut by_crc32: HashMap<u32, Vec<usize>> = HashMap::new();
+            for index in indices {
+                let crc32 = match &pending_entries[index].source {
+                    EntrySource::File(path, _) => file_crc32(path)?,
+                    EntrySource::Bytes(bytes) => crc32fast::hash(bytes),
+                    _ => continue,
+                };
+                by_crc32.entry(crc32).or_default().push(index);
+            }
+            let mut same_crc32: Vec<Vec<usize>> = by_crc32
+                .into_values()
+                .filter(|indices| indices.len() > 1)
+                .collect();
+            same_crc32.sort_unstable();
+            for indices in same_crc32 {
+                // Entries are compared with the first entry of each distinct contents.
+                let mut representatives: Vec<usize> = Vec::new();
+                for index in indices {
+                    let mut representative = None;
+                    for candidate in &representatives {
+                        let first = &pending_entries[*candidate];
+                        if same_contents(first, &pending_entries[index])? {
+                            representative = Some(*candidate);
+                            break;
+                        }
+                    }
+                    let Some(representative) = representative else {
+                        representatives.push(index);
+                        continue;
+                    };
+                    let group = *groups[representative].get_or_insert_with(|| {
+                        group_count += 1;
+                        group_count - 1
+                    });
+                    groups[index] = Some(group);
+                }
+            }
+        }
+        let attributes = pending_entries
+            .iter()
+            .zip(&groups)
+            .map(|(entry, group)| {
+                if group.is_none() {
+                    return Ok(None);
+                }
+                let (modified_time, permissions) = match &entry.source {
+                    EntrySource::File(path, _) => {
+                        options.entry_attributes(path, &std::fs::metadata(path)?)
+                    }
+                    _ => options.memory_entry_attributes(false),
+                };
+                let modified_time = modified_time.map(|time| (time.datepart(), time.timepart()));
+                Ok(Some((modified_time, permissions)))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(DuplicateContents {
+            groups,
+            attributes,
+            names: vec![Vec::new(); group_count],
+            order: Vec::new(),
+            deduplicate: options.deduplicate_contents,
+            compressed: HashMap::new(),
+            copies: 0,
+        })
+    }
+
+    /// Records that the pending entry with the given index is written. If it has the contents
+    /// of another entry and is deduplicated, writes it and updates the progress: the first entry
+    /// of the group is compressed once, and the others are copied from its compressed data if
+    /// they have the same modification time and permissions. Returns whether the entry has been
+    /// written.
+    fn write_entry<W: Write + io::Seek>(
+        &mut self,
+        zip_writer: &mut ZipWriter<W>,
+        entry: &mut PendingEntry,
+        index: usize,
+        options: &CreateOptions,
+        progress: &mut CreateProgress,
+    ) -> ZipResult<bool> {
+        let Some(group) = self.groups[index] else {
+            return Ok(false);
+        };
+        if self.names[group].is_empty() {
+            self.order.push(group);
+        }
+        self.names[group].push(entry.name.clone());
+        if !self.deduplicate
+            || entry.compression_method == CompressionMethod::Stored
+            || options.force_zip64
+            || options.encrypts_with_aes()
+        {
+            return Ok(false);
+        }
+        let size = entry.size();
+        progress.current_path = PathBuf::from(&entry.name);
+        if let Some((first, compressed)) = self.compressed.get_mut(&group) {
+            if self.attributes[*first] != self.attributes[index] {
+                return Ok(false);
+            }
+            compressed.copy_to(zip_writer, &entry.name)?;
+            self.copies += 1;
+            progress.bytes_read += size;
+            progress.files_completed += 1;
+            return Ok(true);
+        }
+        let chunk_size = options.chunk_size;
+        let mut compressed = match std::mem::replace(&mut entry.source, EntrySource::Directory) {
+            EntrySource::File(path, _) => {
+                let mut reader = File::open(path)?;
+                CompressedEntry::compress(
+                    &entry.name,
+                    &mut reader,
+                    entry.options,
+                    size,
+                    chunk_size,
+                )?
+            }
+            EntrySource::Bytes(bytes) => {
+                let mut reader = bytes.as_slice();
+                CompressedEntry::compress(
+                    &entry.name,
+                    &mut reader,
+                    entry.options,
+                    size,
+                    chunk_size,
+                )?
+            }
+            #[cfg(any(feature = "rayon", feature = "aes"))]
+            EntrySource::Compressed(_, compressed) => compressed,
+            source => {
+                entry.source = source;
+                return Ok(false);
+            }
+        };
+        compressed.copy_to(zip_writer, &entry.name)?;
+        self.compressed.insert(group, (index, compressed));
+        progress.bytes_read += size;
+        progress.files_completed += 1;
+        Ok(true)
+    }
+
+    /// Adds the groups of entries that have been written to the report.
+    fn finish(mut self, report: &mut CreateReport) {
+        for group in self.order {
+            let names = std::mem::take(&mut self.names[group]);
+            if names.len() > 1 {
+                report.duplicate_contents.push(names);
+            }
+        }
+        report.files_deduplicated += self.copies;
+    }
+}
+
+/// Determines whether two file entries of the same size have the same contents, by reading them
+/// in chunks.
+fn same_contents(first: &PendingEntry, second: &PendingEntry) -> io::Result<bool> {
+    let (mut first, mut second) = (first.contents()?, second.contents()?);
+    let (mut first_chunk, mut second_chunk) = (Vec::new(), Vec::new());
+    loop {
+        first_chunk.clear();
+        second_chunk.clear();
+        let limit = COPY_CHUNK_SIZE as u64;
+        first.by_ref().take(limit).read_to_end(&mut first_chunk)?;
+        second.by_ref().take(limit).read_to_end(&mut second_chunk)?;
+        if first_chunk != second_chunk {
+            return Ok(false);
+        }
+        if first_chunk.is_empty() {
+            return Ok(true);
+        }
+    }
+}
+
+/// Places the entries in the root prefix of the options, if any, and adds a directory entry for
+/// the prefix in front of them.
+fn apply_root_prefix(
+    options: &CreateOptions,
+    pending_entries: &mut Vec<PendingEntry>,
+) -> ZipResult<()> {
+    let Some(prefix) = options.validated_root_prefix()? else {
+        return Ok(());
+    };
+    for entry in pending_entries.iter_mut() {
+        entry.name = format!("{prefix}/{}", entry.name);
+    }
+    let entry_options = options.memory_entry_options(options.file_options()?, true);
+    pending_entries.insert(0, PendingEntry::directory(prefix, entry_options));
+    Ok(())
+}
+
+/// Enables ZIP64 extra fields for the file entries that need them, and returns the progress
+/// before any of the file entries that are not superseded have been written.
+fn prepare_file_entries(
+    pending_entries: &mut [PendingEntry],
+    superseded: &[bool],
+    force_zip64: bool,
+) -> CreateProgress {
+    let mut progress = CreateProgress::default();
+    for (entry, _) in pending_entries
+        .iter_mut()
+        .zip(superseded)
+        .filter(|(entry, superseded)| entry.has_contents() && !**superseded)
+    {
+        if force_zip64 || entry.size() > ZIP64_SIZE_THRESHOLD {
+            entry.options = entry.options.large_file(true);
+        }
+        progress.total_files += 1;
+        progress.total_bytes += entry.size();
+    }
+    progress
+}
+
+/// Fails if the duplicate entry policy is `Fail` and several of the collected file entries have
+/// the same name, naming the entries and their sources.
+fn check_distinct_names(
+    pending_entries: &[PendingEntry],
+    options: &CreateOptions,
+) -> ZipResult<()> {
+    if options.duplicate_entries != DuplicateEntryPolicy::Fail {
+        return Ok(());
+    }
+    let mut first_entries: HashMap<String, &PendingEntry> = HashMap::new();
+    for entry in pending_entries.iter().filter(|entry| !entry.is_dir()) {
+        let Some(first) = first_entries.insert(options.entry_key(&entry.name), entry) else {
+            continue;
+        };
+        let message = if first.name == entry.name {
+            format!(
+                "The entry {} is added from both {} and {}.",
+                entry.name,
+                first.source_description(),
+                entry.source_description()
+            )
+        } else {
+            format!(
+                "The entries {} from {} and {} from {} only differ in case.",
+                first.name,
+                first.source_description(),
+                entry.name,
+                entry.source_description()
+            )
+        };
+        return Err(ZipError::Io(Error::new(ErrorKind::AlreadyExists, message)));
+    }
+    Ok(())
+}
+
+/// Determines which entries are superseded by a later entry with the same name, and are thus
+/// skipped, according to the duplicate entry policy.
+fn superseded_entries(pending_entries: &[PendingEntry], options: &CreateOptions) -> Vec<bool> {
+    let mut superseded = vec![false; pending_entries.len()];
+    if options.duplicate_entries == DuplicateEntryPolicy::KeepLast {
+        let mut later_names: HashSet<String> = HashSet::new();
+        for (index, entry) in pending_entries.iter().enumerate().rev() {
+            superseded[index] =
+                !entry.is_dir() && !later_names.insert(options.entry_key(&entry.name));
+        }
+    }
+    superseded
+}
+
+/// Determines whether a file entry is written, given the keys of the entries that have already
+/// been written: duplicates fail with `ErrorKind::AlreadyExists` or are skipped, according to the
+/// duplicate entry policy.
+fn is_new_entry(
+    policy: DuplicateEntryPolicy,
+    entry_names: &HashSet<String>,
+    key: &str,
+    name: &str,
+) -> ZipResult<bool> {
+    if !entry_names.contains(key) || policy == DuplicateEntryPolicy::AllowDuplicates {
+        return Ok(true);
+    }
+    if policy == DuplicateEntryPolicy::Fail {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::AlreadyExists,
+            format!("The entry {name} is added more than once."),
+        )));
+    }
+    Ok(false)
+}
+
+/// Compresses the entries of a batch that have contents concurrently, replacing their sources
+/// with the compressed data. Stored entries and superseded duplicates are left unchanged.
+#[cfg(feature = "rayon")]
+fn compress_batch(batch: &mut [(PendingEntry, bool)], chunk_size: usize) -> ZipResult<()> {
+    let entries: Vec<&mut PendingEntry> = batch
+        .iter_mut()
+        .filter(|(entry, superseded)| {
+            !superseded
+                && entry.has_contents()
+                && !matches!(entry.source, EntrySource::Reader(_))
+                && entry.compression_method != CompressionMethod::Stored
+        })
+        .map(|(entry, _)| entry)
+        .collect();
+    try_for_each_parallel(entries, |entry| compress_entry(entry, chunk_size))
+}
+
+/// Compresses the contents of a file, bytes or reader entry, copied in chunks of the given size,
+/// replacing its source with the compressed data. Other entries are left unchanged.
+#[cfg(any(feature = "rayon", feature = "aes"))]
+fn compress_entry(entry: &mut PendingEntry, chunk_size: usize) -> ZipResult<()> {
+    let size = entry.size();
+    let (path, mut reader): (PathBuf, Box<dyn io::Read>) =
+        match std::mem::replace(&mut entry.source, EntrySource::Directory) {
+            EntrySource::File(path, _) => {
+                let reader = File::open(&path)?;
+                (path, Box::new(reader))
+            }
+            EntrySource::Bytes(bytes) => {
+                (PathBuf::from(&entry.name), Box::new(io::Cursor::new(bytes)))
+            }
+            EntrySource::Reader(reader) => (PathBuf::from(&entry.name), reader),
+            source => {
+                entry.source = source;
+                return Ok(());
+            }
+        };
+    let compressed =
+        CompressedEntry::compress(&entry.name, &mut reader, entry.options, size, chunk_size)?;
+    entry.source = EntrySource::Compressed(path, compressed);
+    Ok(())
+}
+
+/// Writes an entry that has been compressed ahead of time, encrypting it with AES-256 if a
+/// password is set for it.
+#[cfg(any(feature = "rayon", feature = "aes"))]
+#[cfg_attr(not(feature = "aes"), allow(unused_mut, unused_variables))]
+fn write_compressed_entry<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    mut compressed: CompressedEntry,
+    name: &str,
+    entry_options: FileOptions,
+    options: &CreateOptions,
+    report: &mut CreateReport,
+) -> ZipResult<()> {
+    #[cfg(feature = "aes")]
+    if let Some(password) = options.aes_password() {
+        let index = report.entries_written();
+        let size = write_aes_entry(zip_writer, &mut compressed, name, entry_options, password)?;
+        report.encrypted_entries.push((index, size));
+        return Ok(());
+    }
+    compressed.write_to(zip_writer)
+}
+
+/// Writes an entry that has been compressed ahead of time as a stored entry of its encrypted
+/// data, with the AES extra field that names its compression method, and returns its
+/// uncompressed size. `mark_aes_entries` marks the entry as encrypted once the archive is
+/// finished, since `ZipWriter` can not. Entries that would need a ZIP64 extra field are rejected.
+#[cfg(feature = "aes")]
+fn write_aes_entry<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    compressed: &mut CompressedEntry,
+    name: &str,
+    entry_options: FileOptions,
+    password: &[u8],
+) -> ZipResult<u64> {
+    let size = compressed.size();
+    let (method, mut data) = compressed.raw_data()?;
+    if size >= ZIP64_SIZE_THRESHOLD || data.compressed_size() + AES_OVERHEAD >= ZIP64_SIZE_THRESHOLD
+    {
+        return Err(ZipError::Io(Error::new(
+            ErrorKind::Unsupported,
+            format!(
+                "The entry {name} is too large to be encrypted with AES-256, which does not \
+                 support ZIP64 extra fields."
+            ),
+        )));
+    }
+    let entry_options = entry_options
+        .compression_method(CompressionMethod::Stored)
+        .compression_level(None)
+        .large_file(false);
+    zip_writer.start_file_with_extra_data(name, entry_options)?;
+    #[allow(deprecated)]
+    zip_writer.write_all(&aes_extra_field(method.to_u16()))?;
+    zip_writer.end_extra_data()?;
+    let mut aes_writer = AesWriter::new(&mut *zip_writer, password)?;
+    io::copy(&mut data, &mut aes_writer)?;
+    aes_writer.finish()?;
+    Ok(size)
+}
+
+/// Writes a directory entry, unless an entry with the same key has already been written.
+/// Returns the number of entries that have been written.
+fn write_directory_entry<W: Write + io::Seek>(
+    zip_writer: &mut ZipWriter<W>,
+    entry_names: &mut HashSet<String>,
+    key: &str,
+    name: String,
+    entry_options: FileOptions,
+) -> ZipResult<usize> {
+    if !entry_names.insert(format!("{key}/")) {
+        return Ok(0);
+    }
+    zip_writer.add_directory(name, entry_options)?;
+    Ok(1)
+}
+
+/// Returns the target of a symbolic link as it is stored in a symlink entry, with forward
+/// slashes as separators.
+fn symlink_target(target: &Path) -> String {
+    let target = target.to_string_lossy();
+    if cfg!(windows) {
+        target.replace('\\', "/")
+    } else {
+        target.into_owned()
+    }
+}
+
+/// Writes a file entry with the contents of the given reader, copied in chunks of the given
+/// size, with the data aligned to the given boundary if any. The callback is invoked with the
+/// size of each chunk that has been written.
+fn write_file_entry<W, R, F>(
+    zip_writer: &mut ZipWriter<W>,
+    reader: &mut R,
+    name: &str,
+    entry_options: FileOptions,
+    alignment: Option<u16>,
+    chunk_size: usize,
+    on_chunk: F,
+) -> ZipResult<()>
+where
+    W: Write + io::Seek,
+    R: io::Read + ?Sized,
+    F: FnMut(usize) -> io::Result<()>,
+{
+    match alignment {
+        Some(alignment) => {
+            zip_writer.start_file_aligned(name, entry_options, alignment)?;
+        }
+        None => zip_writer.start_file(name, entry_options)?,
+    }
+    copy_in_sized_chunks(reader, zip_writer, chunk_size, on_chunk)?;
+    Ok(())
+}
+
+/// Joins an entry name prefix and a relative path to an entry name.
+fn join_entry_name<P: AsRef<Path>>(prefix: &str, relative_path: P) -> String {
+    let relative_name = path_to_entry_name(relative_path);
+    if prefix.is_empty() {
+        relative_name
+    } else {
+        format!("{prefix}/{relative_name}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::Path;
+
+    use zip::ZipArchive;
+
+    use crate::read::ZipArchiveExtensions;
+    use crate::test_utils::TestDir;
+    use crate::zip_create_from_directory;
+
+    #[test]
+    fn zip_create_from_directory_preserves_empty_directories() {
+        let test_dir = TestDir::new("empty-directories");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("empty")).unwrap();
+        fs::create_dir_all(source_dir.join("nested").join("empty")).unwrap();
+        fs::create_dir_all(source_dir.join("a").join("b").join("c")).unwrap();
+        fs::write(source_dir.join("nested").join("file.txt"), b"content").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_directory(&archive_file, &source_dir).unwrap();
+
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        ZipArchiveExtensions::extract(&mut archive, &target_dir).unwrap();
+
+        assert!(target_dir.join("empty").is_dir());
+        assert!(target_dir.join("nested").join("empty").is_dir());
+        assert!(target_dir.join("a").join("b").join("c").is_dir());
+        assert_eq!(
+            fs::read(target_dir.join("nested").join("file.txt")).unwrap(),
+            b"content"
+        );
+    }
+
+    #[test]
+    fn zip_create_from_directory_can_omit_directory_entries() {
+        use crate::{zip_create_from_directory_with_options, CreateOptions};
+
+        let test_dir = TestDir::new("omit-directories");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("a").join("b").join("c")).unwrap();
+        fs::create_dir_all(source_dir.join("logs")).unwrap();
+        fs::write(source_dir.join("logs").join("today.log"), b"entry").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = CreateOptions::default().include_empty_dirs(false);
+        zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(names, ["logs/today.log"]);
+    }
+
+    #[test]
+    fn zip_create_from_directory_with_options_prunes_filtered_paths() {
+        use std::ffi::OsStr;
+
+        use crate::{zip_create_from_directory_with_options, CreateOptions, CreateReport};
+
+        let test_dir = TestDir::new("filter");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("target").join("debug")).unwrap();
+        fs::create_dir_all(source_dir.join("src")).unwrap();
+        fs::write(
+            source_dir.join("target").join("debug").join("app"),
+            b"binary",
+        )
+        .unwrap();
+        fs::write(source_dir.join("src").join("main.rs"), b"fn main() {}").unwrap();
+        fs::write(source_dir.join("src").join("main.o"), b"object").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = CreateOptions::default().filter(|path, _| {
+            path != Path::new("target") && path.extension() != Some(OsStr::new("o"))
+        });
+        let report =
+            zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+
+        let expected = CreateReport {
+            files_added: 1,
+            directories_added: 1,
+            skipped_by_filter: 2,
+            skipped_by_gitignore: 0,
+            skipped_duplicates: 0,
+            symlinks_added: 0,
+            skipped_symlinks: 0,
+            duplicate_contents: Vec::new(),
+            files_deduplicated: 0,
+            entry_comments: Vec::new(),
+            encrypted_entries: Vec::new(),
+        };
+        assert_eq!(report, expected);
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["src/", "src/main.rs"]);
+    }
+
+    #[test]
+    #[cfg(feature = "ignore")]
+    fn zip_create_from_directory_with_options_respects_gitignore() {
+        use crate::{zip_create_from_directory_with_options, CreateOptions};
+
+        let test_dir = TestDir::new("gitignore");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("target")).unwrap();
+        fs::create_dir_all(source_dir.join("src")).unwrap();
+        fs::create_dir_all(source_dir.join(".git")).unwrap();
+        fs::write(source_dir.join(".gitignore"), "target/\n*.log\n").unwrap();
+        fs::write(source_dir.join("src").join(".gitignore"), "generated.rs\n").unwrap();
+        fs::write(source_dir.join("target").join("app"), b"binary").unwrap();
+        fs::write(source_dir.join("src").join("main.rs"), b"fn main() {}").unwrap();
+        fs::write(source_dir.join("src").join("generated.rs"), b"").unwrap();
+        fs::write(source_dir.join("build.log"), b"log").unwrap();
+        fs::write(source_dir.join("keep.log"), b"log").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = CreateOptions::default()
+            .include_empty_dirs(false)
+            .respect_gitignore(true)
+            .force_include(["keep.log"]);
+        let report =
+            zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+
+        assert_eq!(report.skipped_by_gitignore, 4);
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [".gitignore", "keep.log", "src/.gitignore", "src/main.rs"]
+        );
+    }
+
+    #[test]
+    fn zip_create_from_directories_merges_sources() {
+        use std::path::PathBuf;
+
+        use crate::{zip_create_from_directories, CreateOptions};
+
+        let test_dir = TestDir::new("from-directories");
+        let first_dir = test_dir.path().join("first");
+        let second_dir = test_dir.path().join("second");
+        fs::create_dir_all(first_dir.join("docs")).unwrap();
+        fs::create_dir_all(second_dir.join("docs")).unwrap();
+        fs::write(first_dir.join("docs").join("a.txt"), b"a").unwrap();
+        fs::write(second_dir.join("docs").join("b.txt"), b"b").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let sources = [
+            (first_dir.clone(), None),
+            (second_dir.clone(), None),
+            (second_dir.clone(), Some(PathBuf::from("vendor"))),
+        ];
+        let report =
+            zip_create_from_directories(&archive_file, &sources, CreateOptions::default()).unwrap();
+        assert_eq!(report.files_added, 3);
+
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "docs/",
+                "docs/a.txt",
+                "docs/b.txt",
+                "vendor/",
+                "vendor/docs/",
+                "vendor/docs/b.txt"
+            ]
+        );
+
+        let colliding_sources = [(second_dir.clone(), None), (second_dir, None)];
+        let result = zip_create_from_directories(
+            &archive_file,
+            &colliding_sources,
+            CreateOptions::default(),
+        );
+        assert!(result.is_err());
+        assert!(!archive_file.exists());
+    }
+
+    #[test]
+    fn zip_append_adds_entries_to_an_existing_archive() {
+        use std::io::{ErrorKind, Read};
+
+        use crate::{zip_append, zip_create_from_files, CreateOptions, DuplicateEntryPolicy};
+
+        let test_dir = TestDir::new("append");
+        let old_file = test_dir.path().join("old.txt");
+        let new_file = test_dir.path().join("new.txt");
+        fs::write(&old_file, b"old").unwrap();
+        fs::write(&new_file, b"new").unwrap();
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_files(&archive_file, &[("a.txt", &old_file)]).unwrap();
+
+        zip_append(
+            &archive_file,
+            &[("b.txt", &new_file)],
+            CreateOptions::default(),
+        )
+        .unwrap();
+        let original = fs::read(&archive_file).unwrap();
+        let error = zip_append(
+            &archive_file,
+            &[("a.txt", &new_file)],
+            CreateOptions::default(),
+        )
+        .unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert_eq!(fs::read(&archive_file).unwrap(), original);
+
+        let read_entry = |name: &str| {
+            let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+            let mut content = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            (archive.len(), content)
+        };
+        let keep_first =
+            CreateOptions::default().duplicate_entries(DuplicateEntryPolicy::KeepFirst);
+        zip_append(&archive_file, &[("a.txt", &new_file)], keep_first).unwrap();
+        assert_eq!(read_entry("a.txt"), (2, "old".to_string()));
+        assert_eq!(read_entry("b.txt"), (2, "new".to_string()));
+        let keep_last = CreateOptions::default().duplicate_entries(DuplicateEntryPolicy::KeepLast);
+        zip_append(&archive_file, &[("a.txt", &new_file)], keep_last).unwrap();
+        assert_eq!(read_entry("a.txt"), (3, "new".to_string()));
+
+        let not_a_zip = test_dir.path().join("notes.zip");
+        fs::write(&not_a_zip, b"just some text").unwrap();
+        assert!(zip_append(
+            &not_a_zip,
+            &[("b.txt", &new_file)],
+            CreateOptions::default()
+        )
+        .is_err());
+        assert_eq!(fs::read(&not_a_zip).unwrap(), b"just some text");
+    }
+
+    #[test]
+    fn zip_replace_entries_rewrites_the_archive() {
+        use std::io::Read;
+
+        use zip::write::FileOptions;
+        use zip::{DateTime, ZipWriter};
+
+        use super::{create_archive_file, write_entry_comments};
+        use crate::{zip_replace_entries, ReplacementSource};
+
+        let test_dir = TestDir::new("replace-entries");
+        let archive_file = test_dir.path().join("archive.zip");
+        let modified = DateTime::from_date_and_time(2020, 6, 15, 12, 30, 0).unwrap();
+        let mut zip_writer = ZipWriter::new(create_archive_file(&archive_file).unwrap());
+        zip_writer.set_comment("archive comment");
+        for name in ["a.txt", "b.txt"] {
+            let options = FileOptions::default().last_modified_time(modified);
+            zip_writer.start_file(name, options).unwrap();
+            std::io::Write::write_all(&mut zip_writer, name.as_bytes()).unwrap();
+        }
+        let mut file = zip_writer.finish().unwrap();
+        let entry_comments = [(1, "kept".to_string())];
+        write_entry_comments(&mut file, &entry_comments).unwrap();
+
+        let new_file = test_dir.path().join("new.txt");
+        fs::write(&new_file, b"from file").unwrap();
+        // A file of the user that must not be mistaken for a temporary file.
+        let unrelated_file = test_dir.path().join("archive.zip.tmp");
+        fs::write(&unrelated_file, b"unrelated").unwrap();
+        let file_names = || {
+            let mut file_names: Vec<_> = fs::read_dir(test_dir.path())
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name())
+                .collect();
+            file_names.sort_unstable();
+            file_names
+        };
+        let replacements = [
+            ("a.txt", ReplacementSource::Bytes(b"replaced".to_vec())),
+            ("c.txt", ReplacementSource::File(new_file)),
+        ];
+        zip_replace_entries(&archive_file, &replacements).unwrap();
+        assert_eq!(file_names(), ["archive.zip", "archive.zip.tmp", "new.txt"]);
+        assert_eq!(fs::read(&unrelated_file).unwrap(), b"unrelated");
+
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(archive.comment(), b"archive comment");
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(names.len(), 3);
+        let mut read_entry = |index: usize| {
+            let mut entry = archive.by_index(index).unwrap();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            (entry.name().to_string(), content, entry.last_modified())
+        };
+        let (name, content, _) = read_entry(0);
+        assert_eq!((name.as_str(), content.as_str()), ("a.txt", "replaced"));
+        let (name, content, last_modified) = read_entry(1);
+        assert_eq!((name.as_str(), content.as_str()), ("b.txt", "b.txt"));
+        assert_eq!(
+            (last_modified.datepart(), last_modified.timepart()),
+            (modified.datepart(), modified.timepart())
+        );
+        let (name, content, _) = read_entry(2);
+        assert_eq!((name.as_str(), content.as_str()), ("c.txt", "from file"));
+        assert_eq!(archive.by_name("b.txt").unwrap().comment(), "kept");
+
+        let original = fs::read(&archive_file).unwrap();
+        let missing = [(
+            "b.txt",
+            ReplacementSource::File(test_dir.path().join("missing.txt")),
+        )];
+        assert!(zip_replace_entries(&archive_file, &missing).is_err());
+        assert_eq!(fs::read(&archive_file).unwrap(), original);
+        assert_eq!(file_names(), ["archive.zip", "archive.zip.tmp", "new.txt"]);
+        assert_eq!(fs::read(&unrelated_file).unwrap(), b"unrelated");
+    }
+
+    #[test]
+    fn zip_remove_entries_removes_named_and_matching_entries() {
+        use std::io::ErrorKind;
+
+        use crate::{
+            zip_create_in_memory_from_entries, zip_remove_entries, zip_remove_entries_matching,
+            zip_remove_entries_with_options, CreateOptions, RemoveOptions,
+        };
+
+        let test_dir = TestDir::new("remove-entries");
+        let archive_file = test_dir.path().join("archive.zip");
+        let entries = [
+            "keep.txt",
+            "drop.txt",
+            "docs/",
+            "docs/a.md",
+            "logs/old.log",
+            "__MACOSX/",
+            "__MACOSX/._keep.txt",
+        ]
+        .map(|name| (name.to_string(), name.as_bytes().to_vec()));
+        let bytes = zip_create_in_memory_from_entries(entries, CreateOptions::default()).unwrap();
+        fs::write(&archive_file, bytes).unwrap();
+        let names = || {
+            let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+            let mut names: Vec<String> = archive.file_names().map(str::to_string).collect();
+            names.sort_unstable();
+            names
+        };
+
+        let original = fs::read(&archive_file).unwrap();
+        let error = zip_remove_entries(&archive_file, &["drop.txt", "missing.txt"]).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::NotFound);
+        assert!(error.to_string().contains("missing.txt"));
+        assert_eq!(fs::read(&archive_file).unwrap(), original);
+
+        assert_eq!(zip_remove_entries(&archive_file, &["drop.txt"]).unwrap(), 1);
+        assert_eq!(
+            zip_remove_entries_matching(&archive_file, "__MACOSX/**").unwrap(),
+            2
+        );
+        assert_eq!(
+            zip_remove_entries_matching(&archive_file, "*.bak").unwrap(),
+            0
+        );
+        let cascade = RemoveOptions::default().cascade(true);
+        let removed =
+            zip_remove_entries_with_options(&archive_file, &["docs/", "logs"], cascade).unwrap();
+        assert_eq!(removed, 3);
+        assert_eq!(names(), ["keep.txt"]);
+        let file_names: Vec<_> = fs::read_dir(test_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(file_names, ["archive.zip"]);
+    }
+
+    #[test]
+    fn archive_comments_are_written_and_replaced() {
+        use std::io::ErrorKind;
+
+        use crate::{zip_create_from_memory_to_file, zip_set_comment, CreateOptions};
+
+        let test_dir = TestDir::new("archive-comment");
+        let archive_file = test_dir.path().join("archive.zip");
+        let comment = |archive_file: &Path| {
+            let archive = ZipArchive::new(File::open(archive_file).unwrap()).unwrap();
+            (archive.comment().to_vec(), archive.len())
+        };
+        let options = CreateOptions::default().archive_comment("build 1234abcd".to_string());
+        let entries = [("a.txt".to_string(), b"a".to_vec())];
+        zip_create_from_memory_to_file(&archive_file, entries, options).unwrap();
+        assert_eq!(comment(&archive_file), (b"build 1234abcd".to_vec(), 1));
+
+        zip_set_comment(&archive_file, "Gebäude 5678ef").unwrap();
+        assert_eq!(
+            comment(&archive_file),
+            ("Gebäude 5678ef".as_bytes().to_vec(), 1)
+        );
+        zip_set_comment(&archive_file, "").unwrap();
+        assert_eq!(comment(&archive_file), (Vec::new(), 1));
+
+        let original = fs::read(&archive_file).unwrap();
+        let too_long = "x".repeat(65_536);
+        let error = zip_set_comment(&archive_file, &too_long).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert_eq!(fs::read(&archive_file).unwrap(), original);
+
+        let other_file = test_dir.path().join("other.zip");
+        let options = CreateOptions::default().archive_comment(too_long);
+        let entries = [("a.txt".to_string(), b"a".to_vec())];
+        assert!(zip_create_from_memory_to_file(&other_file, entries, options).is_err());
+        assert!(!other_file.exists());
+    }
+
+    #[test]
+    fn entry_comments_are_written_from_the_callback() {
+        use std::io::{Cursor, ErrorKind};
+
+        use zip::{result::ZipError, CompressionMethod, ZipWriter};
+
+        use crate::{
+            zip_create_from_directory_with_options, zip_create_split, CreateOptions,
+            ZipWriterExtensions,
+        };
+
+        let test_dir = TestDir::new("entry-comments");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("licenses")).unwrap();
+        fs::write(source_dir.join("licenses/mit.txt"), vec![b'm'; 2000]).unwrap();
+        fs::write(source_dir.join("readme.txt"), vec![b'r'; 2000]).unwrap();
+        fs::write(source_dir.join("notes.txt"), b"no comment").unwrap();
+        let commenting_options = || {
+            CreateOptions::default()
+                .compression_method(CompressionMethod::Stored)
+                .entry_comment(|path| match path.file_name()?.to_str()? {
+                    "mit.txt" => Some("SPDX-License-Identifier: MIT".to_string()),
+                    "readme.txt" => Some("Übersicht".to_string()),
+                    "licenses" => Some("a directory".to_string()),
+                    _ => None,
+                })
+        };
+        let comments = |archive_file: &Path| {
+            let mut archive = ZipArchive::new(File::open(archive_file).unwrap()).unwrap();
+            let mut comments = (0..archive.len())
+                .map(|i| {
+                    let entry = archive.by_index(i).unwrap();
+                    (entry.name().to_string(), entry.comment().to_string())
+                })
+                .collect::<Vec<_>>();
+            comments.sort();
+            comments
+        };
+        let expected = |names: &[&str]| {
+            [
+                ("licenses/", ""),
+                ("licenses/mit.txt", "SPDX-License-Identifier: MIT"),
+                ("notes.txt", ""),
+                ("readme.txt", "Übersicht"),
+            ]
+            .iter()
+            .filter(|(name, _)| names.contains(name))
+            .map(|&(name, comment)| (name.to_string(), comment.to_string()))
+            .collect::<Vec<_>>()
+        };
+
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_directory_with_options(&archive_file, &source_dir, commenting_options())
+            .unwrap();
+        let all = ["licenses/", "licenses/mit.txt", "notes.txt", "readme.txt"];
+        assert_eq!(comments(&archive_file), expected(&all));
+
+        let base_path = test_dir.path().join("split.zip");
+        let volumes =
+            zip_create_split(&source_dir, &base_path, 2500, commenting_options()).unwrap();
+        assert!(volumes.len() > 1);
+        for volume in &volumes {
+            let volume_comments = comments(&volume.path);
+            let names = volume_comments
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(volume_comments, expected(&names));
+        }
+
+        let other_file = test_dir.path().join("other.zip");
+        let options = CreateOptions::default()
+            .entry_comment(|path| (path.file_name()? == "notes.txt").then(|| "x".repeat(65_536)));
+        let error =
+            zip_create_from_directory_with_options(&other_file, &source_dir, options).unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("notes.txt"));
+        assert!(!other_file.exists());
+
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let error = zip_writer
+            .create_from_directory_with_options(&source_dir, commenting_options())
+            .unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn zip_merge_combines_archives() {
+        use std::io::{ErrorKind, Read};
+
+        use crate::{
+            zip_create_in_memory_from_entries, zip_merge, zip_merge_with_options, CreateOptions,
+            DuplicateEntryPolicy, MergeOptions,
+        };
+
+        let test_dir = TestDir::new("merge");
+        let first = test_dir.path().join("first.zip");
+        let second = test_dir.path().join("second.zip");
+        let output = test_dir.path().join("merged.zip");
+        let write_input = |path: &Path, entries: &[(&str, &str)], comment: &str| {
+            let entries = entries
+                .iter()
+                .map(|(name, contents)| ((*name).to_string(), contents.as_bytes().to_vec()));
+            let bytes =
+                zip_create_in_memory_from_entries(entries, CreateOptions::default()).unwrap();
+            let mut zip_writer = zip::ZipWriter::new_append(std::io::Cursor::new(bytes)).unwrap();
+            zip_writer.set_comment(comment);
+            fs::write(path, zip_writer.finish().unwrap().into_inner()).unwrap();
+        };
+        write_input(
+            &first,
+            &[("docs/", ""), ("docs/a.md", "first"), ("one.txt", "1")],
+            "one",
+        );
+        write_input(
+            &second,
+            &[("docs/", ""), ("docs/a.md", "second"), ("two.txt", "2")],
+            "two",
+        );
+        let contents = |name: &str| {
+            let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+            let mut contents = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut contents)
+                .unwrap();
+            contents
+        };
+
+        let report =
+            zip_merge(&output, &[&first, &second], DuplicateEntryPolicy::KeepFirst).unwrap();
+        assert_eq!(report.entries_written, 4);
+        assert_eq!(report.conflicts.len(), 1);
+        assert_eq!(report.conflicts[0].name, "docs/a.md");
+        assert_eq!(report.conflicts[0].kept, first);
+        assert_eq!(report.conflicts[0].skipped, std::slice::from_ref(&second));
+        assert_eq!(contents("docs/a.md"), "first");
+        assert_eq!(contents("two.txt"), "2");
+        assert!(ZipArchive::new(File::open(&output).unwrap())
+            .unwrap()
+            .comment()
+            .is_empty());
+
+        let options = MergeOptions::default()
+            .duplicate_entries(DuplicateEntryPolicy::KeepLast)
+            .concatenate_comments(true);
+        let report = zip_merge_with_options(&output, &[&first, &second], options).unwrap();
+        assert_eq!(report.conflicts[0].kept, second);
+        assert_eq!(contents("docs/a.md"), "second");
+        let archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        assert_eq!(archive.len(), 4);
+        assert_eq!(archive.comment(), b"one\ntwo");
+
+        fs::remove_file(&output).unwrap();
+        let error = zip_merge(&output, &[&first, &second], DuplicateEntryPolicy::Fail).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert!(!output.exists());
+
+        let error =
+            zip_merge(&first, &[&first, &second], DuplicateEntryPolicy::KeepFirst).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        let long_comment = "c".repeat(40_000);
+        write_input(&first, &[("one.txt", "1")], &long_comment);
+        write_input(&second, &[("two.txt", "2")], &long_comment);
+        let options = MergeOptions::default().concatenate_comments(true);
+        let error = zip_merge_with_options(&output, &[&first, &second], options).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn zip_create_split_writes_volumes_under_the_size_limit() {
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_split, CreateOptions};
+
+        let test_dir = TestDir::new("create-split");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        for name in ["a.bin", "b.bin", "c.bin"] {
+            fs::write(source_dir.join(name), vec![7; 1000]).unwrap();
+        }
+
+        let base_path = test_dir.path().join("backup.zip");
+        fs::write(test_dir.path().join("backup.tmp"), b"unrelated").unwrap();
+        let options = CreateOptions::default().compression_method(CompressionMethod::Stored);
+        let volumes = zip_create_split(&source_dir, &base_path, 2500, options).unwrap();
+        let names: Vec<_> = volumes
+            .iter()
+            .map(|volume| {
+                volume
+                    .path
+                    .file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect();
+        assert_eq!(names, ["backup-001.zip", "backup-002.zip"]);
+        assert_eq!(
+            fs::read(test_dir.path().join("backup.tmp")).unwrap(),
+            b"unrelated"
+        );
+        let mut leftovers: Vec<_> = fs::read_dir(test_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        leftovers.sort_unstable();
+        assert_eq!(
+            leftovers,
+            ["backup-001.zip", "backup-002.zip", "backup.tmp", "source"]
+        );
+
+        let mut entries: Vec<String> = Vec::new();
+        for volume in &volumes {
+            assert!(volume.size <= 2500);
+            assert_eq!(fs::metadata(&volume.path).unwrap().len(), volume.size);
+            let archive = ZipArchive::new(File::open(&volume.path).unwrap()).unwrap();
+            entries.extend(archive.file_names().map(str::to_string));
+        }
+        entries.sort_unstable();
+        assert_eq!(entries, ["a.bin", "b.bin", "c.bin"]);
+
+        let small_base_path = test_dir.path().join("small.zip");
+        let options = CreateOptions::default().compression_method(CompressionMethod::Stored);
+        let error = zip_create_split(&source_dir, &small_base_path, 1000, options).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidInput);
+        assert!(!test_dir.path().join("small-001.zip").exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlinks_are_handled_by_policy() {
+        use std::io::{ErrorKind, Read};
+        use std::os::unix::fs::symlink;
+
+        use crate::{zip_create_from_directory_with_options, CreateOptions, SymlinkPolicy};
+
+        let test_dir = TestDir::new("create-symlinks");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("data")).unwrap();
+        fs::write(source_dir.join("data").join("a.txt"), b"alpha").unwrap();
+        symlink("data/a.txt", source_dir.join("link.txt")).unwrap();
+        symlink("missing.txt", source_dir.join("dangling.txt")).unwrap();
+
+        let create = |name: &str, policy: SymlinkPolicy| {
+            let archive_file = test_dir.path().join(name);
+            let options = CreateOptions::default().symlinks(policy);
+            let report =
+                zip_create_from_directory_with_options(&archive_file, &source_dir, options)
+                    .unwrap();
+            (
+                ZipArchive::new(File::open(archive_file).unwrap()).unwrap(),
+                report,
+            )
+        };
+
+        let (mut archive, report) = create("follow.zip", SymlinkPolicy::Follow);
+        assert_eq!((report.files_added, report.skipped_symlinks), (2, 1));
+        let mut content = String::new();
+        archive
+            .by_name("link.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "alpha");
+
+        let (mut archive, report) = create("store.zip", SymlinkPolicy::Store);
+        assert_eq!((report.files_added, report.symlinks_added), (1, 2));
+        let mut link = archive.by_name("link.txt").unwrap();
+        assert_eq!(link.unix_mode().unwrap() & 0o170_000, 0o120_000);
+        let mut content = String::new();
+        link.read_to_string(&mut content).unwrap();
+        assert_eq!(content, "data/a.txt");
+        drop(link);
+
+        let (archive, report) = create("skip.zip", SymlinkPolicy::Skip);
+        assert_eq!((report.files_added, report.skipped_symlinks), (1, 2));
+        assert!(archive.file_names().all(|name| !name.contains("link")));
+
+        symlink("..", source_dir.join("data").join("parent")).unwrap();
+        let options = CreateOptions::default().symlinks(SymlinkPolicy::Follow);
+        let archive_file = test_dir.path().join("loop.zip");
+        let error = zip_create_from_directory_with_options(&archive_file, &source_dir, options)
+            .unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn zip_create_from_directory_rejects_symlink_loops() {
+        use std::io::ErrorKind;
+        use std::os::unix::fs::symlink;
+
+        let test_dir = TestDir::new("create-symlink-loop");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("a")).unwrap();
+        fs::create_dir_all(source_dir.join("b")).unwrap();
+        fs::write(source_dir.join("b").join("b.txt"), b"beta").unwrap();
+        symlink("../b", source_dir.join("a").join("to_b")).unwrap();
+
+        // A link to a sibling directory is followed, since it does not lead back to itself.
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_directory(&archive_file, &source_dir).unwrap();
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert!(archive.file_names().any(|name| name == "a/to_b/b.txt"));
+        fs::remove_file(&archive_file).unwrap();
+
+        // Two directories that link to each other lead back to the first one.
+        symlink("../a", source_dir.join("b").join("to_a")).unwrap();
+        let error = zip_create_from_directory(&archive_file, &source_dir).unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        assert!(error.to_string().contains("which contains the link"));
+        assert!(!archive_file.exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn zip_create_from_directory_round_trips_unix_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        use crate::{
+            zip_create_from_directory, zip_create_from_directory_with_options,
+            zip_extract_with_options, CreateOptions, ExtractOptions,
+        };
+
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let set_mode = |path: &Path, mode: u32| {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+
+        let test_dir = TestDir::new("create-permissions");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("bin")).unwrap();
+        fs::write(source_dir.join("bin").join("run.sh"), b"#!/bin/sh").unwrap();
+        fs::write(source_dir.join("secret.txt"), b"secret").unwrap();
+        set_mode(&source_dir.join("bin").join("run.sh"), 0o755);
+        set_mode(&source_dir.join("secret.txt"), 0o600);
+        set_mode(&source_dir.join("bin"), 0o750);
+
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_directory(&archive_file, &source_dir).unwrap();
+        let target_dir = test_dir.path().join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let extract_options = ExtractOptions::default().preserve_permissions(true);
+        zip_extract_with_options(&archive_file, &target_dir, extract_options).unwrap();
+
+        for relative_path in ["bin/run.sh", "secret.txt", "bin"] {
+            assert_eq!(
+                mode(&target_dir.join(relative_path)),
+                mode(&source_dir.join(relative_path)),
+                "{relative_path}"
+            );
+        }
+
+        let fixed_archive_file = test_dir.path().join("fixed.zip");
+        let options = CreateOptions::default().fixed_permissions(Some(0o644));
+        zip_create_from_directory_with_options(&fixed_archive_file, &source_dir, options).unwrap();
+        let fixed_target_dir = test_dir.path().join("fixed");
+        fs::create_dir_all(&fixed_target_dir).unwrap();
+        let extract_options = ExtractOptions::default().preserve_permissions(true);
+        zip_extract_with_options(&fixed_archive_file, &fixed_target_dir, extract_options).unwrap();
+
+        assert_eq!(mode(&fixed_target_dir.join("bin").join("run.sh")), 0o644);
+        assert_eq!(mode(&fixed_target_dir.join("bin")), 0o755);
+    }
+
+    #[test]
+    fn zip_create_from_directory_stores_modification_times() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use zip::DateTime;
+
+        use crate::{zip_create_from_directory_with_options, CreateOptions};
+
+        let test_dir = TestDir::new("create-mtimes");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        let file_path = source_dir.join("file.txt");
+        fs::write(&file_path, b"file").unwrap();
+        let modified = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        File::options()
+            .write(true)
+            .open(&file_path)
+            .unwrap()
+            .set_modified(modified)
+            .unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_directory(&archive_file, &source_dir).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let stored = archive.by_name("file.txt").unwrap().last_modified();
+        assert_eq!(
+            (stored.year(), stored.month(), stored.day()),
+            (2023, 11, 14)
+        );
+        assert_eq!(
+            (stored.hour(), stored.minute(), stored.second()),
+            (22, 13, 20)
+        );
+
+        let fixed_archive_file = test_dir.path().join("fixed.zip");
+        let fixed_time = DateTime::from_date_and_time(2000, 1, 2, 3, 4, 6).unwrap();
+        let options = CreateOptions::default().fixed_modified_time(Some(fixed_time));
+        zip_create_from_directory_with_options(&fixed_archive_file, &source_dir, options).unwrap();
+        let mut archive = ZipArchive::new(File::open(&fixed_archive_file).unwrap()).unwrap();
+        let stored = archive.by_name("file.txt").unwrap().last_modified();
+        assert_eq!(
+            (stored.datepart(), stored.timepart()),
+            (fixed_time.datepart(), fixed_time.timepart())
+        );
+    }
+
+    #[test]
+    fn deterministic_creation_produces_identical_archives() {
+        use std::path::PathBuf;
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use crate::{
+            zip_create_from_directory_with_options, zip_create_from_files_with_options,
+            CreateOptions,
+        };
+
+        let test_dir = TestDir::new("create-deterministic");
+        let paths = ["b/z.txt", "a.txt", "b/y.txt", "c/"];
+        for (index, source) in ["first", "second"].into_iter().enumerate() {
+            let source_dir = test_dir.path().join(source);
+            let ordered: Vec<&str> = if index == 0 {
+                paths.to_vec()
+            } else {
+                paths.iter().rev().copied().collect()
+            };
+            for relative_path in ordered {
+                let path = source_dir.join(relative_path);
+                if relative_path.ends_with('/') {
+                    fs::create_dir_all(&path).unwrap();
+                    continue;
+                }
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, relative_path).unwrap();
+                let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000 + index as u64 * 60);
+                let file = File::options().write(true).open(&path).unwrap();
+                file.set_modified(modified).unwrap();
+                #[cfg(unix)]
+                if index == 1 {
+                    use std::os::unix::fs::PermissionsExt;
+                    file.set_permissions(fs::Permissions::from_mode(0o600))
+                        .unwrap();
+                }
+            }
+        }
+
+        let create = |source: &str| {
+            let archive_file = test_dir.path().join(format!("{source}.zip"));
+            let options = CreateOptions::default().deterministic(true);
+            zip_create_from_directory_with_options(
+                &archive_file,
+                test_dir.path().join(source),
+                options,
+            )
+            .unwrap();
+            fs::read(archive_file).unwrap()
+        };
+        let first = create("first");
+        assert_eq!(first, create("second"));
+
+        let mut archive = ZipArchive::new(std::io::Cursor::new(first)).unwrap();
+        let names: Vec<String> = (0..archive.len())
+            .map(|index| archive.by_index(index).unwrap().name().to_string())
+            .collect();
+        assert_eq!(names, ["a.txt", "b/", "b/y.txt", "b/z.txt", "c/"]);
+        assert_eq!(
+            archive.by_name("a.txt").unwrap().unix_mode(),
+            Some(0o100_644)
+        );
+
+        // The walk order of a directory is up to the file system, so feed the same files in two
+        // different orders explicitly.
+        let files: Vec<(PathBuf, &str)> = ["b/z.txt", "a.txt", "b/y.txt"]
+            .into_iter()
+            .map(|name| (test_dir.path().join("first").join(name), name))
+            .collect();
+        let create_from_files = |files: &[(PathBuf, &str)], archive_name: &str| {
+            let archive_file = test_dir.path().join(archive_name);
+            let options = CreateOptions::default().deterministic(true);
+            zip_create_from_files_with_options(&archive_file, files, options).unwrap();
+            fs::read(archive_file).unwrap()
+        };
+        let reversed: Vec<(PathBuf, &str)> = files.iter().rev().cloned().collect();
+        let in_order = create_from_files(&files, "files.zip");
+        assert_eq!(in_order, create_from_files(&reversed, "reversed.zip"));
+        let archive = ZipArchive::new(std::io::Cursor::new(in_order)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["a.txt", "b/y.txt", "b/z.txt"]);
+    }
+
+    #[test]
+    fn zip64_archives_with_many_entries_can_be_read() {
+        use crate::{requires_zip64, zip_create_in_memory_from_entries, CreateOptions};
+
+        let test_dir = TestDir::new("zip64-entries");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join("small.txt"), b"small").unwrap();
+        assert!(!requires_zip64(&source_dir).unwrap());
+        // Sparse on most file systems, so that this does not write 4 GiB of data.
+        let large = File::create(source_dir.join("large.bin")).unwrap();
+        large.set_len(0x1_0000_0001).unwrap();
+        assert!(requires_zip64(&source_dir).unwrap());
+
+        let entries = (0..70_000).map(|index| (format!("{index:05}.txt"), Vec::new()));
+        let bytes = zip_create_in_memory_from_entries(entries, CreateOptions::default()).unwrap();
+        let mut archive = ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 70_000);
+        let info = archive.entry_info(69_999).unwrap();
+        assert_eq!(info.path, Path::new("69999.txt"));
+        assert_eq!(archive.entries().count(), 70_000);
+    }
+
+    #[test]
+    fn zip64_archives_with_large_entries_can_be_read() {
+        use std::io::{Seek, SeekFrom, Write};
+
+        use zip::{CompressionMethod, ZipWriter};
+
+        use crate::{CreateOptions, ZipWriterExtensions};
+
+        /// Skips over large runs of zeros instead of writing them, so that the archive is a
+        /// sparse file that does not occupy 4 GiB of disk space.
+        struct SparseFile(File);
+
+        impl Write for SparseFile {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if buf.len() >= 4096 && buf.iter().all(|byte| *byte == 0) {
+                    self.0
+                        .seek(SeekFrom::Current(buf.len().try_into().unwrap()))?;
+                    return Ok(buf.len());
+                }
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                self.0.flush()
+            }
+        }
+
+        impl Seek for SparseFile {
+            fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+                self.0.seek(pos)
+            }
+        }
+
+        let test_dir = TestDir::new("zip64-large");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        let large = File::create(source_dir.join("large.bin")).unwrap();
+        large.set_len(0x1_0000_0001).unwrap();
+        fs::write(source_dir.join("after.txt"), b"after").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let mut zip_writer = ZipWriter::new(SparseFile(File::create(&archive_file).unwrap()));
+        // Compressing 4 GiB would take minutes in a debug build, so the entry is stored.
+        let options = CreateOptions::default().compression_method(CompressionMethod::Stored);
+        zip_writer
+            .create_from_directory_with_options(&source_dir, options)
+            .unwrap();
+        zip_writer.finish().unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let large_info = archive
+            .entries()
+            .map(Result::unwrap)
+            .find(|info| info.path == Path::new("large.bin"))
+            .unwrap();
+        assert_eq!(large_info.size, 0x1_0000_0001);
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("after.txt").unwrap(), &mut contents)
+            .unwrap();
+        assert_eq!(contents, "after");
+    }
+
+    #[cfg(all(feature = "rayon", feature = "deflate"))]
+    #[test]
+    fn parallel_compression_matches_sequential_creation() {
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_in_memory, CreateOptions};
+
+        let test_dir = TestDir::new("create-parallel");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("nested")).unwrap();
+        for index in 0..10 {
+            let contents = format!("file {index} ").repeat(100 * index + 1);
+            fs::write(source_dir.join(format!("{index}.txt")), contents).unwrap();
+        }
+        fs::write(source_dir.join("nested").join("stored.png"), b"png").unwrap();
+        // Larger than the threshold above which compressed data is spilled to a temporary file.
+        let large = b"large entry ".repeat(800 * 1024);
+        fs::write(source_dir.join("nested").join("large.bin"), &large).unwrap();
+
+        let options = || {
+            CreateOptions::default()
+                .compression_method(CompressionMethod::Deflated)
+                .compression_method_for_extension("png", CompressionMethod::Stored)
+                .deterministic(true)
+        };
+        let sequential = zip_create_in_memory(&source_dir, options()).unwrap();
+        for max_in_flight in [1, 3, 64] {
+            let parallel_options = options().parallel_compression(Some(max_in_flight));
+            let parallel = zip_create_in_memory(&source_dir, parallel_options).unwrap();
+            assert_eq!(parallel, sequential, "{max_in_flight} entries in flight");
+        }
+
+        let zip64_options = || options().force_zip64(true);
+        let zip64 = zip_create_in_memory(&source_dir, zip64_options()).unwrap();
+        assert_ne!(zip64, sequential);
+        let parallel_options = zip64_options().parallel_compression(Some(3));
+        let parallel = zip_create_in_memory(&source_dir, parallel_options).unwrap();
+        assert_eq!(parallel, zip64);
+
+        let mut archive = ZipArchive::new(std::io::Cursor::new(sequential)).unwrap();
+        let mut contents = Vec::new();
+        let mut entry = archive.by_name("nested/large.bin").unwrap();
+        std::io::Read::read_to_end(&mut entry, &mut contents).unwrap();
+        assert_eq!(contents, large);
+    }
+
+    #[test]
+    fn validate_entry_name_rejects_escaping_names() {
+        use super::validate_entry_name;
+
+        assert_eq!(
+            validate_entry_name(Path::new("./docs/readme.txt")).unwrap(),
+            "docs/readme.txt"
+        );
+        for entry_name in ["", "/etc/passwd", "docs/../../secret.txt", "."] {
+            assert!(
+                validate_entry_name(Path::new(entry_name)).is_err(),
+                "{entry_name}"
+            );
+        }
+    }
+
+    #[test]
+    fn normalize_entry_name_canonicalizes_and_rejects_escaping_names() {
+        use crate::{normalize_entry_name, NameError, ZipWriterExtensions};
+
+        for (entry_name, expected) in [
+            ("docs\\guide\\intro.md", "docs/guide/intro.md"),
+            ("./docs//guide/./intro.md", "docs/guide/intro.md"),
+            ("docs/", "docs"),
+        ] {
+            assert_eq!(
+                normalize_entry_name(Path::new(entry_name)).unwrap(),
+                expected
+            );
+        }
+        let rejected = |entry_name: &str| normalize_entry_name(Path::new(entry_name)).unwrap_err();
+        assert_eq!(rejected("./"), NameError::Empty("./".to_string()));
+        assert_eq!(rejected("/etc"), NameError::Absolute("/etc".to_string()));
+        assert_eq!(
+            rejected("C:\\Windows"),
+            NameError::DriveLetter("C:\\Windows".to_string())
+        );
+        assert_eq!(
+            rejected("\\\\server\\share"),
+            NameError::UncPrefix("\\\\server\\share".to_string())
+        );
+        assert_eq!(
+            rejected("docs\\..\\secret.txt"),
+            NameError::ParentDirectory("docs\\..\\secret.txt".to_string())
+        );
+
+        let test_dir = TestDir::new("normalize-entry-names");
+        let source_file = test_dir.path().join("source.txt");
+        fs::write(&source_file, b"contents").unwrap();
+        let mut zip_writer = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        zip_writer
+            .add_path(&source_file, "docs\\source.txt")
+            .unwrap();
+        let error = zip_writer
+            .add_path(&source_file, "C:\\source.txt")
+            .unwrap_err();
+        assert!(error.to_string().contains("C:\\source.txt"), "{error}");
+        let archive = ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+        assert_eq!(
+            archive.file_names().collect::<Vec<_>>(),
+            ["docs/source.txt"]
+        );
+    }
+
+    #[test]
+    fn zip_create_from_files_preserves_order_and_applies_policies() {
+        use crate::{
+            zip_create_from_files, zip_create_from_files_with_options, CreateOptions,
+            DuplicateEntryPolicy,
+        };
+
+        let test_dir = TestDir::new("create-from-files");
+        let first_file = test_dir.path().join("first.txt");
+        let second_file = test_dir.path().join("second.txt");
+        let source_dir = test_dir.path().join("assets");
+        fs::write(&first_file, b"first").unwrap();
+        fs::write(&second_file, b"second").unwrap();
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join("logo.svg"), b"<svg/>").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let entries = [
+            (Path::new("z.txt"), first_file.as_path()),
+            (Path::new("docs/a.txt"), second_file.as_path()),
+            (Path::new("static"), source_dir.as_path()),
+        ];
+        zip_create_from_files(&archive_file, &entries).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let names: Vec<String> = (0..archive.len())
+            .map(|index| archive.by_index(index).unwrap().name().to_string())
+            .collect();
+        assert_eq!(names, ["z.txt", "docs/a.txt", "static/", "static/logo.svg"]);
+
+        let duplicates = [
+            (Path::new("a.txt"), first_file.as_path()),
+            (Path::new("a.txt"), second_file.as_path()),
+        ];
+        assert!(zip_create_from_files(&archive_file, &duplicates).is_err());
+        assert!(!archive_file.exists());
+        let options = CreateOptions::default().duplicate_entries(DuplicateEntryPolicy::KeepLast);
+        let report =
+            zip_create_from_files_with_options(&archive_file, &duplicates, options).unwrap();
+        assert_eq!((report.files_added, report.skipped_duplicates), (1, 1));
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut archive.by_name("a.txt").unwrap(), &mut contents)
+            .unwrap();
+        assert_eq!(contents, "second");
+
+        let options = CreateOptions::default().recurse_directories(false);
+        let directory = [(Path::new("static"), source_dir.as_path())];
+        assert!(zip_create_from_files_with_options(&archive_file, &directory, options).is_err());
+    }
+
+    #[test]
+    fn duplicate_entry_names_follow_the_policy() {
+        use crate::{zip_create_from_files_with_options, CreateOptions, DuplicateEntryPolicy};
+
+        let test_dir = TestDir::new("create-duplicates");
+        let first_file = test_dir.path().join("first.md");
+        let second_file = test_dir.path().join("second.md");
+        fs::write(&first_file, b"first").unwrap();
+        fs::write(&second_file, b"second").unwrap();
+        let archive_file = test_dir.path().join("archive.zip");
+        let names = |archive_file: &Path| {
+            let archive = ZipArchive::new(File::open(archive_file).unwrap()).unwrap();
+            archive.file_names().map(str::to_string).collect::<Vec<_>>()
+        };
+
+        let same_name = [
+            (Path::new("README.md"), first_file.as_path()),
+            (Path::new("README.md"), second_file.as_path()),
+        ];
+        let error =
+            zip_create_from_files_with_options(&archive_file, &same_name, CreateOptions::default())
+                .unwrap_err()
+                .to_string();
+        assert!(error.contains(&first_file.display().to_string()), "{error}");
+        assert!(
+            error.contains(&second_file.display().to_string()),
+            "{error}"
+        );
+        let options =
+            CreateOptions::default().duplicate_entries(DuplicateEntryPolicy::AllowDuplicates);
+        zip_create_from_files_with_options(&archive_file, &same_name, options).unwrap();
+        assert_eq!(names(&archive_file), ["README.md", "README.md"]);
+
+        let different_case = [
+            (Path::new("Readme.md"), first_file.as_path()),
+            (Path::new("README.md"), second_file.as_path()),
+        ];
+        let options = CreateOptions::default();
+        zip_create_from_files_with_options(&archive_file, &different_case, options).unwrap();
+        assert_eq!(names(&archive_file), ["Readme.md", "README.md"]);
+        let options = CreateOptions::default().case_insensitive_names(true);
+        let error = zip_create_from_files_with_options(&archive_file, &different_case, options)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("only differ in case"), "{error}");
+        let options = CreateOptions::default()
+            .case_insensitive_names(true)
+            .duplicate_entries(DuplicateEntryPolicy::KeepFirst);
+        let report =
+            zip_create_from_files_with_options(&archive_file, &different_case, options).unwrap();
+        assert_eq!(report.skipped_duplicates, 1);
+        assert_eq!(names(&archive_file), ["Readme.md"]);
+    }
+
+    #[test]
+    fn zip_create_from_file_wraps_a_single_file() {
+        use std::io::{ErrorKind, Read};
+
+        use zip::result::ZipError;
+
+        use crate::{zip_create_from_file, CreateOptions};
+
+        let test_dir = TestDir::new("create-from-file");
+        let source_file = test_dir.path().join("report.txt");
+        fs::write(&source_file, b"report").unwrap();
+        let archive_file = test_dir.path().join("archive.zip");
+
+        zip_create_from_file(&archive_file, &source_file, None, CreateOptions::default()).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(archive.len(), 1);
+        let mut contents = String::new();
+        archive
+            .by_name("report.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "report");
+
+        let error = zip_create_from_file(
+            &archive_file,
+            &source_file,
+            Some("docs/renamed.txt"),
+            CreateOptions::default(),
+        )
+        .unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+
+        let options = CreateOptions::default().overwrite(true);
+        zip_create_from_file(
+            &archive_file,
+            &source_file,
+            Some("docs/renamed.txt"),
+            options,
+        )
+        .unwrap();
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(
+            archive.file_names().collect::<Vec<_>>(),
+            ["docs/renamed.txt"]
+        );
+
+        let options = CreateOptions::default().overwrite(true);
+        assert!(zip_create_from_file(&archive_file, test_dir.path(), None, options).is_err());
+    }
+
+    #[test]
+    fn zip_sync_directory_counts_changed_duplicate_entries_once() {
+        use std::io::Write;
+
+        use zip::write::FileOptions;
+        use zip::ZipWriter;
+
+        use crate::{zip_sync_directory, SyncOptions, SyncReport};
+
+        let test_dir = TestDir::new("sync-duplicates");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir(&source_dir).unwrap();
+        fs::write(source_dir.join("a.txt"), b"new").unwrap();
+        let archive_file = test_dir.path().join("archive.zip");
+        let mut zip_writer = ZipWriter::new(File::create(&archive_file).unwrap());
+        for content in [b"old", b"OLD"] {
+            zip_writer
+                .start_file("a.txt", FileOptions::default())
+                .unwrap();
+            zip_writer.write_all(content).unwrap();
+        }
+        zip_writer.finish().unwrap();
+
+        let report =
+            zip_sync_directory(&archive_file, &source_dir, SyncOptions::default()).unwrap();
+        assert_eq!(
+            report,
+            SyncReport {
+                added: 0,
+                updated: 1,
+                removed: 0,
+                unchanged: 0,
+            }
+        );
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(archive.len(), 1);
+    }
+
+    #[test]
+    fn zip_sync_directory_rewrites_only_changed_entries() {
+        use std::io::Read;
+
+        use crate::{zip_sync_directory, SyncOptions, SyncReport};
+
+        let test_dir = TestDir::new("sync-directory");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("sub")).unwrap();
+        fs::write(source_dir.join("a.txt"), b"alpha").unwrap();
+        fs::write(source_dir.join("b.txt"), b"beta").unwrap();
+        fs::write(source_dir.join("sub").join("c.txt"), b"gamma").unwrap();
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_directory(&archive_file, &source_dir).unwrap();
+
+        fs::write(source_dir.join("b.txt"), b"BETA").unwrap();
+        fs::remove_file(source_dir.join("sub").join("c.txt")).unwrap();
+        fs::write(source_dir.join("d.txt"), b"delta").unwrap();
+        let kept = zip_sync_directory(&archive_file, &source_dir, SyncOptions::default()).unwrap();
+        assert_eq!(
+            kept,
+            SyncReport {
+                added: 1,
+                updated: 1,
+                removed: 0,
+                unchanged: 3,
+            }
+        );
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert!(archive.file_names().any(|name| name == "sub/c.txt"));
+
+        let options = SyncOptions::default().remove_missing(true);
+        let report = zip_sync_directory(&archive_file, &source_dir, options).unwrap();
+        assert_eq!(
+            report,
+            SyncReport {
+                added: 0,
+                updated: 0,
+                removed: 1,
+                unchanged: 4,
+            }
+        );
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(names, ["a.txt", "b.txt", "d.txt", "sub/"]);
+        let mut content = String::new();
+        archive
+            .by_name("b.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "BETA");
+    }
+
+    #[test]
+    fn zip_create_from_memory_writes_entries_in_order() {
+        use std::io::{Cursor, Read};
+
+        use crate::{zip_create_from_memory, zip_create_from_memory_to_file, CreateOptions};
+
+        let mut buffer = Cursor::new(Vec::new());
+        let entries = vec![
+            ("reports/".to_string(), Vec::new()),
+            ("reports/summary.txt".to_string(), b"summary".to_vec()),
+            ("empty.txt".to_string(), Vec::new()),
+        ];
+        zip_create_from_memory(&mut buffer, entries, CreateOptions::default()).unwrap();
+        let mut archive = ZipArchive::new(buffer).unwrap();
+        let names: Vec<String> = (0..archive.len())
+            .map(|index| archive.by_index(index).unwrap().name().to_string())
+            .collect();
+        assert_eq!(names, ["reports/", "reports/summary.txt", "empty.txt"]);
+        assert!(archive.by_index(0).unwrap().is_dir());
+        let mut contents = String::new();
+        archive
+            .by_name("reports/summary.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "summary");
+        assert_eq!(archive.by_name("empty.txt").unwrap().size(), 0);
+
+        let test_dir = TestDir::new("create-from-memory");
+        let archive_file = test_dir.path().join("empty.zip");
+        zip_create_from_memory_to_file(&archive_file, Vec::new(), CreateOptions::default())
+            .unwrap();
+        assert_eq!(
+            ZipArchive::new(File::open(&archive_file).unwrap())
+                .unwrap()
+                .len(),
+            0
+        );
+
+        let invalid = vec![("../escape.txt".to_string(), Vec::new())];
+        let result =
+            zip_create_from_memory_to_file(&archive_file, invalid, CreateOptions::default());
+        assert!(result.is_err());
+        assert!(!archive_file.exists());
+    }
+
+    #[test]
+    fn align_stored_entries_aligns_entry_data() {
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_from_memory, CreateOptions};
+
+        let mut buffer = std::io::Cursor::new(Vec::new());
+        let entries = vec![
+            ("a.txt".to_string(), b"a".to_vec()),
+            ("odd-name.bin".to_string(), b"abc".to_vec()),
+            ("dir/x".to_string(), b"xyzzy".to_vec()),
+        ];
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .align_stored_entries(Some(4));
+        zip_create_from_memory(&mut buffer, entries, options).unwrap();
+
+        let mut archive = ZipArchive::new(buffer).unwrap();
+        for index in 0..archive.len() {
+            let entry = archive.by_index(index).unwrap();
+            assert!(entry.header_start() < entry.data_start());
+            assert_eq!(entry.data_start() % 4, 0, "{}", entry.name());
+        }
+    }
+
+    #[test]
+    fn zip_create_in_memory_returns_archive_bytes() {
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_in_memory, zip_create_in_memory_from_entries, CreateOptions};
+
+        let test_dir = TestDir::new("create-in-memory");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join("a.txt"), vec![b'a'; 4096]).unwrap();
+
+        let bytes = zip_create_in_memory(&source_dir, CreateOptions::default()).unwrap();
+        let mut archive = ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.by_name("a.txt").unwrap().size(), 4096);
+
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .max_archive_size(Some(1024));
+        assert!(zip_create_in_memory(&source_dir, options).is_err());
+
+        let entries = vec![("b.txt".to_string(), b"b".to_vec())];
+        let bytes = zip_create_in_memory_from_entries(entries, CreateOptions::default()).unwrap();
+        let archive = ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        assert_eq!(archive.len(), 1);
+    }
+
+    #[test]
+    fn on_progress_reports_chunks_and_cancels_creation() {
+        use std::cell::RefCell;
+        use std::ops::ControlFlow;
+        use std::rc::Rc;
+
+        use crate::{zip_create_from_directory_with_options, Cancelled, CreateOptions};
+
+        let test_dir = TestDir::new("create-progress");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("nested")).unwrap();
+        fs::write(source_dir.join("large.bin"), vec![0; 200 * 1024]).unwrap();
+        fs::write(source_dir.join("nested").join("small.txt"), b"small").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let updates = Rc::new(RefCell::new(Vec::new()));
+        let recorder = Rc::clone(&updates);
+        let options = CreateOptions::default().on_progress(move |progress| {
+            recorder.borrow_mut().push(progress.clone());
+            ControlFlow::Continue(())
+        });
+        zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+        let updates = updates.borrow();
+        let last = updates.last().unwrap();
+        assert_eq!((last.files_completed, last.total_files), (2, 2));
+        assert_eq!(last.bytes_read, last.total_bytes);
+        assert_eq!(last.total_bytes, 200 * 1024 + 5);
+        let large_file_updates = updates
+            .iter()
+            .filter(|progress| progress.current_path.ends_with("large.bin"))
+            .count();
+        assert!(large_file_updates > 2);
+
+        let chunk_sizes = Rc::new(RefCell::new(Vec::new()));
+        let recorder = Rc::clone(&chunk_sizes);
+        let mut bytes_read = 0;
+        let options = CreateOptions::default()
+            .chunk_size(16 * 1024)
+            .on_progress(move |progress| {
+                if progress.current_path.ends_with("large.bin") && progress.bytes_read > bytes_read
+                {
+                    recorder.borrow_mut().push(progress.bytes_read - bytes_read);
+                }
+                bytes_read = progress.bytes_read;
+                ControlFlow::Continue(())
+            });
+        zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+        let chunk_sizes = chunk_sizes.borrow();
+        assert!(chunk_sizes.len() >= 13);
+        assert!(chunk_sizes
+            .iter()
+            .all(|chunk_size| *chunk_size <= 16 * 1024));
+
+        let options = CreateOptions::default().on_progress(|progress| match progress.bytes_read {
+            0 => ControlFlow::Continue(()),
+            _ => ControlFlow::Break(()),
+        });
+        let error = zip_create_from_directory_with_options(&archive_file, &source_dir, options)
+            .unwrap_err();
+        let zip::result::ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert!(error
+            .get_ref()
+            .is_some_and(|inner| inner.downcast_ref::<Cancelled>().is_some()));
+        assert!(!archive_file.exists());
+    }
+
+    #[test]
+    fn root_prefix_wraps_all_entries() {
+        use crate::{zip_create_from_directory_with_options, zip_extract, CreateOptions};
+
+        let test_dir = TestDir::new("create-root-prefix");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("src")).unwrap();
+        fs::write(source_dir.join("README.md"), b"readme").unwrap();
+        fs::write(source_dir.join("src").join("lib.rs"), b"lib").unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = CreateOptions::default()
+            .deterministic(true)
+            .root_prefix(Some("project-1.0/".to_string()));
+        zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(
+            names,
+            [
+                "project-1.0/",
+                "project-1.0/README.md",
+                "project-1.0/src/",
+                "project-1.0/src/lib.rs"
+            ]
+        );
+
+        let target_dir = test_dir.path().join("target");
+        zip_extract(&archive_file, &target_dir).unwrap();
+        assert_eq!(
+            fs::read(target_dir.join("project-1.0").join("src").join("lib.rs")).unwrap(),
+            b"lib"
+        );
+
+        for prefix in ["", "/", "..", "project/../.."] {
+            let options = CreateOptions::default().root_prefix(Some(prefix.to_string()));
+            assert!(
+                zip_create_from_directory_with_options(&archive_file, &source_dir, options)
+                    .is_err(),
+                "{prefix}"
+            );
+        }
+    }
+
+    #[test]
+    fn large_files_are_streamed_in_bounded_chunks() {
+        use std::io::{Cursor, Read};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        use zip::ZipWriter;
+
+        use crate::{CreateOptions, SourceData, SourceEntry, ZipWriterExtensions};
+
+        /// Produces zeros, and records the largest buffer that it is asked to fill.
+        struct CountingReader {
+            remaining: usize,
+            largest_read: Arc<AtomicUsize>,
+        }
+
+        impl Read for CountingReader {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.largest_read.fetch_max(buf.len(), Ordering::Relaxed);
+                let length = buf.len().min(self.remaining);
+                buf[..length].fill(0);
+                self.remaining -= length;
+                Ok(length)
+            }
+        }
+
+        let size = 1024 * 1024;
+        let largest_read = Arc::new(AtomicUsize::new(0));
+        let reader = CountingReader {
+            remaining: size,
+            largest_read: Arc::clone(&largest_read),
+        };
+        let entries = [SourceEntry::new(
+            "large.bin",
+            SourceData::Reader(Box::new(reader)),
+        )];
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = CreateOptions::default().chunk_size(4096);
+        zip_writer
+            .create_from_iter_with_options(entries, options)
+            .unwrap();
+        let mut archive = ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+
+        assert_eq!(largest_read.load(Ordering::Relaxed), 4096);
+        assert_eq!(archive.by_name("large.bin").unwrap().size(), size as u64);
+    }
+
+    #[test]
+    fn file_entry_options_selects_method_by_extension_and_selector() {
+        use zip::write::FileOptions;
+        use zip::CompressionMethod;
+
+        use crate::CreateOptions;
+
+        let method = |options: &mut CreateOptions, path: &str| {
+            let (_, method) = options
+                .file_entry_options(FileOptions::default(), Path::new(path))
+                .unwrap();
+            method
+        };
+
+        // Bzip2, which is only a named variant if the `bzip2` feature is enabled.
+        #[allow(deprecated)]
+        let bzip2 = CompressionMethod::from_u16(12);
+        let mut options = CreateOptions::default()
+            .store_precompressed()
+            .compression_method_for_extension(".LOG", bzip2);
+        assert_eq!(method(&mut options, "photo.PNG"), CompressionMethod::Stored);
+        assert_eq!(
+            method(&mut options, "readme.txt"),
+            CompressionMethod::Stored
+        );
+        let log = options.file_entry_options(FileOptions::default(), Path::new("server.log"));
+        assert_eq!(log.is_ok(), cfg!(feature = "bzip2"));
+
+        let mut options = CreateOptions::default()
+            .compression_method_for_extension("txt", bzip2)
+            .compression_method_selector(|_| CompressionMethod::Stored);
+        assert_eq!(
+            method(&mut options, "readme.txt"),
+            CompressionMethod::Stored
+        );
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn store_precompressed_skips_compression_for_media() {
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_in_memory_from_entries, CreateOptions};
+
+        let entries = vec![
+            ("image.png".to_string(), vec![0; 1024]),
+            ("notes.txt".to_string(), vec![b'a'; 1024]),
+        ];
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .compression_level(Some(9))
+            .store_precompressed();
+        let bytes = zip_create_in_memory_from_entries(entries, options).unwrap();
+        let mut archive = ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+        let image = archive.by_name("image.png").unwrap().compression();
+        assert_eq!(image, CompressionMethod::Stored);
+        let notes = archive.by_name("notes.txt").unwrap().compression();
+        assert_eq!(notes, CompressionMethod::Deflated);
+    }
+
+    #[test]
+    fn zip_create_from_directory_writes_empty_archive_for_empty_directory() {
+        let test_dir = TestDir::new("empty-source");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        zip_create_from_directory(&archive_file, &source_dir).unwrap();
+
+        let archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert!(archive.is_empty());
+    }
+
+    #[test]
+    fn password_encrypts_file_entries() {
+        use zip::result::ZipError;
+
+        use crate::{
+            zip_create_from_directory_with_options, zip_extract_with_options, CreateOptions,
+            EncryptionMethod, ExtractOptions,
+        };
+
+        let test_dir = TestDir::new("create-password");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("docs")).unwrap();
+        fs::write(
+            source_dir.join("docs").join("secret.txt"),
+            b"secret contents",
+        )
+        .unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        #[cfg(not(feature = "aes"))]
+        {
+            use std::io::ErrorKind;
+
+            assert_eq!(EncryptionMethod::default(), EncryptionMethod::ZipCrypto);
+            let options = CreateOptions::default()
+                .password(b"hunter2")
+                .encryption(EncryptionMethod::Aes256);
+            let error = zip_create_from_directory_with_options(&archive_file, &source_dir, options)
+                .unwrap_err();
+            let ZipError::Io(error) = error else {
+                panic!("expected an io error, got {error:?}");
+            };
+            assert_eq!(error.kind(), ErrorKind::Unsupported);
+            assert!(error.to_string().contains("`aes` feature"));
+            assert!(!archive_file.exists());
+        }
+
+        let options = CreateOptions::default()
+            .password(b"hunter2")
+            .encryption(EncryptionMethod::ZipCrypto);
+        zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert!(archive.by_name("docs/").is_ok());
+        assert!(archive.by_name("docs/secret.txt").is_err());
+        drop(archive);
+
+        let target_dir = test_dir.path().join("target");
+        let error = zip_extract_with_options(&archive_file, &target_dir, ExtractOptions::default())
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            ZipError::UnsupportedArchive(ZipError::PASSWORD_REQUIRED)
+        ));
+        assert!(!target_dir.join("docs").join("secret.txt").exists());
+
+        let options = ExtractOptions::default().password(b"hunter2");
+        zip_extract_with_options(&archive_file, &target_dir, options).unwrap();
+        assert_eq!(
+            fs::read(target_dir.join("docs").join("secret.txt")).unwrap(),
+            b"secret contents"
+        );
+    }
+
+    #[cfg(feature = "aes")]
+    #[test]
+    fn password_encrypts_file_entries_with_aes() {
+        use std::io::{ErrorKind, Read};
+
+        use zip::result::ZipError;
+        use zip::CompressionMethod;
+
+        use crate::{
+            zip_create_from_directory_with_options, zip_extract_with_options, CreateOptions,
+            EncryptionMethod, ExtractOptions,
+        };
+
+        let test_dir = TestDir::new("create-password-aes");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("docs")).unwrap();
+        let contents = b"secret contents, secret contents, secret contents".repeat(100);
+        fs::write(source_dir.join("docs").join("secret.txt"), &contents).unwrap();
+        fs::write(
+            source_dir.join("docs").join("stored.bin"),
+            b"stored contents",
+        )
+        .unwrap();
+
+        assert_eq!(EncryptionMethod::default(), EncryptionMethod::Aes256);
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = CreateOptions::default()
+            .password(b"hunter2")
+            .force_zip64(true);
+        let error = zip_create_from_directory_with_options(&archive_file, &source_dir, options)
+            .unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::Unsupported);
+        assert!(!archive_file.exists());
+
+        let options = CreateOptions::default()
+            .password(b"hunter2")
+            .compression_method_for_extension("bin", CompressionMethod::Stored);
+        let report =
+            zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+        assert_eq!(report.files_added, 2);
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert!(archive.by_name("docs/").is_ok());
+        assert!(archive.by_name("docs/secret.txt").is_err());
+        assert!(archive
+            .by_name_decrypt("docs/secret.txt", b"wrong")
+            .unwrap()
+            .is_err());
+        let mut decrypted = Vec::new();
+        let mut entry = archive
+            .by_name_decrypt("docs/secret.txt", b"hunter2")
+            .unwrap()
+            .unwrap();
+        assert_eq!(entry.size(), contents.len() as u64);
+        entry.read_to_end(&mut decrypted).unwrap();
+        assert_eq!(decrypted, contents);
+        drop(entry);
+        drop(archive);
+
+        let target_dir = test_dir.path().join("target");
+        let options = ExtractOptions::default().password(b"hunter2");
+        zip_extract_with_options(&archive_file, &target_dir, options).unwrap();
+        assert_eq!(
+            fs::read(target_dir.join("docs").join("secret.txt")).unwrap(),
+            contents
+        );
+        assert_eq!(
+            fs::read(target_dir.join("docs").join("stored.bin")).unwrap(),
+            b"stored contents"
+        );
+    }
+
+    #[test]
+    #[cfg(not(feature = "deflate"))]
+    fn zip_create_from_directory_with_options_names_missing_feature() {
+        use zip::result::ZipError;
+
+        use crate::{zip_create_from_directory_with_options, CreateOptions};
+
+        #[allow(deprecated)]
+        let options = CreateOptions::default()
+            .compression_method(zip::CompressionMethod::Unsupported(8))
+            .compression_level(Some(6));
+        let test_dir = TestDir::new("missing-feature");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let error = zip_create_from_directory_with_options(&archive_file, &source_dir, options)
+            .unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert!(error.to_string().contains("`deflate` feature"));
+        assert!(!archive_file.exists());
+    }
+
+    #[test]
+    fn zip_create_best_compression_keeps_only_the_smallest_archive() {
+        use crate::zip_create_best_compression;
+
+        let test_dir = TestDir::new("best-compression");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        fs::write(source_dir.join("file.txt"), "repetitive ".repeat(1000)).unwrap();
+
+        let archive_file = test_dir.path().join("archive.zip");
+        // A file of the user that must not be mistaken for a candidate archive.
+        fs::write(test_dir.path().join("archive.zip.1.tmp"), b"unrelated").unwrap();
+        let method = zip_create_best_compression(&archive_file, &source_dir).unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(archive.by_index(0).unwrap().compression(), method);
+        let mut remaining_files: Vec<_> = fs::read_dir(test_dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        remaining_files.sort_unstable();
+        assert_eq!(
+            remaining_files,
+            ["archive.zip", "archive.zip.1.tmp", "source"]
+        );
+        assert_eq!(
+            fs::read(test_dir.path().join("archive.zip.1.tmp")).unwrap(),
+            b"unrelated"
+        );
+    }
+
+    #[test]
+    fn add_path_writes_into_any_seekable_writer() {
+        use std::io::{Cursor, Read};
+
+        use zip::ZipWriter;
+
+        use crate::ZipWriterExtensions;
+
+        let test_dir = TestDir::new("add-path");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("nested")).unwrap();
+        fs::write(source_dir.join("nested").join("file.txt"), b"content").unwrap();
+        fs::write(test_dir.path().join("single.txt"), b"single").unwrap();
+
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        zip_writer.add_path(&source_dir, "docs/").unwrap();
+        zip_writer
+            .add_path(test_dir.path().join("single.txt"), "other/single.txt")
+            .unwrap();
+        let zip_data = zip_writer.finish().unwrap();
+
+        let mut archive = ZipArchive::new(zip_data).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort_unstable();
+        assert_eq!(
+            names,
+            [
+                "docs/",
+                "docs/nested/",
+                "docs/nested/file.txt",
+                "other/single.txt"
+            ]
+        );
+        let mut content = String::new();
+        archive
+            .by_name("docs/nested/file.txt")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "content");
+    }
+
+    #[test]
+    fn create_from_iter_writes_entries_in_the_given_order() {
+        use std::io::{Cursor, Read};
+
+        use zip::{CompressionMethod, ZipWriter};
+
+        use crate::{CreateOptions, SourceData, SourceEntry, ZipWriterExtensions};
+
+        let test_dir = TestDir::new("create-from-iter");
+        let source_file = test_dir.path().join("file.txt");
+        fs::write(&source_file, b"from disk").unwrap();
+
+        let entries = vec![
+            SourceEntry::new("generated/", SourceData::Directory),
+            SourceEntry::new("generated/bytes.txt", SourceData::Bytes(b"bytes".to_vec())),
+            SourceEntry::new("disk.txt", SourceData::File(source_file)),
+            SourceEntry::new(
+                "generated/reader.txt",
+                SourceData::Reader(Box::new(Cursor::new(b"reader".to_vec()))),
+            )
+            .compression_method(CompressionMethod::Stored),
+        ];
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let report = zip_writer
+            .create_from_iter_with_options(entries, CreateOptions::default())
+            .unwrap();
+        assert_eq!((report.files_added, report.directories_added), (3, 1));
+        let mut archive = ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+        let names: Vec<&str> = archive.file_names().collect();
+        assert_eq!(
+            names,
+            [
+                "generated/",
+                "generated/bytes.txt",
+                "disk.txt",
+                "generated/reader.txt"
+            ]
+        );
+        for (name, expected) in [
+            ("generated/bytes.txt", "bytes"),
+            ("disk.txt", "from disk"),
+            ("generated/reader.txt", "reader"),
+        ] {
+            let mut content = String::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_string(&mut content)
+                .unwrap();
+            assert_eq!(content, expected);
+        }
+
+        let entries = [SourceEntry::new(
+            "../escape.txt",
+            SourceData::Bytes(Vec::new()),
+        )];
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        assert!(zip_writer.create_from_iter(entries).is_err());
+    }
+
+    #[test]
+    fn zip_create_from_directory_rejects_archive_inside_directory() {
+        let test_dir = TestDir::new("archive-inside-source");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(source_dir.join("nested")).unwrap();
+
+        let archive_file = source_dir.join("nested").join("archive.zip");
+        assert!(zip_create_from_directory(&archive_file, &source_dir).is_err());
+        assert!(!archive_file.exists());
+    }
+
+    #[test]
+    fn zip_create_streaming_writes_to_a_writer_without_seek() {
+        use std::io::{Cursor, Read, Write};
+
+        use crate::{zip_create_streaming, CreateOptions, SourceData, SourceEntry};
+
+        struct Pipe(Vec<u8>);
+
+        impl Write for Pipe {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                self.0.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let mut pipe = Pipe(Vec::new());
+        let entries = vec![
+            SourceEntry::new("logs/", SourceData::Directory),
+            SourceEntry::new(
+                "logs/app.log",
+                SourceData::Reader(Box::new(Cursor::new(b"started".to_vec()))),
+            ),
+        ];
+        let report = zip_create_streaming(entries, &mut pipe, CreateOptions::default()).unwrap();
+        assert_eq!((report.files_added, report.directories_added), (1, 1));
+
+        let mut archive = ZipArchive::new(Cursor::new(pipe.0)).unwrap();
+        let mut content = String::new();
+        archive
+            .by_name("logs/app.log")
+            .unwrap()
+            .read_to_string(&mut content)
+            .unwrap();
+        assert_eq!(content, "started");
+
+        let entries = [SourceEntry::new(
+            "/absolute.txt",
+            SourceData::Bytes(Vec::new()),
+        )];
+        let mut pipe = Pipe(Vec::new());
+        assert!(zip_create_streaming(entries, &mut pipe, CreateOptions::default()).is_err());
+        assert!(pipe.0.is_empty());
+    }
+
+    #[test]
+    fn add_entry_from_reader_streams_contents_with_their_crc() {
+        use std::io::{Cursor, Read};
+
+        use zip::{CompressionMethod, ZipWriter};
+
+        use crate::{CreateOptions, ZipWriterExtensions};
+
+        let contents: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let mut zip_writer = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .chunk_size(4096);
+        let bytes_read = zip_writer
+            .add_entry_from_reader("dump\\data.bin", Cursor::new(&contents), options)
+            .unwrap();
+        assert_eq!(bytes_read, contents.len() as u64);
+        assert!(zip_writer
+            .add_entry_from_reader(
+                "../data.bin",
+                Cursor::new(&contents),
+                CreateOptions::default()
+            )
+            .is_err());
+
+        let mut archive = ZipArchive::new(zip_writer.finish().unwrap()).unwrap();
+        let mut entry = archive.by_name("dump/data.bin").unwrap();
+        assert_eq!(entry.size(), contents.len() as u64);
+        assert_eq!(entry.crc32(), crc32fast::hash(&contents));
+        let mut extracted = Vec::new();
+        entry.read_to_end(&mut extracted).unwrap();
+        assert_eq!(extracted, contents);
+    }
+
+    #[test]
+    fn zip_recompress_rewrites_entries_and_verifies_crcs() {
+        use std::io::{Read, Write};
+
+        use zip::write::FileOptions;
+        use zip::{CompressionMethod, DateTime, ZipWriter};
+
+        use super::write_entry_comments;
+        use crate::{zip_recompress, CreateOptions};
+
+        #[cfg(feature = "deflate")]
+        let method = CompressionMethod::Deflated;
+        #[cfg(not(feature = "deflate"))]
+        let method = CompressionMethod::Stored;
+
+        let test_dir = TestDir::new("recompress");
+        let input = test_dir.path().join("input.zip");
+        let output = test_dir.path().join("output.zip");
+        let contents = "compressible ".repeat(1000);
+        let modified = DateTime::from_date_and_time(2020, 5, 17, 8, 30, 0).unwrap();
+        let stored = FileOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .last_modified_time(modified)
+            .unix_permissions(0o640);
+        let mut zip_writer = ZipWriter::new(File::create(&input).unwrap());
+        // A comment in a legacy code page, which is not valid UTF-8.
+        zip_writer.set_raw_comment(b"legacy \xa9".to_vec());
+        zip_writer.add_directory("data/", stored).unwrap();
+        zip_writer.start_file("data/text.txt", stored).unwrap();
+        zip_writer.write_all(contents.as_bytes()).unwrap();
+        zip_writer.finish().unwrap();
+        let mut input_file = File::options().read(true).write(true).open(&input).unwrap();
+        let entry_comments = [(1, "generated".to_string())];
+        write_entry_comments(&mut input_file, &entry_comments).unwrap();
+
+        let options = CreateOptions::default().compression_method(method);
+        let report = zip_recompress(&input, &output, options).unwrap();
+        assert_eq!(report.size_before, fs::metadata(&input).unwrap().len());
+        assert_eq!(report.size_after, fs::metadata(&output).unwrap().len());
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.entries[0].name, "data/text.txt");
+        #[cfg(feature = "deflate")]
+        assert!(report.entries[0].size_after < report.entries[0].size_before);
+
+        let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        assert_eq!(archive.comment(), b"legacy \xa9");
+        assert!(archive.by_name("data/").unwrap().is_dir());
+        assert!(archive.by_name("data/").unwrap().comment().is_empty());
+        let mut entry = archive.by_name("data/text.txt").unwrap();
+        assert_eq!(entry.comment(), "generated");
+        assert_eq!(entry.compression(), method);
+        assert_eq!(entry.unix_mode().map(|mode| mode & 0o777), Some(0o640));
+        let entry_modified = entry.last_modified();
+        assert_eq!(
+            (entry_modified.datepart(), entry_modified.timepart()),
+            (modified.datepart(), modified.timepart())
+        );
+        let mut extracted = String::new();
+        entry.read_to_string(&mut extracted).unwrap();
+        assert_eq!(extracted, contents);
+
+        let mut bytes = fs::read(&input).unwrap();
+        let data_offset = bytes
+            .windows(12)
+            .position(|window| window == b"compressible")
+            .unwrap();
+        bytes[data_offset] = b'C';
+        fs::write(&input, bytes).unwrap();
+        fs::remove_file(&output).unwrap();
+        assert!(zip_recompress(&input, &output, CreateOptions::default()).is_err());
+        assert!(!output.exists());
+    }
+
+    #[test]
+    fn zip_convert_to_stored_stores_all_entries() {
+        use std::io::Read;
+
+        use zip::CompressionMethod;
+
+        use crate::{zip_convert_to_stored, zip_create_from_memory_to_file, CreateOptions};
+
+        let test_dir = TestDir::new("convert-to-stored");
+        let input = test_dir.path().join("input.zip");
+        let output = test_dir.path().join("stored.zip");
+        let entries = vec![
+            ("assets/".to_string(), Vec::new()),
+            ("assets/level.dat".to_string(), vec![7; 10_000]),
+            ("readme.txt".to_string(), b"read me".to_vec()),
+        ];
+        let options = CreateOptions::default()
+            .archive_comment("game data".to_string())
+            .entry_comment(|path| (path == Path::new("readme.txt")).then(|| "docs".to_string()));
+        zip_create_from_memory_to_file(&input, entries, options).unwrap();
+
+        let report = zip_convert_to_stored(&input, &output).unwrap();
+        assert_eq!(
+            report.size_increase(),
+            i128::from(fs::metadata(&output).unwrap().len())
+                - i128::from(fs::metadata(&input).unwrap().len())
+        );
+        let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let names: Vec<String> = (0..archive.len())
+            .map(|file_number| {
+                archive
+                    .by_index_raw(file_number)
+                    .unwrap()
+                    .name()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names, ["assets/", "assets/level.dat", "readme.txt"]);
+        assert_eq!(archive.comment(), b"game data");
+        assert_eq!(archive.by_name("readme.txt").unwrap().comment(), "docs");
+        let mut entry = archive.by_name("assets/level.dat").unwrap();
+        assert!(entry.comment().is_empty());
+        assert_eq!(entry.compression(), CompressionMethod::Stored);
+        assert_eq!(entry.compressed_size(), 10_000);
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, vec![7; 10_000]);
+    }
+
+    #[test]
+    fn zip_minimize_strips_extra_fields_and_comments() {
+        use std::io::{ErrorKind, Read};
+
+        use zip::result::ZipError;
+
+        use crate::{zip_minimize, MinimizeKeep};
+
+        // A stored `a.txt` written by Info-ZIP, with extended timestamp and Unix owner extra
+        // fields, an entry comment and an archive comment.
+        const ARCHIVE: [u8; 202] = [
+            0x50, 0x4b, 0x03, 0x04, 0x0a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x61, 0x58,
+            0x2d, 0x65, 0x36, 0x3d, 0x0e, 0x00, 0x00, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x05, 0x00,
+            0x1c, 0x00, 0x61, 0x2e, 0x74, 0x78, 0x74, 0x55, 0x54, 0x09, 0x00, 0x03, 0x40, 0xc3,
+            0xe1, 0x65, 0x40, 0xc3, 0xe1, 0x65, 0x75, 0x78, 0x0b, 0x00, 0x01, 0x04, 0x00, 0x00,
+            0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x6d,
+            0x69, 0x6e, 0x69, 0x6d, 0x69, 0x7a, 0x65, 0x50, 0x4b, 0x01, 0x02, 0x1e, 0x03, 0x0a,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x60, 0x61, 0x58, 0x2d, 0x65, 0x36, 0x3d, 0x0e,
+            0x00, 0x00, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x05, 0x00, 0x18, 0x00, 0x0d, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0xa4, 0x81, 0x00, 0x00, 0x00, 0x00, 0x61, 0x2e, 0x74,
+            0x78, 0x74, 0x55, 0x54, 0x05, 0x00, 0x03, 0x40, 0xc3, 0xe1, 0x65, 0x75, 0x78, 0x0b,
+            0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x65, 0x6e,
+            0x74, 0x72, 0x79, 0x20, 0x63, 0x6f, 0x6d, 0x6d, 0x65, 0x6e, 0x74, 0x50, 0x4b, 0x05,
+            0x06, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0x00, 0x58, 0x00, 0x00, 0x00, 0x4d,
+            0x00, 0x00, 0x00, 0x0f, 0x00, 0x61, 0x72, 0x63, 0x68, 0x69, 0x76, 0x65, 0x20, 0x63,
+            0x6f, 0x6d, 0x6d, 0x65, 0x6e, 0x74,
+        ];
+
+        let test_dir = TestDir::new("minimize");
+        let input = test_dir.path().join("input.zip");
+        let minimal = test_dir.path().join("minimal.zip");
+        let partial = test_dir.path().join("partial.zip");
+        fs::write(&input, ARCHIVE).unwrap();
+
+        let report = zip_minimize(&input, &minimal, MinimizeKeep::default()).unwrap();
+        assert_eq!(report.size_before, 202);
+        assert_eq!(report.size_after, 122);
+        assert_eq!(report.bytes_saved(), 80);
+        assert_eq!(report.extra_fields_removed, 4);
+        assert_eq!(report.comments_removed, 2);
+        let mut archive = ZipArchive::new(File::open(&minimal).unwrap()).unwrap();
+        assert!(archive.comment().is_empty());
+        let mut entry = archive.by_name("a.txt").unwrap();
+        assert_eq!(entry.crc32(), 0x3d36_652d);
+        assert!(entry.comment().is_empty());
+        assert!(entry.extra_data().is_empty());
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "hello minimize");
+        drop(entry);
+
+        let keep = MinimizeKeep::default()
+            .timestamps(true)
+            .entry_comments(true)
+            .archive_comment(true);
+        let report = zip_minimize(&input, &partial, keep).unwrap();
+        assert_eq!(report.size_after, 172);
+        assert_eq!(report.extra_fields_removed, 2);
+        assert_eq!(report.comments_removed, 0);
+        let mut archive = ZipArchive::new(File::open(&partial).unwrap()).unwrap();
+        assert_eq!(archive.comment(), b"archive comment");
+        let entry = archive.by_name("a.txt").unwrap();
+        assert_eq!(entry.comment(), "entry comment");
+        assert_eq!(&entry.extra_data()[..2], [0x55, 0x54]);
+        drop(entry);
+
+        let error = zip_minimize(&input, &input, MinimizeKeep::default()).unwrap_err();
+        assert!(matches!(error, ZipError::Io(error) if error.kind() == ErrorKind::InvalidInput));
+        assert_eq!(fs::read(&input).unwrap(), ARCHIVE);
+    }
+
+    #[test]
+    fn copy_entries_raw_copies_named_entries() {
+        use std::io::{ErrorKind, Read};
+
+        use zip::result::ZipError;
+
+        use super::create_archive_file;
+        use crate::{
+            copy_entries_raw, copy_entries_raw_with_policy, zip_create_from_memory_to_file,
+            CreateOptions, DuplicateEntryPolicy, RawCopyWriter,
+        };
+
+        let test_dir = TestDir::new("copy-entries-raw");
+        let source = test_dir.path().join("source.zip");
+        let output = test_dir.path().join("output.zip");
+        let entries = vec![
+            ("assets/".to_string(), Vec::new()),
+            ("assets/level.dat".to_string(), vec![7; 1000]),
+            ("readme.txt".to_string(), b"first".to_vec()),
+            ("readme.txt".to_string(), b"second".to_vec()),
+            ("unused.txt".to_string(), b"unused".to_vec()),
+        ];
+        let mut readme_comments = ["first readme", "second readme"].into_iter();
+        let options = CreateOptions::default()
+            .duplicate_entries(DuplicateEntryPolicy::AllowDuplicates)
+            .entry_comment(move |path| match path.to_str()? {
+                "assets/level.dat" => Some("level data".to_string()),
+                "readme.txt" => readme_comments.next().map(str::to_string),
+                _ => None,
+            });
+        zip_create_from_memory_to_file(&source, entries, options).unwrap();
+        let mut src = ZipArchive::new(File::open(&source).unwrap()).unwrap();
+
+        let mut dest_writer = RawCopyWriter::new(create_archive_file(&output).unwrap());
+        let error = copy_entries_raw(&mut dest_writer, &mut src, &["readme.txt"]).unwrap_err();
+        assert!(matches!(error, ZipError::Io(error) if error.kind() == ErrorKind::AlreadyExists));
+        let error = copy_entries_raw(&mut dest_writer, &mut src, &["missing.txt", "gone.txt"])
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("missing.txt, gone.txt"), "{error}");
+
+        let names = [
+            Path::new("assets"),
+            Path::new("assets\\level.dat"),
+            Path::new("readme.txt"),
+            Path::new("assets/level.dat"),
+        ];
+        let copied = copy_entries_raw_with_policy(
+            &mut dest_writer,
+            &mut src,
+            &names,
+            DuplicateEntryPolicy::KeepLast,
+        )
+        .unwrap();
+        assert_eq!(copied, 3);
+        dest_writer.finish().unwrap();
+
+        let mut archive = ZipArchive::new(File::open(&output).unwrap()).unwrap();
+        let names: Vec<String> = (0..archive.len())
+            .map(|file_number| {
+                archive
+                    .by_index_raw(file_number)
+                    .unwrap()
+                    .name()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(names, ["assets/", "assets/level.dat", "readme.txt"]);
+        for name in ["assets/level.dat", "readme.txt"] {
+            let copy = archive.by_name(name).unwrap();
+            let (crc32, compressed_size) = (copy.crc32(), copy.compressed_size());
+            drop(copy);
+            let original = src
+                .by_index_raw(if name == "readme.txt" { 3 } else { 1 })
+                .unwrap();
+            assert_eq!(crc32, original.crc32());
+            assert_eq!(compressed_size, original.compressed_size());
+        }
+        let mut contents = String::new();
+        let mut readme = archive.by_name("readme.txt").unwrap();
+        assert_eq!(readme.comment(), "second readme");
+        readme.read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "second");
+        drop(readme);
+        assert_eq!(
+            archive.by_name("assets/level.dat").unwrap().comment(),
+            "level data"
+        );
+        assert!(archive.by_name("assets/").unwrap().comment().is_empty());
+    }
+
+    #[test]
+    fn copy_entries_raw_applies_the_policy_to_the_destination() {
+        use std::io::ErrorKind;
+
+        use zip::result::ZipError;
+
+        use super::create_archive_file;
+        use crate::{
+            copy_entries_raw, copy_entries_raw_with_policy, zip_create_from_memory_to_file,
+            CreateOptions, DuplicateEntryPolicy, RawCopyWriter,
+        };
+
+        let test_dir = TestDir::new("copy-entries-raw-destination");
+        let source = test_dir.path().join("source.zip");
+        let output = test_dir.path().join("output.zip");
+        let entries = vec![
+            ("assets/".to_string(), Vec::new()),
+            ("assets/level.dat".to_string(), vec![7; 1000]),
+            ("readme.txt".to_string(), b"read me".to_vec()),
+        ];
+        let options = CreateOptions::default()
+            .entry_comment(|path| (path == Path::new("readme.txt")).then(|| "docs".to_string()));
+        zip_create_from_memory_to_file(&source, entries, options).unwrap();
+        let mut src = ZipArchive::new(File::open(&source).unwrap()).unwrap();
+        let names = |output: &Path| {
+            let mut archive = ZipArchive::new(File::open(output).unwrap()).unwrap();
+            (0..archive.len())
+                .map(|file_number| {
+                    let entry = archive.by_index_raw(file_number).unwrap();
+                    (entry.name().to_string(), entry.comment().to_string())
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let mut dest_writer = RawCopyWriter::new(create_archive_file(&output).unwrap());
+        copy_entries_raw(&mut dest_writer, &mut src, &["assets/level.dat"]).unwrap();
+        let error = copy_entries_raw(
+            &mut dest_writer,
+            &mut src,
+            &["readme.txt", "assets/level.dat"],
+        )
+        .unwrap_err();
+        let ZipError::Io(error) = error else {
+            panic!("expected an io error, got {error:?}");
+        };
+        assert_eq!(error.kind(), ErrorKind::AlreadyExists);
+        assert!(error.to_string().contains("assets/level.dat"), "{error}");
+        let names_to_copy = ["assets", "assets/level.dat", "readme.txt"];
+        let copied = copy_entries_raw_with_policy(
+            &mut dest_writer,
+            &mut src,
+            &names_to_copy,
+            DuplicateEntryPolicy::KeepFirst,
+        )
+        .unwrap();
+        assert_eq!(copied, 2);
+        dest_writer.finish().unwrap();
+        let docs = ("readme.txt".to_string(), "docs".to_string());
+        let expected = vec![
+            ("assets/level.dat".to_string(), String::new()),
+            ("assets/".to_string(), String::new()),
+            docs.clone(),
+        ];
+        assert_eq!(names(&output), expected);
+
+        // Entries of an archive that is appended to count as well, and keep their comments.
+        let file = File::options()
+            .read(true)
+            .write(true)
+            .open(&output)
+            .unwrap();
+        let mut dest_writer = RawCopyWriter::new_append(file).unwrap();
+        let copied = copy_entries_raw_with_policy(
+            &mut dest_writer,
+            &mut src,
+            &names_to_copy,
+            DuplicateEntryPolicy::KeepLast,
+        )
+        .unwrap();
+        assert_eq!(copied, 2);
+        dest_writer.finish().unwrap();
+        let mut expected = expected;
+        expected.extend([("assets/level.dat".to_string(), String::new()), docs]);
+        assert_eq!(names(&output), expected);
+    }
+
+    #[test]
+    fn zip_append_directory_adds_missing_files() {
+        use std::io::{ErrorKind, Read};
+
+        use zip::result::ZipError;
+
+        use crate::zip_append_directory;
+
+        let test_dir = TestDir::new("append-directory");
+        let source_dir = test_dir.path().join("source");
+        let archive_file = test_dir.path().join("backup.zip");
+        fs::create_dir_all(source_dir.join("docs")).unwrap();
+        fs::write(source_dir.join("first.txt"), "first").unwrap();
+        zip_create_from_directory(&archive_file, &source_dir).unwrap();
+
+        fs::write(source_dir.join("first.txt"), "changed").unwrap();
+        fs::write(source_dir.join("docs/second.txt"), "second").unwrap();
+        let report = zip_append_directory(&archive_file, &source_dir).unwrap();
+        assert_eq!(report.files_added, 1);
+        assert_eq!(report.directories_added, 0);
+        assert_eq!(report.skipped_duplicates, 1);
+        let report = zip_append_directory(&archive_file, &source_dir).unwrap();
+        assert_eq!(report.files_added, 0);
+        assert_eq!(report.skipped_duplicates, 2);
+
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(archive.len(), 3);
+        let mut contents = String::new();
+        archive
+            .by_name("first.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "first");
+        contents.clear();
+        archive
+            .by_name("docs/second.txt")
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "second");
+
+        let inside = source_dir.join("inside.zip");
+        fs::copy(&archive_file, &inside).unwrap();
+        let error = zip_append_directory(&inside, &source_dir).unwrap_err();
+        assert!(matches!(error, ZipError::Io(error) if error.kind() == ErrorKind::InvalidInput));
+    }
+
+    #[test]
+    fn create_report_lists_files_with_identical_contents() {
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_from_files_with_options, CreateOptions};
+
+        let test_dir = TestDir::new("duplicate-contents");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        let contents = [
+            ("a.txt", "same contents"),
+            ("b.txt", "more contents"),
+            ("c.txt", "same contents"),
+            ("d.txt", ""),
+            ("e.txt", ""),
+        ];
+        for (name, content) in contents {
+            fs::write(source_dir.join(name), content).unwrap();
+        }
+        let mut files: Vec<(String, std::path::PathBuf)> = contents
+            .iter()
+            .map(|(name, _)| (format!("files/{name}"), source_dir.join(name)))
+            .collect();
+        files.push(("copy.txt".to_string(), source_dir.join("a.txt")));
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Stored)
+            .deduplicate_contents(true);
+        let report = zip_create_from_files_with_options(&archive_file, &files, options).unwrap();
+        assert_eq!(
+            report.duplicate_contents,
+            [["files/a.txt", "files/c.txt", "copy.txt"]]
+        );
+        // Stored entries are written as usual.
+        assert_eq!(report.files_deduplicated, 0);
+        assert_eq!(report.files_added, 6);
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        assert_eq!(archive.by_name("files/c.txt").unwrap().size(), 13);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn deduplicate_contents_copies_compressed_data() {
+        use std::io::Read;
+
+        use zip::{CompressionMethod, ZipWriter};
+
+        use crate::{CreateOptions, SourceData, SourceEntry, ZipWriterExtensions};
+
+        let test_dir = TestDir::new("deduplicate-contents");
+        let archive_file = test_dir.path().join("archive.zip");
+        let entries = vec![
+            SourceEntry::new("first.txt", SourceData::Bytes(vec![b'a'; 10_000])),
+            SourceEntry::new("other.txt", SourceData::Bytes(vec![b'b'; 10_000])),
+            SourceEntry::new("second.txt", SourceData::Bytes(vec![b'a'; 10_000])),
+        ];
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .deduplicate_contents(true);
+        let mut zip_writer = ZipWriter::new(File::create(&archive_file).unwrap());
+        let report = zip_writer
+            .create_from_iter_with_options(entries, options)
+            .unwrap();
+        zip_writer.finish().unwrap();
+        assert_eq!(report.duplicate_contents, [["first.txt", "second.txt"]]);
+        assert_eq!(report.files_deduplicated, 1);
+
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let first = archive.by_name("first.txt").unwrap();
+        let (first_size, first_crc32) = (first.compressed_size(), first.crc32());
+        drop(first);
+        let mut second = archive.by_name("second.txt").unwrap();
+        assert_eq!(second.compression(), CompressionMethod::Deflated);
+        assert_eq!(second.compressed_size(), first_size);
+        assert_eq!(second.crc32(), first_crc32);
+        let mut contents = Vec::new();
+        second.read_to_end(&mut contents).unwrap();
+        assert_eq!(contents, vec![b'a'; 10_000]);
+    }
+
+    #[cfg(feature = "deflate")]
+    #[test]
+    fn deduplicate_contents_keeps_the_modification_time_of_each_file() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use zip::CompressionMethod;
+
+        use crate::{zip_create_from_directory_with_options, CreateOptions};
+
+        let test_dir = TestDir::new("deduplicate-mtimes");
+        let source_dir = test_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        for (name, seconds) in [("new.txt", 1_700_000_000), ("old.txt", 1_600_000_000)] {
+            let path = source_dir.join(name);
+            fs::write(&path, vec![b'a'; 10_000]).unwrap();
+            File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(UNIX_EPOCH + Duration::from_secs(seconds))
+                .unwrap();
+        }
+
+        let archive_file = test_dir.path().join("archive.zip");
+        let options = CreateOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .deduplicate_contents(true);
+        let report =
+            zip_create_from_directory_with_options(&archive_file, &source_dir, options).unwrap();
+        assert_eq!(report.duplicate_contents.len(), 1);
+        assert_eq!(report.files_deduplicated, 0);
+
+        let mut archive = ZipArchive::new(File::open(&archive_file).unwrap()).unwrap();
+        let year = |archive: &mut ZipArchive<File>, name: &str| {
+            archive.by_name(name).unwrap().last_modified().year()
+        };
+        assert_eq!(year(&mut archive, "new.txt"), 2023);
+        assert_eq!(year(&mut archive, "old.txt"), 2020);
     }
 }